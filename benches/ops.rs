@@ -13,7 +13,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         b.iter(|| client.echo(black_box("hello")).is_ok())
     });
     c.bench_function("exists", |b| {
-        b.iter(|| client.exists(black_box(key), black_box(vec![])).is_ok())
+        b.iter(|| client.exists_one(black_box(key)).is_ok())
     });
     c.bench_function("expire", |b| {
         b.iter(|| {
@@ -75,6 +75,47 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
     c.bench_function("ttl", |b| b.iter(|| client.ttl(black_box(key)).is_ok()));
     c.bench_function("type", |b| b.iter(|| client.dtype(black_box(key)).is_ok()));
+
+    let mut nagle_client = dicedb_rs::client::Client::builder()
+        .nodelay(false)
+        .build()
+        .unwrap();
+    c.bench_function("get_nagle", |b| {
+        b.iter(|| nagle_client.get(black_box(key)).is_ok())
+    });
+
+    // Replies larger than the initial read buffer make the buffer grow once, after which it is
+    // reused. Compare against a saved baseline with `cargo bench -- --baseline <name>`.
+    let large_key = "benchkey_large";
+    client
+        .set(large_key, "x".repeat(64 * 1024).as_str())
+        .unwrap();
+    c.bench_function("get_large", |b| {
+        b.iter(|| client.get(black_box(large_key)).is_ok())
+    });
+    c.bench_function("set_get", |b| {
+        b.iter(|| {
+            client.set(black_box(key), black_box(1)).is_ok() && client.get(black_box(key)).is_ok()
+        })
+    });
+
+    // A batch of commands costs one round trip when pipelined, instead of one per command.
+    c.bench_function("set_1000_sequential", |b| {
+        b.iter(|| {
+            for i in 0..1000 {
+                client.set(black_box(key), black_box(i)).unwrap();
+            }
+        })
+    });
+    c.bench_function("set_1000_pipelined", |b| {
+        b.iter(|| {
+            let mut pipeline = client.pipeline();
+            for i in 0..1000 {
+                pipeline.set(black_box(key), black_box(i));
+            }
+            pipeline.execute().unwrap()
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use dicedb_rs::{
+    asyncclient::AsyncDiceCommands,
+    commands::ScalarValue,
+    errors::{ClientError, CommandError},
+    mock::MockAsyncClient,
+};
+
+/// The service under test: counts a visit of a page, retrying once if the database timed out.
+/// It takes any implementation of the commands, so it runs against the real client in
+/// production and against the mock in tests.
+async fn count_visit(db: &impl AsyncDiceCommands, page: &str) -> Result<i64, ClientError> {
+    let key = format!("visits:{}", page);
+    let reply = match db.incr(&key).await {
+        Err(e) if e.is_timeout() => db.incr(&key).await?,
+        reply => reply?,
+    };
+    match reply {
+        ScalarValue::VInt(count) => Ok(count),
+        other => Err(CommandError::UnexpectedResponse(other).into()),
+    }
+}
+
+// Time is paused, so the latencies below pass instantly.
+#[tokio::main(flavor = "current_thread", start_paused = true)]
+async fn main() -> Result<(), ClientError> {
+    let mock = MockAsyncClient::new();
+    mock.set_latency(Duration::from_millis(20));
+    mock.set_read_timeout(Some(Duration::from_millis(100)));
+
+    // The happy path.
+    println!("First visit: {}", count_visit(&mock, "home").await?);
+
+    // A timeout is retried.
+    mock.fail_next(std::io::Error::from(std::io::ErrorKind::TimedOut));
+    println!("After a retry: {}", count_visit(&mock, "home").await?);
+
+    // A database slower than the read timeout fails the service.
+    mock.set_latency(Duration::from_secs(1));
+    match count_visit(&mock, "home").await {
+        Err(e) => println!("Too slow: {}", e),
+        Ok(count) => println!("Unexpected count: {}", count),
+    }
+
+    println!("Commands sent: {}", mock.commands().len());
+    Ok(())
+}
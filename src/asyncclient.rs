@@ -0,0 +1,684 @@
+//! # AsyncClient Module
+//! An async client on tokio, which multiplexes the commands of many tasks over a single
+//! connection. Create one with [`ClientBuilder::build_async`](crate::client::ClientBuilder::build_async).
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::codec::{Decoder, Encoder};
+use uuid::Uuid;
+
+use crate::{
+    codec::{DiceCodec, Reply},
+    commands::{Command, CommandDescriptor, DelInput, ScalarValue, SetInput, SetOption},
+    commandstream::CommandStream,
+    config::{random_unit, ConnectionOptions},
+    errors::{ClientError, CommandStreamError, StreamError},
+    stream::Stream,
+    transport::Transport,
+    LOG_TARGET,
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+/// How many queued commands are written together at most, so reading replies is not held up by
+/// a steady stream of new commands.
+const MAX_BATCH: usize = 128;
+
+/// An async client, created with [`ClientBuilder::build_async`](crate::client::ClientBuilder::build_async). The client is a handle that can
+/// be cloned and shared by any number of tasks, which all send their commands over the same
+/// connection.
+///
+/// A background task owns the connection. Commands are queued for it, written in the order they
+/// were queued, and each caller waits for its own reply. As replies carry no ids, they are
+/// matched to commands strictly in order. The queue is bounded, see
+/// [`ClientBuilder::queue_capacity`](crate::client::ClientBuilder::queue_capacity), so callers wait while it is full.
+///
+/// If the connection is lost, or a reply cannot be matched to its command, every command waiting
+/// for a reply fails with [`StreamError::OutcomeUnknown`], and the client reconnects following
+/// its reconnect policy when the next command is queued. If the policy is
+/// [`disabled`](crate::config::ReconnectPolicy::disabled), every later command fails with
+/// [`StreamError::ReconnectDisabled`] instead, and a new client has to be built. The read timeout
+/// of the builder bounds how long a command waits for its reply, queueing included.
+///
+/// The background task stops once every handle of the client was dropped and every reply was
+/// received.
+/// # Example
+/// ```no_run
+/// use dicedb_rs::client::Client;
+///
+/// # async fn run() -> Result<(), dicedb_rs::errors::ClientError> {
+/// let client = Client::builder().host("localhost").port(7379).build_async().await?;
+/// let tasks: Vec<_> = (0..10)
+///     .map(|_| {
+///         let client = client.clone();
+///         tokio::spawn(async move { client.incr("visits").await })
+///     })
+///     .collect();
+/// for task in tasks {
+///     println!("visits: {}", task.await.unwrap()?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    requests: mpsc::Sender<Request>,
+    prefix: Option<String>,
+    read_timeout: Option<Duration>,
+}
+
+/// A command queued for the connection, with where to send its reply.
+#[derive(Debug)]
+struct Request {
+    command: CommandDescriptor,
+    reply: Waiter,
+}
+
+type Waiter = oneshot::Sender<std::result::Result<Reply, StreamError>>;
+
+impl AsyncClient {
+    pub(crate) async fn connect(mut options: ConnectionOptions) -> Result<Self> {
+        // Every connection of the client identifies itself with the same id.
+        options
+            .client_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
+        let socket = connect(options.clone()).await?;
+        let (requests, receiver) = mpsc::channel(options.queue_capacity);
+        let client = AsyncClient {
+            requests,
+            prefix: None,
+            read_timeout: options.read_timeout,
+        };
+        let connection = Connection {
+            codec: DiceCodec::new()
+                .max_response_size(options.read_limit())
+                .max_command_size(options.max_command_size),
+            options,
+            requests: receiver,
+        };
+        tokio::spawn(connection.run(socket));
+        Ok(client)
+    }
+
+    /// Scope the client to a namespace, like [`Client::with_prefix`](crate::client::Client::with_prefix).
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Returns the namespace the client is scoped to, if any.
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Sends a command as is, without scoping its keys, and returns the reply undecoded.
+    /// # Errors
+    /// * [`ClientError`] - If the command could not be sent or its reply was not received.
+    pub async fn execute(&self, command: CommandDescriptor) -> Result<Reply> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(Request { command, reply })
+            .await
+            .map_err(|_| stopped())?;
+        let reply = match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, receiver)
+                .await
+                .map_err(|_| StreamError::from(io::Error::from(io::ErrorKind::TimedOut)))?,
+            None => receiver.await,
+        };
+        Ok(reply.map_err(|_| stopped())??)
+    }
+
+    async fn execute_scalar(&self, command: Command) -> Result<ScalarValue> {
+        let command = match &self.prefix {
+            Some(prefix) => command.prefix_keys(prefix),
+            None => command,
+        };
+        command.check_args()?;
+        let reply = self.execute(command.into()).await?;
+        Ok(reply.value()?)
+    }
+
+    /// Decrements the integer at `key` by one, see [`Client::decr`](crate::client::Client::decr).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn decr(&self, key: &str) -> Result<ScalarValue> {
+        self.execute_scalar(Command::DECR {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Decrements the integer at `key` by `delta`, see
+    /// [`Client::decrby`](crate::client::Client::decrby).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn decrby(&self, key: &str, delta: i64) -> Result<ScalarValue> {
+        self.execute_scalar(Command::DECRBY {
+            key: key.to_string(),
+            delta,
+        })
+        .await
+    }
+
+    /// Deletes the keys and returns how many were deleted, see
+    /// [`Client::del`](crate::client::Client::del).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn del<'a, T: Into<DelInput<'a>>>(&self, keys: T) -> Result<ScalarValue> {
+        let keys = keys.into().into_keys();
+        self.execute_scalar(Command::DEL { keys }).await
+    }
+
+    /// Returns `message` as the server received it.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn echo(&self, message: &str) -> Result<ScalarValue> {
+        self.execute_scalar(Command::ECHO {
+            message: message.to_string(),
+        })
+        .await
+    }
+
+    /// Returns the value of `key`, see [`Client::get`](crate::client::Client::get).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn get(&self, key: &str) -> Result<ScalarValue> {
+        self.execute_scalar(Command::GET {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Increments the integer at `key` by one, see [`Client::incr`](crate::client::Client::incr).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn incr(&self, key: &str) -> Result<ScalarValue> {
+        self.execute_scalar(Command::INCR {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Increments the integer at `key` by `delta`, see
+    /// [`Client::incrby`](crate::client::Client::incrby).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn incrby(&self, key: &str, delta: i64) -> Result<ScalarValue> {
+        self.execute_scalar(Command::INCRBY {
+            key: key.to_string(),
+            delta,
+        })
+        .await
+    }
+
+    /// Checks that the server is reachable.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn ping(&self) -> Result<ScalarValue> {
+        self.execute_scalar(Command::PING).await
+    }
+
+    /// Sets `key` to `value`, see [`Client::set`](crate::client::Client::set).
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub async fn set<T: Into<SetInput>>(&self, key: &str, value: T) -> Result<ScalarValue> {
+        self.execute_scalar(Command::SET {
+            key: key.to_string(),
+            value: value.into(),
+            option: SetOption::None,
+            get: false,
+            nx: false,
+        })
+        .await
+    }
+}
+
+/// The commands of an async client, implemented by [`AsyncClient`] and, with the `test-util`
+/// feature, by the `MockAsyncClient` of the `mock` module. Code that takes any implementation can
+/// be tested without a server.
+/// # Example
+/// ```no_run
+/// use dicedb_rs::asyncclient::AsyncDiceCommands;
+/// use dicedb_rs::commands::ScalarValue;
+/// use dicedb_rs::errors::ClientError;
+///
+/// async fn count_visit(db: &impl AsyncDiceCommands, page: &str) -> Result<ScalarValue, ClientError> {
+///     db.incr(&format!("visits:{}", page)).await
+/// }
+/// ```
+pub trait AsyncDiceCommands: Send + Sync {
+    /// See [`AsyncClient::decr`].
+    fn decr(&self, key: &str) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::decrby`].
+    fn decrby(&self, key: &str, delta: i64) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::del`].
+    fn del<'a, T: Into<DelInput<'a>> + Send>(
+        &self,
+        keys: T,
+    ) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::echo`].
+    fn echo(&self, message: &str) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::get`].
+    fn get(&self, key: &str) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::incr`].
+    fn incr(&self, key: &str) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::incrby`].
+    fn incrby(&self, key: &str, delta: i64) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::ping`].
+    fn ping(&self) -> impl Future<Output = Result<ScalarValue>> + Send;
+    /// See [`AsyncClient::set`].
+    fn set<T: Into<SetInput> + Send>(
+        &self,
+        key: &str,
+        value: T,
+    ) -> impl Future<Output = Result<ScalarValue>> + Send;
+}
+
+impl AsyncDiceCommands for AsyncClient {
+    async fn decr(&self, key: &str) -> Result<ScalarValue> {
+        AsyncClient::decr(self, key).await
+    }
+
+    async fn decrby(&self, key: &str, delta: i64) -> Result<ScalarValue> {
+        AsyncClient::decrby(self, key, delta).await
+    }
+
+    async fn del<'a, T: Into<DelInput<'a>> + Send>(&self, keys: T) -> Result<ScalarValue> {
+        AsyncClient::del(self, keys).await
+    }
+
+    async fn echo(&self, message: &str) -> Result<ScalarValue> {
+        AsyncClient::echo(self, message).await
+    }
+
+    async fn get(&self, key: &str) -> Result<ScalarValue> {
+        AsyncClient::get(self, key).await
+    }
+
+    async fn incr(&self, key: &str) -> Result<ScalarValue> {
+        AsyncClient::incr(self, key).await
+    }
+
+    async fn incrby(&self, key: &str, delta: i64) -> Result<ScalarValue> {
+        AsyncClient::incrby(self, key, delta).await
+    }
+
+    async fn ping(&self) -> Result<ScalarValue> {
+        AsyncClient::ping(self).await
+    }
+
+    async fn set<T: Into<SetInput> + Send>(&self, key: &str, value: T) -> Result<ScalarValue> {
+        AsyncClient::set(self, key, value).await
+    }
+}
+
+/// The error of a command whose client has no connection task anymore.
+fn stopped() -> StreamError {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the connection task of the client has stopped",
+    )
+    .into()
+}
+
+/// Opens a connection and runs the handshake with a blocking command stream, which takes care of
+/// endpoints, timeouts and authentication, then hands the socket over to tokio.
+async fn connect(options: ConnectionOptions) -> std::result::Result<TcpStream, StreamError> {
+    let socket = tokio::task::spawn_blocking(move || -> std::result::Result<_, StreamError> {
+        let mut stream = CommandStream::new(options).map_err(|e| match e {
+            CommandStreamError::ReadError(e) => StreamError::IoError(e),
+            e => StreamError::IoError(io::Error::other(e)),
+        })?;
+        stream.handshake()?;
+        match stream.stream {
+            Transport::Tcp(socket) => Ok(socket),
+            #[cfg(feature = "tls")]
+            Transport::Tls(_) => Err(StreamError::IoError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the async client does not support TLS",
+            ))),
+        }
+    })
+    .await
+    .map_err(|e| StreamError::IoError(io::Error::other(e)))??;
+    socket.set_read_timeout(None)?;
+    socket.set_write_timeout(None)?;
+    socket.set_nonblocking(true)?;
+    Ok(TcpStream::from_std(socket)?)
+}
+
+/// The background task owning the connection of a client.
+struct Connection {
+    options: ConnectionOptions,
+    codec: DiceCodec,
+    requests: mpsc::Receiver<Request>,
+}
+
+impl Connection {
+    /// Serves commands until every handle of the client was dropped, reconnecting when the next
+    /// command is queued after the connection was lost.
+    async fn run(mut self, socket: TcpStream) {
+        let mut lost = self.serve(socket, None).await;
+        while lost {
+            let Some(request) = self.requests.recv().await else {
+                return;
+            };
+            if !self.options.reconnect_policy.is_automatic() {
+                let _ = request.reply.send(Err(StreamError::ReconnectDisabled));
+                continue;
+            }
+            match self.reconnect().await {
+                Ok(socket) => lost = self.serve(socket, Some(request)).await,
+                Err(e) => {
+                    let _ = request.reply.send(Err(e));
+                }
+            }
+        }
+    }
+
+    /// Writes queued commands and hands out replies in order. Returns true once the connection
+    /// is lost or poisoned, and false once every handle was dropped and every reply received.
+    async fn serve(&mut self, socket: TcpStream, first: Option<Request>) -> bool {
+        let (mut reader, mut writer) = socket.into_split();
+        let mut waiters = VecDeque::new();
+        let mut incoming = BytesMut::with_capacity(self.options.initial_read_buffer);
+        let mut outgoing = BytesMut::new();
+        let mut next = first;
+        let mut open = true;
+        loop {
+            let mut batch = 0;
+            while let Some(request) = next.take().or_else(|| match batch < MAX_BATCH {
+                true => self.requests.try_recv().ok(),
+                false => None,
+            }) {
+                batch += 1;
+                match self.codec.encode(request.command, &mut outgoing) {
+                    Ok(()) => waiters.push_back(request.reply),
+                    // Nothing was written, so the connection stays usable.
+                    Err(e) => {
+                        let _ = request.reply.send(Err(e));
+                    }
+                }
+            }
+            if let Err(e) = writer.write_all_buf(&mut outgoing).await {
+                return poison(waiters, e.into());
+            }
+            if !open && waiters.is_empty() {
+                return false;
+            }
+            tokio::select! {
+                request = self.requests.recv(), if open => match request {
+                    Some(request) => next = Some(request),
+                    None => open = false,
+                },
+                read = reader.read_buf(&mut incoming) => match read {
+                    Ok(0) => {
+                        let e = io::Error::from(io::ErrorKind::UnexpectedEof);
+                        return poison(waiters, e.into());
+                    }
+                    Ok(_) => {
+                        if let Err(e) = self.hand_out(&mut incoming, &mut waiters) {
+                            return poison(waiters, e);
+                        }
+                    }
+                    Err(e) => return poison(waiters, e.into()),
+                },
+            }
+        }
+    }
+
+    /// Sends every complete reply in `incoming` to the command waiting for it.
+    fn hand_out(
+        &mut self,
+        incoming: &mut BytesMut,
+        waiters: &mut VecDeque<Waiter>,
+    ) -> std::result::Result<(), StreamError> {
+        while let Some(reply) = self.codec.decode(incoming)? {
+            if reply.is_watch_push() {
+                return Err(StreamError::ProtocolDesync);
+            }
+            match waiters.pop_front() {
+                // The caller may have stopped waiting, e.g. after a timeout.
+                Some(waiter) => {
+                    let _ = waiter.send(Ok(reply));
+                }
+                None => return Err(StreamError::ProtocolDesync),
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens a new connection following the reconnect policy.
+    async fn reconnect(&self) -> std::result::Result<TcpStream, StreamError> {
+        let policy = self.options.reconnect_policy;
+        let started = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match connect(self.options.clone()).await {
+                Ok(socket) => {
+                    log::info!(target: LOG_TARGET, "Reconnected after {} attempts", attempts);
+                    return Ok(socket);
+                }
+                Err(StreamError::IoError(e)) if attempts >= policy.max_attempts() => {
+                    return Err(StreamError::ReconnectFailed {
+                        attempts,
+                        elapsed: started.elapsed(),
+                        source: e,
+                    })
+                }
+                Err(StreamError::IoError(e)) => {
+                    log::debug!(target: LOG_TARGET, "Reconnect attempt {} failed: {}", attempts, e);
+                    tokio::time::sleep(policy.delay(attempts, random_unit())).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Fails every command waiting for a reply, as replies can no longer be matched to them. Returns
+/// true, as the connection must be replaced.
+fn poison(waiters: VecDeque<Waiter>, cause: StreamError) -> bool {
+    if waiters.is_empty() {
+        log::debug!(target: LOG_TARGET, "Connection lost while idle: {}", cause);
+        return true;
+    }
+    log::warn!(
+        target: LOG_TARGET,
+        "Connection lost with {} commands waiting for a reply: {}",
+        waiters.len(),
+        cause
+    );
+    let kind = match &cause {
+        StreamError::IoError(e) => e.kind(),
+        _ => io::ErrorKind::InvalidData,
+    };
+    for waiter in waiters {
+        let error = io::Error::new(kind, cause.to_string());
+        let _ = waiter.send(Err(StreamError::OutcomeUnknown(error)));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicI64, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use tokio::net::TcpListener;
+
+    use crate::{
+        client::Client,
+        commands::{decode_command, encode_reply},
+        config::ReconnectPolicy,
+        framing,
+    };
+
+    use super::*;
+
+    /// A server keeping a single counter, which answers HANDSHAKE, INCR and ECHO, and closes the
+    /// connection without replying when it receives GET.
+    struct CounterServer {
+        port: u16,
+        counter: Arc<AtomicI64>,
+        connections: Arc<AtomicUsize>,
+    }
+
+    impl CounterServer {
+        async fn start() -> CounterServer {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = CounterServer {
+                port: listener.local_addr().unwrap().port(),
+                counter: Arc::default(),
+                connections: Arc::default(),
+            };
+            let (counter, connections) = (server.counter.clone(), server.connections.clone());
+            tokio::spawn(async move {
+                loop {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    connections.fetch_add(1, Ordering::Relaxed);
+                    tokio::spawn(CounterServer::serve(socket, counter.clone()));
+                }
+            });
+            server
+        }
+
+        async fn serve(mut socket: TcpStream, counter: Arc<AtomicI64>) {
+            let mut pending = Vec::new();
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                while let Some(len) = framing::command_len(&pending) {
+                    let (name, args) = decode_command(&pending[..len]).unwrap();
+                    pending.drain(..len);
+                    let reply = match name.as_str() {
+                        "HANDSHAKE" => ScalarValue::VStr("OK".to_string()),
+                        "INCR" => ScalarValue::VInt(counter.fetch_add(1, Ordering::Relaxed) + 1),
+                        "ECHO" => ScalarValue::VStr(args[0].clone()),
+                        _ => return,
+                    };
+                    socket.write_all(&encode_reply(&reply)).await.unwrap();
+                }
+                match socket.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(size) => pending.extend_from_slice(&buffer[..size]),
+                }
+            }
+        }
+
+        async fn client(&self) -> AsyncClient {
+            Client::builder()
+                .host("127.0.0.1")
+                .port(self.port)
+                .build_async()
+                .await
+                .unwrap()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_tasks_share_one_connection() {
+        let server = CounterServer::start().await;
+        let client = server.client().await;
+        let tasks: Vec<_> = (0..100)
+            .map(|task| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let count = client.incr("counter").await.unwrap();
+                    let payload = format!("task-{}", task);
+                    let echoed = client.echo(&payload).await.unwrap();
+                    assert_eq!(echoed, ScalarValue::VStr(payload));
+                    match count {
+                        ScalarValue::VInt(count) => count,
+                        other => panic!("unexpected reply {:?}", other),
+                    }
+                })
+            })
+            .collect();
+        let mut counts = HashSet::new();
+        for task in tasks {
+            counts.insert(task.await.unwrap());
+        }
+        assert_eq!(counts, (1..=100).collect());
+        assert_eq!(server.counter.load(Ordering::Relaxed), 100);
+        assert_eq!(server.connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lost_connection_fails_waiters_and_reconnects() {
+        let server = CounterServer::start().await;
+        let client = server.client().await;
+        // Both commands are written before the server closes the connection on GET.
+        let (get, echo) = tokio::join!(client.get("key"), client.echo("after"));
+        for result in [get, echo] {
+            assert!(matches!(
+                result,
+                Err(ClientError::StreamError(StreamError::OutcomeUnknown(_)))
+            ));
+        }
+        assert_eq!(client.incr("counter").await.unwrap(), ScalarValue::VInt(1));
+        assert_eq!(server.connections.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_disabled_fails_fast() {
+        let server = CounterServer::start().await;
+        let client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .reconnect_policy(ReconnectPolicy::disabled())
+            .build_async()
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.get("key").await,
+            Err(ClientError::StreamError(StreamError::OutcomeUnknown(_)))
+        ));
+        for _ in 0..2 {
+            assert!(matches!(
+                client.incr("counter").await,
+                Err(ClientError::StreamError(StreamError::ReconnectDisabled))
+            ));
+        }
+        assert_eq!(server.counter.load(Ordering::Relaxed), 0);
+        assert_eq!(server.connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_command_too_large_keeps_connection() {
+        let server = CounterServer::start().await;
+        let client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .max_command_size(64)
+            .build_async()
+            .await
+            .unwrap();
+        let error = client.echo(&"x".repeat(100)).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::StreamError(StreamError::CommandTooLarge { limit: 64, .. })
+        ));
+        assert_eq!(
+            client.echo("small").await.unwrap(),
+            ScalarValue::VStr("small".to_string())
+        );
+        assert_eq!(server.connections.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,184 @@
+//! # Breaker Module
+//! The state of a circuit breaker, configured with
+//! [`ClientBuilder::circuit_breaker`](crate::client::ClientBuilder::circuit_breaker).
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{config::CircuitBreakerPolicy, errors::StreamError, LOG_TARGET};
+
+/// Counts consecutive failures to reach the server and fails commands fast while the server is
+/// considered down. Shared by every client built from the same builder.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Commands are sent, counting consecutive failures.
+    Closed { failures: u32 },
+    /// Commands fail without being sent until the given time.
+    Open { until: Instant },
+    /// Up to the number of probes of the policy are sent, and the breaker closes once that many
+    /// succeeded.
+    HalfOpen { in_flight: u32, succeeded: u32 },
+}
+
+/// Whether an attempt let through by the breaker is a probe of a half-open breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Permit {
+    Closed,
+    Probe,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(policy: CircuitBreakerPolicy) -> Self {
+        CircuitBreaker {
+            policy,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Lets an attempt through, or fails with [`StreamError::CircuitOpen`] if the breaker is open
+    /// or every probe of a half-open breaker is under way.
+    pub(crate) fn acquire(&self) -> Result<Permit, StreamError> {
+        let mut state = self.state();
+        match *state {
+            State::Closed { .. } => Ok(Permit::Closed),
+            State::Open { until } => {
+                let retry_after = until.saturating_duration_since(Instant::now());
+                if !retry_after.is_zero() {
+                    return Err(StreamError::CircuitOpen { retry_after });
+                }
+                log::info!(target: LOG_TARGET, "Circuit half-open, probing the server");
+                *state = State::HalfOpen {
+                    in_flight: 1,
+                    succeeded: 0,
+                };
+                Ok(Permit::Probe)
+            }
+            State::HalfOpen {
+                in_flight,
+                succeeded,
+            } if in_flight + succeeded < self.policy.probes() => {
+                *state = State::HalfOpen {
+                    in_flight: in_flight + 1,
+                    succeeded,
+                };
+                Ok(Permit::Probe)
+            }
+            State::HalfOpen { .. } => Err(StreamError::CircuitOpen {
+                retry_after: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Records the outcome of an attempt. Only a failure to reach the server counts as a failure,
+    /// as any reply shows the server is up.
+    pub(crate) fn record<T>(&self, permit: Permit, result: &Result<T, StreamError>) {
+        let failed = match result {
+            Err(e) => e.is_connection_error() || e.is_timeout(),
+            Ok(_) => false,
+        };
+        let mut state = self.state();
+        *state = match (*state, permit, failed) {
+            (State::Closed { .. }, Permit::Closed, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, Permit::Closed, true) => {
+                let failures = failures + 1;
+                match failures >= self.policy.failure_threshold() {
+                    true => self.open(failures),
+                    false => State::Closed { failures },
+                }
+            }
+            (State::HalfOpen { .. }, Permit::Probe, true) => self.open(1),
+            (
+                State::HalfOpen {
+                    in_flight,
+                    succeeded,
+                },
+                Permit::Probe,
+                false,
+            ) => match succeeded + 1 >= self.policy.probes() {
+                true => {
+                    log::info!(target: LOG_TARGET, "Circuit closed, the server is back");
+                    State::Closed { failures: 0 }
+                }
+                false => State::HalfOpen {
+                    in_flight: in_flight.saturating_sub(1),
+                    succeeded: succeeded + 1,
+                },
+            },
+            // The breaker changed state while the attempt was under way, which decides alone.
+            (state, _, _) => state,
+        };
+    }
+
+    fn open(&self, failures: u32) -> State {
+        log::warn!(
+            target: LOG_TARGET,
+            "Circuit open after {} failures, failing commands for {:?}",
+            failures,
+            self.policy.open_duration()
+        );
+        State::Open {
+            until: Instant::now() + self.policy.open_duration(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    fn failure() -> Result<(), StreamError> {
+        Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+    }
+
+    #[test]
+    fn test_transitions() {
+        let breaker = CircuitBreaker::new(
+            CircuitBreakerPolicy::new(2, Duration::from_millis(20)).half_open_probes(2),
+        );
+        let permit = breaker.acquire().unwrap();
+        breaker.record(permit, &failure());
+        // A reply, even an error, resets the count.
+        breaker.record(permit, &Err::<(), _>(StreamError::ProtocolDesync));
+        breaker.record(permit, &failure());
+        assert_eq!(*breaker.state(), State::Closed { failures: 1 });
+        breaker.record(permit, &failure());
+        assert!(matches!(*breaker.state(), State::Open { .. }));
+        assert!(matches!(
+            breaker.acquire(),
+            Err(StreamError::CircuitOpen { retry_after }) if retry_after > Duration::ZERO
+        ));
+
+        std::thread::sleep(Duration::from_millis(25));
+        let first = breaker.acquire().unwrap();
+        let second = breaker.acquire().unwrap();
+        assert_eq!((first, second), (Permit::Probe, Permit::Probe));
+        assert!(matches!(
+            breaker.acquire(),
+            Err(StreamError::CircuitOpen { retry_after }) if retry_after == Duration::ZERO
+        ));
+        breaker.record(first, &Ok(()));
+        assert!(matches!(*breaker.state(), State::HalfOpen { .. }));
+        breaker.record(second, &failure());
+        assert!(matches!(*breaker.state(), State::Open { .. }));
+
+        std::thread::sleep(Duration::from_millis(25));
+        for _ in 0..2 {
+            let probe = breaker.acquire().unwrap();
+            breaker.record(probe, &Ok(()));
+        }
+        assert_eq!(*breaker.state(), State::Closed { failures: 0 });
+    }
+}
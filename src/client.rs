@@ -2,17 +2,532 @@
 //! The client module contains the main client struct and its implementation.
 //! The SDK is centered around the `Client` struct, which is used to interact with the DiceDB
 //! server.
-use crate::commandstream::CommandStream;
-use crate::errors::ClientError;
-use crate::stream::Stream;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+use crate::asyncclient::AsyncClient;
+use crate::breaker::CircuitBreaker;
+use crate::commands::{
+    prefixed_key, AttrValue, Command, CommandDescriptor, CommandExecutor, Execute, ListValue,
+    MapValue, ScalarValue, ServerVersion,
+};
+use crate::commandstream::{lock, CommandStream};
+use crate::config::{
+    random_unit, CircuitBreakerPolicy, ConnectionEvents, ConnectionOptions, ConnectionState,
+    ReconnectPolicy, RetryPolicy, Secret,
+};
+use crate::errors::{ClientError, CommandError, CommandStreamError, StreamError};
+use crate::heartbeat::Heartbeat;
+use crate::interceptor::CommandInterceptor;
+use crate::latency::{LatencyReport, LatencyTracker};
+use crate::observer::{CommandMeta, CommandObserver, Observer};
+use crate::pipeline::Pipeline;
+use crate::replay::Recorder;
+use crate::stats::{ClientStats, Counters};
+use crate::stream::{reconnect_automatically, Reconnectable, Stream};
+use crate::subscription::Registry;
+use crate::LOG_TARGET;
+use uuid::Uuid;
 
 /// The main client struct used to interact with the DiceDB server.
-/// Create a new client with `Client::new(host: String, port: u16)`.
+/// Create a new client with `Client::new(host: String, port: u16)`, or configure one with
+/// [`Client::builder`].
 #[derive(Debug)]
 pub struct Client {
-    pub(crate) port: u16,
-    pub(crate) host: String,
-    pub(crate) command_client: CommandStream,
+    pub(crate) options: ConnectionOptions,
+    backend: Backend,
+    /// The traffic counters, shared with the command stream.
+    pub(crate) counters: Arc<Counters>,
+    pub(crate) prefix: Option<String>,
+    /// The version of the server, if it was checked when connecting.
+    server_version: Option<ServerVersion>,
+    /// The keys of the live subscriptions of the client.
+    pub(crate) subscriptions: Arc<Registry>,
+    /// Stops the heartbeat thread when the client is dropped.
+    _heartbeat: Option<Heartbeat>,
+}
+
+/// Where the commands of a client are executed.
+enum Backend {
+    /// A connection to a server, shared with the heartbeat thread.
+    Stream(Arc<Mutex<CommandStream>>),
+    /// An executor set with [`Client::with_executor`].
+    Executor(Mutex<Box<dyn CommandExecutor>>),
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Stream(stream) => f.debug_tuple("Stream").field(stream).finish(),
+            Backend::Executor(_) => f.write_str("Executor"),
+        }
+    }
+}
+
+/// The oldest server version the client supports, see [`ClientBuilder::check_server_version`].
+pub const MINIMUM_SERVER_VERSION: ServerVersion = ServerVersion::new(1, 0, 0);
+
+/// Builder for a [`Client`], created with [`Client::builder`].
+///
+/// The builder is not consumed by [`build`](ClientBuilder::build), so one configuration can be
+/// used to create many clients.
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use dicedb_rs::client::Client;
+/// use dicedb_rs::errors::ClientError;
+/// fn main() -> Result<(), ClientError> {
+///    let builder = Client::builder()
+///        .host("localhost")
+///        .port(7379)
+///        .read_timeout(Duration::from_secs(5));
+///    let first = builder.build()?;
+///    let second = builder.build()?;
+///    Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    pub(crate) options: ConnectionOptions,
+}
+
+impl ClientBuilder {
+    /// Create a builder connecting to `localhost:7379` with a 5 second connect timeout and no
+    /// read or write timeouts.
+    pub fn new() -> Self {
+        ClientBuilder::default()
+    }
+
+    /// Create a builder from the environment, so the connection can be configured without code
+    /// changes.
+    ///
+    /// `DICEDB_URL` (`dicedb://[:password@]host[:port]`) takes precedence over `DICEDB_HOST` and
+    /// `DICEDB_PORT`. `DICEDB_PASSWORD` sets the password, and `DICEDB_CONNECT_TIMEOUT_MS`,
+    /// `DICEDB_READ_TIMEOUT_MS` and `DICEDB_WRITE_TIMEOUT_MS` set the timeouts in milliseconds.
+    /// # Errors
+    /// Returns [`ClientError::ConfigError`] if neither `DICEDB_URL` nor `DICEDB_HOST` is set, or
+    /// if a value cannot be parsed.
+    pub fn from_env() -> Result<Self, ClientError> {
+        Ok(ClientBuilder {
+            options: ConnectionOptions::from_env()?,
+        })
+    }
+
+    /// Set the host of the server.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.options.host = host.into();
+        self
+    }
+
+    /// Set the port of the server.
+    pub fn port(mut self, port: u16) -> Self {
+        self.options.port = port;
+        self
+    }
+
+    /// Set an ordered list of endpoints to fail over between, such as a primary and a standby.
+    /// Connections start with the first endpoint and move on to the next one when an endpoint
+    /// cannot be reached. The host and port settings are ignored when endpoints are set.
+    pub fn endpoints(mut self, endpoints: Vec<(impl Into<String>, u16)>) -> Self {
+        self.options.endpoints = endpoints
+            .into_iter()
+            .map(|(host, port)| (host.into(), port))
+            .collect();
+        self
+    }
+
+    /// Set a function called with the host and port of the new endpoint whenever a connection
+    /// fails over to another endpoint.
+    pub fn on_endpoint_change(mut self, hook: fn(&str, u16)) -> Self {
+        self.options.on_endpoint_change = Some(hook);
+        self
+    }
+
+    /// Set the maximum time to wait for a connection to be established, 5 seconds by default.
+    /// When the host resolves to several addresses, each is given an equal share of the timeout.
+    /// The timeout also applies to every reconnect attempt.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the maximum time to wait for the server to answer the handshake of a new connection,
+    /// 5 seconds by default. This bounds creating a client and every reconnect, even when no
+    /// read timeout is set, so a server that accepts connections but never replies fails with
+    /// [`StreamError::HandshakeTimeout`].
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.options.handshake_timeout = timeout;
+        self
+    }
+
+    /// Set the maximum time to wait for a reply to a command. Watch streams are not affected, as
+    /// they wait for changes indefinitely.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.options.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum time to wait for a command to be written.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.options.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether `TCP_NODELAY` is enabled on the connections, which is the default. Disabling
+    /// it lets the OS batch small writes, at the cost of latency for every command.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.options.nodelay = nodelay;
+        self
+    }
+
+    /// Set the local address connections are made from, for hosts with several interfaces. Every
+    /// connection, including reconnects and watch streams, is bound to the address with an
+    /// ephemeral port before connecting, and only server addresses of the same family are tried.
+    /// A failure to bind is reported as [`ErrorKind::Bind`](crate::errors::ErrorKind::Bind)
+    /// rather than as a connection error.
+    pub fn local_address(mut self, address: IpAddr) -> Self {
+        self.options.local_address = Some(address);
+        self
+    }
+
+    /// Set whether connections are encrypted with TLS. The server certificate is verified
+    /// against the bundled web PKI roots, unless CA certificates are given with
+    /// [`ca_certificate`](ClientBuilder::ca_certificate).
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn tls(mut self, enabled: bool) -> Self {
+        self.options.tls = enabled.then(|| self.options.tls.take().unwrap_or_default());
+        self
+    }
+
+    /// Set the name the server certificate is verified against, which defaults to the host.
+    /// Enables TLS.
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn tls_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.options
+            .tls
+            .get_or_insert_with(Default::default)
+            .server_name = Some(server_name.into());
+        self
+    }
+
+    /// Trust the PEM encoded CA certificates for verifying the server, instead of the bundled web
+    /// PKI roots. Can be called several times to trust more certificates. Enables TLS.
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn ca_certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.options
+            .tls
+            .get_or_insert_with(Default::default)
+            .ca_certificates
+            .push(pem.into());
+        self
+    }
+
+    /// Authenticate to the server with the PEM encoded client certificate chain and private key.
+    /// Enables TLS.
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn client_certificate(
+        mut self,
+        certificate_chain_pem: impl Into<Vec<u8>>,
+        private_key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.options
+            .tls
+            .get_or_insert_with(Default::default)
+            .client_certificate =
+            Some((certificate_chain_pem.into(), Secret(private_key_pem.into())));
+        self
+    }
+
+    /// Set the id the client identifies itself with during the handshake. A random id is used if
+    /// none is set. Clients built from the same builder share the id, and a client keeps its id
+    /// across reconnects, see [`Client::client_id`].
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.options.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Set the password to authenticate with before the handshake.
+    pub fn auth(mut self, password: impl Into<String>) -> Self {
+        self.options.password = Some(Secret(password.into()));
+        self
+    }
+
+    /// Set how lost connections are re-established.
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect_policy = policy;
+        self
+    }
+
+    /// Set callbacks invoked when connections are established, lost or re-established.
+    pub fn events(mut self, events: ConnectionEvents) -> Self {
+        self.options.events = events;
+        self
+    }
+
+    /// Apply the reconnect policy to the first connection as well, so creating the client waits
+    /// for a server that is still starting up instead of failing right away. Disabled by
+    /// default.
+    pub fn retry_initial_connect(mut self, retry: bool) -> Self {
+        self.options.retry_initial_connect = retry;
+        self
+    }
+
+    /// Ask the server for its version with INFO when creating a client, and fail with
+    /// [`ClientError::UnsupportedServer`] if it is older than [`MINIMUM_SERVER_VERSION`]. The
+    /// version is then available from [`Client::server_version`]. Disabled by default.
+    pub fn check_server_version(mut self, check: bool) -> Self {
+        self.options.check_server_version = check;
+        self
+    }
+
+    /// Set the maximum size in bytes of a single reply, 32 MB by default. Larger replies fail
+    /// with [`StreamError::ResponseTooLarge`] instead of being buffered.
+    pub fn max_response_size(mut self, size: usize) -> Self {
+        self.options.max_response_size = size;
+        self
+    }
+
+    /// Set the maximum size in bytes of a single encoded command, 32 MB by default. Larger
+    /// commands fail with [`StreamError::CommandTooLarge`] without being sent, and the
+    /// connection stays usable.
+    pub fn max_command_size(mut self, size: usize) -> Self {
+        self.options.max_command_size = size;
+        self
+    }
+
+    /// Set the size in bytes of the read buffer of a new stream, 4 KB by default. The buffer
+    /// doubles as needed for larger replies, so this only saves the first reallocations for
+    /// workloads of large values. A shrunk buffer goes back to this size.
+    pub fn initial_read_buffer(mut self, size: usize) -> Self {
+        self.options.initial_read_buffer = size;
+        self
+    }
+
+    /// Set the largest size in bytes the read buffer of a stream grows to, which is the maximum
+    /// response size by default. Replies that do not fit fail with
+    /// [`StreamError::ResponseTooLarge`], so this bounds the memory held by each command and
+    /// watch stream below the maximum response size.
+    pub fn max_read_buffer(mut self, size: usize) -> Self {
+        self.options.max_read_buffer = Some(size);
+        self
+    }
+
+    /// Set the size in bytes beyond which a read buffer that grew for a large reply is shrunk
+    /// back to its initial size once the reply is consumed, 1 MB by default. With `None` the
+    /// buffer keeps its largest size, which suits workloads that read large values again and
+    /// again. The largest size reached is reported by [`Client::stats`].
+    pub fn shrink_after(mut self, size: Option<usize>) -> Self {
+        self.options.shrink_read_buffer_after = size;
+        self
+    }
+
+    /// Fail commands fast while the server cannot be reached, see [`CircuitBreakerPolicy`].
+    /// Clients built from this builder after this call share the breaker. Disabled by default.
+    pub fn circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.options.circuit_breaker = Some(Arc::new(CircuitBreaker::new(policy)));
+        self
+    }
+
+    /// Reject every command that changes data, such as SET, DEL or FLUSHDB, with
+    /// [`ClientError::ReadOnlyViolation`] without sending it. Reads and watches are allowed.
+    /// Disabled by default.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    /// Allow [`Client::subscribe`] to subscribe to a key that a live subscription of the client
+    /// already watches, rather than failing with [`ClientError::AlreadySubscribed`]. Disabled by
+    /// default.
+    pub fn allow_duplicate_subscriptions(mut self, allow: bool) -> Self {
+        self.options.duplicate_subscriptions = allow;
+        self
+    }
+
+    /// Split DEL, EXISTS and HSET with more than `max` arguments into several commands, whose
+    /// counts are summed. The key of HSET and each field and value count as an argument. If a
+    /// chunk fails, the error is a [`ClientError::ChunkFailed`] reporting how many chunks were
+    /// executed. Unlimited by default.
+    pub fn max_args_per_command(mut self, max: usize) -> Self {
+        self.options.max_args_per_command = Some(max.max(1));
+        self
+    }
+
+    /// Like [`ClientBuilder::max_args_per_command`], but limits the encoded size in bytes of the
+    /// arguments of a single command, keys included. Unlimited by default.
+    pub fn max_command_bytes(mut self, max: usize) -> Self {
+        self.options.max_command_bytes = Some(max);
+        self
+    }
+
+    /// Send a PING whenever the connection has been idle for the interval, so long-idle clients
+    /// are not dropped by firewalls and proxies. The pings are sent from a background thread,
+    /// which stops when the client is dropped. Disabled by default.
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.options.heartbeat = Some(interval);
+        self
+    }
+
+    /// Validate a connection that has been idle for longer than `max_idle` before using it again:
+    /// the next command is preceded by a PING, or by a reconnect if the server closed the
+    /// connection in the meantime, so a connection that died while idle does not fail the
+    /// command. Active connections are not affected, and the validations are counted in
+    /// [`Client::stats`]. Disabled by default.
+    pub fn max_idle(mut self, max_idle: Duration) -> Self {
+        self.options.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Record the latency of every command in histograms per command, read with
+    /// [`Client::latency_report`]. Pipelined commands are recorded with the latency of their whole
+    /// batch. Each client built has its own histograms of fixed size. Disabled by default.
+    pub fn track_latency(mut self, enabled: bool) -> Self {
+        self.options.latency = enabled.then(|| Arc::new(LatencyTracker::new()));
+        self
+    }
+
+    /// Set an observer notified of every executed command, reconnect and watch message, for
+    /// example to export metrics. Pass an [`Arc`](std::sync::Arc) to keep a handle to it.
+    pub fn observer(mut self, observer: impl CommandObserver + 'static) -> Self {
+        self.options.observer = Observer::new(observer);
+        self
+    }
+
+    /// Add an interceptor that sees every command before it is sent and can change or reject it.
+    /// Interceptors run in the order they were added.
+    pub fn interceptor(mut self, interceptor: impl CommandInterceptor + 'static) -> Self {
+        self.options.interceptors.push(interceptor);
+        self
+    }
+
+    /// Record every command and reply of the client and of its watch streams with `recorder`,
+    /// to read back with [`read_recording`](crate::replay::read_recording). Nothing is recorded
+    /// by default.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.options.recorder = Some(recorder);
+        self
+    }
+
+    /// Set how commands that are safe to repeat are retried after a failure. Commands are not
+    /// retried by default.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.options.retry_policy = policy;
+        self
+    }
+
+    /// Set how many commands of an [`AsyncClient`] can wait to be written, beyond which
+    /// submitting a command waits for room. 1024 by default.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.options.queue_capacity = capacity.max(1);
+        self
+    }
+
+    /// Connect to the server and create a client with the configured options.
+    /// # Errors
+    /// Returns a [`ClientError`] if the connection, authentication or handshake fails.
+    pub fn build(&self) -> Result<Client, ClientError> {
+        let mut options = ConnectionOptions {
+            // Each client selects its endpoint independently of other clients from this builder.
+            active_endpoint: Default::default(),
+            latency: self.options.latency.as_ref().map(|_| Arc::default()),
+            ..self.options.clone()
+        };
+        // Every connection attempt and reconnect identifies itself with the same id.
+        options
+            .client_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
+        let mut command_client = connect(&options)?;
+        command_client.handshake()?;
+        let server_version = match options.check_server_version {
+            true => Some(check_server_version(&mut command_client)?),
+            false => None,
+        };
+        let counters = command_client.counters.clone();
+        let command_client = Arc::new(Mutex::new(command_client));
+        let heartbeat = options
+            .heartbeat
+            .map(|interval| Heartbeat::start(command_client.clone(), interval));
+        Ok(Client {
+            options,
+            backend: Backend::Stream(command_client),
+            counters,
+            prefix: None,
+            server_version,
+            subscriptions: Arc::default(),
+            _heartbeat: heartbeat,
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl ClientBuilder {
+    /// Connect to the server and create an [`AsyncClient`] with the configured options. Must be
+    /// called from within a tokio runtime.
+    /// # Errors
+    /// Returns a [`ClientError`] if the connection, authentication or handshake fails, or if TLS
+    /// is configured, which the async client does not support yet.
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn build_async(&self) -> Result<AsyncClient, ClientError> {
+        let options = ConnectionOptions {
+            active_endpoint: Default::default(),
+            ..self.options.clone()
+        };
+        AsyncClient::connect(options).await
+    }
+}
+
+/// Asks the server for its version and rejects servers older than [`MINIMUM_SERVER_VERSION`].
+fn check_server_version(command_client: &mut CommandStream) -> Result<ServerVersion, ClientError> {
+    let reply = command_client.execute_scalar_command(Command::INFO)?;
+    let found = reply
+        .as_str()
+        .and_then(ServerVersion::from_info)
+        .ok_or_else(|| StreamError::from(CommandError::UnexpectedResponse(reply.clone())))?;
+    if found < MINIMUM_SERVER_VERSION {
+        return Err(ClientError::UnsupportedServer {
+            found,
+            minimum: MINIMUM_SERVER_VERSION,
+        });
+    }
+    Ok(found)
+}
+
+/// Opens the command stream of a new client, retrying per the reconnect policy if enabled.
+fn connect(options: &ConnectionOptions) -> Result<CommandStream, ClientError> {
+    if !options.retry_initial_connect {
+        return Ok(CommandStream::new(options.clone())?);
+    }
+    let policy = options.reconnect_policy;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match CommandStream::new(options.clone()) {
+            Ok(command_client) => return Ok(command_client),
+            Err(CommandStreamError::ReadError(e)) if attempts >= policy.max_attempts() => {
+                return Err(ClientError::ConnectFailed {
+                    attempts,
+                    source: e,
+                })
+            }
+            Err(CommandStreamError::ReadError(e)) => {
+                log::debug!(target: LOG_TARGET, "Connection attempt {} failed: {}", attempts, e);
+                std::thread::sleep(policy.delay(attempts, random_unit()));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
 impl Client {
@@ -30,19 +545,444 @@ impl Client {
     /// # Errors
     /// Returns a [`ClientError`] if the connection to the server fails.
     pub fn new(host: String, port: u16) -> Result<Self, ClientError> {
-        let mut command_client = CommandStream::new(host.clone(), port)?;
-        command_client.handshake()?;
-        Ok(Client {
-            command_client,
-            host,
-            port,
-        })
+        Client::builder().host(host).port(port).build()
+    }
+
+    /// Create a new client configured from the environment, see [`ClientBuilder::from_env`].
+    /// # Errors
+    /// Returns a [`ClientError`] if the configuration is missing or invalid, or if the connection
+    /// to the server fails.
+    pub fn from_env() -> Result<Self, ClientError> {
+        ClientBuilder::from_env()?.build()
+    }
+
+    /// Create a [`ClientBuilder`] to configure a new client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Create a client that executes its commands with `executor` instead of sending them to a
+    /// server, so code that uses a client can be tested without one. See [`CommandExecutor`].
+    ///
+    /// The client is always connected, deadlines do not apply and watching a key fails, as a
+    /// watch needs its own connection to a server.
+    pub fn with_executor(executor: impl CommandExecutor + 'static) -> Self {
+        Client {
+            options: ConnectionOptions {
+                client_id: Some(Uuid::new_v4().to_string()),
+                ..ConnectionOptions::default()
+            },
+            backend: Backend::Executor(Mutex::new(Box::new(executor))),
+            counters: Arc::new(Counters::new()),
+            prefix: None,
+            server_version: None,
+            subscriptions: Arc::default(),
+            _heartbeat: None,
+        }
+    }
+
+    /// Scope the client to a namespace. Every key the client sends, including keys of multi-key
+    /// commands and watch subscriptions, is prefixed with `"{prefix}:"`, so clients with different
+    /// prefixes cannot see each other's keys.
+    ///
+    /// Commands without keys, such as [`flushdb`](Client::flushdb), are not scoped and still
+    /// affect the whole database.
+    /// # Example
+    /// ```
+    /// use dicedb_rs::client::Client;
+    /// use dicedb_rs::errors::ClientError;
+    /// fn main() -> Result<(), ClientError> {
+    ///    let mut client = Client::new("localhost".to_string(), 7379)?.with_prefix("billing");
+    ///    // Stored on the server as "billing:invoices"
+    ///    client.set("invoices", 3)?;
+    ///    Ok(())
+    /// }
+    /// ```
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Returns the namespace the client is scoped to, if any.
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Start a pipeline, which sends several commands before reading their replies. See
+    /// [`Pipeline`].
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self)
+    }
+
+    /// Run `f` with replies to commands bounded by `deadline` instead of the configured read
+    /// timeout.
+    ///
+    /// If a reply does not arrive in time the call fails with a timed out
+    /// [`StreamError::IoError`]. The late reply is never mistaken for the reply of a later
+    /// command, as the connection is re-established before the next command is sent.
+    /// # Errors
+    /// Returns the error of `f`, or a [`ClientError`] if the deadline is zero or cannot be
+    /// applied to the connection.
+    pub fn with_deadline<T>(
+        &mut self,
+        deadline: Duration,
+        f: impl FnOnce(&mut Client) -> Result<T, ClientError>,
+    ) -> Result<T, ClientError> {
+        if let Some(stream) = self.stream() {
+            stream.stream.tcp().set_read_timeout(Some(deadline))?;
+        }
+        let result = f(self);
+        if let Some(stream) = self.stream() {
+            stream
+                .stream
+                .tcp()
+                .set_read_timeout(self.options.read_timeout)?;
+        }
+        result
+    }
+
+    /// Returns whether the client is believed to be connected, without sending anything to the
+    /// server. This turns false after a failed read or when the server closed the connection.
+    pub fn is_connected(&self) -> bool {
+        self.stream().map_or(true, |stream| stream.is_connected())
+    }
+
+    /// Returns the state of the connection, without sending anything to the server. A connection
+    /// that is not [`Healthy`](ConnectionState::Healthy) is re-established before the next
+    /// command.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.stream()
+            .map_or(ConnectionState::Healthy, |stream| stream.connection_state())
+    }
+
+    /// Checks that the connection is usable by sending a PING, reconnecting first if the
+    /// connection is known to be broken and automatic reconnection is not disabled. Connection
+    /// pools should use this to validate clients.
+    /// # Returns
+    /// The round-trip time of the PING.
+    /// # Errors
+    /// Returns a [`ClientError`] if the connection cannot be re-established or the PING fails.
+    pub fn check(&mut self) -> Result<Duration, ClientError> {
+        if let Some(mut stream) = self.stream() {
+            if !stream.is_connected() {
+                reconnect_automatically(&mut *stream)?;
+            }
+        }
+        let started = Instant::now();
+        match self.ping()? {
+            ScalarValue::VStr(pong) if pong == "PONG" => Ok(started.elapsed()),
+            value => Err(CommandError::UnexpectedResponse(value).into()),
+        }
+    }
+
+    /// Re-establishes the connection right away, with the attempts and delays of the reconnect
+    /// policy, or a single attempt if automatic reconnection is
+    /// [`disabled`](ReconnectPolicy::disabled). The handshake is repeated with the same
+    /// [client id](Client::client_id).
+    /// # Errors
+    /// Returns a [`ClientError`] if the connection, authentication or handshake fails.
+    pub fn reconnect(&mut self) -> Result<(), ClientError> {
+        if let Some(mut stream) = self.stream() {
+            stream.reconnect()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the host and port of the endpoint the client is currently connected to. This is
+    /// the configured host and port unless [`endpoints`](ClientBuilder::endpoints) are set.
+    pub fn endpoint(&self) -> (&str, u16) {
+        self.options.active_endpoint()
+    }
+
+    /// Returns the version of the server, if it was checked when the client was created, see
+    /// [`ClientBuilder::check_server_version`].
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.server_version
+    }
+
+    /// Returns the id the client identifies itself with in the handshake, to correlate its logs
+    /// with the diagnostics of the server. The id is set with [`ClientBuilder::client_id`] or
+    /// generated when the client is built, and is kept across reconnects. Watch streams have ids
+    /// of their own, see [`WatchStream::client_id`](crate::watchstream::WatchStream::client_id).
+    pub fn client_id(&self) -> &str {
+        self.options.client_id.as_deref().unwrap_or_default()
+    }
+
+    /// Returns a snapshot of the counters of the client: the commands it sent, the replies and
+    /// bytes it received, its failed commands and reconnects. A client created with
+    /// [`Client::with_executor`] only counts failed commands, as it sends nothing.
+    pub fn stats(&self) -> ClientStats {
+        self.counters.client_stats()
+    }
+
+    /// Sets the counters returned by [`Client::stats`] back to zero, and clears the latency
+    /// histograms.
+    pub fn reset_stats(&self) {
+        self.counters.reset();
+        if let Some(latency) = &self.options.latency {
+            latency.reset();
+        }
+    }
+
+    /// Returns the latency of every command executed since the client was built or its stats were
+    /// reset: the count, percentiles and maximum per command. Empty unless latency tracking was
+    /// enabled with [`ClientBuilder::track_latency`].
+    pub fn latency_report(&self) -> LatencyReport {
+        self.options
+            .latency
+            .as_ref()
+            .map(|latency| latency.report())
+            .unwrap_or_default()
+    }
+
+    /// Returns the address of the server the client is currently connected to.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream()?.stream.tcp().peer_addr().ok()
+    }
+
+    pub(crate) fn scoped_key(&self, key: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => prefixed_key(prefix, key),
+            None => key.to_string(),
+        }
+    }
+
+    pub(crate) fn scoped(&self, command: Command) -> Command {
+        match &self.prefix {
+            Some(prefix) => command.prefix_keys(prefix),
+            None => command,
+        }
+    }
+}
+
+impl Client {
+    /// Executes the command, retrying it according to the retry policy if it is idempotent.
+    fn execute_with_retry<T>(
+        &mut self,
+        command: Command,
+        execute: fn(&mut CommandStream, Command) -> Result<T, StreamError>,
+        executor: fn(&mut dyn CommandExecutor, &CommandDescriptor) -> Result<T, StreamError>,
+    ) -> Result<T, StreamError> {
+        let (command, descriptor) = match self.prepare(command) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                self.counters.error(e.kind());
+                return Err(e.into());
+            }
+        };
+        let result = self.execute_prepared(command, execute, executor);
+        if let Some(descriptor) = descriptor {
+            self.options
+                .interceptors
+                .after_receive(&descriptor, result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    /// Scopes the keys of the command, rejects it if an argument cannot be sent, passes it
+    /// through the interceptors and rejects it if the client is read-only and the command changes
+    /// data, both before the interceptors see it and as it is sent. Returns the command to send
+    /// and, if there are interceptors, what it looks like on the wire.
+    pub(crate) fn prepare(
+        &self,
+        command: Command,
+    ) -> Result<(Command, Option<CommandDescriptor>), CommandError> {
+        let command = self.scoped(command);
+        command.check_args()?;
+        if self.options.read_only && command.is_write() {
+            return Err(CommandError::ReadOnlyViolation {
+                command: command.name().to_string(),
+            });
+        }
+        let (command, descriptor) = match self.options.interceptors.is_empty() {
+            true => (command, None),
+            false => {
+                let (command, descriptor) = self.options.interceptors.before_send(command)?;
+                (command, Some(descriptor))
+            }
+        };
+        if self.options.read_only && command.is_write() {
+            let command = match &descriptor {
+                Some(descriptor) => descriptor.name().to_string(),
+                None => command.name().to_string(),
+            };
+            return Err(CommandError::ReadOnlyViolation { command });
+        }
+        Ok((command, descriptor))
+    }
+
+    /// Executes a prepared command, retrying it if it is idempotent.
+    fn execute_prepared<T>(
+        &mut self,
+        command: Command,
+        execute: fn(&mut CommandStream, Command) -> Result<T, StreamError>,
+        executor: fn(&mut dyn CommandExecutor, &CommandDescriptor) -> Result<T, StreamError>,
+    ) -> Result<T, StreamError> {
+        if !command.is_idempotent() {
+            return self.execute_once(command, execute, executor);
+        }
+        let policy = self.options.retry_policy;
+        let mut attempt = 1;
+        loop {
+            match self.execute_once(command.clone(), execute, executor) {
+                Err(e) if policy.should_retry(attempt, &e) => {
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "Retrying {} after error: {:?}",
+                        command.name(),
+                        e
+                    );
+                    if !matches!(e, StreamError::OutcomeUnknown(_)) {
+                        // Reconnect and handshake again before the next attempt.
+                        if let Some(mut stream) = self.stream() {
+                            stream.mark_dirty();
+                        }
+                    }
+                    std::thread::sleep(policy.backoff());
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Executes the command once on the locked command stream, or the executor of the client.
+    fn execute_once<T>(
+        &mut self,
+        command: Command,
+        execute: fn(&mut CommandStream, Command) -> Result<T, StreamError>,
+        executor: fn(&mut dyn CommandExecutor, &CommandDescriptor) -> Result<T, StreamError>,
+    ) -> Result<T, StreamError> {
+        let meta = self
+            .options
+            .observer
+            .is_set()
+            .then(|| CommandMeta::new(&command));
+        let name = command.name();
+        let started = Instant::now();
+        let result = self.through_breaker(|| match &self.backend {
+            Backend::Stream(stream) => {
+                let mut stream = lock(stream);
+                stream.refresh_if_idle();
+                let result = execute(&mut stream, command).map_err(|e| stream.recover(e));
+                stream.last_used = Instant::now();
+                result
+            }
+            Backend::Executor(mutex) => {
+                let mut guard = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+                executor(guard.as_mut(), &command.into())
+            }
+        });
+        if let Err(e) = &result {
+            self.counters.error(e.kind());
+        }
+        if let Some(latency) = &self.options.latency {
+            latency.record(name, started.elapsed());
+        }
+        if let Some(meta) = meta {
+            let outcome = result.as_ref().map(|_| ());
+            self.options
+                .observer
+                .command(&meta, started.elapsed(), outcome);
+        }
+        result
+    }
+
+    /// Makes an attempt to reach the server through the circuit breaker of the client, if any.
+    pub(crate) fn through_breaker<T>(
+        &self,
+        attempt: impl FnOnce() -> Result<T, StreamError>,
+    ) -> Result<T, StreamError> {
+        let Some(breaker) = &self.options.circuit_breaker else {
+            return attempt();
+        };
+        let permit = breaker.acquire()?;
+        let result = attempt();
+        breaker.record(permit, &result);
+        result
+    }
+
+    /// Locks the command stream, which is shared with the heartbeat thread, or returns `None` for
+    /// a client created with [`Client::with_executor`].
+    pub(crate) fn stream(&self) -> Option<MutexGuard<'_, CommandStream>> {
+        match &self.backend {
+            Backend::Stream(stream) => Some(lock(stream)),
+            Backend::Executor(_) => None,
+        }
+    }
+
+    /// Executes the commands one after another with the executor of the client, keeping errors
+    /// the executor replied with for the command they belong to like a pipeline does. Returns
+    /// `None` for a client connected to a server.
+    pub(crate) fn execute_each(
+        &self,
+        commands: Vec<Command>,
+    ) -> Option<Result<Vec<Result<ScalarValue, CommandError>>, StreamError>> {
+        let Backend::Executor(mutex) = &self.backend else {
+            return None;
+        };
+        let mut executor = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut replies = Vec::with_capacity(commands.len());
+        for command in commands {
+            match executor.execute(&command.into()) {
+                Ok(value) => replies.push(Ok(value)),
+                Err(StreamError::CommandError(e)) => replies.push(Err(e)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(replies))
+    }
+}
+
+impl Execute for Client {
+    fn execute_scalar_command(&mut self, command: Command) -> Result<ScalarValue, StreamError> {
+        self.execute_with_retry(
+            command,
+            CommandStream::execute_scalar_command,
+            |executor, command| executor.execute(command),
+        )
+    }
+
+    fn execute_scalar_command_with_attrs(
+        &mut self,
+        command: Command,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError> {
+        self.execute_with_retry(
+            command,
+            CommandStream::execute_scalar_command_with_attrs,
+            |executor, command| executor.execute_with_attrs(command),
+        )
+    }
+
+    fn execute_map_command(&mut self, command: Command) -> Result<Option<MapValue>, StreamError> {
+        self.execute_with_retry(
+            command,
+            CommandStream::execute_map_command,
+            |executor, command| executor.execute_map(command),
+        )
+    }
+
+    fn execute_list_command(&mut self, command: Command) -> Result<ListValue, StreamError> {
+        self.execute_with_retry(
+            command,
+            CommandStream::execute_list_command,
+            |executor, command| executor.execute_list(command),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::watchstream::WatchStream;
+    #[cfg(feature = "tls")]
+    use crate::testutil::TEST_CA;
+    use crate::{
+        commands::ExpireOption,
+        config::ConnInfo,
+        errors::ErrorKind,
+        testutil::{FakeServer, Reply},
+        watchstream::WatchStream,
+    };
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
     use super::*;
     const HOST: &str = "localhost";
@@ -62,7 +1002,1139 @@ mod tests {
 
     #[test]
     fn test_client_error2() {
-        let wc = WatchStream::new(HOST.to_string(), 0); // invalid port
+        let options = ConnectionOptions {
+            port: 0, // invalid port
+            ..ConnectionOptions::default()
+        };
+        let wc = WatchStream::new(options);
         assert!(wc.is_err());
     }
+
+    #[test]
+    fn test_prefix_single_key() {
+        let mut client = Client::new(HOST.to_string(), PORT)
+            .unwrap()
+            .with_prefix("testprefixsingle");
+        let mut unscoped = Client::new(HOST.to_string(), PORT).unwrap();
+        assert_eq!(client.prefix(), Some("testprefixsingle"));
+        client.set("key", "value").unwrap();
+        assert_eq!(
+            unscoped.get("testprefixsingle:key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_prefix_multi_key() {
+        let mut client = Client::new(HOST.to_string(), PORT)
+            .unwrap()
+            .with_prefix("testprefixmulti");
+        let mut unscoped = Client::new(HOST.to_string(), PORT).unwrap();
+        client.set("a", 1).unwrap();
+        client.set("b", 2).unwrap();
+        assert_eq!(
+            unscoped
+                .exists_count(["testprefixmulti:a", "testprefixmulti:b"])
+                .unwrap(),
+            2
+        );
+        assert_eq!(client.exists_count(["a", "b"]).unwrap(), 2);
+        assert_eq!(client.del_count(["a", "b"]).unwrap(), 2);
+        assert_eq!(
+            unscoped
+                .exists_count(["testprefixmulti:a", "testprefixmulti:b"])
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_prefix_isolation() {
+        let mut first = Client::new(HOST.to_string(), PORT)
+            .unwrap()
+            .with_prefix("testprefixfirst");
+        let mut second = Client::new(HOST.to_string(), PORT)
+            .unwrap()
+            .with_prefix("testprefixsecond");
+        first.set("shared", "first").unwrap();
+        second.del("shared").unwrap();
+        assert_eq!(second.get("shared").unwrap(), ScalarValue::VNull);
+        assert_eq!(
+            first.get("shared").unwrap(),
+            ScalarValue::VStr("first".to_string())
+        );
+    }
+
+    #[test]
+    fn test_builder_is_reusable() {
+        let builder = Client::builder()
+            .host("example.com")
+            .port(1234)
+            .client_id("builder")
+            .auth("secret")
+            .reconnect_policy(ReconnectPolicy::fixed(3, Duration::from_millis(10)));
+        let cloned = builder.clone();
+        for options in [&builder.options, &cloned.options] {
+            assert_eq!(options.host, "example.com");
+            assert_eq!(options.port, 1234);
+            assert_eq!(options.client_id.as_deref(), Some("builder"));
+            assert_eq!(options.password, Some(Secret("secret".to_string())));
+            assert_eq!(options.reconnect_policy.max_attempts(), 3);
+        }
+    }
+
+    #[test]
+    fn test_builder_timeouts() {
+        let client = Client::builder()
+            .host(HOST)
+            .port(PORT)
+            .read_timeout(Duration::from_secs(3))
+            .write_timeout(Duration::from_secs(4))
+            .connect_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let command_client = client.stream().unwrap();
+        let stream = command_client.stream.tcp();
+        assert_eq!(stream.read_timeout().unwrap(), Some(Duration::from_secs(3)));
+        assert_eq!(
+            stream.write_timeout().unwrap(),
+            Some(Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn test_builder_client_id() {
+        let client = Client::builder()
+            .host(HOST)
+            .port(PORT)
+            .client_id("testbuilderclientid")
+            .build()
+            .unwrap();
+        assert_eq!(client.stream().unwrap().id, "testbuilderclientid");
+    }
+
+    #[test]
+    fn test_builder_connect_timeout() {
+        let start = std::time::Instant::now();
+        let result = Client::builder()
+            .host("10.255.255.1")
+            .port(PORT)
+            .connect_timeout(Duration::from_millis(200))
+            .build();
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_builder_local_address_bind_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        // An address from TEST-NET-1, which no interface has.
+        let error = Client::builder()
+            .host("127.0.0.1")
+            .port(listener.local_addr().unwrap().port())
+            .local_address(IpAddr::from([192, 0, 2, 1]))
+            .build()
+            .unwrap_err();
+        assert_eq!(error.kind(), crate::errors::ErrorKind::Bind);
+        assert!(error.to_string().contains("192.0.2.1"), "{}", error);
+    }
+
+    #[test]
+    fn test_retry_idempotent_command() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), Reply::Close],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("value".to_string())),
+            ],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .retry_policy(RetryPolicy::new(3, Duration::ZERO))
+        .build()
+        .unwrap();
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE", "GET", "HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_endpoint_failover() {
+        static CHANGES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_port = dead.local_addr().unwrap().port();
+        drop(dead);
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("value".to_string())),
+        ]]);
+        let mut client = ClientBuilder::new()
+            .endpoints(vec![("127.0.0.1", dead_port), ("127.0.0.1", server.port)])
+            .on_endpoint_change(|_, _| {
+                CHANGES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            })
+            .build()
+            .unwrap();
+        assert_eq!(client.endpoint(), ("127.0.0.1", server.port));
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        assert_eq!(CHANGES.load(std::sync::atomic::Ordering::Relaxed), 1);
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_reconnect_after_connection_lost_while_receiving() {
+        let server = FakeServer::start(vec![
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("first".to_string())),
+                Reply::Close,
+            ],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("second".to_string())),
+            ],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("first".to_string())
+        );
+        assert!(matches!(
+            client.get("key"),
+            Err(ClientError::StreamError(StreamError::OutcomeUnknown(_)))
+        ));
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("second".to_string())
+        );
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(
+            commands,
+            vec!["HANDSHAKE", "GET", "GET", "HANDSHAKE", "GET"]
+        );
+    }
+
+    #[test]
+    fn test_connection_events() {
+        use std::sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        };
+
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), Reply::Close],
+            vec![Reply::ok(), Reply::ok()],
+        ]);
+        let connects = Arc::new(AtomicU32::new(0));
+        let disconnects = Arc::new(AtomicU32::new(0));
+        let reconnect_attempts = Arc::new(AtomicU32::new(0));
+        let events = ConnectionEvents::new()
+            .on_connect({
+                let connects = connects.clone();
+                // Panics in callbacks are caught, so conditions are checked through the counts.
+                move |info| {
+                    if info.mode == crate::commands::ExecutionMode::Command {
+                        connects.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .on_disconnect({
+                let disconnects = disconnects.clone();
+                move |_, error| {
+                    if matches!(error, StreamError::OutcomeUnknown(_)) {
+                        disconnects.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .on_reconnect({
+                let reconnect_attempts = reconnect_attempts.clone();
+                move |info, attempts| {
+                    if info.peer_addr.map(|addr| addr.port()) == Some(info.port) {
+                        reconnect_attempts.fetch_add(attempts, Ordering::Relaxed);
+                    }
+                }
+            });
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .events(events)
+        .build()
+        .unwrap();
+        assert_eq!(connects.load(Ordering::Relaxed), 1);
+        assert!(client.get("key").is_err());
+        assert_eq!(connects.load(Ordering::Relaxed), 2);
+        assert_eq!(disconnects.load(Ordering::Relaxed), 1);
+        assert_eq!(reconnect_attempts.load(Ordering::Relaxed), 1);
+        assert!(client.set("key", "value").is_ok());
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_client_id_kept_across_reconnect() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), Reply::Close],
+            vec![Reply::ok(), Reply::ok()],
+        ]);
+        let reconnected_as = Arc::new(Mutex::new(None));
+        let events = ConnectionEvents::new().on_reconnect({
+            let reconnected_as = reconnected_as.clone();
+            move |info, _| *reconnected_as.lock().unwrap() = Some(info.client_id.clone())
+        });
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .events(events)
+        .build()
+        .unwrap();
+        let id = client.client_id().to_string();
+        assert!(!id.is_empty());
+        assert!(client.get("key").is_err());
+        assert!(client.set("key", "value").is_ok());
+        assert_eq!(client.client_id(), id);
+        assert_eq!(reconnected_as.lock().unwrap().as_deref(), Some(id.as_str()));
+        drop(client);
+        let handshakes: Vec<String> = server
+            .received()
+            .into_iter()
+            .filter(|(command, _)| command == "HANDSHAKE")
+            .map(|(_, args)| args[0].clone())
+            .collect();
+        assert_eq!(handshakes, [id.clone(), id]);
+    }
+
+    #[test]
+    fn test_reconnect_disabled_fails_fast() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1))],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .reconnect_policy(ReconnectPolicy::disabled())
+        .build()
+        .unwrap();
+        // The server closes the connection after the handshake.
+        let started = Instant::now();
+        let error = client.get("key").unwrap_err();
+        assert!(error.is_connection_error(), "{:?}", error);
+        let error = client.get("key").unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::StreamError(StreamError::ReconnectDisabled)
+        ));
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(client.check().is_err());
+        client.reconnect().unwrap();
+        assert_eq!(client.get("key").unwrap(), ScalarValue::VInt(1));
+        drop(client);
+        // The first GET may or may not have reached the server before it closed the connection.
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands.iter().filter(|c| *c == "HANDSHAKE").count(), 2);
+        assert_eq!(commands[commands.len() - 2..], ["HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_connection_event_panic_is_caught() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let client = ClientBuilder {
+            options: server.options(),
+        }
+        .events(ConnectionEvents::new().on_connect(|_| panic!("callback failed")))
+        .build();
+        assert!(client.is_ok());
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_retry_initial_connect() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let server = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            FakeServer::start_on(port, vec![vec![Reply::ok()]])
+        });
+        let client = ClientBuilder::new()
+            .host("127.0.0.1")
+            .port(port)
+            .reconnect_policy(ReconnectPolicy::exponential(
+                10,
+                Duration::from_millis(20),
+                Duration::from_millis(200),
+            ))
+            .retry_initial_connect(true)
+            .build();
+        assert!(client.is_ok());
+        drop(client);
+        assert_eq!(server.join().unwrap().received()[0].0, "HANDSHAKE");
+    }
+
+    #[test]
+    fn test_retry_initial_connect_gives_up() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let client = ClientBuilder::new()
+            .host("127.0.0.1")
+            .port(port)
+            .reconnect_policy(ReconnectPolicy::fixed(3, Duration::from_millis(5)))
+            .retry_initial_connect(true)
+            .build();
+        assert!(matches!(
+            client,
+            Err(ClientError::ConnectFailed { attempts: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        // Accepts the connection but never replies to the handshake.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let silent = std::thread::spawn(move || {
+            let (_socket, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_secs(2));
+        });
+        let started = Instant::now();
+        let client = ClientBuilder::new()
+            .host("127.0.0.1")
+            .port(port)
+            .handshake_timeout(Duration::from_millis(100))
+            .build();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        match client {
+            Err(ClientError::StreamError(StreamError::HandshakeTimeout { endpoint, timeout })) => {
+                assert_eq!(endpoint, format!("127.0.0.1:{}", port));
+                assert_eq!(timeout, Duration::from_millis(100));
+            }
+            other => panic!("expected HandshakeTimeout, got {:?}", other),
+        }
+        silent.join().unwrap();
+    }
+
+    #[test]
+    fn test_rejected_handshake() {
+        let server = FakeServer::start(vec![vec![
+            Reply::Error("ERR unsupported client version".to_string()),
+            Reply::ok(),
+        ]]);
+        let error = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap_err();
+        assert_eq!(error.kind(), crate::errors::ErrorKind::Handshake);
+        match error {
+            ClientError::StreamError(StreamError::HandshakeFailed {
+                reply,
+                server_error: Some(message),
+            }) => {
+                assert_eq!(reply, ScalarValue::VNull);
+                assert_eq!(message, "ERR unsupported client version");
+            }
+            other => panic!("expected HandshakeFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VInt(1)),
+            Reply::Value(ScalarValue::VInt(2)),
+            Reply::Value(ScalarValue::VInt(3)),
+            Reply::Error("ERR value is not an integer or out of range".to_string()),
+            Reply::Value(ScalarValue::VInt(4)),
+            Reply::ok(),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        for _ in 0..3 {
+            client.get("key").unwrap();
+        }
+        client.incr("key").unwrap_err();
+        // The handshake is counted as well.
+        let stats = client.stats();
+        assert_eq!(stats.commands_sent, 5);
+        assert_eq!(stats.responses_received, 5);
+        assert!(stats.bytes_written > 0);
+        assert!(stats.bytes_read > 0);
+        assert_eq!(
+            stats.errors,
+            HashMap::from([(crate::errors::ErrorKind::Server, 1)])
+        );
+        assert_eq!(stats.reconnects, 0);
+
+        client.reset_stats();
+        let stats = client.stats();
+        assert_eq!(
+            stats,
+            ClientStats {
+                uptime: stats.uptime,
+                ..ClientStats::default()
+            }
+        );
+        client.get("key").unwrap();
+        let stats = client.stats();
+        assert_eq!((stats.commands_sent, stats.responses_received), (1, 1));
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_stats_count_reconnects() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), Reply::Close],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1)), Reply::ok()],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        let lost = client.get("key").unwrap_err();
+        assert_eq!(lost.kind(), crate::errors::ErrorKind::OutcomeUnknown);
+        assert_eq!(client.get("key").unwrap(), ScalarValue::VInt(1));
+        let stats = client.stats();
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(
+            stats.errors,
+            HashMap::from([(crate::errors::ErrorKind::OutcomeUnknown, 1)])
+        );
+        assert!(stats.uptime < Duration::from_secs(60));
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_check_server_version() {
+        let info = "# Server\r\ndicedb_version:1.0.2\r\nuptime_in_seconds:10\r\n";
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr(info.to_string())),
+        ]]);
+        let client = ClientBuilder {
+            options: server.options(),
+        }
+        .check_server_version(true)
+        .build()
+        .unwrap();
+        assert_eq!(client.server_version(), Some(ServerVersion::new(1, 0, 2)));
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["HANDSHAKE", "INFO"]);
+    }
+
+    #[test]
+    fn test_check_server_version_rejects_old_server() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("version:0.1.0".to_string())),
+        ]]);
+        let client = ClientBuilder {
+            options: server.options(),
+        }
+        .check_server_version(true)
+        .build();
+        match client {
+            Err(ClientError::UnsupportedServer { found, minimum }) => {
+                assert_eq!(found, ServerVersion::new(0, 1, 0));
+                assert_eq!(minimum, MINIMUM_SERVER_VERSION);
+            }
+            other => panic!("expected UnsupportedServer, got {:?}", other),
+        }
+        server.received();
+    }
+
+    #[test]
+    fn test_server_version_is_not_checked_by_default() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        assert_eq!(client.server_version(), None);
+        drop(client);
+        assert_eq!(server.received().len(), 1);
+    }
+
+    #[test]
+    fn test_command_after_lost_connection() {
+        let server = FakeServer::start(vec![
+            // The extra replies keep the connections open until they are shut down.
+            vec![Reply::ok(), Reply::ok()],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1)), Reply::ok()],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        assert_eq!(client.connection_state(), ConnectionState::Healthy);
+        client
+            .stream()
+            .unwrap()
+            .stream
+            .tcp()
+            .shutdown(std::net::Shutdown::Both)
+            .unwrap();
+        // The command may have reached the server before the connection broke, so it fails once.
+        assert!(matches!(
+            client.incr("key"),
+            Err(ClientError::StreamError(StreamError::OutcomeUnknown(_)))
+        ));
+        assert_eq!(client.incr("key").unwrap(), ScalarValue::VInt(1));
+        assert_eq!(client.connection_state(), ConnectionState::Healthy);
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_check_repairs_closed_connection() {
+        let pong = || Reply::Value(ScalarValue::VStr("PONG".to_string()));
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), pong()],
+            // The extra reply keeps the second connection open until the client is dropped.
+            vec![Reply::ok(), pong(), pong()],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        assert!(client.is_connected());
+        assert!(client.check().is_ok());
+        // The server closes the first connection once its script runs out.
+        let closed_by = Instant::now() + Duration::from_secs(5);
+        while client.is_connected() && Instant::now() < closed_by {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!client.is_connected());
+        assert!(client.check().is_ok());
+        assert!(client.is_connected());
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE", "PING", "HANDSHAKE", "PING"]);
+    }
+
+    #[test]
+    fn test_heartbeat_pings_idle_connection() {
+        let pong = || Reply::Value(ScalarValue::VStr("PONG".to_string()));
+        let mut script = vec![Reply::ok()];
+        script.extend((0..50).map(|_| pong()));
+        let server = FakeServer::start(vec![script]);
+        let client = ClientBuilder {
+            options: server.options(),
+        }
+        .heartbeat(Duration::from_millis(20))
+        .build()
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        drop(client);
+        let pings = server
+            .received()
+            .into_iter()
+            .filter(|(command, _)| command == "PING")
+            .count();
+        assert!(pings >= 2, "expected pings while idle, got {}", pings);
+    }
+
+    #[test]
+    fn test_max_idle_validates_idle_connection() {
+        let pong = || Reply::Value(ScalarValue::VStr("PONG".to_string()));
+        let value = || Reply::Value(ScalarValue::VStr("value".to_string()));
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), value(), pong(), value()],
+            vec![Reply::ok(), value()],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .max_idle(Duration::from_millis(50))
+        .reconnect_policy(ReconnectPolicy::fixed(1, Duration::ZERO))
+        .build()
+        .unwrap();
+        client.get("key").unwrap();
+        assert_eq!(client.stats().idle_validations, 0);
+        std::thread::sleep(Duration::from_millis(100));
+        // The idle connection is pinged before the GET.
+        client.get("key").unwrap();
+        assert_eq!(client.stats().idle_validations, 1);
+        // The server closed the connection while it was idle, so it is replaced without a PING.
+        std::thread::sleep(Duration::from_millis(100));
+        client.get("key").unwrap();
+        assert_eq!(client.stats().idle_validations, 2);
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(
+            commands,
+            vec!["HANDSHAKE", "GET", "PING", "GET", "HANDSHAKE", "GET"]
+        );
+    }
+
+    #[test]
+    fn test_latency_report() {
+        let mut replies = vec![Reply::ok()];
+        replies.extend((0..20).map(|_| Reply::Value(ScalarValue::VStr("value".to_string()))));
+        replies.extend((0..5).map(|_| Reply::ok()));
+        replies.push(Reply::ok());
+        let server = FakeServer::start(vec![replies]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .track_latency(true)
+        .build()
+        .unwrap();
+        let started = Instant::now();
+        for _ in 0..20 {
+            client.get("key").unwrap();
+        }
+        for i in 0..5 {
+            client.set("key", i).unwrap();
+        }
+        client.flushdb().unwrap();
+        let elapsed = started.elapsed();
+        let report = client.latency_report();
+        assert_eq!(report.commands.len(), 3);
+        for (command, count) in [("GET", 20), ("SET", 5), (crate::latency::OTHER, 1)] {
+            let latency = report.get(command).unwrap();
+            assert_eq!(latency.count, count, "{}", command);
+            assert!(!latency.max.is_zero(), "{}", command);
+            assert!(latency.max <= elapsed, "{}", command);
+            assert!(latency.p50 <= latency.p95, "{}", command);
+            assert!(latency.p95 <= latency.p99, "{}", command);
+            assert!(latency.p99 <= latency.max, "{}", command);
+        }
+        client.reset_stats();
+        assert!(client.latency_report().commands.is_empty());
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_latency_report_disabled() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("value".to_string())),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        client.get("key").unwrap();
+        assert_eq!(client.latency_report(), LatencyReport::default());
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_max_response_size() {
+        let server = FakeServer::start(vec![
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("x".repeat(1000))),
+            ],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("small".to_string())),
+            ],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .max_response_size(64)
+        .build()
+        .unwrap();
+        match client.get("key") {
+            Err(ClientError::StreamError(StreamError::ResponseTooLarge { limit, observed })) => {
+                assert_eq!(limit, 64);
+                assert!(observed >= limit);
+            }
+            other => panic!("expected ResponseTooLarge, got {:?}", other),
+        }
+        // The next command reconnects, discarding the rest of the oversized reply.
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("small".to_string())
+        );
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_command_larger_than_limit() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("small".to_string())),
+            Reply::ok(),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .max_command_size(1024)
+        .build()
+        .unwrap();
+        match client.set("key", crate::commands::SetInput::Str("x".repeat(2048))) {
+            Err(ClientError::StreamError(StreamError::CommandTooLarge { size, limit })) => {
+                assert_eq!(limit, 1024);
+                assert!(size > 2048);
+            }
+            other => panic!("expected CommandTooLarge, got {:?}", other),
+        }
+        assert!(client.is_connected());
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("small".to_string())
+        );
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_no_retry_for_non_idempotent_command() {
+        let server = FakeServer::start(vec![vec![Reply::ok(), Reply::Close], vec![Reply::ok()]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .retry_policy(RetryPolicy::new(3, Duration::ZERO))
+        .build()
+        .unwrap();
+        assert!(matches!(
+            client.incr("key"),
+            Err(ClientError::StreamError(StreamError::OutcomeUnknown(_)))
+        ));
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        // The connection is re-established, but INCR is not sent again.
+        assert_eq!(commands, vec!["HANDSHAKE", "INCR", "HANDSHAKE"]);
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        commands: Mutex<Vec<(&'static str, usize, bool)>>,
+        reconnects: AtomicUsize,
+    }
+
+    impl CommandObserver for RecordingObserver {
+        fn on_command(&self, meta: &CommandMeta, _: Duration, result: Result<(), &StreamError>) {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((meta.name(), meta.arg_count(), result.is_err()));
+        }
+
+        fn on_reconnect(&self, _: &ConnInfo, _: u32) {
+            self.reconnects.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_read_only_rejects_writes() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("value".to_string())),
+            Reply::Value(ScalarValue::VInt(1)),
+            Reply::Value(ScalarValue::VInt(-1)),
+        ]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .read_only(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        assert_eq!(client.exists("key", vec![]).unwrap(), ScalarValue::VInt(1));
+        assert_eq!(client.ttl("key").unwrap(), ScalarValue::VInt(-1));
+        let rejected = |result: Result<ScalarValue, ClientError>| match result {
+            Err(ClientError::ReadOnlyViolation { command }) => command,
+            result => panic!("expected a read-only violation, got {:?}", result),
+        };
+        assert_eq!(rejected(client.set("key", "value")), "SET");
+        assert_eq!(rejected(client.del("key")), "DEL");
+        assert_eq!(
+            rejected(client.expire("key", 10, ExpireOption::None)),
+            "EXPIRE"
+        );
+        assert_eq!(rejected(client.flushdb()), "FLUSHDB");
+        let mut pipeline = client.pipeline();
+        pipeline.get("key");
+        pipeline.incr("count");
+        assert!(matches!(
+            pipeline.execute(),
+            Err(ClientError::ReadOnlyViolation { command }) if command == "INCR"
+        ));
+        assert_eq!(client.stats().errors[&ErrorKind::InvalidInput], 5);
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["HANDSHAKE", "GET", "EXISTS", "TTL"]);
+    }
+
+    #[test]
+    fn test_read_only_checks_intercepted_commands() {
+        /// Rewrites every GET into another command.
+        struct Rewrite(&'static str);
+
+        impl crate::interceptor::CommandInterceptor for Rewrite {
+            fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+                if command.name() == "GET" {
+                    let args = match self.0 {
+                        "FLUSHDB" => vec![],
+                        _ => command.args().to_vec(),
+                    };
+                    *command = CommandDescriptor::new(self.0, args);
+                }
+                Ok(())
+            }
+        }
+
+        for rewritten in ["DEL", "FLUSHDB"] {
+            let server = FakeServer::start(vec![vec![Reply::ok()]]);
+            let mut client = Client::builder()
+                .host("127.0.0.1")
+                .port(server.port)
+                .read_only(true)
+                .interceptor(Rewrite(rewritten))
+                .build()
+                .unwrap();
+            assert!(matches!(
+                client.get("key"),
+                Err(ClientError::ReadOnlyViolation { command }) if command == rewritten
+            ));
+            drop(client);
+            let names: Vec<String> = server
+                .received()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            assert_eq!(names, ["HANDSHAKE"]);
+        }
+    }
+
+    #[test]
+    fn test_read_only_rejects_writes_before_interceptors() {
+        /// Records the name of every command it sees.
+        struct Record(Arc<Mutex<Vec<String>>>);
+
+        impl crate::interceptor::CommandInterceptor for Record {
+            fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+                self.0.lock().unwrap().push(command.name().to_string());
+                Ok(())
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let server =
+            FakeServer::start(vec![vec![Reply::ok(), Reply::Value(ScalarValue::VInt(-1))]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .read_only(true)
+            .interceptor(Record(seen.clone()))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.del("key"),
+            Err(ClientError::ReadOnlyViolation { command }) if command == "DEL"
+        ));
+        assert_eq!(client.ttl("key").unwrap(), ScalarValue::VInt(-1));
+        assert_eq!(*seen.lock().unwrap(), ["TTL"]);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let down = Arc::new(AtomicBool::new(true));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (server_down, server_calls) = (down.clone(), calls.clone());
+        let mut client = Client::with_executor(move |_: &CommandDescriptor| {
+            server_calls.fetch_add(1, Ordering::SeqCst);
+            match server_down.load(Ordering::SeqCst) {
+                true => Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into()),
+                false => Ok(ScalarValue::VStr("PONG".to_string())),
+            }
+        });
+        let policy = CircuitBreakerPolicy::new(3, Duration::from_millis(50));
+        client.options.circuit_breaker = Some(Arc::new(CircuitBreaker::new(policy)));
+        let circuit_open = |result: Result<ScalarValue, ClientError>| {
+            matches!(
+                result,
+                Err(ClientError::StreamError(StreamError::CircuitOpen { .. }))
+            )
+        };
+
+        // Closed: failures reach the server until the threshold opens the breaker.
+        for _ in 0..3 {
+            assert!(client.ping().unwrap_err().is_connection_error());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        // Open: commands fail without reaching the server.
+        assert!(circuit_open(client.ping()));
+        let mut pipeline = client.pipeline();
+        pipeline.ping();
+        assert!(matches!(
+            pipeline.execute(),
+            Err(ClientError::StreamError(StreamError::CircuitOpen { .. }))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        // Half-open: a failed probe opens the breaker again.
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(client.ping().unwrap_err().is_connection_error());
+        assert!(circuit_open(client.ping()));
+        // Half-open: a successful probe closes it.
+        down.store(false, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(client.ping().is_ok());
+        assert!(client.ping().is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_observer() {
+        let server = FakeServer::start(vec![
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("value".to_string())),
+                Reply::Close,
+            ],
+            vec![Reply::ok()],
+        ]);
+        let observer = Arc::new(RecordingObserver::default());
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .observer(observer.clone())
+        .build()
+        .unwrap();
+        client.get("key").unwrap();
+        assert!(client.incr("key").is_err());
+        assert_eq!(
+            *observer.commands.lock().unwrap(),
+            vec![("GET", 1, false), ("INCR", 1, true)]
+        );
+        assert_eq!(observer.reconnects.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_builder_tls_options() {
+        let builder = Client::builder()
+            .tls_server_name("dice.internal")
+            .ca_certificate(TEST_CA);
+        let tls = builder.options.tls.as_ref().unwrap();
+        assert_eq!(tls.server_name.as_deref(), Some("dice.internal"));
+        assert_eq!(tls.ca_certificates.len(), 1);
+        assert!(builder.tls(false).options.tls.is_none());
+        assert!(Client::builder().tls(true).options.tls.is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_tls_round_trip() {
+        let server = FakeServer::start_tls(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("PONG".to_string())),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .tls_server_name("localhost")
+        .ca_certificate(TEST_CA)
+        .build()
+        .unwrap();
+        assert_eq!(
+            client.ping().unwrap(),
+            ScalarValue::VStr("PONG".to_string())
+        );
+        drop(client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE", "PING"]);
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_tls_reconnect() {
+        let server = FakeServer::start_tls(vec![
+            vec![Reply::ok(), Reply::Close],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("PONG".to_string())),
+            ],
+        ]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .ca_certificate(TEST_CA)
+        .tls_server_name("localhost")
+        .retry_policy(RetryPolicy::new(2, Duration::ZERO))
+        .build()
+        .unwrap();
+        assert_eq!(
+            client.ping().unwrap(),
+            ScalarValue::VStr("PONG".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_tls_against_plain_server_fails() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            use std::io::Write;
+            let (mut socket, _) = listener.accept().unwrap();
+            socket.write_all(b"not a TLS server\n").unwrap();
+        });
+        let result = Client::builder()
+            .host("127.0.0.1")
+            .port(port)
+            .tls_server_name("localhost")
+            .ca_certificate(TEST_CA)
+            .connect_timeout(Duration::from_secs(2))
+            .build();
+        assert!(result.is_err());
+        server.join().unwrap();
+    }
 }
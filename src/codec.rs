@@ -0,0 +1,260 @@
+//! # Codec Module
+//! The framing of the wire protocol as a [`tokio_util::codec`] codec, for building async
+//! integrations on top of any async transport.
+//!
+//! Commands are encoded from a [`CommandDescriptor`] and replies are decoded into [`Reply`]
+//! frames. As replies are bare protobuf messages without a length prefix, a reply is complete
+//! once every field received so far is complete, like for the blocking client: the server writes
+//! each reply at once.
+//! # Example
+//! ```no_run
+//! use bytes::BytesMut;
+//! use dicedb_rs::codec::DiceCodec;
+//! use dicedb_rs::commands::CommandDescriptor;
+//! use tokio_util::codec::{Decoder, Encoder};
+//!
+//! let mut codec = DiceCodec::new();
+//! let mut outgoing = BytesMut::new();
+//! codec.encode(CommandDescriptor::new("GET", vec!["key".to_string()]), &mut outgoing)?;
+//! // Write `outgoing` to the connection, and append what is read from it to `incoming`.
+//! let mut incoming = BytesMut::new();
+//! if let Some(reply) = codec.decode(&mut incoming)? {
+//!     println!("{}", reply.value()?);
+//! }
+//! # Ok::<(), dicedb_rs::errors::StreamError>(())
+//! ```
+use bytes::{Bytes, BytesMut};
+use prost::Message;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    commands::{wire, CommandDescriptor, ScalarValue, WatchValue},
+    config::{DEFAULT_MAX_COMMAND_SIZE, DEFAULT_MAX_RESPONSE_SIZE},
+    errors::{CommandError, StreamError},
+    framing,
+};
+
+/// Encodes commands and splits the bytes read from the server into replies, enforcing the same
+/// size limits as the blocking client.
+///
+/// A decoding error leaves the buffer where the offending reply starts, so the connection must
+/// not be used for further replies after one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceCodec {
+    max_response_size: usize,
+    max_command_size: usize,
+}
+
+impl DiceCodec {
+    /// Create a codec with the default limits, see
+    /// [`ClientBuilder::max_response_size`](crate::client::ClientBuilder::max_response_size) and
+    /// [`ClientBuilder::max_command_size`](crate::client::ClientBuilder::max_command_size).
+    pub fn new() -> Self {
+        DiceCodec {
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_command_size: DEFAULT_MAX_COMMAND_SIZE,
+        }
+    }
+
+    /// Set the maximum size in bytes of a reply, above which decoding fails with
+    /// [`StreamError::ResponseTooLarge`].
+    pub fn max_response_size(mut self, size: usize) -> Self {
+        self.max_response_size = size;
+        self
+    }
+
+    /// Set the maximum size in bytes of an encoded command, above which encoding fails with
+    /// [`StreamError::CommandTooLarge`] without writing anything.
+    pub fn max_command_size(mut self, size: usize) -> Self {
+        self.max_command_size = size;
+        self
+    }
+}
+
+impl Default for DiceCodec {
+    fn default() -> Self {
+        DiceCodec::new()
+    }
+}
+
+impl Encoder<CommandDescriptor> for DiceCodec {
+    type Error = StreamError;
+
+    fn encode(&mut self, item: CommandDescriptor, dst: &mut BytesMut) -> Result<(), StreamError> {
+        let command = wire::Command::from(item);
+        let size = command.encoded_len();
+        if size > self.max_command_size {
+            return Err(StreamError::CommandTooLarge {
+                size,
+                limit: self.max_command_size,
+            });
+        }
+        dst.reserve(size);
+        command.encode_raw(dst);
+        Ok(())
+    }
+}
+
+impl Decoder for DiceCodec {
+    type Item = Reply;
+    type Error = StreamError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Reply>, StreamError> {
+        let limit = self.max_response_size;
+        match framing::message_len(src) {
+            Some(len) if len <= limit => Ok(Some(Reply {
+                bytes: src.split_to(len).freeze(),
+            })),
+            // A reply that declares more bytes than the limit is rejected before reading them.
+            None if framing::needed_len(src) <= limit => {
+                src.reserve(framing::needed_len(src).saturating_sub(src.len()));
+                Ok(None)
+            }
+            _ => Err(StreamError::ResponseTooLarge {
+                limit,
+                observed: framing::needed_len(src),
+            }),
+        }
+    }
+}
+
+/// A single reply of the server, as decoded by [`DiceCodec`]. The reply is kept encoded until it
+/// is read as the type the command returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    bytes: Bytes,
+}
+
+impl Reply {
+    /// Returns the encoded reply.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Decodes the reply as a value.
+    /// # Errors
+    /// * [`CommandError::ServerError`] - If the server replied with an error.
+    /// * [`CommandError::DecodeError`] - If the reply is not a valid message.
+    pub fn value(&self) -> Result<ScalarValue, CommandError> {
+        ScalarValue::decode(&self.bytes)
+    }
+
+    /// Returns true if the reply is a change pushed to a watch stream, rather than the reply to
+    /// a command.
+    pub fn is_watch_push(&self) -> bool {
+        WatchValue::is_watch_push(&self.bytes)
+    }
+
+    /// Decodes the reply as a change pushed to a watch stream.
+    /// # Errors
+    /// * [`CommandError`] - If the reply is an error or not a watch push.
+    pub fn watch_value(&self) -> Result<WatchValue, CommandError> {
+        WatchValue::decode_watchvalue(&self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{decode_command, encode_error_reply, encode_reply, encode_watch_reply};
+
+    use super::*;
+
+    fn replies() -> Vec<Vec<u8>> {
+        vec![
+            encode_reply(&ScalarValue::VStr("value".to_string())),
+            encode_reply(&ScalarValue::VInt(-7)),
+            encode_reply(&ScalarValue::VNull),
+            encode_error_reply("ERR unknown command"),
+            encode_reply(&ScalarValue::VBytes(vec![0; 300])),
+            encode_reply(&ScalarValue::VFloat(1.5)),
+        ]
+    }
+
+    /// Feeds `bytes` to a decoder in chunks of `chunk` bytes, returning every reply decoded.
+    fn decode_in_chunks(bytes: &[u8], chunk: usize) -> Vec<Reply> {
+        let mut codec = DiceCodec::new();
+        let mut buffer = BytesMut::new();
+        let mut decoded = Vec::new();
+        for part in bytes.chunks(chunk) {
+            buffer.extend_from_slice(part);
+            while let Some(reply) = codec.decode(&mut buffer).unwrap() {
+                decoded.push(reply);
+            }
+        }
+        assert!(buffer.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn test_decode_byte_by_byte_and_coalesced() {
+        let replies = replies();
+        let stream = replies.concat();
+        let coalesced = decode_in_chunks(&stream, stream.len());
+        assert_eq!(decode_in_chunks(&stream, 1), coalesced);
+        assert_eq!(decode_in_chunks(&stream, 7), coalesced);
+        let bytes: Vec<&[u8]> = coalesced.iter().map(Reply::as_bytes).collect();
+        assert_eq!(bytes, replies);
+        assert_eq!(coalesced[1].value().unwrap(), ScalarValue::VInt(-7));
+        assert!(matches!(
+            coalesced[3].value(),
+            Err(CommandError::ServerError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_watch_push() {
+        let push = encode_watch_reply(&ScalarValue::VInt(2), "fingerprint");
+        let mut buffer = BytesMut::from(&push[..]);
+        let reply = DiceCodec::new().decode(&mut buffer).unwrap().unwrap();
+        assert!(reply.is_watch_push());
+        let change = reply.watch_value().unwrap();
+        assert_eq!(change.value, ScalarValue::VInt(2));
+        assert_eq!(change.fingerprint, "fingerprint");
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let mut codec = DiceCodec::new().max_response_size(64).max_command_size(16);
+        // Rejected from its first bytes, which declare the size of the value.
+        let large = encode_reply(&ScalarValue::VBytes(vec![0; 100]));
+        let mut buffer = BytesMut::from(&large[..4]);
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(StreamError::ResponseTooLarge { limit: 64, .. })
+        ));
+
+        let mut buffer = BytesMut::new();
+        let command = CommandDescriptor::new("SET", vec!["key".to_string(), "x".repeat(16)]);
+        assert!(matches!(
+            codec.encode(command, &mut buffer),
+            Err(StreamError::CommandTooLarge { limit: 16, .. })
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let commands = [
+            CommandDescriptor::new("SET", vec!["key".to_string(), "value".to_string()]),
+            CommandDescriptor::new("PING", Vec::new()),
+            CommandDescriptor::new("GET.WATCH", vec!["key".to_string()]),
+        ];
+        let mut codec = DiceCodec::new();
+        let mut buffer = BytesMut::new();
+        for command in commands.clone() {
+            codec.encode(command, &mut buffer).unwrap();
+        }
+        let mut decoded = Vec::new();
+        while let Some(len) = framing::command_len(&buffer) {
+            let (name, args) = decode_command(&buffer.split_to(len)).unwrap();
+            decoded.push(CommandDescriptor::new(name, args));
+        }
+        assert_eq!(decoded, commands);
+
+        let value = ScalarValue::VStr("value".to_string());
+        let mut buffer = BytesMut::from(&encode_reply(&value)[..]);
+        let reply = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(reply.value().unwrap(), value);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    }
+}
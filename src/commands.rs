@@ -2,22 +2,53 @@
 //! Contains structures and options related to interact with the server.
 //! It contains structures for all the commands, value types and options.
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use prost::Message;
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    borrow::Cow,
+    collections::{hash_map, BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use crate::config::Secret;
 use crate::errors::{CommandError, StreamError};
+use crate::framing;
+use crate::LOG_TARGET;
 
-mod wire {
+/// The generated messages, exported as `dicedb_rs::wire` by the `wire` feature.
+#[allow(missing_docs)]
+pub(crate) mod wire {
     tonic::include_proto!("wire");
 }
 
 /// A special input type for the DEL oeration.
+/// Any iterator of string-likes can be collected into it, e.g. `keys.into_iter().collect()`.
 #[derive(Debug, Clone, PartialEq)]
 pub enum DelInput<'a> {
     /// A single key to delete.
     Single(&'a str),
     /// Multiple keys to delete.
     Multiple(Vec<&'a str>),
+    /// Multiple owned keys to delete.
+    Owned(Vec<String>),
+}
+
+impl DelInput<'_> {
+    pub(crate) fn into_keys(self) -> Vec<String> {
+        match self {
+            DelInput::Single(key) => vec![key.to_string()],
+            DelInput::Multiple(keys) => keys.iter().map(|&x| x.to_string()).collect(),
+            DelInput::Owned(keys) => keys,
+        }
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for DelInput<'_> {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        DelInput::Owned(iter.into_iter().map(Into::into).collect())
+    }
 }
 
 /// A special input type for the HSET operation.
@@ -60,6 +91,7 @@ impl TryInto<SetInput> for ScalarValue {
             ScalarValue::VStr(s) => Ok(SetInput::Str(s)),
             ScalarValue::VInt(i) => Ok(SetInput::Int(i)),
             ScalarValue::VFloat(f) => Ok(SetInput::Float(f)),
+            ScalarValue::VBytes(b) => Ok(SetInput::Str(encode_bytes_arg(&b))),
             ScalarValue::VBool(_) => Err("Cannot convert Value::VBool to SetValue".to_string()),
             ScalarValue::VNull => Err("Cannot convert Value::VNull to SetValue".to_string()),
         }
@@ -142,10 +174,494 @@ pub enum ScalarValue {
     VFloat(f64),
     /// A boolean value.
     VBool(bool),
+    /// A binary value, kept exactly as received from the server.
+    VBytes(Vec<u8>),
     /// A null value. A null value is not indicative of failure, but just the absence of a value.
     VNull,
 }
 
+/// Conversion of a [`ScalarValue`] received from the server into a Rust type, used by the typed
+/// getters such as [`getdel_as`](crate::client::Client::getdel_as).
+///
+/// Numbers and booleans are also parsed from string values, since the server may return them as
+/// strings.
+pub trait FromScalarValue: Sized {
+    /// Converts the value, handing it back unchanged if it cannot be represented as `Self`.
+    /// # Errors
+    /// Returns the original value if the conversion is not possible.
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue>;
+}
+
+impl FromScalarValue for ScalarValue {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        Ok(value)
+    }
+}
+
+/// Converts [`ScalarValue::VNull`] to `None`, such as the reply for a key that does not exist.
+impl<T: FromScalarValue> FromScalarValue for Option<T> {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        match value {
+            ScalarValue::VNull => Ok(None),
+            value => T::from_scalar_value(value).map(Some),
+        }
+    }
+}
+
+impl FromScalarValue for String {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        match value {
+            ScalarValue::VStr(s) => Ok(s),
+            ScalarValue::VBytes(b) => {
+                String::from_utf8(b).map_err(|e| ScalarValue::VBytes(e.into_bytes()))
+            }
+            ScalarValue::VNull => Err(ScalarValue::VNull),
+            value => Ok(value.to_string()),
+        }
+    }
+}
+
+impl FromScalarValue for f64 {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        match value {
+            ScalarValue::VFloat(f) => Ok(f),
+            ScalarValue::VInt(i) => Ok(i as f64),
+            ScalarValue::VStr(ref s) => s.parse().map_err(|_| value),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromScalarValue for bool {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        match value {
+            ScalarValue::VBool(b) => Ok(b),
+            ScalarValue::VStr(ref s) => s.parse().map_err(|_| value),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromScalarValue for Vec<u8> {
+    fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+        match value {
+            ScalarValue::VBytes(b) => Ok(decode_bytes_value(b)),
+            ScalarValue::VStr(s) => Ok(decode_bytes_value(s.into_bytes())),
+            value => Err(value),
+        }
+    }
+}
+
+macro_rules! impl_from_scalar_value_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromScalarValue for $t {
+                fn from_scalar_value(value: ScalarValue) -> Result<Self, ScalarValue> {
+                    match value {
+                        ScalarValue::VInt(i) => <$t>::try_from(i).map_err(|_| value),
+                        ScalarValue::VStr(ref s) => s.parse().map_err(|_| value),
+                        value => Err(value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_scalar_value_for_int!(i64, i32, i16, i8, u64, u32, u16, u8);
+
+/// Converts the value read from `key` to `T`, mapping [`ScalarValue::VNull`] to `None`.
+pub(crate) fn convert_value<T: FromScalarValue>(
+    key: &str,
+    value: ScalarValue,
+) -> Result<Option<T>, CommandError> {
+    match value {
+        ScalarValue::VNull => Ok(None),
+        value => {
+            T::from_scalar_value(value)
+                .map(Some)
+                .map_err(|value| CommandError::ConversionError {
+                    key: key.to_string(),
+                    value,
+                    target: std::any::type_name::<T>(),
+                })
+        }
+    }
+}
+
+/// The version of a DiceDB server, see
+/// [`Client::server_version`](crate::client::Client::server_version).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    /// The major version.
+    pub major: u32,
+    /// The minor version.
+    pub minor: u32,
+    /// The patch version.
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    /// Creates a version from its parts.
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        ServerVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Finds the version in the reply to INFO, which holds `name:value` lines, or is the bare
+    /// version.
+    pub(crate) fn from_info(info: &str) -> Option<Self> {
+        const NAMES: [&str; 3] = ["dicedb_version", "version", "server_version"];
+        info.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| NAMES.contains(&name.trim()))
+            .map_or(info, |(_, value)| value)
+            .parse()
+            .ok()
+    }
+}
+
+impl FromStr for ServerVersion {
+    type Err = CommandError;
+
+    /// Parses versions such as `1.0.2`, `v0.1` or `1.0.0-rc1`. Missing parts are zero and
+    /// anything after the numbers is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CommandError::InvalidInput(format!("Invalid server version: {}", s));
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_prefix(['v', 'V']).unwrap_or(trimmed);
+        let end = trimmed
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(trimmed.len());
+        let mut parts = trimmed[..end].split('.');
+        let mut next = || -> Result<u32, CommandError> {
+            match parts.next() {
+                None => Ok(0),
+                Some(part) => part.parse().map_err(|_| invalid()),
+            }
+        };
+        let version = ServerVersion::new(next()?, next()?, next()?);
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl Display for ServerVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The type of the value stored at a key, as reported by the TYPE command.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiceType {
+    /// A string value.
+    Str,
+    /// An integer value.
+    Int,
+    /// A floating point value.
+    Float,
+    /// A boolean value.
+    Bool,
+    /// A binary value.
+    Bytes,
+    /// A hash of fields and values.
+    Hash,
+    /// A list of values.
+    List,
+    /// A set of values.
+    Set,
+    /// A sorted set of values.
+    SortedSet,
+    /// The key does not exist.
+    None,
+    /// A type unknown to this version of the SDK, holding the name reported by the server.
+    Other(String),
+}
+
+impl From<&str> for DiceType {
+    fn from(s: &str) -> Self {
+        match s {
+            "string" => DiceType::Str,
+            "int" => DiceType::Int,
+            "float" => DiceType::Float,
+            "bool" => DiceType::Bool,
+            "bytes" => DiceType::Bytes,
+            "hash" => DiceType::Hash,
+            "list" => DiceType::List,
+            "set" => DiceType::Set,
+            "zset" => DiceType::SortedSet,
+            "none" => DiceType::None,
+            other => DiceType::Other(other.to_string()),
+        }
+    }
+}
+
+impl FromStr for DiceType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl Display for DiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiceType::Str => write!(f, "string"),
+            DiceType::Int => write!(f, "int"),
+            DiceType::Float => write!(f, "float"),
+            DiceType::Bool => write!(f, "bool"),
+            DiceType::Bytes => write!(f, "bytes"),
+            DiceType::Hash => write!(f, "hash"),
+            DiceType::List => write!(f, "list"),
+            DiceType::Set => write!(f, "set"),
+            DiceType::SortedSet => write!(f, "zset"),
+            DiceType::None => write!(f, "none"),
+            DiceType::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// The remaining time to live of a key, as reported by the TTL command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ttl {
+    /// The key exists and expires after the given duration.
+    Expires(Duration),
+    /// The key exists but has no expiry.
+    NoExpiry,
+    /// The key does not exist.
+    Missing,
+}
+
+impl Ttl {
+    /// Maps a TTL reply in seconds, where -1 means no expiry and -2 means no key.
+    pub(crate) fn from_seconds_reply(value: ScalarValue) -> Result<Self, CommandError> {
+        match value {
+            ScalarValue::VInt(-2) => Ok(Ttl::Missing),
+            ScalarValue::VInt(-1) => Ok(Ttl::NoExpiry),
+            ScalarValue::VInt(seconds) if seconds >= 0 => {
+                Ok(Ttl::Expires(Duration::from_secs(seconds.unsigned_abs())))
+            }
+            value => Err(CommandError::UnexpectedResponse(value)),
+        }
+    }
+}
+
+/// When a key expires, as reported by the EXPIRETIME and PEXPIRETIME commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpiryInfo {
+    /// The key exists and expires at the given time.
+    Expires(SystemTime),
+    /// The key exists but has no expiry.
+    NoExpiry,
+    /// The key does not exist.
+    Missing,
+}
+
+impl ExpiryInfo {
+    /// Maps an EXPIRETIME reply in Unix seconds, where -1 means no expiry and -2 means no key.
+    pub(crate) fn from_seconds_reply(value: ScalarValue) -> Result<Self, CommandError> {
+        ExpiryInfo::from_reply(value, Duration::from_secs)
+    }
+
+    /// Maps a PEXPIRETIME reply in Unix milliseconds, with the same sentinels as EXPIRETIME.
+    pub(crate) fn from_millis_reply(value: ScalarValue) -> Result<Self, CommandError> {
+        ExpiryInfo::from_reply(value, Duration::from_millis)
+    }
+
+    fn from_reply(
+        value: ScalarValue,
+        since_epoch: fn(u64) -> Duration,
+    ) -> Result<Self, CommandError> {
+        match value {
+            ScalarValue::VInt(-2) => Ok(ExpiryInfo::Missing),
+            ScalarValue::VInt(-1) => Ok(ExpiryInfo::NoExpiry),
+            ScalarValue::VInt(timestamp) if timestamp >= 0 => UNIX_EPOCH
+                .checked_add(since_epoch(timestamp.unsigned_abs()))
+                .map(ExpiryInfo::Expires)
+                .ok_or(CommandError::UnexpectedResponse(value)),
+            value => Err(CommandError::UnexpectedResponse(value)),
+        }
+    }
+}
+
+/// Maps a TIME reply, the Unix time in seconds and the microseconds into the current second, to
+/// the time it denotes.
+pub(crate) fn server_time_from_reply(reply: ListValue) -> Result<SystemTime, CommandError> {
+    let [seconds, micros]: [ScalarValue; 2] =
+        reply
+            .0
+            .try_into()
+            .map_err(|_| CommandError::UnexpectedShape {
+                expected: "list of seconds and microseconds",
+                found: "list of another length",
+            })?;
+    let part = |value: &ScalarValue| match value {
+        ScalarValue::VInt(n) => u64::try_from(*n).ok(),
+        ScalarValue::VStr(s) => s.parse().ok(),
+        _ => None,
+    };
+    let micros = part(&micros)
+        .filter(|micros| *micros < 1_000_000)
+        .ok_or(CommandError::UnexpectedResponse(micros))?;
+    part(&seconds)
+        .and_then(|seconds| UNIX_EPOCH.checked_add(Duration::from_secs(seconds)))
+        .and_then(|time| time.checked_add(Duration::from_micros(micros)))
+        .ok_or(CommandError::UnexpectedResponse(seconds))
+}
+
+/// How far the clock of the server is off from the local clock, as returned by
+/// [`Client::clock_skew`](crate::client::Client::clock_skew).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClockSkew {
+    /// The server clock is ahead of the local clock by the given duration, or equal to it.
+    Ahead(Duration),
+    /// The server clock is behind the local clock by the given duration.
+    Behind(Duration),
+}
+
+impl ClockSkew {
+    /// Returns the skew between a server time and the local time it was compared with.
+    pub(crate) fn between(server: SystemTime, local: SystemTime) -> Self {
+        match server.duration_since(local) {
+            Ok(ahead) => ClockSkew::Ahead(ahead),
+            Err(e) => ClockSkew::Behind(e.duration()),
+        }
+    }
+
+    /// Returns the size of the skew, regardless of its direction.
+    pub fn magnitude(&self) -> Duration {
+        match self {
+            ClockSkew::Ahead(d) | ClockSkew::Behind(d) => *d,
+        }
+    }
+
+    /// Returns the skew in seconds, positive if the server clock is ahead and negative if it is
+    /// behind.
+    pub fn as_secs_f64(&self) -> f64 {
+        match self {
+            ClockSkew::Ahead(d) => d.as_secs_f64(),
+            ClockSkew::Behind(d) => -d.as_secs_f64(),
+        }
+    }
+}
+
+/// Converts a duration to whole seconds, rounding sub-second durations up so a key never expires
+/// earlier than requested.
+pub(crate) fn duration_to_seconds(duration: Duration) -> Result<i64, CommandError> {
+    duration
+        .as_secs()
+        .checked_add(u64::from(duration.subsec_nanos() > 0))
+        .and_then(|seconds| i64::try_from(seconds).ok())
+        .ok_or_else(|| CommandError::InvalidInput(format!("duration {:?} is too large", duration)))
+}
+
+/// A point in time at or after the Unix epoch, used to set absolute expirations.
+///
+/// It can be created from a [`SystemTime`], and with the `chrono` or `time` features from a
+/// `chrono::DateTime<Utc>` or a `time::OffsetDateTime`. Times before the Unix epoch, or too far
+/// in the future for the server to represent, are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExpireAtTimestamp(Duration);
+
+impl ExpireAtTimestamp {
+    fn from_unix_duration(since_epoch: Duration) -> Result<Self, CommandError> {
+        duration_to_seconds(since_epoch).map_err(|_| {
+            CommandError::InvalidInput(format!(
+                "time {:?} after the Unix epoch is out of range",
+                since_epoch
+            ))
+        })?;
+        Ok(ExpireAtTimestamp(since_epoch))
+    }
+
+    /// Returns the Unix timestamp in seconds, rounding sub-second parts up so a key never expires
+    /// earlier than requested.
+    pub fn as_secs(&self) -> i64 {
+        duration_to_seconds(self.0).unwrap_or(i64::MAX)
+    }
+
+    /// Returns the Unix timestamp in milliseconds.
+    pub fn as_millis(&self) -> u64 {
+        u64::try_from(self.0.as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+impl TryFrom<SystemTime> for ExpireAtTimestamp {
+    type Error = CommandError;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).map_err(|_| {
+            CommandError::InvalidInput(format!("time {:?} is before the Unix epoch", time))
+        })?;
+        ExpireAtTimestamp::from_unix_duration(since_epoch)
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for ExpireAtTimestamp {
+    type Error = CommandError;
+
+    fn try_from(time: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        let seconds = u64::try_from(time.timestamp()).map_err(|_| {
+            CommandError::InvalidInput(format!("time {} is before the Unix epoch", time))
+        })?;
+        ExpireAtTimestamp::from_unix_duration(Duration::new(seconds, time.timestamp_subsec_nanos()))
+    }
+}
+
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+impl TryFrom<time::OffsetDateTime> for ExpireAtTimestamp {
+    type Error = CommandError;
+
+    fn try_from(time: time::OffsetDateTime) -> Result<Self, Self::Error> {
+        let seconds = u64::try_from(time.unix_timestamp()).map_err(|_| {
+            CommandError::InvalidInput(format!("time {} is before the Unix epoch", time))
+        })?;
+        ExpireAtTimestamp::from_unix_duration(Duration::new(seconds, time.nanosecond()))
+    }
+}
+
+impl From<ExpireAtTimestamp> for GetexOption {
+    fn from(timestamp: ExpireAtTimestamp) -> Self {
+        GetexOption::PXAT(timestamp.as_millis())
+    }
+}
+
+/// Marker prepended to binary payloads that are stored as base64 by
+/// [`Client::set_bytes`](crate::client::Client::set_bytes).
+///
+/// Command arguments travel as UTF-8 strings on the wire, so payloads that are not valid UTF-8
+/// (or that happen to start with this marker) are stored as the marker followed by the standard
+/// base64 encoding of the bytes. [`Client::get_bytes`](crate::client::Client::get_bytes) strips
+/// the marker and decodes the payload transparently.
+pub const BYTES_MARKER: &str = "dicedb-rs:b64:";
+
+/// Encodes bytes as a command argument without losing data.
+pub(crate) fn encode_bytes_arg(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.starts_with(BYTES_MARKER) => s.to_string(),
+        _ => format!("{}{}", BYTES_MARKER, BASE64.encode(bytes)),
+    }
+}
+
+/// Decodes a value stored with [`encode_bytes_arg`] back into the original bytes.
+/// Values without the marker, or with a marker but an invalid payload, are returned as is.
+pub(crate) fn decode_bytes_value(bytes: Vec<u8>) -> Vec<u8> {
+    match bytes.strip_prefix(BYTES_MARKER.as_bytes()) {
+        Some(encoded) => BASE64.decode(encoded).unwrap_or(bytes),
+        None => bytes,
+    }
+}
+
 impl Display for ScalarValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -153,18 +669,36 @@ impl Display for ScalarValue {
             ScalarValue::VInt(i) => write!(f, "{}", i),
             ScalarValue::VFloat(fl) => write!(f, "{}", fl),
             ScalarValue::VBool(b) => write!(f, "{}", b),
+            ScalarValue::VBytes(b) => write!(f, "{}", String::from_utf8_lossy(b)),
             ScalarValue::VNull => write!(f, "null"),
         }
     }
 }
 
+/// Formats a float argument as the shortest text that parses back to the same value. Magnitudes
+/// outside of `1e-7..1e21` are written with an exponent, as written out they run to hundreds of
+/// digits. The text always has a fraction or an exponent, so the server never reads a float as an
+/// integer.
+pub(crate) fn format_float(value: f64) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-7..1e21).contains(&magnitude) {
+        return format!("{:e}", value);
+    }
+    let mut text = value.to_string();
+    if !text.contains('.') {
+        text.push_str(".0");
+    }
+    text
+}
+
 impl AsArg for ScalarValue {
     fn as_arg(&self) -> String {
         match self {
             ScalarValue::VStr(s) => s.clone(),
             ScalarValue::VInt(i) => i.to_string(),
-            ScalarValue::VFloat(f) => f.to_string(),
+            ScalarValue::VFloat(f) => format_float(*f),
             ScalarValue::VBool(b) => b.to_string(),
+            ScalarValue::VBytes(b) => encode_bytes_arg(b),
             ScalarValue::VNull => "".to_string(),
         }
     }
@@ -177,20 +711,133 @@ impl Into<ScalarValue> for wire::response::Value {
             wire::response::Value::VInt(i) => ScalarValue::VInt(i),
             wire::response::Value::VStr(s) => ScalarValue::VStr(s),
             wire::response::Value::VFloat(f) => ScalarValue::VFloat(f),
-            wire::response::Value::VBytes(b) => {
-                ScalarValue::VStr(String::from_utf8_lossy(&b).to_string())
-            }
+            wire::response::Value::VBytes(b) => ScalarValue::VBytes(b),
         }
     }
 }
 
 /// A watch value is a value that originates from a GET.WATCH command.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WatchValue {
     /// The value from the watch session, it indicates a change in a watched key.
     pub value: ScalarValue,
     /// The fingerprint of the value, which is a unique identifier for the value.
     pub fingerprint: String,
+    /// Whether the key was set or removed. Left out of the serialized form of updates.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "ChangeKind::is_updated")
+    )]
+    pub change: ChangeKind,
+}
+
+/// What happened to a watched key, as told by a [`WatchValue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ChangeKind {
+    /// The key was set, and the value of the watch value is its new value.
+    #[default]
+    Updated,
+    /// The key no longer exists because it was deleted or expired, and the value of the watch
+    /// value is [`ScalarValue::VNull`]. The server pushes the same nil for deletions and
+    /// expirations, so they cannot be told apart.
+    Removed,
+}
+
+impl ChangeKind {
+    /// Returns true if the key was set.
+    pub fn is_updated(&self) -> bool {
+        *self == ChangeKind::Updated
+    }
+
+    /// Returns true if the key was deleted or expired.
+    pub fn is_removed(&self) -> bool {
+        *self == ChangeKind::Removed
+    }
+}
+
+/// Encodes `value` as the server would reply with it.
+#[cfg(test)]
+pub(crate) fn encode_reply(value: &ScalarValue) -> Vec<u8> {
+    wire::Response {
+        value: Some(wire_value(value)),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Encodes `values` as the server would reply with a list.
+#[cfg(test)]
+pub(crate) fn encode_list_reply(values: &[ScalarValue]) -> Vec<u8> {
+    use prost_types::value::Kind;
+
+    let v_list = values
+        .iter()
+        .map(|value| prost_types::Value {
+            kind: Some(match value {
+                ScalarValue::VStr(s) => Kind::StringValue(s.clone()),
+                ScalarValue::VInt(i) => Kind::NumberValue(*i as f64),
+                ScalarValue::VFloat(f) => Kind::NumberValue(*f),
+                ScalarValue::VBool(b) => Kind::BoolValue(*b),
+                ScalarValue::VBytes(b) => Kind::StringValue(String::from_utf8_lossy(b).into()),
+                ScalarValue::VNull => Kind::NullValue(0),
+            }),
+        })
+        .collect();
+    wire::Response {
+        v_list,
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Encodes an error reply as the server would send it for a rejected command.
+#[cfg(test)]
+pub(crate) fn encode_error_reply(message: &str) -> Vec<u8> {
+    wire::Response {
+        err: message.to_string(),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Encodes `value` as the server would push it to a watch stream subscribed with `fingerprint`.
+#[cfg(test)]
+pub(crate) fn encode_watch_reply(value: &ScalarValue, fingerprint: &str) -> Vec<u8> {
+    let fingerprint = prost_types::Value {
+        kind: Some(prost_types::value::Kind::StringValue(
+            fingerprint.to_string(),
+        )),
+    };
+    wire::Response {
+        value: Some(wire_value(value)),
+        attrs: Some(prost_types::Struct {
+            fields: [("fingerprint".to_string(), fingerprint)].into(),
+        }),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+#[cfg(test)]
+fn wire_value(value: &ScalarValue) -> wire::response::Value {
+    match value {
+        ScalarValue::VStr(s) => wire::response::Value::VStr(s.clone()),
+        ScalarValue::VInt(i) => wire::response::Value::VInt(*i),
+        ScalarValue::VFloat(f) => wire::response::Value::VFloat(*f),
+        ScalarValue::VBool(b) => wire::response::Value::VStr(b.to_string()),
+        ScalarValue::VBytes(b) => wire::response::Value::VBytes(b.clone()),
+        ScalarValue::VNull => wire::response::Value::VNil(true),
+    }
+}
+
+/// Decodes a command as the server would receive it, into its name and arguments.
+pub(crate) fn decode_command(bytes: &[u8]) -> Result<(String, Vec<String>), prost::DecodeError> {
+    let command = wire::Command::decode(bytes)?;
+    Ok((command.cmd, command.args))
 }
 
 impl Into<ScalarValue> for WatchValue {
@@ -200,8 +847,19 @@ impl Into<ScalarValue> for WatchValue {
 }
 
 impl WatchValue {
+    /// Returns whether the reply in `bytes` is a watch notification, which carries the
+    /// fingerprint of the watch in its attributes.
+    pub(crate) fn is_watch_push(bytes: &[u8]) -> bool {
+        // Command replies never have attributes, so only those that do are decoded.
+        framing::has_field(bytes, 7)
+            && wire::Response::decode(bytes)
+                .ok()
+                .and_then(|v| v.attrs)
+                .is_some_and(|attrs| attrs.fields.contains_key("fingerprint"))
+    }
+
     pub(crate) fn decode_watchvalue(bytes: &[u8]) -> Result<Self, CommandError> {
-        match wire::Response::decode(bytes) {
+        match wire::Response::decode(single_reply(bytes)?) {
             Ok(v) => {
                 if v.err == "" {
                     let fingerprint = match v
@@ -226,16 +884,21 @@ impl WatchValue {
                             ))
                         }
                     };
-                    let value = v
-                        .value
-                        .ok_or(CommandError::WatchValueExpectationError(
-                            "Missing value from response".to_string(),
-                        ))?
-                        .into();
-
-                    Ok(WatchValue { value, fingerprint })
+                    let value = v.value.ok_or(CommandError::WatchValueExpectationError(
+                        "Missing value from response".to_string(),
+                    ))?;
+                    // Only a key that no longer exists is pushed as nil, as SET cannot store one.
+                    let change = match value {
+                        wire::response::Value::VNil(_) => ChangeKind::Removed,
+                        _ => ChangeKind::Updated,
+                    };
+                    Ok(WatchValue {
+                        value: value.into(),
+                        fingerprint,
+                        change,
+                    })
                 } else {
-                    Err(CommandError::ServerError(v.err))
+                    Err(CommandError::server(v.err))
                 }
             }
             Err(e) => Err(CommandError::DecodeError(e)),
@@ -243,28 +906,103 @@ impl WatchValue {
     }
 }
 
-/// HSetValue is a value that originates from a HGETALL command.
-#[derive(Debug, Clone, PartialEq)]
-pub struct HSetValue {
-    /// The fields of the hash set.
+/// MapValue is a value that originates from a command replying with fields and their values,
+/// such as HGETALL.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct MapValue {
+    /// The fields of the map.
     pub fields: HashMap<String, String>,
 }
 
-impl Into<HashMap<String, String>> for HSetValue {
+/// HSetValue is a value that originates from a HGETALL command.
+pub type HSetValue = MapValue;
+
+impl Into<HashMap<String, String>> for MapValue {
     fn into(self) -> HashMap<String, String> {
         self.fields
     }
 }
 
-impl HSetValue {
-    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, CommandError> {
-        match wire::Response::decode(bytes) {
+impl From<MapValue> for BTreeMap<String, String> {
+    fn from(value: MapValue) -> Self {
+        value.fields.into_iter().collect()
+    }
+}
+
+impl IntoIterator for MapValue {
+    type Item = (String, String);
+    type IntoIter = hash_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MapValue {
+    type Item = (&'a String, &'a String);
+    type IntoIter = hash_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.iter()
+    }
+}
+
+impl MapValue {
+    /// Returns the value of `field`, or `None` if the field is not present.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(String::as_str)
+    }
+
+    /// Returns the value of `field` parsed as `T`, or `None` if the field is not present.
+    /// # Errors
+    /// Returns the parse error of `T` if the field is present but cannot be parsed.
+    pub fn get_as<T: FromStr>(&self, field: &str) -> Result<Option<T>, T::Err> {
+        self.get(field).map(str::parse).transpose()
+    }
+
+    /// Returns the number of fields in the map.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns true if the map has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns true if `field` is present in the map.
+    pub fn contains_field(&self, field: &str) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    /// Returns an iterator over the fields and values of the map, in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, String, String> {
+        self.fields.iter()
+    }
+}
+
+impl MapValue {
+    /// Decodes a reply holding a map. A reply holding a nil value, as for a key that does not
+    /// exist, is `None`, and a reply without any value is a map, empty if it has no fields. A
+    /// reply holding another value or a list is not map-shaped.
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Option<Self>, CommandError> {
+        match wire::Response::decode(single_reply(bytes)?) {
             Ok(v) => {
-                if v.err == "" {
-                    let fields = v.v_ss_map;
-                    Ok(HSetValue { fields })
-                } else {
-                    Err(CommandError::ServerError(v.err))
+                if !v.err.is_empty() {
+                    return Err(CommandError::server(v.err));
+                }
+                if !v.v_list.is_empty() {
+                    return Err(CommandError::UnexpectedShape {
+                        expected: "map",
+                        found: "list",
+                    });
+                }
+                match v.value {
+                    None => Ok(Some(MapValue { fields: v.v_ss_map })),
+                    Some(wire::response::Value::VNil(_)) => Ok(None),
+                    Some(value) => Err(CommandError::UnexpectedResponse(value.into())),
                 }
             }
             Err(e) => Err(CommandError::DecodeError(e)),
@@ -272,46 +1010,442 @@ impl HSetValue {
     }
 }
 
-impl ScalarValue {
-    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, CommandError> {
-        let decoded = match wire::Response::decode(bytes) {
-            Ok(v) => {
-                if v.err == "" {
-                    match v.value {
-                        Some(value) => Ok(value.into()),
-                        None => Ok(ScalarValue::VNull),
-                    }
-                } else {
-                    Err(CommandError::ServerError(v.err))
-                }
-            }
-            Err(e) => Err(CommandError::DecodeError(e)),
-        };
-        eprintln!("Decoded value: {:?}", decoded);
+/// ListValue is a value that originates from a command replying with several values, such as
+/// KEYS or LRANGE.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ListValue(pub Vec<ScalarValue>);
 
-        decoded
+impl From<ListValue> for Vec<ScalarValue> {
+    fn from(value: ListValue) -> Self {
+        value.0
     }
 }
 
-trait AsArg {
-    fn as_arg(&self) -> String;
-}
+impl IntoIterator for ListValue {
+    type Item = ScalarValue;
+    type IntoIter = std::vec::IntoIter<ScalarValue>;
 
-trait AsArgs {
-    fn as_args(&self) -> Vec<String>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
-pub(crate) trait CommandExecutor {
-    fn execute_scalar_command(&mut self, command: Command) -> Result<ScalarValue, StreamError>;
-    fn execute_hset_command(&mut self, command: Command) -> Result<HSetValue, StreamError>;
+impl<'a> IntoIterator for &'a ListValue {
+    type Item = &'a ScalarValue;
+    type IntoIter = std::slice::Iter<'a, ScalarValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
 }
 
-/// Expire options for the EXPIRE command
-#[derive(Debug, Clone, Copy)]
-pub enum ExpireOption {
-    /// Don't overwrite existing expiration time
-    NX,
-    /// Only set the expiration time if it already exists
+impl ListValue {
+    /// Returns the value at `index`, or `None` if the list is shorter.
+    pub fn get(&self, index: usize) -> Option<&ScalarValue> {
+        self.0.get(index)
+    }
+
+    /// Returns the number of values in the list.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the list has no values.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over the values of the list, in the order the server sent them.
+    pub fn iter(&self) -> std::slice::Iter<'_, ScalarValue> {
+        self.0.iter()
+    }
+
+    /// Returns the values as text, see [`ScalarValue::to_string_lossy`]. Useful for lists of
+    /// keys or members.
+    pub fn to_strings(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+impl ListValue {
+    /// Decodes a reply holding a list. A reply without any value is an empty list, and a reply
+    /// holding a single value or a map is not list-shaped.
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, CommandError> {
+        match wire::Response::decode(single_reply(bytes)?) {
+            Ok(v) => {
+                if !v.err.is_empty() {
+                    return Err(CommandError::server(v.err));
+                }
+                if !v.v_ss_map.is_empty() {
+                    return Err(CommandError::UnexpectedShape {
+                        expected: "list",
+                        found: "map",
+                    });
+                }
+                match v.value {
+                    // A nil reply, as for a key that does not exist, is an empty list.
+                    None | Some(wire::response::Value::VNil(_)) => v
+                        .v_list
+                        .into_iter()
+                        .map(list_element)
+                        .collect::<Result<_, _>>()
+                        .map(ListValue),
+                    Some(value) => Err(CommandError::UnexpectedResponse(value.into())),
+                }
+            }
+            Err(e) => Err(CommandError::DecodeError(e)),
+        }
+    }
+}
+
+/// Converts an element of a list reply. The server sends every number as floating point, so
+/// whole numbers that fit an `i64` exactly become [`ScalarValue::VInt`].
+fn list_element(value: prost_types::Value) -> Result<ScalarValue, CommandError> {
+    use prost_types::value::Kind;
+
+    /// Largest magnitude up to which every integer is exactly representable as an `f64`.
+    const MAX_EXACT: f64 = (1u64 << 53) as f64;
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Ok(ScalarValue::VNull),
+        Some(Kind::NumberValue(n)) if n.fract() == 0.0 && n.abs() <= MAX_EXACT => {
+            Ok(ScalarValue::VInt(n as i64))
+        }
+        Some(Kind::NumberValue(n)) => Ok(ScalarValue::VFloat(n)),
+        Some(Kind::StringValue(s)) => Ok(ScalarValue::VStr(s)),
+        Some(Kind::BoolValue(b)) => Ok(ScalarValue::VBool(b)),
+        Some(Kind::StructValue(_)) => Err(CommandError::UnexpectedShape {
+            expected: "list of values",
+            found: "nested map",
+        }),
+        Some(Kind::ListValue(_)) => Err(CommandError::UnexpectedShape {
+            expected: "list of values",
+            found: "nested list",
+        }),
+    }
+}
+
+impl ScalarValue {
+    /// Returns the string if the value is a [`ScalarValue::VStr`], otherwise `None`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ScalarValue::VStr(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if the value is a [`ScalarValue::VInt`], otherwise `None`.
+    /// Floating point values are not truncated and also return `None`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ScalarValue::VInt(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if the value is a [`ScalarValue::VFloat`] or a [`ScalarValue::VInt`],
+    /// widening integers to floating point, otherwise `None`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ScalarValue::VFloat(f) => Some(*f),
+            ScalarValue::VInt(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean if the value is a [`ScalarValue::VBool`], otherwise `None`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ScalarValue::VBool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the value is [`ScalarValue::VNull`].
+    pub fn is_null(&self) -> bool {
+        matches!(self, ScalarValue::VNull)
+    }
+
+    /// Consumes the value and returns the string if it is a [`ScalarValue::VStr`], otherwise
+    /// `None`.
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            ScalarValue::VStr(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as text, replacing invalid UTF-8 in a [`ScalarValue::VBytes`] with
+    /// `U+FFFD`. Strings and valid UTF-8 bytes are borrowed, other values are formatted.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        match self {
+            ScalarValue::VStr(s) => Cow::Borrowed(s),
+            ScalarValue::VBytes(b) => String::from_utf8_lossy(b),
+            value => Cow::Owned(value.to_string()),
+        }
+    }
+}
+
+/// A value of the attributes the server attaches to a reply, such as the fingerprint of a watch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    /// A null value, or a value of a kind this client does not know.
+    Null,
+    /// A number. The server sends every number as floating point.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// A boolean.
+    Bool(bool),
+    /// A nested map of attributes.
+    Map(HashMap<String, AttrValue>),
+    /// A list of values.
+    List(Vec<AttrValue>),
+}
+
+impl AttrValue {
+    /// Returns the string if the value is an [`AttrValue::String`], otherwise `None`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AttrValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<prost_types::Value> for AttrValue {
+    fn from(value: prost_types::Value) -> Self {
+        use prost_types::value::Kind;
+
+        match value.kind {
+            None | Some(Kind::NullValue(_)) => AttrValue::Null,
+            Some(Kind::NumberValue(n)) => AttrValue::Number(n),
+            Some(Kind::StringValue(s)) => AttrValue::String(s),
+            Some(Kind::BoolValue(b)) => AttrValue::Bool(b),
+            Some(Kind::StructValue(s)) => AttrValue::Map(attrs_map(s)),
+            Some(Kind::ListValue(l)) => {
+                AttrValue::List(l.values.into_iter().map(AttrValue::from).collect())
+            }
+        }
+    }
+}
+
+/// Converts the attributes of a reply into a map, which is empty if the reply has none.
+/// Checks that `bytes` hold no more than one reply. Bytes after the end of the reply are
+/// reported as [`CommandError::TrailingData`] instead of being decoded as part of it, as they
+/// belong to the next reply.
+fn single_reply(bytes: &[u8]) -> Result<&[u8], CommandError> {
+    match framing::message_len(bytes) {
+        Some(len) if len < bytes.len() => Err(CommandError::TrailingData {
+            remaining: bytes.len() - len,
+        }),
+        _ => Ok(bytes),
+    }
+}
+
+fn attrs_map(attrs: prost_types::Struct) -> HashMap<String, AttrValue> {
+    attrs
+        .fields
+        .into_iter()
+        .map(|(name, value)| (name, value.into()))
+        .collect()
+}
+
+impl ScalarValue {
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, CommandError> {
+        Self::decode_with_attrs(bytes).map(|(value, _)| value)
+    }
+
+    /// Decodes a reply along with the attributes the server attached to it.
+    pub(crate) fn decode_with_attrs(
+        bytes: &[u8],
+    ) -> Result<(Self, HashMap<String, AttrValue>), CommandError> {
+        let decoded = match wire::Response::decode(single_reply(bytes)?) {
+            Ok(v) => {
+                if v.err == "" {
+                    let attrs = v.attrs.map(attrs_map).unwrap_or_default();
+                    match v.value {
+                        Some(value) => Ok((value.into(), attrs)),
+                        None => Ok((ScalarValue::VNull, attrs)),
+                    }
+                } else {
+                    Err(CommandError::server(v.err))
+                }
+            }
+            Err(e) => Err(CommandError::DecodeError(e)),
+        };
+        log::debug!(target: LOG_TARGET, "Decoded a reply of {} bytes", bytes.len());
+        log::trace!(target: LOG_TARGET, "Decoded reply: {:?}", decoded);
+
+        decoded
+    }
+}
+
+pub(crate) trait AsArg {
+    fn as_arg(&self) -> String;
+}
+
+trait AsArgs {
+    fn as_args(&self) -> Vec<String>;
+}
+
+/// Executes the typed commands the client builds, implemented by the streams and the client.
+pub(crate) trait Execute {
+    fn execute_scalar_command(&mut self, command: Command) -> Result<ScalarValue, StreamError>;
+    /// Like `execute_scalar_command`, but also returns the attributes of the reply.
+    fn execute_scalar_command_with_attrs(
+        &mut self,
+        command: Command,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError>;
+    fn execute_map_command(&mut self, command: Command) -> Result<Option<MapValue>, StreamError>;
+    fn execute_list_command(&mut self, command: Command) -> Result<ListValue, StreamError>;
+}
+
+/// Describes a command as it is sent to the server, by its name and arguments. Keys are already
+/// scoped to the prefix of the client, see [`Client::with_prefix`](crate::client::Client::with_prefix).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandDescriptor {
+    name: String,
+    args: Vec<String>,
+}
+
+impl CommandDescriptor {
+    /// Creates a descriptor, for example to compare with the commands an executor received.
+    pub fn new(name: impl Into<String>, args: Vec<String>) -> Self {
+        CommandDescriptor {
+            name: name.into(),
+            args,
+        }
+    }
+
+    /// The name of the command, such as `GET` or `GET.WATCH`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The arguments of the command, in the order they are sent.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns true if the command changes data on the server, judged by the name and arguments
+    /// it is sent with, so that it also holds for a command an interceptor rewrote. Commands the
+    /// client does not know are taken to be writes.
+    pub(crate) fn is_write(&self) -> bool {
+        match self.name.to_ascii_uppercase().as_str() {
+            "AUTH" | "ECHO" | "EXISTS" | "EXPIRETIME" | "GET" | "GET.WATCH" | "HANDSHAKE"
+            | "HGET" | "HGETALL" | "HGET.WATCH" | "INFO" | "KEYS" | "LPOS" | "PEXPIRETIME"
+            | "PING" | "SCAN" | "TIME" | "TTL" | "TYPE" | "UNWATCH" => false,
+            "CONFIG" => !self
+                .args
+                .first()
+                .is_some_and(|subcommand| subcommand.eq_ignore_ascii_case("GET")),
+            // The first argument is the key, which may be called "store" itself.
+            "SORT" => self
+                .args
+                .iter()
+                .skip(1)
+                .any(|arg| arg.eq_ignore_ascii_case("STORE")),
+            _ => true,
+        }
+    }
+}
+
+impl From<CommandDescriptor> for wire::Command {
+    fn from(descriptor: CommandDescriptor) -> Self {
+        wire::Command {
+            cmd: descriptor.name,
+            args: descriptor.args,
+        }
+    }
+}
+
+impl From<Command> for CommandDescriptor {
+    fn from(command: Command) -> Self {
+        let command: wire::Command = command.into();
+        CommandDescriptor {
+            name: command.cmd,
+            args: command.args,
+        }
+    }
+}
+
+/// Executes the commands of a [`Client`](crate::client::Client) in place of a connection to a
+/// server, set with [`Client::with_executor`](crate::client::Client::with_executor). This lets
+/// code that uses a client be tested without a server.
+///
+/// Any closure taking a [`CommandDescriptor`] and returning the reply is an executor.
+/// # Example
+/// ```
+/// use dicedb_rs::client::Client;
+/// use dicedb_rs::commands::{CommandDescriptor, ScalarValue};
+/// use dicedb_rs::errors::StreamError;
+///
+/// let mut client = Client::with_executor(|command: &CommandDescriptor| {
+///     match command.name() {
+///         "GET" => Ok(ScalarValue::VStr(format!("value of {}", command.args()[0]))),
+///         _ => Ok(ScalarValue::VStr("OK".to_string())),
+///     }
+/// });
+/// assert_eq!(
+///     client.get("key").unwrap(),
+///     ScalarValue::VStr("value of key".to_string())
+/// );
+/// ```
+pub trait CommandExecutor: Send {
+    /// Executes the command and returns the value it replied with. An error the server replies
+    /// with is returned as a [`CommandError::ServerError`] inside a [`StreamError`].
+    fn execute(&mut self, command: &CommandDescriptor) -> Result<ScalarValue, StreamError>;
+
+    /// Like [`execute`](CommandExecutor::execute), but also returns the attributes of the reply.
+    /// Replies have no attributes by default.
+    fn execute_with_attrs(
+        &mut self,
+        command: &CommandDescriptor,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError> {
+        Ok((self.execute(command)?, HashMap::new()))
+    }
+
+    /// Executes a command that replies with fields and their values, such as HGETALL, or `None`
+    /// for a nil reply. By default a [`ScalarValue::VNull`] reply is nil, and any other value is
+    /// unexpected.
+    fn execute_map(
+        &mut self,
+        command: &CommandDescriptor,
+    ) -> Result<Option<MapValue>, StreamError> {
+        match self.execute(command)? {
+            ScalarValue::VNull => Ok(None),
+            value => Err(CommandError::UnexpectedResponse(value).into()),
+        }
+    }
+
+    /// Executes a command that replies with a list, such as KEYS. By default a
+    /// [`ScalarValue::VNull`] reply is an empty list, and any other value is unexpected.
+    fn execute_list(&mut self, command: &CommandDescriptor) -> Result<ListValue, StreamError> {
+        match self.execute(command)? {
+            ScalarValue::VNull => Ok(ListValue::default()),
+            value => Err(CommandError::UnexpectedResponse(value).into()),
+        }
+    }
+}
+
+impl<F> CommandExecutor for F
+where
+    F: FnMut(&CommandDescriptor) -> Result<ScalarValue, StreamError> + Send,
+{
+    fn execute(&mut self, command: &CommandDescriptor) -> Result<ScalarValue, StreamError> {
+        self(command)
+    }
+}
+
+/// Expire options for the EXPIRE command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireOption {
+    /// Don't overwrite existing expiration time
+    NX,
+    /// Only set the expiration time if it already exists
     XX,
     /// Always set the expiration time
     None,
@@ -381,6 +1515,140 @@ impl AsArgs for GetexOption {
     }
 }
 
+/// The default number of keys deleted per DEL by
+/// [`Client::del_by_pattern`](crate::client::Client::del_by_pattern).
+pub const DEFAULT_DELETE_BATCH_SIZE: usize = 100;
+
+/// Options for [`Client::del_by_pattern`](crate::client::Client::del_by_pattern).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeleteByPatternOptions {
+    /// The number of keys deleted per DEL command, [`DEFAULT_DELETE_BATCH_SIZE`] by default.
+    pub batch_size: usize,
+    /// Only count the matching keys, without deleting them.
+    pub dry_run: bool,
+    /// The most keys that may match. If more do, nothing is deleted and an error is returned.
+    pub max_keys: Option<usize>,
+}
+
+impl Default for DeleteByPatternOptions {
+    fn default() -> Self {
+        DeleteByPatternOptions {
+            batch_size: DEFAULT_DELETE_BATCH_SIZE,
+            dry_run: false,
+            max_keys: None,
+        }
+    }
+}
+
+/// Options for the LPOS command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LposOptions {
+    /// Which match to start from: 1 for the first, 2 for the second and so on, or negative to
+    /// search from the tail of the list, -1 being the last match.
+    pub rank: Option<i64>,
+    /// Compare at most this many elements, searching the whole list if not set.
+    pub maxlen: Option<u64>,
+}
+
+/// Options for the SORT command, built with chained calls. By default the elements are sorted
+/// as numbers in ascending order.
+/// # Example
+/// ```
+/// use dicedb_rs::commands::SortOptions;
+/// let options = SortOptions::new().alpha().desc().limit(0, 10);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SortOptions {
+    by: Option<String>,
+    limit: Option<(u64, u64)>,
+    get: Vec<String>,
+    descending: bool,
+    alpha: bool,
+}
+
+impl SortOptions {
+    /// Create options sorting numerically in ascending order.
+    pub fn new() -> Self {
+        SortOptions::default()
+    }
+
+    /// Sort the elements as strings in lexicographical order instead of as numbers.
+    pub fn alpha(mut self) -> Self {
+        self.alpha = true;
+        self
+    }
+
+    /// Sort in ascending order, which is the default.
+    pub fn asc(mut self) -> Self {
+        self.descending = false;
+        self
+    }
+
+    /// Sort in descending order.
+    pub fn desc(mut self) -> Self {
+        self.descending = true;
+        self
+    }
+
+    /// Return only `count` elements, starting at `offset` in the sorted elements.
+    pub fn limit(mut self, offset: u64, count: u64) -> Self {
+        self.limit = Some((offset, count));
+        self
+    }
+
+    /// Sort by the values of the keys matching `pattern`, in which `*` is replaced by each
+    /// element, such as `weight_*`. The pattern `nosort` skips sorting.
+    pub fn by(mut self, pattern: &str) -> Self {
+        self.by = Some(pattern.to_string());
+        self
+    }
+
+    /// Return the values of the keys matching `pattern` instead of the elements, in which `*` is
+    /// replaced by each element. The pattern `#` returns the element itself. Can be called
+    /// several times to return several values per element.
+    pub fn get(mut self, pattern: &str) -> Self {
+        self.get.push(pattern.to_string());
+        self
+    }
+
+    /// Scopes the key patterns to a prefix, leaving the special `nosort` and `#` patterns as is.
+    fn prefix_patterns(self, prefixed: impl Fn(String) -> String) -> Self {
+        SortOptions {
+            by: self
+                .by
+                .map(|by| if by == "nosort" { by } else { prefixed(by) }),
+            get: self
+                .get
+                .into_iter()
+                .map(|get| if get == "#" { get } else { prefixed(get) })
+                .collect(),
+            ..self
+        }
+    }
+}
+
+impl AsArgs for SortOptions {
+    fn as_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(by) = &self.by {
+            args.extend(["BY".to_string(), by.clone()]);
+        }
+        if let Some((offset, count)) = self.limit {
+            args.extend(["LIMIT".to_string(), offset.to_string(), count.to_string()]);
+        }
+        for get in &self.get {
+            args.extend(["GET".to_string(), get.clone()]);
+        }
+        if self.descending {
+            args.push("DESC".to_string());
+        }
+        if self.alpha {
+            args.push("ALPHA".to_string());
+        }
+        args
+    }
+}
+
 /// Options for the SET command
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum SetOption {
@@ -422,7 +1690,7 @@ impl AsArg for SetInput {
         match self {
             SetInput::Str(s) => s.clone(),
             SetInput::Int(i) => i.to_string(),
-            SetInput::Float(f) => f.to_string(),
+            SetInput::Float(f) => format_float(*f),
         }
     }
 }
@@ -444,9 +1712,12 @@ impl AsArgs for Vec<(String, SetInput)> {
     }
 }
 
-#[derive(Debug)]
-pub(crate) enum ExecutionMode {
+/// The kind of connection, either executing commands or receiving watch updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// A connection executing commands.
     Command,
+    /// A connection receiving the updates of a watch subscription.
     Watch,
 }
 
@@ -459,8 +1730,18 @@ impl AsArg for ExecutionMode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum Command {
+    AUTH {
+        password: Secret,
+    },
+    CONFIGGET {
+        parameter: String,
+    },
+    CONFIGSET {
+        parameter: String,
+        value: String,
+    },
     DECR {
         key: String,
     },
@@ -502,6 +1783,10 @@ pub(crate) enum Command {
         key: String,
         ex: GetexOption,
     },
+    HDEL {
+        key: String,
+        fields: Vec<String>,
+    },
     HSET {
         key: String,
         fields: Vec<(String, String)>,
@@ -516,6 +1801,10 @@ pub(crate) enum Command {
     GETWATCH {
         key: String,
     },
+    HGETWATCH {
+        key: String,
+        field: String,
+    },
     HANDSHAKE {
         client_id: String,
         execution_mode: ExecutionMode,
@@ -523,17 +1812,56 @@ pub(crate) enum Command {
     INCR {
         key: String,
     },
+    INFO,
     INCRBY {
         key: String,
         delta: i64,
     },
+    KEYS {
+        pattern: String,
+    },
+    LPOS {
+        key: String,
+        element: String,
+        rank: Option<i64>,
+        /// The number of matches to return, all of them if 0. A single index is returned if not
+        /// set.
+        count: Option<u64>,
+        maxlen: Option<u64>,
+    },
+    MSETNX {
+        pairs: Vec<(String, SetInput)>,
+    },
+    PEXPIRE {
+        key: String,
+        milliseconds: u64,
+    },
+    PEXPIRETIME {
+        key: String,
+    },
     PING,
+    SCAN {
+        cursor: u64,
+        pattern: String,
+        /// A hint of how many keys to look at for one page, left to the server if not set.
+        count: Option<u64>,
+    },
     SET {
         key: String,
         value: SetInput,
         option: SetOption,
         get: bool,
+        /// Only set the key if it does not exist, which unlike [`SetOption::NX`] can be combined
+        /// with an expiry.
+        nx: bool,
+    },
+    SORT {
+        key: String,
+        options: SortOptions,
+        /// The key the sorted elements are stored at instead of being returned, if any.
+        store: Option<String>,
     },
+    TIME,
     TTL {
         key: String,
     },
@@ -542,12 +1870,33 @@ pub(crate) enum Command {
     },
     UNWATCH {
         key: String,
+        /// The field of a HGET.WATCH subscription, if the watch is of a single field.
+        field: Option<String>,
+    },
+    /// A command rewritten by an interceptor, sent as `descriptor` but otherwise handled like
+    /// `command`, see [`Command::intercepted`].
+    INTERCEPTED {
+        command: Box<Command>,
+        descriptor: CommandDescriptor,
     },
 }
 
 impl Into<wire::Command> for Command {
     fn into(self) -> wire::Command {
         match self {
+            Command::INTERCEPTED { descriptor, .. } => descriptor.into(),
+            Command::AUTH { password } => wire::Command {
+                cmd: "AUTH".to_string(),
+                args: vec![password.0],
+            },
+            Command::CONFIGGET { parameter } => wire::Command {
+                cmd: "CONFIG".to_string(),
+                args: vec!["GET".to_string(), parameter],
+            },
+            Command::CONFIGSET { parameter, value } => wire::Command {
+                cmd: "CONFIG".to_string(),
+                args: vec!["SET".to_string(), parameter, value],
+            },
             Command::DECR { key } => wire::Command {
                 cmd: "DECR".to_string(),
                 args: vec![key],
@@ -614,6 +1963,33 @@ impl Into<wire::Command> for Command {
                 cmd: "FLUSHDB".to_string(),
                 args: vec![],
             },
+            Command::KEYS { pattern } => wire::Command {
+                cmd: "KEYS".to_string(),
+                args: vec![pattern],
+            },
+            Command::LPOS {
+                key,
+                element,
+                rank,
+                count,
+                maxlen,
+            } => {
+                let mut args = vec![key, element];
+                let options = [
+                    ("RANK", rank.map(|rank| rank.to_string())),
+                    ("COUNT", count.map(|count| count.to_string())),
+                    ("MAXLEN", maxlen.map(|maxlen| maxlen.to_string())),
+                ];
+                for (name, value) in options {
+                    if let Some(value) = value {
+                        args.extend([name.to_string(), value]);
+                    }
+                }
+                wire::Command {
+                    cmd: "LPOS".to_string(),
+                    args,
+                }
+            }
             Command::GET { key } => wire::Command {
                 cmd: "GET".to_string(),
                 args: vec![key],
@@ -630,6 +2006,14 @@ impl Into<wire::Command> for Command {
                     args,
                 }
             }
+            Command::HDEL { key, fields } => {
+                let mut args = vec![key];
+                args.extend(fields);
+                wire::Command {
+                    cmd: "HDEL".to_string(),
+                    args,
+                }
+            }
             Command::HSET { key, fields } => {
                 let mut args = vec![key];
                 for (field, value) in fields {
@@ -653,6 +2037,10 @@ impl Into<wire::Command> for Command {
                 cmd: "GET.WATCH".to_string(),
                 args: vec![key],
             },
+            Command::HGETWATCH { key, field } => wire::Command {
+                cmd: "HGET.WATCH".to_string(),
+                args: vec![key, field],
+            },
             Command::HANDSHAKE {
                 client_id,
                 execution_mode,
@@ -660,6 +2048,10 @@ impl Into<wire::Command> for Command {
                 cmd: "HANDSHAKE".to_string(),
                 args: vec![client_id, execution_mode.as_arg()],
             },
+            Command::INFO => wire::Command {
+                cmd: "INFO".to_string(),
+                args: vec![],
+            },
             Command::INCR { key } => wire::Command {
                 cmd: "INCR".to_string(),
                 args: vec![key],
@@ -668,19 +2060,45 @@ impl Into<wire::Command> for Command {
                 cmd: "INCRBY".to_string(),
                 args: vec![key, delta.to_string()],
             },
+            Command::PEXPIRE { key, milliseconds } => wire::Command {
+                cmd: "PEXPIRE".to_string(),
+                args: vec![key, milliseconds.to_string()],
+            },
+            Command::PEXPIRETIME { key } => wire::Command {
+                cmd: "PEXPIRETIME".to_string(),
+                args: vec![key],
+            },
             Command::PING => wire::Command {
                 cmd: "PING".to_string(),
                 args: vec![],
             },
+            Command::SCAN {
+                cursor,
+                pattern,
+                count,
+            } => {
+                let mut args = vec![cursor.to_string(), "MATCH".to_string(), pattern];
+                if let Some(count) = count {
+                    args.extend(["COUNT".to_string(), count.to_string()]);
+                }
+                wire::Command {
+                    cmd: "SCAN".to_string(),
+                    args,
+                }
+            }
             Command::SET {
                 key,
                 value,
                 option,
                 get,
+                nx,
             } => {
                 let value: ScalarValue = value.into();
                 let mut args = vec![key, value.as_arg()];
                 args.extend(option.as_args());
+                if nx {
+                    args.push("NX".to_string());
+                }
                 match get {
                     true => args.push("GET".to_string()),
                     false => {}
@@ -694,34 +2112,408 @@ impl Into<wire::Command> for Command {
                 cmd: "TTL".to_string(),
                 args: vec![key],
             },
+            Command::MSETNX { pairs } => wire::Command {
+                cmd: "MSETNX".to_string(),
+                args: pairs
+                    .into_iter()
+                    .flat_map(|(key, value)| {
+                        let value: ScalarValue = value.into();
+                        [key, value.as_arg()]
+                    })
+                    .collect(),
+            },
+            Command::SORT {
+                key,
+                options,
+                store,
+            } => {
+                let mut args = vec![key];
+                args.extend(options.as_args());
+                if let Some(store) = store {
+                    args.extend(["STORE".to_string(), store]);
+                }
+                wire::Command {
+                    cmd: "SORT".to_string(),
+                    args,
+                }
+            }
+            Command::TIME => wire::Command {
+                cmd: "TIME".to_string(),
+                args: vec![],
+            },
             Command::TYPE { key } => wire::Command {
                 cmd: "TYPE".to_string(),
                 args: vec![key],
             },
-            Command::UNWATCH { key } => wire::Command {
+            Command::UNWATCH { key, field } => wire::Command {
                 cmd: "UNWATCH".to_string(),
-                args: vec![key],
+                args: [key].into_iter().chain(field).collect(),
             },
         }
     }
 }
 
-impl Command {
-    pub(crate) fn encode(self) -> Vec<u8> {
-        let command: wire::Command = self.into();
-        eprintln!("Sending command: {:?}", command);
-        command.encode_to_vec()
-    }
+pub(crate) fn prefixed_key(prefix: &str, key: &str) -> String {
+    format!("{}:{}", prefix, key)
 }
 
-#[cfg(test)]
-mod tests {
-
-    use super::*;
-
-    #[test]
-    fn test_try_into() {
-        let v: ScalarValue = ScalarValue::VInt(42);
+impl Command {
+    /// Prepends `"{prefix}:"` to every key the command operates on.
+    pub(crate) fn prefix_keys(self, prefix: &str) -> Command {
+        let prefixed = |key: String| prefixed_key(prefix, &key);
+        match self {
+            Command::DECR { key } => Command::DECR { key: prefixed(key) },
+            Command::DECRBY { key, delta } => Command::DECRBY {
+                key: prefixed(key),
+                delta,
+            },
+            Command::DEL { keys } => Command::DEL {
+                keys: keys.into_iter().map(prefixed).collect(),
+            },
+            Command::EXISTS {
+                key,
+                additional_keys,
+            } => Command::EXISTS {
+                key: prefixed(key),
+                additional_keys: additional_keys.into_iter().map(prefixed).collect(),
+            },
+            Command::EXPIRE {
+                key,
+                seconds,
+                option,
+            } => Command::EXPIRE {
+                key: prefixed(key),
+                seconds,
+                option,
+            },
+            Command::EXPIREAT {
+                key,
+                timestamp,
+                option,
+            } => Command::EXPIREAT {
+                key: prefixed(key),
+                timestamp,
+                option,
+            },
+            Command::EXPIRETIME { key } => Command::EXPIRETIME { key: prefixed(key) },
+            Command::GET { key } => Command::GET { key: prefixed(key) },
+            Command::GETDEL { key } => Command::GETDEL { key: prefixed(key) },
+            Command::GETEX { key, ex } => Command::GETEX {
+                key: prefixed(key),
+                ex,
+            },
+            Command::HDEL { key, fields } => Command::HDEL {
+                key: prefixed(key),
+                fields,
+            },
+            Command::HSET { key, fields } => Command::HSET {
+                key: prefixed(key),
+                fields,
+            },
+            Command::HGET { key, field } => Command::HGET {
+                key: prefixed(key),
+                field,
+            },
+            Command::HGETALL { key } => Command::HGETALL { key: prefixed(key) },
+            Command::GETWATCH { key } => Command::GETWATCH { key: prefixed(key) },
+            Command::HGETWATCH { key, field } => Command::HGETWATCH {
+                key: prefixed(key),
+                field,
+            },
+            Command::INCR { key } => Command::INCR { key: prefixed(key) },
+            Command::INCRBY { key, delta } => Command::INCRBY {
+                key: prefixed(key),
+                delta,
+            },
+            Command::SET {
+                key,
+                value,
+                option,
+                get,
+                nx,
+            } => Command::SET {
+                key: prefixed(key),
+                value,
+                option,
+                get,
+                nx,
+            },
+            Command::KEYS { pattern } => Command::KEYS {
+                pattern: prefixed(pattern),
+            },
+            Command::SCAN {
+                cursor,
+                pattern,
+                count,
+            } => Command::SCAN {
+                cursor,
+                pattern: prefixed(pattern),
+                count,
+            },
+            Command::LPOS {
+                key,
+                element,
+                rank,
+                count,
+                maxlen,
+            } => Command::LPOS {
+                key: prefixed(key),
+                element,
+                rank,
+                count,
+                maxlen,
+            },
+            Command::PEXPIRE { key, milliseconds } => Command::PEXPIRE {
+                key: prefixed(key),
+                milliseconds,
+            },
+            Command::PEXPIRETIME { key } => Command::PEXPIRETIME { key: prefixed(key) },
+            Command::MSETNX { pairs } => Command::MSETNX {
+                pairs: pairs
+                    .into_iter()
+                    .map(|(key, value)| (prefixed(key), value))
+                    .collect(),
+            },
+            Command::SORT {
+                key,
+                options,
+                store,
+            } => Command::SORT {
+                key: prefixed(key),
+                options: options.prefix_patterns(prefixed),
+                store: store.map(prefixed),
+            },
+            Command::TTL { key } => Command::TTL { key: prefixed(key) },
+            Command::TYPE { key } => Command::TYPE { key: prefixed(key) },
+            Command::UNWATCH { key, field } => Command::UNWATCH {
+                key: prefixed(key),
+                field,
+            },
+            command @ (Command::AUTH { .. }
+            | Command::CONFIGGET { .. }
+            | Command::CONFIGSET { .. }
+            | Command::ECHO { .. }
+            | Command::FLUSHDB
+            | Command::HANDSHAKE { .. }
+            | Command::INFO
+            | Command::INTERCEPTED { .. }
+            | Command::PING
+            | Command::TIME) => command,
+        }
+    }
+
+    /// Rejects arguments the server cannot store meaningfully, which are floats that are NaN or
+    /// infinite.
+    pub(crate) fn check_args(&self) -> Result<(), CommandError> {
+        match self {
+            Command::SET {
+                value: SetInput::Float(value),
+                ..
+            } if !value.is_finite() => Err(CommandError::NonFiniteFloat(*value)),
+            Command::MSETNX { pairs } => match pairs.iter().find_map(|(_, value)| match value {
+                SetInput::Float(value) if !value.is_finite() => Some(*value),
+                _ => None,
+            }) {
+                Some(value) => Err(CommandError::NonFiniteFloat(value)),
+                None => Ok(()),
+            },
+            Command::INTERCEPTED { command, .. } => command.check_args(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns true if repeating the command has the same effect and reply as running it once,
+    /// so that it can be retried safely when the outcome of an attempt is unknown.
+    pub(crate) fn is_idempotent(&self) -> bool {
+        match self {
+            Command::CONFIGGET { .. }
+            | Command::CONFIGSET { .. }
+            | Command::ECHO { .. }
+            | Command::EXISTS { .. }
+            | Command::EXPIRETIME { .. }
+            | Command::GET { .. }
+            | Command::HGET { .. }
+            | Command::HGETALL { .. }
+            | Command::INFO
+            | Command::KEYS { .. }
+            | Command::LPOS { .. }
+            | Command::PEXPIRETIME { .. }
+            | Command::PING
+            | Command::SCAN { .. }
+            | Command::SORT { .. }
+            | Command::TIME
+            | Command::TTL { .. }
+            | Command::TYPE { .. } => true,
+            Command::SET {
+                option, get, nx, ..
+            } => *option != SetOption::NX && !get && !nx,
+            Command::INTERCEPTED { command, .. } => command.is_idempotent(),
+            Command::AUTH { .. }
+            | Command::DECR { .. }
+            | Command::DECRBY { .. }
+            | Command::DEL { .. }
+            | Command::EXPIRE { .. }
+            | Command::EXPIREAT { .. }
+            | Command::FLUSHDB
+            | Command::GETDEL { .. }
+            | Command::GETEX { .. }
+            | Command::GETWATCH { .. }
+            | Command::HANDSHAKE { .. }
+            | Command::HGETWATCH { .. }
+            | Command::HDEL { .. }
+            | Command::HSET { .. }
+            | Command::INCR { .. }
+            | Command::INCRBY { .. }
+            | Command::MSETNX { .. }
+            | Command::PEXPIRE { .. }
+            | Command::UNWATCH { .. } => false,
+        }
+    }
+
+    /// Returns true if the command changes data on the server, so a read-only client rejects it.
+    /// Connection setup and watching are not writes.
+    pub(crate) fn is_write(&self) -> bool {
+        match self {
+            Command::CONFIGSET { .. }
+            | Command::DECR { .. }
+            | Command::DECRBY { .. }
+            | Command::DEL { .. }
+            | Command::EXPIRE { .. }
+            | Command::EXPIREAT { .. }
+            | Command::FLUSHDB
+            | Command::GETDEL { .. }
+            | Command::GETEX { .. }
+            | Command::HDEL { .. }
+            | Command::HSET { .. }
+            | Command::INCR { .. }
+            | Command::INCRBY { .. }
+            | Command::MSETNX { .. }
+            | Command::PEXPIRE { .. }
+            | Command::SET { .. } => true,
+            Command::SORT { store, .. } => store.is_some(),
+            Command::AUTH { .. }
+            | Command::CONFIGGET { .. }
+            | Command::ECHO { .. }
+            | Command::EXISTS { .. }
+            | Command::EXPIRETIME { .. }
+            | Command::GET { .. }
+            | Command::GETWATCH { .. }
+            | Command::HANDSHAKE { .. }
+            | Command::HGET { .. }
+            | Command::HGETALL { .. }
+            | Command::HGETWATCH { .. }
+            | Command::INFO
+            | Command::KEYS { .. }
+            | Command::LPOS { .. }
+            | Command::PEXPIRETIME { .. }
+            | Command::PING
+            | Command::SCAN { .. }
+            | Command::TIME
+            | Command::TTL { .. }
+            | Command::TYPE { .. }
+            | Command::UNWATCH { .. } => false,
+            Command::INTERCEPTED { descriptor, .. } => descriptor.is_write(),
+        }
+    }
+
+    /// The name of the command as sent to the server.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Command::AUTH { .. } => "AUTH",
+            Command::CONFIGGET { .. } | Command::CONFIGSET { .. } => "CONFIG",
+            Command::DECR { .. } => "DECR",
+            Command::DECRBY { .. } => "DECRBY",
+            Command::DEL { .. } => "DEL",
+            Command::ECHO { .. } => "ECHO",
+            Command::EXISTS { .. } => "EXISTS",
+            Command::EXPIRE { .. } => "EXPIRE",
+            Command::EXPIREAT { .. } => "EXPIREAT",
+            Command::EXPIRETIME { .. } => "EXPIRETIME",
+            Command::FLUSHDB => "FLUSHDB",
+            Command::GET { .. } => "GET",
+            Command::GETWATCH { .. } => "GET.WATCH",
+            Command::GETDEL { .. } => "GETDEL",
+            Command::GETEX { .. } => "GETEX",
+            Command::HANDSHAKE { .. } => "HANDSHAKE",
+            Command::HGET { .. } => "HGET",
+            Command::HGETALL { .. } => "HGETALL",
+            Command::HGETWATCH { .. } => "HGET.WATCH",
+            Command::HDEL { .. } => "HDEL",
+            Command::HSET { .. } => "HSET",
+            Command::INCR { .. } => "INCR",
+            Command::INCRBY { .. } => "INCRBY",
+            Command::INFO => "INFO",
+            Command::KEYS { .. } => "KEYS",
+            Command::LPOS { .. } => "LPOS",
+            Command::MSETNX { .. } => "MSETNX",
+            Command::PEXPIRE { .. } => "PEXPIRE",
+            Command::PEXPIRETIME { .. } => "PEXPIRETIME",
+            Command::PING => "PING",
+            Command::SCAN { .. } => "SCAN",
+            Command::SET { .. } => "SET",
+            Command::SORT { .. } => "SORT",
+            Command::TIME => "TIME",
+            Command::TTL { .. } => "TTL",
+            Command::TYPE { .. } => "TYPE",
+            Command::UNWATCH { .. } => "UNWATCH",
+            Command::INTERCEPTED { command, .. } => command.name(),
+        }
+    }
+
+    /// Sends the command as `descriptor`, which an interceptor rewrote it to. The reply is still
+    /// read as the reply of the original command.
+    pub(crate) fn intercepted(self, descriptor: CommandDescriptor) -> Command {
+        Command::INTERCEPTED {
+            command: Box::new(self),
+            descriptor,
+        }
+    }
+
+    /// Converts the command for the wire, so its size is known before it is encoded.
+    pub(crate) fn into_wire(self) -> WireCommand {
+        log::trace!(target: LOG_TARGET, "Encoding {:?}", self);
+        let name = self.name();
+        let command: wire::Command = self.into();
+        WireCommand {
+            name,
+            len: command.encoded_len(),
+            command,
+        }
+    }
+}
+
+/// A command converted for the wire, see [`Command::into_wire`].
+pub(crate) struct WireCommand {
+    name: &'static str,
+    command: wire::Command,
+    /// The size of the encoded command in bytes.
+    pub(crate) len: usize,
+}
+
+impl WireCommand {
+    /// Appends the encoded command to `buffer`, so a buffer can be reused for every command.
+    pub(crate) fn encode_into(self, buffer: &mut Vec<u8>) {
+        buffer.reserve(self.len);
+        self.command.encode_raw(buffer);
+        log::debug!(
+            target: LOG_TARGET,
+            "Encoded {} command of {} bytes",
+            self.name,
+            self.len
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use proptest::{collection, prelude::any, prop_assert, prop_assert_eq, proptest};
+
+    use super::*;
+
+    #[test]
+    fn test_try_into() {
+        let v: ScalarValue = ScalarValue::VInt(42);
         let v_setval: SetInput = v.try_into().unwrap();
         assert_eq!(v_setval, SetInput::Int(42));
         let v: ScalarValue = ScalarValue::VStr("42".to_string());
@@ -778,4 +2570,1159 @@ mod tests {
         let value = ScalarValue::VBool(true);
         assert_eq!(format!("{}", value), "true");
     }
+
+    #[test]
+    fn test_decode_trailing_data() {
+        let first = encode_reply(&ScalarValue::VStr("first".to_string()));
+        let second = encode_reply(&ScalarValue::VInt(2));
+        let mut both = first.clone();
+        both.extend(&second);
+        match ScalarValue::decode(&both) {
+            Err(CommandError::TrailingData { remaining }) => assert_eq!(remaining, second.len()),
+            other => panic!("expected TrailingData, got {:?}", other),
+        }
+        // Both replies are decoded once split at the end of the first.
+        let len = both.len() - second.len();
+        assert_eq!(
+            ScalarValue::decode(&both[..len]).unwrap(),
+            ScalarValue::VStr("first".to_string())
+        );
+        assert_eq!(
+            ScalarValue::decode(&both[len..]).unwrap(),
+            ScalarValue::VInt(2)
+        );
+        let mut watch = encode_watch_reply(&ScalarValue::VInt(1), "fingerprint");
+        watch.extend(&second);
+        assert!(matches!(
+            WatchValue::decode_watchvalue(&watch),
+            Err(CommandError::TrailingData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_with_attrs() {
+        use prost_types::value::Kind;
+
+        let value = |kind: Kind| prost_types::Value { kind: Some(kind) };
+        let nested = prost_types::Struct {
+            fields: [("flag".to_string(), value(Kind::BoolValue(true)))].into(),
+        };
+        let list = prost_types::ListValue {
+            values: vec![
+                value(Kind::NullValue(0)),
+                value(Kind::StringValue("x".to_string())),
+            ],
+        };
+        let response = wire::Response {
+            value: Some(wire::response::Value::VStr("value".to_string())),
+            attrs: Some(prost_types::Struct {
+                fields: [
+                    (
+                        "fingerprint".to_string(),
+                        value(Kind::StringValue("123".to_string())),
+                    ),
+                    ("count".to_string(), value(Kind::NumberValue(2.0))),
+                    ("nested".to_string(), value(Kind::StructValue(nested))),
+                    ("list".to_string(), value(Kind::ListValue(list))),
+                ]
+                .into(),
+            }),
+            ..Default::default()
+        };
+        let (decoded, attrs) = ScalarValue::decode_with_attrs(&response.encode_to_vec()).unwrap();
+        assert_eq!(decoded, ScalarValue::VStr("value".to_string()));
+        assert_eq!(attrs.len(), 4);
+        assert_eq!(attrs["fingerprint"].as_str(), Some("123"));
+        assert_eq!(attrs["count"], AttrValue::Number(2.0));
+        assert_eq!(
+            attrs["nested"],
+            AttrValue::Map([("flag".to_string(), AttrValue::Bool(true))].into())
+        );
+        assert_eq!(
+            attrs["list"],
+            AttrValue::List(vec![AttrValue::Null, AttrValue::String("x".to_string())])
+        );
+
+        let (_, attrs) =
+            ScalarValue::decode_with_attrs(&encode_reply(&ScalarValue::VInt(1))).unwrap();
+        assert!(attrs.is_empty());
+    }
+
+    #[test]
+    fn test_bytes_reply_is_lossless() {
+        let binary = vec![b'a', 0, 255, b'b'];
+        let value: ScalarValue = wire::response::Value::VBytes(binary.clone()).into();
+        assert_eq!(value, ScalarValue::VBytes(binary));
+        assert_eq!(value.to_string_lossy(), "a\0\u{FFFD}b");
+        let text = ScalarValue::VBytes(b"text".to_vec());
+        assert!(matches!(text.to_string_lossy(), Cow::Borrowed("text")));
+        let value = ScalarValue::VStr("text".to_string());
+        assert!(matches!(value.to_string_lossy(), Cow::Borrowed("text")));
+        assert_eq!(ScalarValue::VInt(1).to_string_lossy(), "1");
+    }
+
+    #[test]
+    fn test_bytes_arg_round_trip() {
+        let utf8 = b"plain text".to_vec();
+        assert_eq!(encode_bytes_arg(&utf8), "plain text");
+        assert_eq!(
+            decode_bytes_value(encode_bytes_arg(&utf8).into_bytes()),
+            utf8
+        );
+
+        let binary = vec![0, 159, 146, 150, 255, 0];
+        let encoded = encode_bytes_arg(&binary);
+        assert!(encoded.starts_with(BYTES_MARKER));
+        assert_eq!(decode_bytes_value(encoded.into_bytes()), binary);
+
+        let looks_like_marker = format!("{}not base64", BYTES_MARKER).into_bytes();
+        let encoded = encode_bytes_arg(&looks_like_marker);
+        assert_ne!(encoded.as_bytes(), looks_like_marker.as_slice());
+        assert_eq!(decode_bytes_value(encoded.into_bytes()), looks_like_marker);
+    }
+
+    fn hset_value() -> HSetValue {
+        let mut fields = HashMap::new();
+        fields.insert("name".to_string(), "dice".to_string());
+        fields.insert("count".to_string(), "42".to_string());
+        HSetValue { fields }
+    }
+
+    #[test]
+    fn test_hset_value_get() {
+        let value = hset_value();
+        assert_eq!(value.get("name"), Some("dice"));
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn test_hset_value_get_as() {
+        let value = hset_value();
+        assert_eq!(value.get_as::<i64>("count"), Ok(Some(42)));
+        assert_eq!(value.get_as::<i64>("missing"), Ok(None));
+        assert!(value.get_as::<i64>("name").is_err());
+    }
+
+    #[test]
+    fn test_hset_value_len_and_contains() {
+        let value = hset_value();
+        assert_eq!(value.len(), 2);
+        assert!(!value.is_empty());
+        assert!(value.contains_field("name"));
+        assert!(!value.contains_field("missing"));
+        let empty = HSetValue {
+            fields: HashMap::new(),
+        };
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_hset_value_iter() {
+        let value = hset_value();
+        let mut borrowed: Vec<(&String, &String)> = value.iter().collect();
+        borrowed.sort();
+        assert_eq!(borrowed.len(), 2);
+        assert_eq!(borrowed[0].0, "count");
+        let mut by_ref = 0;
+        for (_, _) in &value {
+            by_ref += 1;
+        }
+        assert_eq!(by_ref, 2);
+        let mut owned: Vec<(String, String)> = value.into_iter().collect();
+        owned.sort();
+        assert_eq!(
+            owned,
+            vec![
+                ("count".to_string(), "42".to_string()),
+                ("name".to_string(), "dice".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hset_value_into_btreemap() {
+        let map: BTreeMap<String, String> = hset_value().into();
+        let keys: Vec<&String> = map.keys().collect();
+        assert_eq!(keys, vec!["count", "name"]);
+    }
+
+    #[test]
+    fn test_dice_type_from_str() {
+        let cases = vec![
+            ("string", DiceType::Str),
+            ("int", DiceType::Int),
+            ("float", DiceType::Float),
+            ("bool", DiceType::Bool),
+            ("bytes", DiceType::Bytes),
+            ("hash", DiceType::Hash),
+            ("list", DiceType::List),
+            ("set", DiceType::Set),
+            ("zset", DiceType::SortedSet),
+            ("none", DiceType::None),
+            ("stream", DiceType::Other("stream".to_string())),
+        ];
+        for (name, expected) in cases {
+            let parsed: DiceType = name.parse().unwrap();
+            assert_eq!(parsed, expected);
+            assert_eq!(parsed.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_ttl_from_seconds_reply() {
+        assert_eq!(
+            Ttl::from_seconds_reply(ScalarValue::VInt(-2)).unwrap(),
+            Ttl::Missing
+        );
+        assert_eq!(
+            Ttl::from_seconds_reply(ScalarValue::VInt(-1)).unwrap(),
+            Ttl::NoExpiry
+        );
+        assert_eq!(
+            Ttl::from_seconds_reply(ScalarValue::VInt(0)).unwrap(),
+            Ttl::Expires(Duration::ZERO)
+        );
+        assert_eq!(
+            Ttl::from_seconds_reply(ScalarValue::VInt(30)).unwrap(),
+            Ttl::Expires(Duration::from_secs(30))
+        );
+        assert!(Ttl::from_seconds_reply(ScalarValue::VInt(-3)).is_err());
+        assert!(Ttl::from_seconds_reply(ScalarValue::VNull).is_err());
+    }
+
+    #[test]
+    fn test_server_time_from_reply() {
+        let reply = |values: Vec<ScalarValue>| server_time_from_reply(ListValue(values));
+        let expected = UNIX_EPOCH + Duration::new(1_700_000_000, 250_000_000);
+        assert_eq!(
+            reply(vec![
+                ScalarValue::VInt(1_700_000_000),
+                ScalarValue::VInt(250_000)
+            ])
+            .unwrap(),
+            expected
+        );
+        assert_eq!(
+            reply(vec![
+                ScalarValue::VStr("1700000000".to_string()),
+                ScalarValue::VStr("250000".to_string())
+            ])
+            .unwrap(),
+            expected
+        );
+        assert_eq!(
+            reply(vec![ScalarValue::VInt(0), ScalarValue::VInt(0)]).unwrap(),
+            UNIX_EPOCH
+        );
+        assert!(matches!(
+            reply(vec![ScalarValue::VInt(1_700_000_000)]),
+            Err(CommandError::UnexpectedShape { .. })
+        ));
+        for invalid in [
+            vec![ScalarValue::VInt(-1), ScalarValue::VInt(0)],
+            vec![ScalarValue::VInt(0), ScalarValue::VInt(1_000_000)],
+            vec![ScalarValue::VStr("now".to_string()), ScalarValue::VInt(0)],
+            vec![ScalarValue::VInt(0), ScalarValue::VNull],
+        ] {
+            assert!(matches!(
+                reply(invalid),
+                Err(CommandError::UnexpectedResponse(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let local = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let ahead = ClockSkew::between(local + Duration::from_millis(1500), local);
+        assert_eq!(ahead, ClockSkew::Ahead(Duration::from_millis(1500)));
+        assert_eq!(ahead.as_secs_f64(), 1.5);
+        let behind = ClockSkew::between(local - Duration::from_secs(2), local);
+        assert_eq!(behind, ClockSkew::Behind(Duration::from_secs(2)));
+        assert_eq!(behind.as_secs_f64(), -2.0);
+        assert_eq!(behind.magnitude(), Duration::from_secs(2));
+        assert_eq!(
+            ClockSkew::between(local, local),
+            ClockSkew::Ahead(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_expiry_info_from_reply() {
+        for from_reply in [
+            ExpiryInfo::from_seconds_reply,
+            ExpiryInfo::from_millis_reply,
+        ] {
+            assert_eq!(
+                from_reply(ScalarValue::VInt(-2)).unwrap(),
+                ExpiryInfo::Missing
+            );
+            assert_eq!(
+                from_reply(ScalarValue::VInt(-1)).unwrap(),
+                ExpiryInfo::NoExpiry
+            );
+            assert_eq!(
+                from_reply(ScalarValue::VInt(0)).unwrap(),
+                ExpiryInfo::Expires(UNIX_EPOCH)
+            );
+            assert!(from_reply(ScalarValue::VInt(-3)).is_err());
+            assert!(from_reply(ScalarValue::VStr("1".to_string())).is_err());
+        }
+        assert_eq!(
+            ExpiryInfo::from_seconds_reply(ScalarValue::VInt(1_700_000_000)).unwrap(),
+            ExpiryInfo::Expires(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(
+            ExpiryInfo::from_millis_reply(ScalarValue::VInt(1_700_000_000_250)).unwrap(),
+            ExpiryInfo::Expires(UNIX_EPOCH + Duration::from_millis(1_700_000_000_250))
+        );
+    }
+
+    #[test]
+    fn test_duration_to_seconds() {
+        assert_eq!(duration_to_seconds(Duration::from_secs(0)).unwrap(), 0);
+        assert_eq!(duration_to_seconds(Duration::from_secs(3)).unwrap(), 3);
+        assert_eq!(duration_to_seconds(Duration::from_millis(1500)).unwrap(), 2);
+        assert_eq!(duration_to_seconds(Duration::from_nanos(1)).unwrap(), 1);
+        assert!(duration_to_seconds(Duration::MAX).is_err());
+    }
+
+    #[test]
+    fn test_expire_at_timestamp_from_system_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let timestamp = ExpireAtTimestamp::try_from(time).unwrap();
+        assert_eq!(timestamp.as_secs(), 1_700_000_000);
+        assert_eq!(timestamp.as_millis(), 1_700_000_000_000);
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_001);
+        let timestamp = ExpireAtTimestamp::try_from(time).unwrap();
+        assert_eq!(timestamp.as_secs(), 1_700_000_001);
+        assert_eq!(
+            GetexOption::from(timestamp),
+            GetexOption::PXAT(1_700_000_000_001)
+        );
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert!(matches!(
+            ExpireAtTimestamp::try_from(before_epoch),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn test_expire_at_timestamp_from_chrono() {
+        use chrono::{TimeZone, Utc};
+        let time = Utc.timestamp_opt(1_700_000_000, 500_000_000).unwrap();
+        let timestamp = ExpireAtTimestamp::try_from(time).unwrap();
+        assert_eq!(timestamp.as_secs(), 1_700_000_001);
+        assert_eq!(timestamp.as_millis(), 1_700_000_000_500);
+        let before_epoch = Utc.timestamp_opt(-1, 0).unwrap();
+        assert!(matches!(
+            ExpireAtTimestamp::try_from(before_epoch),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "time")]
+    fn test_expire_at_timestamp_from_time() {
+        let time = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let timestamp = ExpireAtTimestamp::try_from(time).unwrap();
+        assert_eq!(timestamp.as_secs(), 1_700_000_000);
+        let before_epoch = time::OffsetDateTime::from_unix_timestamp(-1).unwrap();
+        assert!(matches!(
+            ExpireAtTimestamp::try_from(before_epoch),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_scalar_value_accessors() {
+        let values = [
+            ScalarValue::VStr("text".to_string()),
+            ScalarValue::VInt(42),
+            ScalarValue::VFloat(1.5),
+            ScalarValue::VBool(true),
+            ScalarValue::VBytes(vec![1, 2, 3]),
+            ScalarValue::VNull,
+        ];
+        let expected: [(Option<&str>, Option<i64>, Option<f64>, Option<bool>, bool); 6] = [
+            (Some("text"), None, None, None, false),
+            (None, Some(42), Some(42.0), None, false),
+            (None, None, Some(1.5), None, false),
+            (None, None, None, Some(true), false),
+            (None, None, None, None, false),
+            (None, None, None, None, true),
+        ];
+        for (value, (as_str, as_i64, as_f64, as_bool, is_null)) in values.into_iter().zip(expected)
+        {
+            assert_eq!(value.as_str(), as_str, "as_str on {:?}", value);
+            assert_eq!(value.as_i64(), as_i64, "as_i64 on {:?}", value);
+            assert_eq!(value.as_f64(), as_f64, "as_f64 on {:?}", value);
+            assert_eq!(value.as_bool(), as_bool, "as_bool on {:?}", value);
+            assert_eq!(value.is_null(), is_null, "is_null on {:?}", value);
+            assert_eq!(
+                value.clone().into_string(),
+                as_str.map(str::to_string),
+                "into_string on {:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefix_keys() {
+        let args = |command: Command| -> Vec<String> {
+            let command: wire::Command = command.into();
+            command.args
+        };
+        let command = Command::DEL {
+            keys: vec!["a".to_string(), "b".to_string()],
+        };
+        assert_eq!(args(command.prefix_keys("ns")), vec!["ns:a", "ns:b"]);
+        let command = Command::EXISTS {
+            key: "a".to_string(),
+            additional_keys: vec!["b".to_string()],
+        };
+        assert_eq!(args(command.prefix_keys("ns")), vec!["ns:a", "ns:b"]);
+        let command = Command::HGET {
+            key: "a".to_string(),
+            field: "f".to_string(),
+        };
+        assert_eq!(args(command.prefix_keys("ns")), vec!["ns:a", "f"]);
+        let command = Command::MSETNX {
+            pairs: vec![
+                ("a".to_string(), SetInput::Int(1)),
+                ("b".to_string(), SetInput::Str("x".to_string())),
+            ],
+        };
+        assert_eq!(
+            args(command.prefix_keys("ns")),
+            vec!["ns:a", "1", "ns:b", "x"]
+        );
+        let command = Command::SCAN {
+            cursor: 7,
+            pattern: "a*".to_string(),
+            count: Some(10),
+        };
+        assert_eq!(
+            args(command.prefix_keys("ns")),
+            vec!["7", "MATCH", "ns:a*", "COUNT", "10"]
+        );
+        let command = Command::ECHO {
+            message: "a".to_string(),
+        };
+        assert_eq!(args(command.prefix_keys("ns")), vec!["a"]);
+    }
+
+    #[test]
+    fn test_sort_options_args() {
+        assert!(SortOptions::new().as_args().is_empty());
+        assert_eq!(
+            SortOptions::new().alpha().desc().as_args(),
+            ["DESC", "ALPHA"]
+        );
+        assert!(SortOptions::new().desc().asc().as_args().is_empty());
+        // The arguments follow the order of the SORT syntax, whatever order they were set in.
+        let options = SortOptions::new()
+            .alpha()
+            .get("#")
+            .desc()
+            .limit(5, 10)
+            .get("name_*")
+            .by("weight_*");
+        assert_eq!(
+            options.as_args(),
+            ["BY", "weight_*", "LIMIT", "5", "10", "GET", "#", "GET", "name_*", "DESC", "ALPHA"]
+        );
+        let command: wire::Command = Command::SORT {
+            key: "list".to_string(),
+            options: SortOptions::new().limit(0, 3),
+            store: Some("sorted".to_string()),
+        }
+        .into();
+        assert_eq!(command.args, ["list", "LIMIT", "0", "3", "STORE", "sorted"]);
+    }
+
+    #[test]
+    fn test_sort_prefix_keys() {
+        let command = Command::SORT {
+            key: "list".to_string(),
+            options: SortOptions::new().by("nosort").get("#").get("name_*"),
+            store: Some("sorted".to_string()),
+        };
+        let command: wire::Command = command.prefix_keys("ns").into();
+        assert_eq!(
+            command.args,
+            [
+                "ns:list",
+                "BY",
+                "nosort",
+                "GET",
+                "#",
+                "GET",
+                "ns:name_*",
+                "STORE",
+                "ns:sorted"
+            ]
+        );
+        let command = Command::SORT {
+            key: "list".to_string(),
+            options: SortOptions::new().by("weight_*"),
+            store: None,
+        };
+        let command: wire::Command = command.prefix_keys("ns").into();
+        assert_eq!(command.args, ["ns:list", "BY", "ns:weight_*"]);
+    }
+
+    #[test]
+    fn test_exists_args_round_trip() {
+        let round_trip = |key: &str, additional_keys: Vec<String>| {
+            let mut buffer = Vec::new();
+            Command::EXISTS {
+                key: key.to_string(),
+                additional_keys,
+            }
+            .into_wire()
+            .encode_into(&mut buffer);
+            decode_command(&buffer).unwrap()
+        };
+        let key = "test_ilegal_key_exists";
+        let (name, args) = round_trip(key, vec![key.to_string(), key.to_string()]);
+        assert_eq!(name, "EXISTS");
+        assert_eq!(args, vec![key, key, key]);
+        let keys = vec!["with space".to_string(), "with_underscore".to_string()];
+        let (_, args) = round_trip("a b_c", keys);
+        assert_eq!(args, vec!["a b_c", "with space", "with_underscore"]);
+        let keys: Vec<String> = (0..1000).map(|i| format!("key_{}", i)).collect();
+        let (_, args) = round_trip("key", keys.clone());
+        assert_eq!(args.len(), 1001);
+        assert_eq!(args[1..], keys[..]);
+    }
+
+    #[test]
+    fn test_convert_value() {
+        assert_eq!(
+            convert_value::<String>("k", ScalarValue::VStr("a".to_string())).unwrap(),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            convert_value::<String>("k", ScalarValue::VInt(3)).unwrap(),
+            Some("3".to_string())
+        );
+        assert_eq!(
+            convert_value::<i64>("k", ScalarValue::VInt(3)).unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            convert_value::<i64>("k", ScalarValue::VStr("-4".to_string())).unwrap(),
+            Some(-4)
+        );
+        assert_eq!(
+            convert_value::<f64>("k", ScalarValue::VInt(2)).unwrap(),
+            Some(2.0)
+        );
+        assert_eq!(
+            convert_value::<bool>("k", ScalarValue::VStr("true".to_string())).unwrap(),
+            Some(true)
+        );
+        assert_eq!(convert_value::<i64>("k", ScalarValue::VNull).unwrap(), None);
+        assert!(matches!(
+            convert_value::<u8>("k", ScalarValue::VInt(256)),
+            Err(CommandError::ConversionError { .. })
+        ));
+        match convert_value::<i64>("k", ScalarValue::VStr("abc".to_string())) {
+            Err(CommandError::ConversionError { key, value, target }) => {
+                assert_eq!(key, "k");
+                assert_eq!(value, ScalarValue::VStr("abc".to_string()));
+                assert_eq!(target, "i64");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_server_version() {
+        let cases = [
+            ("1.0.2", ServerVersion::new(1, 0, 2)),
+            ("v0.1.0", ServerVersion::new(0, 1, 0)),
+            ("1.2", ServerVersion::new(1, 2, 0)),
+            ("2", ServerVersion::new(2, 0, 0)),
+            ("1.0.0-rc1", ServerVersion::new(1, 0, 0)),
+            (" 0.0.5\r\n", ServerVersion::new(0, 0, 5)),
+        ];
+        for (s, version) in cases {
+            assert_eq!(s.parse::<ServerVersion>().unwrap(), version, "{:?}", s);
+        }
+        for s in ["", "dicedb", "1.2.3.4", "1..2"] {
+            assert!(s.parse::<ServerVersion>().is_err(), "{:?}", s);
+        }
+        assert!(ServerVersion::new(0, 9, 9) < ServerVersion::new(1, 0, 0));
+        assert_eq!(ServerVersion::new(1, 0, 2).to_string(), "1.0.2");
+    }
+
+    #[test]
+    fn test_server_version_from_info() {
+        let info = "# Server\r\ndicedb_version:1.0.2\r\nuptime_in_seconds:10\r\n";
+        assert_eq!(
+            ServerVersion::from_info(info),
+            Some(ServerVersion::new(1, 0, 2))
+        );
+        assert_eq!(
+            ServerVersion::from_info("version: v1.1.0"),
+            Some(ServerVersion::new(1, 1, 0))
+        );
+        assert_eq!(
+            ServerVersion::from_info("1.0.0"),
+            Some(ServerVersion::new(1, 0, 0))
+        );
+        assert_eq!(ServerVersion::from_info("uptime_in_seconds:10"), None);
+    }
+
+    /// Feeds `bytes` to every reply decoder, none of which may panic.
+    fn decode_all(bytes: &[u8]) -> [bool; 4] {
+        [
+            ScalarValue::decode(bytes).is_ok(),
+            MapValue::decode(bytes).is_ok(),
+            ListValue::decode(bytes).is_ok(),
+            WatchValue::decode_watchvalue(bytes).is_ok(),
+        ]
+    }
+
+    #[test]
+    fn test_format_float() {
+        let cases = [
+            (1.0, "1.0"),
+            (-0.0, "-0.0"),
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (1e-7, "0.0000001"),
+            (1e-8, "1e-8"),
+            (1e20, "100000000000000000000.0"),
+            (1e21, "1e21"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            (5e-324, "5e-324"),
+        ];
+        for (value, text) in cases {
+            assert_eq!(format_float(value), text);
+            assert_eq!(SetInput::Float(value).as_arg(), text);
+        }
+    }
+
+    #[test]
+    fn test_non_finite_float_rejected() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let command = Command::SET {
+                key: "key".to_string(),
+                value: SetInput::Float(value),
+                option: SetOption::None,
+                get: false,
+                nx: false,
+            };
+            assert!(matches!(
+                command.check_args(),
+                Err(CommandError::NonFiniteFloat(v)) if v.to_bits() == value.to_bits()
+            ));
+            let command = Command::MSETNX {
+                pairs: vec![
+                    ("a".to_string(), SetInput::Float(1.5)),
+                    ("b".to_string(), SetInput::Float(value)),
+                ],
+            };
+            assert!(matches!(
+                command.check_args(),
+                Err(CommandError::NonFiniteFloat(v)) if v.to_bits() == value.to_bits()
+            ));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_format_float_round_trips(bits in any::<u64>()) {
+            let value = f64::from_bits(bits);
+            if value.is_finite() {
+                let text = format_float(value);
+                prop_assert_eq!(text.parse::<f64>().unwrap().to_bits(), bits);
+                prop_assert!(text.parse::<i64>().is_err());
+                // At most 17 significant digits, a sign, a point and the zeros before 1e-7.
+                prop_assert!(text.len() <= 26, "{} is not the shortest form", text);
+            }
+        }
+
+        #[test]
+        fn test_decode_random_bytes(bytes in collection::vec(any::<u8>(), 0..512)) {
+            decode_all(&bytes);
+            if let Some(len) = framing::message_len(&bytes) {
+                prop_assert!(len <= bytes.len());
+            }
+            prop_assert!(framing::needed_len(&bytes) > 0);
+        }
+
+        #[test]
+        fn test_decode_truncated_reply(
+            value in ".{0,200}",
+            fingerprint in "[a-z0-9]{1,20}",
+            cut in 0.0..1.0,
+        ) {
+            let reply = encode_watch_reply(&ScalarValue::VStr(value), &fingerprint);
+            let end = (reply.len() as f64 * cut) as usize;
+            // A reply cut within a field is incomplete and must not decode.
+            if framing::message_len(&reply[..end]).is_none() && end > 0 {
+                prop_assert_eq!(decode_all(&reply[..end]), [false; 4]);
+            } else {
+                decode_all(&reply[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_map() {
+        // The reply to HGETALL for a hash with one field, as the server sends it.
+        let hash = [
+            0x4a, 0x0c, 0x0a, 0x04, b'n', b'a', b'm', b'e', 0x12, 0x04, b'd', b'i', b'c', b'e',
+        ];
+        let map = MapValue::decode(&hash).unwrap().unwrap();
+        assert_eq!(map.get("name"), Some("dice"));
+        assert_eq!(map.len(), 1);
+
+        // An empty map has no fields at all, a nil reply sets the nil value.
+        let empty: [u8; 0] = [];
+        let nil = [0x10, 0x01];
+        assert_eq!(nil, encode_reply(&ScalarValue::VNull)[..]);
+        assert_eq!(MapValue::decode(&empty).unwrap(), Some(MapValue::default()));
+        assert_eq!(MapValue::decode(&nil).unwrap(), None);
+
+        assert!(matches!(
+            MapValue::decode(&encode_reply(&ScalarValue::VStr("OK".to_string()))),
+            Err(CommandError::UnexpectedResponse(ScalarValue::VStr(_)))
+        ));
+        let list = encode_list_reply(&[ScalarValue::VStr("name".to_string())]);
+        assert_eq!(
+            MapValue::decode(&list).unwrap_err().to_string(),
+            "expected a map reply, got a list"
+        );
+    }
+
+    #[test]
+    fn test_decode_list() {
+        // The reply to KEYS with two matching keys and to LRANGE over ["7", "b"], as the server
+        // sends them: numbers are doubles of google.protobuf.Value.
+        let keys = [
+            0x42, 0x07, 0x1a, 0x05, b'u', b's', b'e', b'r', b'1', //
+            0x42, 0x07, 0x1a, 0x05, b'u', b's', b'e', b'r', b'2',
+        ];
+        assert_eq!(
+            ListValue::decode(&keys).unwrap().to_strings(),
+            ["user1", "user2"]
+        );
+        let mut mixed = vec![0x42, 0x09, 0x11];
+        mixed.extend(7.0f64.to_le_bytes());
+        mixed.extend([0x42, 0x03, 0x1a, 0x01, b'b']);
+        assert_eq!(
+            ListValue::decode(&mixed).unwrap(),
+            ListValue(vec![
+                ScalarValue::VInt(7),
+                ScalarValue::VStr("b".to_string())
+            ])
+        );
+
+        let values = vec![
+            ScalarValue::VInt(-3),
+            ScalarValue::VFloat(2.5),
+            ScalarValue::VStr("value".to_string()),
+            ScalarValue::VBool(true),
+            ScalarValue::VNull,
+        ];
+        let list = ListValue::decode(&encode_list_reply(&values)).unwrap();
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.get(1), Some(&ScalarValue::VFloat(2.5)));
+        assert_eq!(Vec::from(list), values);
+        // Numbers beyond the exact range of a double stay floating point.
+        let large = encode_list_reply(&[ScalarValue::VFloat(1e300)]);
+        assert_eq!(
+            ListValue::decode(&large).unwrap().0,
+            [ScalarValue::VFloat(1e300)]
+        );
+    }
+
+    #[test]
+    fn test_decode_empty_list() {
+        assert_eq!(encode_list_reply(&[]), Vec::<u8>::new());
+        assert!(ListValue::decode(&encode_list_reply(&[]))
+            .unwrap()
+            .is_empty());
+        let nil = encode_reply(&ScalarValue::VNull);
+        assert!(ListValue::decode(&nil).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_list_of_other_shapes() {
+        assert!(matches!(
+            ListValue::decode(&encode_reply(&ScalarValue::VInt(1))),
+            Err(CommandError::UnexpectedResponse(ScalarValue::VInt(1)))
+        ));
+        let map = wire::Response {
+            v_ss_map: [("field".to_string(), "value".to_string())].into(),
+            ..Default::default()
+        };
+        let error = ListValue::decode(&map.encode_to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "expected a list reply, got a map");
+        let nested = wire::Response {
+            v_list: vec![prost_types::Value {
+                kind: Some(prost_types::value::Kind::ListValue(
+                    prost_types::ListValue::default(),
+                )),
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            ListValue::decode(&nested.encode_to_vec()),
+            Err(CommandError::UnexpectedShape {
+                found: "nested list",
+                ..
+            })
+        ));
+        assert!(matches!(
+            ListValue::decode(&encode_error_reply("WRONGTYPE not a list")),
+            Err(CommandError::ServerError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_watch_change_kind() {
+        let decode = |value: &ScalarValue| {
+            WatchValue::decode_watchvalue(&encode_watch_reply(value, "f")).unwrap()
+        };
+        let removed = decode(&ScalarValue::VNull);
+        assert_eq!(removed.change, ChangeKind::Removed);
+        assert_eq!(removed.value, ScalarValue::VNull);
+        // Placeholder values are updates, unlike the nil of a removed key.
+        for value in [
+            ScalarValue::VStr(String::new()),
+            ScalarValue::VStr("null".to_string()),
+            ScalarValue::VInt(0),
+        ] {
+            let updated = decode(&value);
+            assert_eq!(updated.change, ChangeKind::Updated);
+            assert_eq!(updated.value, value);
+        }
+    }
+
+    #[test]
+    fn test_decode_missing_fields() {
+        // An empty reply has neither a value nor an error.
+        assert_eq!(ScalarValue::decode(&[]).unwrap(), ScalarValue::VNull);
+        assert!(HSetValue::decode(&[]).unwrap().unwrap().is_empty());
+        let without_attrs = encode_reply(&ScalarValue::VInt(1));
+        assert!(matches!(
+            WatchValue::decode_watchvalue(&without_attrs),
+            Err(CommandError::WatchValueExpectationError(_))
+        ));
+        let without_value = wire::Response {
+            attrs: Some(prost_types::Struct {
+                fields: [(
+                    "fingerprint".to_string(),
+                    prost_types::Value {
+                        kind: Some(prost_types::value::Kind::StringValue("f".to_string())),
+                    },
+                )]
+                .into(),
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            WatchValue::decode_watchvalue(&without_value.encode_to_vec()),
+            Err(CommandError::WatchValueExpectationError(_))
+        ));
+        let fingerprint_without_kind = wire::Response {
+            value: Some(wire::response::Value::VInt(1)),
+            attrs: Some(prost_types::Struct {
+                fields: [("fingerprint".to_string(), prost_types::Value { kind: None })].into(),
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            WatchValue::decode_watchvalue(&fingerprint_without_kind.encode_to_vec()),
+            Err(CommandError::WatchValueExpectationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_unknown_values() {
+        // Field 15 is unknown to this client and skipped.
+        let mut reply = encode_reply(&ScalarValue::VInt(7));
+        reply.extend([0x78, 0x01]);
+        assert_eq!(ScalarValue::decode(&reply).unwrap(), ScalarValue::VInt(7));
+        // A null attribute with an unknown enum value.
+        let response = wire::Response {
+            value: Some(wire::response::Value::VInt(1)),
+            attrs: Some(prost_types::Struct {
+                fields: [(
+                    "odd".to_string(),
+                    prost_types::Value {
+                        kind: Some(prost_types::value::Kind::NullValue(99)),
+                    },
+                )]
+                .into(),
+            }),
+            ..Default::default()
+        };
+        let (_, attrs) = ScalarValue::decode_with_attrs(&response.encode_to_vec()).unwrap();
+        assert_eq!(attrs["odd"], AttrValue::Null);
+        // Wire type 7 does not exist.
+        assert_eq!(decode_all(&[0x0f, 0x01, 0x02]), [false; 4]);
+    }
+
+    #[test]
+    fn test_decode_large_map() {
+        let response = wire::Response {
+            v_ss_map: (0..100_000)
+                .map(|i| (format!("field{}", i), i.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        let bytes = response.encode_to_vec();
+        assert_eq!(HSetValue::decode(&bytes).unwrap().unwrap().len(), 100_000);
+        // A map entry declaring more bytes than the reply holds.
+        let mut truncated = bytes[..bytes.len() / 2].to_vec();
+        truncated.extend([0x4a, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(HSetValue::decode(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_is_idempotent() {
+        let set = |option, get| Command::SET {
+            key: "k".to_string(),
+            value: SetInput::Int(1),
+            option,
+            get,
+            nx: false,
+        };
+        assert!(Command::GET {
+            key: "k".to_string()
+        }
+        .is_idempotent());
+        assert!(Command::PING.is_idempotent());
+        assert!(set(SetOption::None, false).is_idempotent());
+        assert!(!set(SetOption::NX, false).is_idempotent());
+        assert!(!set(SetOption::None, true).is_idempotent());
+        assert!(!Command::SET {
+            key: "k".to_string(),
+            value: SetInput::Int(1),
+            option: SetOption::PX(100),
+            get: false,
+            nx: true,
+        }
+        .is_idempotent());
+        assert!(!Command::INCR {
+            key: "k".to_string()
+        }
+        .is_idempotent());
+        assert!(!Command::GETDEL {
+            key: "k".to_string()
+        }
+        .is_idempotent());
+    }
+
+    #[test]
+    fn test_is_write() {
+        let key = || "k".to_string();
+        let commands = [
+            Command::AUTH {
+                password: Secret("secret".to_string()),
+            },
+            Command::CONFIGGET { parameter: key() },
+            Command::CONFIGSET {
+                parameter: key(),
+                value: key(),
+            },
+            Command::DECR { key: key() },
+            Command::DECRBY {
+                key: key(),
+                delta: 1,
+            },
+            Command::DEL { keys: vec![key()] },
+            Command::ECHO { message: key() },
+            Command::EXISTS {
+                key: key(),
+                additional_keys: vec![],
+            },
+            Command::EXPIRE {
+                key: key(),
+                seconds: 1,
+                option: ExpireOption::None,
+            },
+            Command::EXPIREAT {
+                key: key(),
+                timestamp: 1,
+                option: ExpireAtOption::None,
+            },
+            Command::EXPIRETIME { key: key() },
+            Command::FLUSHDB,
+            Command::GET { key: key() },
+            Command::GETDEL { key: key() },
+            Command::GETEX {
+                key: key(),
+                ex: GetexOption::PERSIST,
+            },
+            Command::HDEL {
+                key: key(),
+                fields: vec![key()],
+            },
+            Command::HSET {
+                key: key(),
+                fields: vec![(key(), key())],
+            },
+            Command::HGET {
+                key: key(),
+                field: key(),
+            },
+            Command::HGETALL { key: key() },
+            Command::GETWATCH { key: key() },
+            Command::HGETWATCH {
+                key: key(),
+                field: key(),
+            },
+            Command::HANDSHAKE {
+                client_id: key(),
+                execution_mode: ExecutionMode::Command,
+            },
+            Command::INCR { key: key() },
+            Command::INFO,
+            Command::INCRBY {
+                key: key(),
+                delta: 1,
+            },
+            Command::PING,
+            Command::SCAN {
+                cursor: 0,
+                pattern: key(),
+                count: Some(10),
+            },
+            Command::SET {
+                key: key(),
+                value: SetInput::Int(1),
+                option: SetOption::None,
+                get: false,
+                nx: false,
+            },
+            Command::LPOS {
+                key: key(),
+                element: key(),
+                rank: None,
+                count: None,
+                maxlen: None,
+            },
+            Command::MSETNX {
+                pairs: vec![(key(), SetInput::Int(1))],
+            },
+            Command::PEXPIRE {
+                key: key(),
+                milliseconds: 1,
+            },
+            Command::SORT {
+                key: key(),
+                options: SortOptions::new(),
+                store: None,
+            },
+            Command::SORT {
+                key: key(),
+                options: SortOptions::new(),
+                store: Some(key()),
+            },
+            Command::TIME,
+            Command::TTL { key: key() },
+            Command::TYPE { key: key() },
+            Command::UNWATCH {
+                key: key(),
+                field: None,
+            },
+            Command::GET { key: key() }.intercepted(CommandDescriptor::new("DEL", vec![key()])),
+        ];
+        let writes: Vec<String> = commands
+            .iter()
+            .filter(|command| command.is_write())
+            .map(|command| CommandDescriptor::from(command.clone()).name().to_string())
+            .collect();
+        assert_eq!(
+            writes,
+            [
+                "CONFIG", "DECR", "DECRBY", "DEL", "EXPIRE", "EXPIREAT", "FLUSHDB", "GETDEL",
+                "GETEX", "HDEL", "HSET", "INCR", "INCRBY", "SET", "MSETNX", "PEXPIRE", "SORT",
+                "DEL"
+            ]
+        );
+        // Classifying a command by what is sent agrees with classifying it by its variant.
+        for command in commands {
+            assert_eq!(
+                CommandDescriptor::from(command.clone()).is_write(),
+                command.is_write(),
+                "{:?}",
+                command
+            );
+        }
+        assert!(CommandDescriptor::new("APPEND", vec![key(), key()]).is_write());
+        assert!(!CommandDescriptor::new("sort", vec!["store".to_string()]).is_write());
+    }
+
+    /// Records every log record, so tests can check what the client logs and at which level.
+    struct CapturingLogger;
+
+    static RECORDS: std::sync::Mutex<Vec<(log::Level, String, String)>> =
+        std::sync::Mutex::new(Vec::new());
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            RECORDS.lock().unwrap().push((
+                record.level(),
+                record.target().to_string(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_values_are_only_logged_at_trace() {
+        static LOGGER: CapturingLogger = CapturingLogger;
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let value = "logged-value-3f2a";
+        Command::SET {
+            key: "k".to_string(),
+            value: SetInput::Str(value.to_string()),
+            option: SetOption::None,
+            get: false,
+            nx: false,
+        }
+        .into_wire()
+        .encode_into(&mut Vec::new());
+        ScalarValue::decode(&encode_reply(&ScalarValue::VStr(value.to_string()))).unwrap();
+
+        let records = RECORDS.lock().unwrap();
+        let with_value = |level: log::Level| {
+            records
+                .iter()
+                .filter(|(l, target, message)| {
+                    *l == level && target == LOG_TARGET && message.contains(value)
+                })
+                .count()
+        };
+        assert_eq!(with_value(log::Level::Trace), 2);
+        assert_eq!(with_value(log::Level::Debug), 0);
+        assert_eq!(with_value(log::Level::Info), 0);
+        assert!(records.iter().any(|(level, target, message)| {
+            *level == log::Level::Debug && target == LOG_TARGET && message.contains("SET command")
+        }));
+        assert!(!records
+            .iter()
+            .any(|(level, _, message)| *level <= log::Level::Info && message.contains(value)));
+    }
 }
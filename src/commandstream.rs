@@ -1,63 +1,178 @@
-use std::io::{self, ErrorKind};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
 
 use uuid::Uuid;
 
 use crate::{
-    commands::{Command, CommandExecutor, ExecutionMode, ScalarValue},
+    commands::{Command, Execute, ExecutionMode},
+    config::{ConnectionOptions, ConnectionState},
     errors::{CommandStreamError, StreamError},
-    stream::Stream,
+    stats::Counters,
+    stream::{
+        bounded_handshake, check_handshake, conn_info, Authenticator, ReadBuffer, Reconnectable,
+        Stream, WriteBuffer,
+    },
+    transport::Transport,
+    LOG_TARGET,
 };
 
 #[derive(Debug)]
 pub(crate) struct CommandStream {
-    host: String,
-    port: u16,
+    options: ConnectionOptions,
     pub id: String,
-    pub stream: std::net::TcpStream,
+    pub stream: Transport,
+    state: ConnectionState,
+    read_buffer: ReadBuffer,
+    write_buffer: WriteBuffer,
+    /// The traffic counters, shared with the client so they can be read without locking.
+    pub(crate) counters: Arc<Counters>,
+    /// When a command was last executed by the client, used by the heartbeat to detect idleness.
+    pub(crate) last_used: Instant,
+}
+
+/// Locks a shared command stream. A panic while the stream was locked leaves it in a state the
+/// next command recovers from, so a poisoned lock is used as is.
+pub(crate) fn lock(stream: &Mutex<CommandStream>) -> MutexGuard<'_, CommandStream> {
+    stream.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 impl CommandStream {
-    pub(crate) fn new(host: String, port: u16) -> Result<Self, CommandStreamError> {
-        let stream = std::net::TcpStream::connect(format!("{}:{}", host, port))?;
-        let id = Uuid::new_v4().to_string();
+    pub(crate) fn new(options: ConnectionOptions) -> Result<Self, CommandStreamError> {
+        let stream = options.connect(&ExecutionMode::Command, None)?;
+        let id = options
+            .client_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let read_buffer = ReadBuffer::new(&options);
         Ok(CommandStream {
-            stream,
+            options,
             id,
-            host,
-            port,
+            stream,
+            state: ConnectionState::Healthy,
+            read_buffer,
+            write_buffer: WriteBuffer::default(),
+            counters: Arc::new(Counters::new()),
+            last_used: Instant::now(),
         })
     }
 }
 
+impl CommandStream {
+    /// The state of the connection, which is closed once the server closed it even if no read
+    /// failed yet.
+    pub(crate) fn connection_state(&self) -> ConnectionState {
+        match self.state {
+            ConnectionState::Healthy if self.stream.is_closed() => ConnectionState::Closed,
+            state => state,
+        }
+    }
+
+    /// Validates a connection that has been idle for longer than
+    /// [`ClientBuilder::max_idle`](crate::client::ClientBuilder::max_idle) before the next command
+    /// is sent, as such connections are often dropped silently by firewalls and proxies. A
+    /// connection the server already closed is reconnected without a round trip, any other is
+    /// pinged, and a failed ping leaves the stream to reconnect before the command.
+    pub(crate) fn refresh_if_idle(&mut self) {
+        let Some(max_idle) = self.options.max_idle else {
+            return;
+        };
+        if self.last_used.elapsed() <= max_idle || self.needs_reconnect() {
+            return;
+        }
+        self.counters.idle_validation();
+        if self.stream.is_closed() {
+            log::debug!(target: LOG_TARGET, "Idle connection was closed by the server");
+            self.state = ConnectionState::Closed;
+            return;
+        }
+        if let Err(e) = self.execute_scalar_command(Command::PING) {
+            log::debug!(target: LOG_TARGET, "Idle connection failed validation: {:?}", e);
+            self.mark_dirty();
+        }
+        self.last_used = Instant::now();
+    }
+
+    /// Whether the connection is believed to be usable: no read failed since the last handshake
+    /// and the server has not closed the connection.
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connection_state() == ConnectionState::Healthy
+    }
+
+    /// Reconnects right away when the connection was lost while waiting for a reply, so the next
+    /// command finds a working connection. If reconnecting fails, the stream stays unhealthy and
+    /// the next command tries again. Nothing is done if automatic reconnection is disabled.
+    pub(crate) fn recover(&mut self, error: StreamError) -> StreamError {
+        // A stream that lost its connection while sending has already reconnected.
+        if matches!(error, StreamError::OutcomeUnknown(_))
+            && self.needs_reconnect()
+            && self.options.reconnect_policy.is_automatic()
+        {
+            if let Err(e) = self.reconnect() {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "Failed to reconnect after losing the connection: {:?}",
+                    e
+                );
+            }
+        }
+        error
+    }
+}
+
 impl Stream for CommandStream {
-    fn host(&self) -> &str {
-        self.host.as_str()
+    fn options(&self) -> &ConnectionOptions {
+        &self.options
+    }
+
+    fn mode(&self) -> ExecutionMode {
+        ExecutionMode::Command
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.stream = transport;
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.state
     }
 
-    fn port(&self) -> u16 {
-        self.port
+    fn set_state(&mut self, state: ConnectionState) {
+        self.state = state;
     }
 
-    fn set_stream(&mut self, stream: std::net::TcpStream) {
-        self.stream = stream;
+    fn transport(&mut self) -> &mut Transport {
+        &mut self.stream
     }
 
-    fn tcp_stream(&mut self) -> &std::net::TcpStream {
-        &self.stream
+    fn read_buffer(&mut self) -> &mut ReadBuffer {
+        &mut self.read_buffer
+    }
+
+    fn write_buffer(&mut self) -> &mut WriteBuffer {
+        &mut self.write_buffer
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn counters(&self) -> &Counters {
+        &self.counters
     }
 
     fn handshake(&mut self) -> Result<(), StreamError> {
-        let handshake = Command::HANDSHAKE {
-            client_id: self.id.clone(),
-            execution_mode: ExecutionMode::Command,
-        };
-        let reply = self.execute_scalar_command(handshake)?;
-        match reply {
-            ScalarValue::VStr(v) if v == "OK" => Ok(()),
-            value => Err(StreamError::IoError(io::Error::new(
-                ErrorKind::Other,
-                format!("Handshake error: {:?}", value),
-            ))),
-        }
+        bounded_handshake(self, |stream| {
+            stream.authenticate()?;
+            let handshake = Command::HANDSHAKE {
+                client_id: stream.id.clone(),
+                execution_mode: ExecutionMode::Command,
+            };
+            check_handshake(stream.execute_scalar_command(handshake))?;
+            let info = conn_info(stream);
+            stream.options.events.connected(&info);
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,287 @@
+//! # Counter Module
+//! A handle on an integer key, for counters such as page views that are only ever added to, read
+//! and reset. Create one with [`Client::counter`].
+use crate::{
+    client::Client,
+    commands::{convert_value, DiceType, ScalarValue},
+    errors::{ClientError, CommandError},
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+impl Client {
+    /// Returns a handle on the counter at `key`, checking that the key is absent or holds an
+    /// integer.
+    /// # Example
+    /// ```no_run
+    /// use dicedb_rs::client::Client;
+    ///
+    /// let mut client = Client::new("localhost".to_string(), 7379)?;
+    /// let mut views = client.counter("metrics:pageviews")?.checked();
+    /// views.increment()?;
+    /// println!("page views since the last report: {}", views.get_and_reset()?);
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Arguments
+    /// * `key` - The key of the counter.
+    /// # Returns
+    /// * [`Counter`] - A handle on the counter.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::InvalidInput`] - If the key holds something other than an integer.
+    pub fn counter(&mut self, key: &str) -> Result<Counter<'_>> {
+        match self.key_type(key)? {
+            DiceType::Int | DiceType::None => Ok(Counter {
+                client: self,
+                key: key.to_string(),
+                checked: false,
+            }),
+            found => Err(CommandError::InvalidInput(format!(
+                "key {:?} holds a {} value, a counter needs an int",
+                key, found
+            ))
+            .into()),
+        }
+    }
+}
+
+/// A handle on an integer key, returned by [`Client::counter`]. A missing key counts as zero.
+///
+/// The server wraps around when a counter is incremented past [`i64::MAX`] or decremented past
+/// [`i64::MIN`], and so does the counter unless it is [`checked`](Counter::checked).
+#[derive(Debug)]
+pub struct Counter<'a> {
+    client: &'a mut Client,
+    key: String,
+    checked: bool,
+}
+
+impl Counter<'_> {
+    /// Makes additions that wrap around fail with [`CommandError::Overflow`] instead. The
+    /// addition is undone by subtracting it again, which wraps back to where the counter was,
+    /// even if it was changed by someone else in between.
+    pub fn checked(mut self) -> Self {
+        self.checked = true;
+        self
+    }
+
+    /// Returns the key of the counter.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Adds one to the counter.
+    /// # Returns
+    /// * `i64` - The value of the counter after the increment.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::Overflow`] - If the counter is checked and the increment wrapped around.
+    pub fn increment(&mut self) -> Result<i64> {
+        let value = self.client.incr(&self.key)?;
+        self.added(1, value)
+    }
+
+    /// Adds `delta` to the counter, which subtracts if it is negative.
+    /// # Returns
+    /// * `i64` - The value of the counter after the addition.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::Overflow`] - If the counter is checked and the addition wrapped around.
+    pub fn add(&mut self, delta: i64) -> Result<i64> {
+        let value = self.client.incrby(&self.key, delta)?;
+        self.added(delta, value)
+    }
+
+    /// Returns the value of the counter.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::ConversionError`] - If the key no longer holds an integer.
+    pub fn get(&mut self) -> Result<i64> {
+        let value = self.client.get(&self.key)?;
+        self.value(value)
+    }
+
+    /// Sets the counter back to zero by deleting its key.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    pub fn reset(&mut self) -> Result<()> {
+        self.client.del(self.key.as_str())?;
+        Ok(())
+    }
+
+    /// Returns the value of the counter and sets it back to zero, in a single GETDEL. Every
+    /// addition is counted exactly once: additions made before are part of the returned value,
+    /// additions made after count from zero.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::ConversionError`] - If the key no longer holds an integer, in which case
+    /// it is deleted all the same.
+    pub fn get_and_reset(&mut self) -> Result<i64> {
+        let value = self.client.getdel(&self.key)?;
+        self.value(value)
+    }
+
+    fn value(&self, value: ScalarValue) -> Result<i64> {
+        Ok(convert_value(&self.key, value)?.unwrap_or(0))
+    }
+
+    /// Checks the reply to an addition of `delta`, undoing it if it wrapped around.
+    fn added(&mut self, delta: i64, value: ScalarValue) -> Result<i64> {
+        let value = match value {
+            ScalarValue::VInt(value) => value,
+            value => return Err(CommandError::UnexpectedResponse(value).into()),
+        };
+        // The result wrapped around if and only if taking the delta off again overflows.
+        if self.checked && value.checked_sub(delta).is_none() {
+            self.client.incrby(&self.key, delta.wrapping_neg())?;
+            return Err(CommandError::Overflow {
+                key: self.key.clone(),
+                delta,
+            }
+            .into());
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        errors::StreamError,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    fn int(value: i64) -> Reply {
+        Reply::Value(ScalarValue::VInt(value))
+    }
+
+    fn client(server: &FakeServer) -> Client {
+        Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap()
+    }
+
+    fn commands(server: FakeServer) -> Vec<String> {
+        server
+            .received()
+            .into_iter()
+            .skip(1)
+            .map(|(name, args)| format!("{} {}", name, args.join(" ")))
+            .collect()
+    }
+
+    #[test]
+    fn test_counter() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("none".to_string())),
+            int(1),
+            int(11),
+            int(11),
+            int(11),
+            int(1),
+            int(1),
+            Reply::Value(ScalarValue::VNull),
+            int(1),
+            Reply::Value(ScalarValue::VNull),
+        ]]);
+        let mut client = client(&server);
+        let mut counter = client.counter("views").unwrap();
+        assert_eq!(counter.increment().unwrap(), 1);
+        assert_eq!(counter.add(10).unwrap(), 11);
+        assert_eq!(counter.get().unwrap(), 11);
+        assert_eq!(counter.get_and_reset().unwrap(), 11);
+        // Additions after the GETDEL count from zero.
+        assert_eq!(counter.increment().unwrap(), 1);
+        assert_eq!(counter.get_and_reset().unwrap(), 1);
+        assert_eq!(counter.get_and_reset().unwrap(), 0);
+        counter.reset().unwrap();
+        assert_eq!(counter.get().unwrap(), 0);
+        drop(client);
+        assert_eq!(
+            commands(server),
+            [
+                "TYPE views",
+                "INCR views",
+                "INCRBY views 10",
+                "GET views",
+                "GETDEL views",
+                "INCR views",
+                "GETDEL views",
+                "GETDEL views",
+                "DEL views",
+                "GET views"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_key_of_other_type() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("string".to_string())),
+        ]]);
+        let mut client = client(&server);
+        let error = client.counter("name").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid input: key \"name\" holds a string value, a counter needs an int"
+        );
+    }
+
+    #[test]
+    fn test_checked_counter_undoes_overflow() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("int".to_string())),
+            int(i64::MAX),
+            int(i64::MIN),
+            int(i64::MAX),
+            int(i64::MIN + 1),
+            int(i64::MAX),
+            int(-1),
+        ]]);
+        let mut client = client(&server);
+        let mut counter = client.counter("views").unwrap().checked();
+        assert_eq!(counter.add(i64::MAX - 1).unwrap(), i64::MAX);
+        let overflow = |result: Result<i64>, delta| match result {
+            Err(ClientError::StreamError(StreamError::CommandError(CommandError::Overflow {
+                key,
+                delta: d,
+            }))) => key == "views" && d == delta,
+            _ => false,
+        };
+        assert!(overflow(counter.increment(), 1));
+        assert_eq!(counter.add(i64::MIN + 2).unwrap(), i64::MIN + 1);
+        assert!(overflow(counter.add(i64::MIN), i64::MIN));
+        drop(client);
+        assert_eq!(
+            commands(server),
+            [
+                "TYPE views",
+                &format!("INCRBY views {}", i64::MAX - 1),
+                "INCR views",
+                "INCRBY views -1",
+                &format!("INCRBY views {}", i64::MIN + 2),
+                &format!("INCRBY views {}", i64::MIN),
+                &format!("INCRBY views {}", i64::MIN),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unchecked_counter_wraps() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("int".to_string())),
+            int(i64::MIN),
+        ]]);
+        let mut client = client(&server);
+        let mut counter = client.counter("views").unwrap();
+        assert_eq!(counter.increment().unwrap(), i64::MIN);
+    }
+}
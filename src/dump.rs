@@ -0,0 +1,721 @@
+//! # Dump Module
+//! Exports keys with their values and expiries as newline-delimited JSON, for backups and for
+//! inspecting a keyspace with ordinary JSON tools, and restores them. Every line is a
+//! [`DumpRecord`].
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::Client,
+    commands::{
+        Command, DiceType, ExpireAtOption, HSetValue, ScalarValue, SetInput, SetOption, Ttl,
+    },
+    errors::{ClientError, CommandError},
+    pipeline::Slot,
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+/// A key as written by [`Client::dump_to_writer`], one per line.
+/// # Example
+/// ```json
+/// {"key":"visits","type":"int","ttl":3600,"dumped_at":1767225600,"value":42}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DumpRecord {
+    /// The key, without the prefix of the client.
+    pub key: String,
+    /// The type of the value.
+    #[serde(rename = "type")]
+    pub key_type: DiceType,
+    /// The seconds the key had left to live when it was dumped, `None` if it does not expire.
+    pub ttl: Option<u64>,
+    /// When the key was dumped, in seconds since the Unix epoch. The key expires `ttl` seconds
+    /// after this.
+    pub dumped_at: u64,
+    /// The value of the key.
+    pub value: DumpValue,
+}
+
+/// The value of a [`DumpRecord`]: a JSON object for a hash, otherwise the JSON form of the
+/// [`ScalarValue`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DumpValue {
+    /// The fields of a hash.
+    Hash(HSetValue),
+    /// A single value.
+    Scalar(ScalarValue),
+}
+
+/// What [`Client::dump_to_writer`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DumpStats {
+    /// The keys written as records.
+    pub keys: u64,
+    /// The keys that were not written, because they did not exist or hold a type that cannot be
+    /// read yet, such as a list or a set.
+    pub skipped: u64,
+    /// The bytes written.
+    pub bytes: u64,
+}
+
+/// How [`Client::restore_from_reader`] writes records. By default records are written in
+/// batches of 100, existing keys are overwritten and failing records are collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreOptions {
+    batch_size: usize,
+    skip_existing: bool,
+    fail_fast: bool,
+}
+
+impl RestoreOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        RestoreOptions::default()
+    }
+
+    /// Writes up to `batch_size` records per round trip. A size of zero is treated as one.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Leaves keys that already exist as they are, instead of overwriting them. Single values are
+    /// set with NX. Hashes are checked with EXISTS before they are written, so a hash created by
+    /// someone else in between is merged with the restored fields.
+    pub fn skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Stops at the first failing record and returns its error, instead of collecting it in
+    /// [`RestoreStats::errors`]. Records before it in its batch may have been written.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            batch_size: 100,
+            skip_existing: false,
+            fail_fast: false,
+        }
+    }
+}
+
+/// What [`Client::restore_from_reader`] did.
+#[derive(Debug, Default)]
+pub struct RestoreStats {
+    /// The records written.
+    pub restored: u64,
+    /// The records left out because their key already existed, see
+    /// [`RestoreOptions::skip_existing`].
+    pub skipped: u64,
+    /// The records left out because their key would already have expired.
+    pub expired: u64,
+    /// The records that could not be read or written.
+    pub errors: Vec<RestoreError>,
+}
+
+/// A record that [`Client::restore_from_reader`] could not restore.
+#[derive(Debug)]
+pub struct RestoreError {
+    /// The line of the record, counting from one.
+    pub line: usize,
+    /// The key of the record, `None` if the line could not be parsed.
+    pub key: Option<String>,
+    /// Why the record could not be restored.
+    pub error: ClientError,
+}
+
+impl RestoreStats {
+    /// Collects the error of a record, or returns it when failing fast.
+    fn failed(
+        &mut self,
+        options: &RestoreOptions,
+        line: usize,
+        key: Option<&str>,
+        error: ClientError,
+    ) -> Result<()> {
+        if options.fail_fast {
+            return Err(error);
+        }
+        self.errors.push(RestoreError {
+            line,
+            key: key.map(str::to_string),
+            error,
+        });
+        Ok(())
+    }
+}
+
+impl DumpRecord {
+    /// When the key expires, in seconds since the Unix epoch.
+    fn expires_at(&self) -> Option<u64> {
+        self.ttl.map(|ttl| self.dumped_at.saturating_add(ttl))
+    }
+}
+
+impl Client {
+    /// Writes every key matching a glob-style pattern, with its type, expiry and value, as a line
+    /// of JSON, sorted by key. See [`DumpRecord`] for the format. The keys are found
+    /// with [`Client::keys`], so the pattern is relative to the prefix of the client, if any.
+    ///
+    /// Keys are read one at a time, so the dump is not a consistent snapshot of keys that change
+    /// while it runs. The writer is not buffered, so wrap files in a
+    /// [`BufWriter`](std::io::BufWriter).
+    /// # Example
+    /// ```no_run
+    /// use std::{fs::File, io::BufWriter};
+    /// use dicedb_rs::client::Client;
+    ///
+    /// let mut client = Client::new("localhost".to_string(), 7379)?;
+    /// let file = BufWriter::new(File::create("backup.ndjson")?);
+    /// let stats = client.dump_to_writer(Some("settings:*"), file)?;
+    /// println!("dumped {} keys", stats.keys);
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Arguments
+    /// * `pattern` - The keys to dump, such as `user:*`, or `None` for all keys.
+    /// * `writer` - Where to write the records.
+    /// # Returns
+    /// * [`DumpStats`] - How many keys were written and skipped.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::JsonError`] - If a record could not be written, with the key of the
+    /// record.
+    pub fn dump_to_writer<W: Write>(
+        &mut self,
+        pattern: Option<&str>,
+        writer: W,
+    ) -> Result<DumpStats> {
+        let mut keys = self.keys(pattern.unwrap_or("*"))?;
+        keys.sort_unstable();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.dump_keys_to_writer(&keys, writer)
+    }
+
+    /// Writes the given keys that exist as lines of JSON, skipping the others.
+    fn dump_keys_to_writer<W: Write>(&mut self, keys: &[&str], mut writer: W) -> Result<DumpStats> {
+        let mut stats = DumpStats::default();
+        for key in keys {
+            let Some(record) = self.dump_record(key)? else {
+                stats.skipped += 1;
+                continue;
+            };
+            let mut line = serde_json::to_vec(&record).map_err(|e| json_error(key, e))?;
+            line.push(b'\n');
+            writer
+                .write_all(&line)
+                .map_err(|e| json_error(key, serde_json::Error::io(e)))?;
+            stats.keys += 1;
+            stats.bytes += line.len() as u64;
+        }
+        writer
+            .flush()
+            .map_err(|e| json_error("", serde_json::Error::io(e)))?;
+        Ok(stats)
+    }
+
+    /// Reads the type, expiry and value of a key, or `None` if it cannot be dumped.
+    fn dump_record(&mut self, key: &str) -> Result<Option<DumpRecord>> {
+        let dumped_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // Most keys hold a single value, which is read along with the type in one round trip.
+        let mut pipeline = self.pipeline();
+        let key_type = pipeline.dtype(key);
+        let ttl = pipeline.ttl(key);
+        let value = pipeline.get(key);
+        let results = pipeline.execute()?;
+        let key_type = DiceType::from(key_type.get(&results)?.as_str());
+        let ttl = match Ttl::from_seconds_reply(ScalarValue::VInt(ttl.get(&results)?))? {
+            Ttl::Expires(left) => Some(left.as_secs()),
+            Ttl::NoExpiry => None,
+            Ttl::Missing => return Ok(None),
+        };
+        let value = match key_type {
+            DiceType::Str | DiceType::Int | DiceType::Float | DiceType::Bool | DiceType::Bytes => {
+                DumpValue::Scalar(value.get(&results)?)
+            }
+            DiceType::Hash => DumpValue::Hash(self.hgetall(key)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(DumpRecord {
+            key: key.to_string(),
+            key_type,
+            ttl,
+            dumped_at,
+            value,
+        }))
+    }
+}
+
+impl Client {
+    /// Restores keys from newline-delimited [`DumpRecord`]s, such as those written by
+    /// [`Client::dump_to_writer`]. Expiring keys expire at the same point in time as the
+    /// dumped keys would have, and keys that would already have expired are left out.
+    ///
+    /// Records are written in pipelined batches, see [`RestoreOptions`]. A record that cannot be
+    /// parsed or written is collected in [`RestoreStats::errors`] and the others are restored.
+    /// # Example
+    /// ```no_run
+    /// use std::fs::File;
+    /// use dicedb_rs::{client::Client, dump::RestoreOptions};
+    ///
+    /// let mut client = Client::new("localhost".to_string(), 7379)?;
+    /// let file = File::open("backup.ndjson")?;
+    /// let stats = client.restore_from_reader(file, RestoreOptions::new().skip_existing(true))?;
+    /// for failed in stats.errors {
+    ///     println!("line {}: {}", failed.line, failed.error);
+    /// }
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Arguments
+    /// * `reader` - Where to read the records from. It is buffered by the restore.
+    /// * `options` - How to write the records.
+    /// # Returns
+    /// * [`RestoreStats`] - How many records were restored, and which failed.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, in which case the
+    /// records of the current batch may or may not have been written, or the first failing
+    /// record with [`RestoreOptions::fail_fast`].
+    /// * [`CommandError::JsonError`] - If the reader failed.
+    pub fn restore_from_reader<R: Read>(
+        &mut self,
+        reader: R,
+        options: RestoreOptions,
+    ) -> Result<RestoreStats> {
+        let mut stats = RestoreStats::default();
+        let mut batch = Vec::with_capacity(options.batch_size);
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| json_error("", serde_json::Error::io(e)))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<DumpRecord>(&line) {
+                Ok(record) => batch.push((index + 1, record)),
+                Err(e) => stats.failed(&options, index + 1, None, json_error("", e))?,
+            }
+            if batch.len() == options.batch_size {
+                self.restore_batch(std::mem::take(&mut batch), &options, &mut stats)?;
+            }
+        }
+        self.restore_batch(batch, &options, &mut stats)?;
+        Ok(stats)
+    }
+
+    fn restore_batch(
+        &mut self,
+        records: Vec<(usize, DumpRecord)>,
+        options: &RestoreOptions,
+        stats: &mut RestoreStats,
+    ) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut records: Vec<(usize, DumpRecord)> = records
+            .into_iter()
+            .filter(|(_, record)| {
+                let expired = record.expires_at().is_some_and(|at| at <= now);
+                stats.expired += u64::from(expired);
+                !expired
+            })
+            .collect();
+        if options.skip_existing {
+            records = self.missing_records(records, options, stats)?;
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipeline = self.pipeline();
+        let mut queued: Vec<(usize, String, Vec<Slot<ScalarValue>>)> = Vec::new();
+        for (line, record) in records {
+            let expires_at = record.expires_at();
+            let slots = match record.value {
+                DumpValue::Scalar(value) => {
+                    let value: SetInput = match value.try_into() {
+                        Ok(value) => value,
+                        Err(message) => {
+                            let error = CommandError::InvalidInput(message).into();
+                            stats.failed(options, line, Some(&record.key), error)?;
+                            continue;
+                        }
+                    };
+                    vec![pipeline.queue(Command::SET {
+                        key: record.key.clone(),
+                        value,
+                        option: expires_at.map_or(SetOption::None, SetOption::EXAT),
+                        get: false,
+                        nx: options.skip_existing,
+                    })]
+                }
+                DumpValue::Hash(hash) if hash.fields.is_empty() => {
+                    let error = CommandError::InvalidInput("a hash has no fields".to_string());
+                    stats.failed(options, line, Some(&record.key), error.into())?;
+                    continue;
+                }
+                DumpValue::Hash(hash) => {
+                    let mut slots = vec![pipeline.queue(Command::HSET {
+                        key: record.key.clone(),
+                        fields: hash.fields.into_iter().collect(),
+                    })];
+                    if let Some(at) = expires_at {
+                        slots.push(pipeline.queue(Command::EXPIREAT {
+                            key: record.key.clone(),
+                            timestamp: i64::try_from(at).unwrap_or(i64::MAX),
+                            option: ExpireAtOption::None,
+                        }));
+                    }
+                    slots
+                }
+            };
+            queued.push((line, record.key, slots));
+        }
+        let results = pipeline.execute()?;
+
+        for (line, key, slots) in queued {
+            let replies: std::result::Result<Vec<ScalarValue>, CommandError> =
+                slots.iter().map(|slot| slot.get(&results)).collect();
+            match replies {
+                // Only a SET with NX replies with null, when the key exists.
+                Ok(replies) if replies[0] == ScalarValue::VNull => stats.skipped += 1,
+                Ok(_) => stats.restored += 1,
+                Err(e) => stats.failed(options, line, Some(&key), e.into())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the records whose key does not exist, counting the others as skipped.
+    fn missing_records(
+        &mut self,
+        records: Vec<(usize, DumpRecord)>,
+        options: &RestoreOptions,
+        stats: &mut RestoreStats,
+    ) -> Result<Vec<(usize, DumpRecord)>> {
+        if records.is_empty() {
+            return Ok(records);
+        }
+        let mut pipeline = self.pipeline();
+        let slots: Vec<Slot<u64>> = records
+            .iter()
+            .map(|(_, record)| pipeline.exists(&record.key, Vec::new()))
+            .collect();
+        let results = pipeline.execute()?;
+        let mut missing = Vec::with_capacity(records.len());
+        for ((line, record), slot) in records.into_iter().zip(slots) {
+            match slot.get(&results) {
+                Ok(0) => missing.push((line, record)),
+                Ok(_) => stats.skipped += 1,
+                Err(e) => stats.failed(options, line, Some(&record.key), e.into())?,
+            }
+        }
+        Ok(missing)
+    }
+}
+
+fn json_error(key: &str, source: serde_json::Error) -> ClientError {
+    CommandError::JsonError {
+        key: key.to_string(),
+        source,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        commands::{CommandDescriptor, CommandExecutor, ListValue},
+        errors::{ErrorKind, StreamError},
+    };
+
+    use super::*;
+
+    /// A prepared keyspace of typed values with expiries in seconds, which also takes the writes
+    /// of a restore.
+    struct Keyspace(HashMap<String, (&'static str, i64, DumpValue)>);
+
+    fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    }
+
+    impl CommandExecutor for Keyspace {
+        fn execute(
+            &mut self,
+            command: &CommandDescriptor,
+        ) -> std::result::Result<ScalarValue, StreamError> {
+            let args = command.args();
+            let key = args[0].clone();
+            let entry = self.0.get_mut(&key);
+            let value = match (command.name(), entry) {
+                ("TYPE", None) => ScalarValue::VStr("none".to_string()),
+                ("TYPE", Some((dtype, _, _))) => ScalarValue::VStr(dtype.to_string()),
+                ("TTL", None) => ScalarValue::VInt(-2),
+                ("TTL", Some((_, ttl, _))) => ScalarValue::VInt(*ttl),
+                ("GET", None) => ScalarValue::VNull,
+                ("GET", Some((_, _, DumpValue::Scalar(value)))) => value.clone(),
+                ("EXISTS", entry) => ScalarValue::VInt(i64::from(entry.is_some())),
+                ("SET", Some(_)) if args.last().unwrap() == "NX" => ScalarValue::VNull,
+                ("SET", _) => {
+                    let (dtype, value) = match args[1].parse::<i64>() {
+                        Ok(int) => ("int", ScalarValue::VInt(int)),
+                        Err(_) => ("string", ScalarValue::VStr(args[1].clone())),
+                    };
+                    let ttl = match args.get(2).map(String::as_str) {
+                        Some("EXAT") => args[3].parse::<i64>().unwrap() - now(),
+                        _ => -1,
+                    };
+                    self.0.insert(key, (dtype, ttl, DumpValue::Scalar(value)));
+                    ScalarValue::VStr("OK".to_string())
+                }
+                ("HSET", _) => {
+                    let fields = args[1..]
+                        .chunks(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone()))
+                        .collect();
+                    let hash = DumpValue::Hash(HSetValue { fields });
+                    self.0.insert(key, ("hash", -1, hash));
+                    ScalarValue::VInt(args.len() as i64 / 2)
+                }
+                ("EXPIREAT", Some((_, ttl, _))) => {
+                    *ttl = args[1].parse::<i64>().unwrap() - now();
+                    ScalarValue::VInt(1)
+                }
+                _ => {
+                    return Err(CommandError::server(
+                        "WRONGTYPE Operation against a key holding the wrong kind of value"
+                            .to_string(),
+                    )
+                    .into())
+                }
+            };
+            Ok(value)
+        }
+
+        fn execute_list(
+            &mut self,
+            command: &CommandDescriptor,
+        ) -> std::result::Result<ListValue, StreamError> {
+            // Only KEYS is listed, with either a whole key or a prefix followed by `*`.
+            let pattern = &command.args()[0];
+            let keys = self
+                .0
+                .keys()
+                .filter(|key| match pattern.strip_suffix('*') {
+                    Some(prefix) => key.starts_with(prefix),
+                    None => *key == pattern,
+                })
+                .map(|key| ScalarValue::VStr(key.clone()))
+                .collect();
+            Ok(ListValue(keys))
+        }
+
+        fn execute_map(
+            &mut self,
+            command: &CommandDescriptor,
+        ) -> std::result::Result<Option<HSetValue>, StreamError> {
+            match self.0.get(&command.args()[0]) {
+                Some((_, _, DumpValue::Hash(hash))) => Ok(Some(hash.clone())),
+                _ => Err(CommandError::server("WRONGTYPE".to_string()).into()),
+            }
+        }
+    }
+
+    fn keyspace() -> Keyspace {
+        let hash = HSetValue {
+            fields: HashMap::from([
+                ("name".to_string(), "dice".to_string()),
+                ("zip".to_string(), "8000".to_string()),
+            ]),
+        };
+        let entries = [
+            ("greeting", "string", -1, DumpValue::Scalar("hello".into())),
+            (
+                "visits",
+                "int",
+                90,
+                DumpValue::Scalar(ScalarValue::VInt(42)),
+            ),
+            ("user", "hash", -1, DumpValue::Hash(hash)),
+            ("queue", "list", -1, DumpValue::Scalar(ScalarValue::VNull)),
+        ];
+        Keyspace(
+            entries
+                .into_iter()
+                .map(|(key, dtype, ttl, value)| (key.to_string(), (dtype, ttl, value)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_dump_keys() {
+        let mut client = Client::with_executor(keyspace());
+        let mut output = Vec::new();
+        let stats = client
+            .dump_keys_to_writer(
+                &["greeting", "visits", "user", "queue", "missing"],
+                &mut output,
+            )
+            .unwrap();
+        assert_eq!(stats.keys, 3);
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(stats.bytes, output.len() as u64);
+
+        let output = String::from_utf8(output).unwrap();
+        let records: Vec<DumpRecord> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<(&str, DiceType, Option<u64>)> = records
+            .iter()
+            .map(|record| (record.key.as_str(), record.key_type.clone(), record.ttl))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("greeting", DiceType::Str, None),
+                ("visits", DiceType::Int, Some(90)),
+                ("user", DiceType::Hash, None),
+            ]
+        );
+        let expected = keyspace();
+        for record in &records {
+            assert_eq!(record.value, expected.0[&record.key].2);
+        }
+        let line = output.lines().nth(1).unwrap();
+        assert!(line.starts_with(r#"{"key":"visits","type":"int","ttl":90,"dumped_at":"#));
+        assert!(line.ends_with(r#","value":42}"#));
+
+        // All keys are dumped in order, and a pattern narrows them down.
+        let keys_of = |client: &mut Client, pattern| {
+            let mut output = Vec::new();
+            let stats = client.dump_to_writer(pattern, &mut output).unwrap();
+            let keys: Vec<String> = String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<DumpRecord>(line).unwrap().key)
+                .collect();
+            (keys, stats.skipped)
+        };
+        assert_eq!(
+            keys_of(&mut client, None),
+            (vec!["greeting".into(), "user".into(), "visits".into()], 1)
+        );
+        assert_eq!(keys_of(&mut client, Some("u*")), (vec!["user".into()], 0));
+        assert_eq!(keys_of(&mut client, Some("missing")), (vec![], 0));
+    }
+
+    #[test]
+    fn test_dump_reports_write_errors_with_key() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::StorageFull,
+                    "disk full",
+                ))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut client = Client::with_executor(keyspace());
+        let result = client.dump_keys_to_writer(&["visits"], Full);
+        assert!(matches!(
+            result,
+            Err(ClientError::StreamError(StreamError::CommandError(
+                CommandError::JsonError { key, .. }
+            ))) if key == "visits"
+        ));
+    }
+
+    fn dump(client: &mut Client) -> String {
+        let mut output = Vec::new();
+        client
+            .dump_keys_to_writer(&["greeting", "visits", "user"], &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_restore_dump() {
+        let dumped = dump(&mut Client::with_executor(keyspace()));
+        let mut client = Client::with_executor(Keyspace(HashMap::new()));
+        let options = RestoreOptions::new().batch_size(2);
+        let stats = client
+            .restore_from_reader(dumped.as_bytes(), options)
+            .unwrap();
+        assert_eq!((stats.restored, stats.skipped, stats.expired), (3, 0, 0));
+        assert!(stats.errors.is_empty());
+
+        let restored = dump(&mut client);
+        for (original, restored) in dumped.lines().zip(restored.lines()) {
+            let original: DumpRecord = serde_json::from_str(original).unwrap();
+            let restored: DumpRecord = serde_json::from_str(restored).unwrap();
+            assert_eq!(restored.key, original.key);
+            assert_eq!(restored.key_type, original.key_type);
+            assert_eq!(restored.value, original.value);
+            // The restored key expires when the dumped key would have.
+            assert_eq!(restored.ttl.is_some(), original.ttl.is_some());
+            assert!(restored.ttl <= original.ttl);
+            assert!(restored.ttl >= original.ttl.map(|ttl| ttl - 2));
+        }
+
+        let options = RestoreOptions::new().skip_existing(true);
+        let stats = client
+            .restore_from_reader(dumped.as_bytes(), options)
+            .unwrap();
+        assert_eq!((stats.restored, stats.skipped), (0, 3));
+    }
+
+    #[test]
+    fn test_restore_collects_failing_records() {
+        let input = format!(
+            "{}\n\nnot json\n{}\n{}\n",
+            r#"{"key":"a","type":"int","ttl":null,"dumped_at":0,"value":1}"#,
+            r#"{"key":"b","type":"string","ttl":null,"dumped_at":0,"value":null}"#,
+            r#"{"key":"c","type":"int","ttl":60,"dumped_at":0,"value":1}"#,
+        );
+        let mut client = Client::with_executor(Keyspace(HashMap::new()));
+        let stats = client
+            .restore_from_reader(input.as_bytes(), RestoreOptions::new())
+            .unwrap();
+        assert_eq!((stats.restored, stats.skipped, stats.expired), (1, 0, 1));
+        let failed: Vec<(usize, Option<&str>, ErrorKind)> = stats
+            .errors
+            .iter()
+            .map(|e| (e.line, e.key.as_deref(), e.error.kind()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                (3, None, ErrorKind::Conversion),
+                (4, Some("b"), ErrorKind::InvalidInput)
+            ]
+        );
+
+        let options = RestoreOptions::new().fail_fast(true);
+        let result = client.restore_from_reader(input.as_bytes(), options);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Conversion);
+    }
+}
@@ -2,133 +2,1358 @@
 //! This module contains the error types for the client and the server.
 //! The error types are used to handle errors that occur during the execution of the client and
 //! server.
-use crate::commands::ScalarValue;
+//!
+//! The error enums are `#[non_exhaustive]`, so new variants can be added without breaking
+//! callers, and a `match` on them needs a wildcard arm. To branch on what went wrong without
+//! depending on how errors are nested, match on [`ErrorKind`] instead, which every error returns
+//! from its `kind()` method:
+//! ```
+//! use dicedb_rs::errors::{ClientError, ErrorKind};
+//!
+//! fn describe(error: &ClientError) -> &'static str {
+//!     match error.kind() {
+//!         ErrorKind::Connection | ErrorKind::Timeout => "try again later",
+//!         ErrorKind::Server => "the server rejected the command",
+//!         _ => "something else went wrong",
+//!     }
+//! }
+//! ```
+//! What the server reported for an [`ErrorKind::Server`] error is returned by
+//! [`CommandError::server_error_kind`], which was called `kind` before `ErrorKind` existed.
+use crate::commands::{ScalarValue, ServerVersion};
 use prost::DecodeError;
-use std::io::Error;
+use std::{io::Error, net::IpAddr, time::Duration};
+
+/// A stable classification of an error, returned by the `kind()` method of every error type.
+/// An error keeps its kind when the variants of the error enums change, but new kinds may be
+/// added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server could not be reached or the connection to it was lost.
+    Connection,
+    /// The server did not reply in time.
+    Timeout,
+    /// The connection was lost after the command was sent, so it may or may not have been
+    /// executed. See [`StreamError::OutcomeUnknown`].
+    OutcomeUnknown,
+    /// The server replied with an error, see [`CommandError::server_error_kind`].
+    Server,
+    /// The command was rejected before being sent, because its input is invalid.
+    InvalidInput,
+    /// A reply could not be converted to the requested type.
+    Conversion,
+    /// The server replied with something the client did not expect, or that it could not
+    /// decode.
+    Protocol,
+    /// The server replied with more bytes than the maximum response size.
+    ResponseTooLarge,
+    /// The client configuration is missing or invalid.
+    Config,
+    /// The server is older than the oldest version the client supports.
+    UnsupportedServer,
+    /// The server rejected the handshake of a new connection.
+    Handshake,
+    /// A connection could not be bound to the configured local address, see [`BindError`].
+    Bind,
+}
+
+/// The kind of an IO error, which is a timeout or else a connection error.
+fn io_kind(error: &Error) -> ErrorKind {
+    if is_bind_error(error) {
+        ErrorKind::Bind
+    } else if is_timeout(error) {
+        ErrorKind::Timeout
+    } else {
+        ErrorKind::Connection
+    }
+}
 
 /// The errors that originates from handling commands.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum CommandError {
     /// A server side error occured. This might be caused by a bug in the SDK, or uninteded usage.
-    ServerError(String),
+    #[error("server error: {message}")]
+    ServerError {
+        /// What kind of error the server reported, parsed from the message.
+        kind: ServerErrorKind,
+        /// The error message as sent by the server.
+        message: String,
+    },
     /// The server returned an unexpected response, this can be caused by running on an
     /// incompatible server version.
-    DecodeError(DecodeError),
+    #[error("failed to decode reply: {0}")]
+    DecodeError(#[from] DecodeError),
+    /// The bytes passed to be decoded as one reply hold more than that reply. The extra bytes
+    /// belong to the next reply and are left to be decoded on their own.
+    #[error("{remaining} bytes follow the end of the reply")]
+    TrailingData {
+        /// The number of bytes after the end of the reply.
+        remaining: usize,
+    },
     /// The server returned an unexpected watch response, this can be caused by running on an
     /// incompatible server version.
+    #[error("unexpected watch reply: {0}")]
     WatchValueExpectationError(String),
+    /// The server replied with a value of an unexpected type for the command.
+    #[error("unexpected reply: {0}")]
+    UnexpectedResponse(ScalarValue),
+    /// The command was rejected before being sent, because its input is invalid.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    /// The reply has another shape than the command replies with, such as a map where a list
+    /// was expected.
+    #[error("expected a {expected} reply, got a {found}")]
+    UnexpectedShape {
+        /// The shape the command replies with.
+        expected: &'static str,
+        /// The shape of the reply.
+        found: &'static str,
+    },
+    /// A float argument is NaN or infinite, which the server cannot store. The command was not
+    /// sent.
+    #[error("cannot send the float {0}, only finite floats are supported")]
+    NonFiniteFloat(f64),
+    /// A value received for a key could not be converted to the requested type.
+    #[error("cannot convert value {value} of key {key:?} to {target}")]
+    ConversionError {
+        /// The key the value was read from.
+        key: String,
+        /// The value that could not be converted.
+        value: ScalarValue,
+        /// The name of the requested type.
+        target: &'static str,
+    },
+    /// Adding to a checked [`Counter`](crate::counter::Counter) wrapped around. The addition was
+    /// undone.
+    #[error("adding {delta} to counter {key:?} overflows")]
+    Overflow {
+        /// The key of the counter.
+        key: String,
+        /// The amount that was added.
+        delta: i64,
+    },
+    /// A [`CommandInterceptor`](crate::interceptor::CommandInterceptor) rejected the command, so
+    /// it was not sent.
+    #[error("{command} rejected: {reason}")]
+    Rejected {
+        /// The name of the command.
+        command: String,
+        /// Why the command was rejected.
+        reason: String,
+    },
+    /// A read-only client rejected a command that changes data, so it was not sent. Callers of
+    /// the client see it as [`ClientError::ReadOnlyViolation`].
+    #[error("{command} changes data, which a read-only client does not allow")]
+    ReadOnlyViolation {
+        /// The name of the command, as it would have been sent.
+        command: String,
+    },
+    /// An item of a multi-key operation failed, which fails the whole operation.
+    #[error("item {index} failed: {source}")]
+    BatchItemError {
+        /// The position of the failing item in the input.
+        index: usize,
+        /// The error of the failing item.
+        source: Box<CommandError>,
+    },
+    /// The reply of a pipelined command could not be retrieved through its
+    /// [`Slot`](crate::pipeline::Slot).
+    #[error("{}", slot_message(*index, command, source.as_deref()))]
+    SlotError {
+        /// The position of the command in the pipeline.
+        index: usize,
+        /// The name of the command.
+        command: &'static str,
+        /// Why the reply could not be retrieved, `None` if the results hold no reply at the
+        /// position of the command.
+        source: Option<Box<CommandError>>,
+    },
+    /// A value could not be serialized to or deserialized from JSON.
+    #[cfg(feature = "serde")]
+    #[error("invalid JSON for key {key:?}: {source}")]
+    JsonError {
+        /// The key the value was read from or written to.
+        key: String,
+        /// The underlying serde error.
+        source: serde_json::Error,
+    },
+}
+
+/// Formats a [`CommandError::SlotError`], which has a cause only if the results hold a reply.
+fn slot_message(index: usize, command: &str, source: Option<&CommandError>) -> String {
+    match source {
+        Some(source) => format!("reply of {} at {} failed: {}", command, index, source),
+        None => format!("no reply of {} at {}", command, index),
+    }
+}
+
+/// Formats a [`StreamError::HandshakeFailed`], preferring the error message of the server.
+fn handshake_message(reply: &ScalarValue, server_error: Option<&str>) -> String {
+    match server_error {
+        Some(message) => format!("handshake failed: {}", message),
+        None => format!("unexpected handshake reply: {}", reply),
+    }
+}
+
+impl CommandError {
+    /// Creates a [`CommandError::ServerError`] from the message the server replied with, such as
+    /// in a [`CommandExecutor`](crate::commands::CommandExecutor) standing in for a server.
+    pub fn server(message: String) -> Self {
+        CommandError::ServerError {
+            kind: ServerErrorKind::parse(&message),
+            message,
+        }
+    }
+
+    /// Returns the kind of a [`CommandError::ServerError`], or `None` for any other error.
+    pub fn server_error_kind(&self) -> Option<ServerErrorKind> {
+        match self {
+            CommandError::ServerError { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
+    /// Returns the stable classification of the error. An item of a batch or pipeline that
+    /// failed has the kind of its cause.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CommandError::ServerError { .. } => ErrorKind::Server,
+            CommandError::DecodeError(_)
+            | CommandError::TrailingData { .. }
+            | CommandError::WatchValueExpectationError(_)
+            | CommandError::UnexpectedResponse(_)
+            | CommandError::UnexpectedShape { .. } => ErrorKind::Protocol,
+            CommandError::InvalidInput(_)
+            | CommandError::NonFiniteFloat(_)
+            | CommandError::Overflow { .. }
+            | CommandError::Rejected { .. }
+            | CommandError::ReadOnlyViolation { .. } => ErrorKind::InvalidInput,
+            CommandError::ConversionError { .. } => ErrorKind::Conversion,
+            #[cfg(feature = "serde")]
+            CommandError::JsonError { .. } => ErrorKind::Conversion,
+            CommandError::BatchItemError { source, .. } => source.kind(),
+            CommandError::SlotError {
+                source: Some(source),
+                ..
+            } => source.kind(),
+            CommandError::SlotError { source: None, .. } => ErrorKind::InvalidInput,
+        }
+    }
+
+    /// Returns true if the server rejected the command because the key holds another type.
+    pub fn is_wrong_type(&self) -> bool {
+        self.server_error_kind() == Some(ServerErrorKind::WrongType)
+    }
+
+    /// Returns true if the server rejected the command because the key does not exist.
+    pub fn is_no_such_key(&self) -> bool {
+        self.server_error_kind() == Some(ServerErrorKind::NoSuchKey)
+    }
+
+    /// Returns true if the server rejected the syntax or arguments of the command.
+    pub fn is_syntax(&self) -> bool {
+        self.server_error_kind() == Some(ServerErrorKind::Syntax)
+    }
+
+    /// Returns true if the server rejected a value as not a number or out of range.
+    pub fn is_out_of_range(&self) -> bool {
+        self.server_error_kind() == Some(ServerErrorKind::OutOfRange)
+    }
+
+    /// Returns true if the server rejected the command for missing or wrong credentials.
+    pub fn is_auth(&self) -> bool {
+        self.server_error_kind() == Some(ServerErrorKind::Auth)
+    }
+}
+
+/// The kind of an error the server replied with, see [`CommandError::server_error_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerErrorKind {
+    /// The key holds a value of another type than the command works on.
+    WrongType,
+    /// The key does not exist.
+    NoSuchKey,
+    /// The command has a syntax error or the wrong number of arguments.
+    Syntax,
+    /// A value is not a number, or is out of range for the command.
+    OutOfRange,
+    /// Authentication is required or the credentials are wrong.
+    Auth,
+    /// Any other error. The message is kept in [`CommandError::ServerError`].
+    Unknown,
+}
+
+impl ServerErrorKind {
+    /// Parses the kind from the message of a server error. Unrecognized messages are
+    /// [`ServerErrorKind::Unknown`].
+    fn parse(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let (prefix, _) = message.split_once(' ').unwrap_or((&message, ""));
+        match prefix {
+            "wrongtype" => return ServerErrorKind::WrongType,
+            "noauth" | "wrongpass" => return ServerErrorKind::Auth,
+            _ => {}
+        }
+        let contains = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        if contains(&["wrongtype", "wrong kind of value"]) {
+            ServerErrorKind::WrongType
+        } else if contains(&["no such key", "could not find", "key does not exist"]) {
+            ServerErrorKind::NoSuchKey
+        } else if contains(&[
+            "syntax error",
+            "wrong number of arguments",
+            "unknown command",
+        ]) {
+            ServerErrorKind::Syntax
+        } else if contains(&[
+            "out of range",
+            "not an integer",
+            "not a valid float",
+            "overflow",
+        ]) {
+            ServerErrorKind::OutOfRange
+        } else if contains(&["auth", "password"]) {
+            ServerErrorKind::Auth
+        } else {
+            ServerErrorKind::Unknown
+        }
+    }
 }
 
 /// The errors that originates from the command stream.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum CommandStreamError {
     /// An error occured while reading from the stream. This is caused by the underlying IO to the
     /// server. Connection to server could be lost, or the server could have closed the connection.
-    ReadError(Error),
+    #[error("failed to read reply: {0}")]
+    ReadError(#[from] Error),
     /// An error occured while decoding the response from the server. This can be caused by an
     /// incompatible server version.
-    DecodeError(prost::DecodeError),
+    #[error("failed to decode reply: {0}")]
+    DecodeError(#[from] DecodeError),
     /// An unexpected value was received from the server during handshake. This can be caused by
     /// incompatible server version.
+    #[error("unexpected handshake reply: {0}")]
     HandshakeError(ScalarValue),
     /// An error occured in the command stream, this can be caused by an unexpected response from
     /// the server.
+    #[error("command failed: {0}")]
     CommandError(String),
 }
 
-impl From<Error> for CommandStreamError {
-    fn from(error: Error) -> Self {
-        CommandStreamError::ReadError(error)
-    }
-}
-impl From<prost::DecodeError> for CommandStreamError {
-    fn from(error: prost::DecodeError) -> Self {
-        CommandStreamError::DecodeError(error)
+impl CommandStreamError {
+    /// Returns the stable classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CommandStreamError::ReadError(e) => io_kind(e),
+            CommandStreamError::DecodeError(_) | CommandStreamError::CommandError(_) => {
+                ErrorKind::Protocol
+            }
+            CommandStreamError::HandshakeError(_) => ErrorKind::Handshake,
+        }
     }
 }
 
 /// The errors that originates from the Client.
-#[derive(Debug)]
+///
+/// Every method of [`Client`](crate::client::Client) returns this error, so commands and watches
+/// can be mixed in one function with `?`. The inner [`StreamError`] can still be matched on.
+/// ```no_run
+/// use dicedb_rs::{client::Client, commands::ScalarValue, errors::ClientError};
+///
+/// fn greet(client: &mut Client) -> Result<ScalarValue, ClientError> {
+///     client.set("Hello", "World")?;
+///     let (_changes, value) = client.get_watch("Hello")?;
+///     client.check()?;
+///     client.pipeline().execute()?;
+///     assert_eq!(client.get("Hello")?, value);
+///     Ok(value)
+/// }
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum ClientError {
     /// An error occured with the command stream
-    CommandStreamError(CommandStreamError),
+    #[error("{0}")]
+    CommandStreamError(#[from] CommandStreamError),
     /// An error occured with the watch stream
-    WatchStreamError(WatchStreamError),
+    #[error("{0}")]
+    WatchStreamError(#[from] WatchStreamError),
     /// An error occured in the clients stream
-    StreamError(StreamError),
+    #[error("{0}")]
+    StreamError(#[source] StreamError),
+    /// The client configuration is missing or invalid.
+    #[error("invalid configuration: {0}")]
+    ConfigError(String),
+    /// The server could not be reached within the reconnect policy when creating the client.
+    #[error("failed to connect after {attempts} attempts: {source}")]
+    ConnectFailed {
+        /// The number of connection attempts made.
+        attempts: u64,
+        /// The error of the last attempt.
+        source: Error,
+    },
+    /// The server is older than the oldest version this client supports. Only checked when
+    /// enabled with
+    /// [`ClientBuilder::check_server_version`](crate::client::ClientBuilder::check_server_version).
+    #[error("server version {found} is not supported, the oldest supported version is {minimum}")]
+    UnsupportedServer {
+        /// The version reported by the server.
+        found: ServerVersion,
+        /// The oldest supported version.
+        minimum: ServerVersion,
+    },
+    /// A command split into chunks, see
+    /// [`ClientBuilder::max_args_per_command`](crate::client::ClientBuilder::max_args_per_command),
+    /// failed part way. The chunks before the failed one were executed, the ones after it were
+    /// not sent.
+    #[error("chunk {} of {chunks} failed: {source}", succeeded + 1)]
+    ChunkFailed {
+        /// The number of chunks executed before the failure.
+        succeeded: usize,
+        /// The number of chunks the command was split into.
+        chunks: usize,
+        /// The error of the failed chunk.
+        source: Box<ClientError>,
+    },
+    /// A subscription to the key is still alive, see
+    /// [`ClientBuilder::allow_duplicate_subscriptions`](crate::client::ClientBuilder::allow_duplicate_subscriptions).
+    #[error("already subscribed to {key:?}")]
+    AlreadySubscribed {
+        /// The key, scoped to the prefix of the client.
+        key: String,
+    },
+    /// A read-only client rejected a command that changes data, so it was not sent, see
+    /// [`ClientBuilder::read_only`](crate::client::ClientBuilder::read_only).
+    #[error("{command} changes data, which a read-only client does not allow")]
+    ReadOnlyViolation {
+        /// The name of the command, as it would have been sent.
+        command: String,
+    },
 }
 
-impl From<CommandStreamError> for ClientError {
-    fn from(error: CommandStreamError) -> Self {
-        ClientError::CommandStreamError(error)
+impl ClientError {
+    /// The stream error this error wraps, if any.
+    fn stream_error(&self) -> Option<&StreamError> {
+        match self {
+            ClientError::StreamError(e)
+            | ClientError::WatchStreamError(
+                WatchStreamError::StreamError(e)
+                | WatchStreamError::Handshake { source: e, .. }
+                | WatchStreamError::Subscribe { source: e, .. },
+            ) => Some(e),
+            ClientError::ChunkFailed { source, .. } => source.stream_error(),
+            _ => None,
+        }
     }
-}
 
-impl From<WatchStreamError> for ClientError {
-    fn from(error: WatchStreamError) -> Self {
-        ClientError::WatchStreamError(error)
+    /// The IO error this error wraps outside of a [`StreamError`], if any.
+    fn io_error(&self) -> Option<&Error> {
+        match self {
+            ClientError::CommandStreamError(CommandStreamError::ReadError(e))
+            | ClientError::WatchStreamError(
+                WatchStreamError::IoError(e) | WatchStreamError::Connect { source: e, .. },
+            )
+            | ClientError::ConnectFailed { source: e, .. } => Some(e),
+            ClientError::ChunkFailed { source, .. } => source.io_error(),
+            _ => None,
+        }
+    }
+
+    /// Returns the stable classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ClientError::CommandStreamError(e) => e.kind(),
+            ClientError::WatchStreamError(e) => e.kind(),
+            ClientError::StreamError(e) => e.kind(),
+            ClientError::ConfigError(_) => ErrorKind::Config,
+            ClientError::ConnectFailed { source, .. } => match io_kind(source) {
+                ErrorKind::Bind => ErrorKind::Bind,
+                _ => ErrorKind::Connection,
+            },
+            ClientError::UnsupportedServer { .. } => ErrorKind::UnsupportedServer,
+            ClientError::ChunkFailed { source, .. } => source.kind(),
+            ClientError::AlreadySubscribed { .. } | ClientError::ReadOnlyViolation { .. } => {
+                ErrorKind::InvalidInput
+            }
+        }
+    }
+
+    /// Returns true if the server could not be reached or the connection to it was lost, see
+    /// [`StreamError::is_connection_error`].
+    pub fn is_connection_error(&self) -> bool {
+        match (self.stream_error(), self.io_error()) {
+            (Some(e), _) => e.is_connection_error(),
+            (_, Some(e)) => !is_timeout(e),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the server did not reply in time, see [`StreamError::is_timeout`].
+    pub fn is_timeout(&self) -> bool {
+        match (self.stream_error(), self.io_error()) {
+            (Some(e), _) => e.is_timeout(),
+            (_, Some(e)) => is_timeout(e),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the server rejected the command with an error reply, see
+    /// [`StreamError::is_server_error`].
+    pub fn is_server_error(&self) -> bool {
+        self.stream_error()
+            .is_some_and(StreamError::is_server_error)
+    }
+
+    /// Returns true if the error is transient, see [`StreamError::is_retryable`]. A client that
+    /// failed to connect in the first place is not, as its reconnect policy has already given
+    /// up.
+    pub fn is_retryable(&self) -> bool {
+        self.stream_error().is_some_and(StreamError::is_retryable)
     }
 }
 
+/// A command rejected by a read-only client becomes [`ClientError::ReadOnlyViolation`] rather
+/// than staying nested in the stream error that carried it out of the client.
 impl From<StreamError> for ClientError {
     fn from(error: StreamError) -> Self {
-        ClientError::StreamError(error)
+        match error {
+            StreamError::CommandError(CommandError::ReadOnlyViolation { command }) => {
+                ClientError::ReadOnlyViolation { command }
+            }
+            error => ClientError::StreamError(error),
+        }
+    }
+}
+
+impl From<CommandError> for ClientError {
+    fn from(error: CommandError) -> Self {
+        StreamError::CommandError(error).into()
+    }
+}
+
+impl From<Error> for ClientError {
+    fn from(error: Error) -> Self {
+        ClientError::StreamError(StreamError::IoError(error))
     }
 }
 
 /// The errors that originates from base functionality of a stream, either command stream or watch
 /// stream.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum StreamError {
     /// An error occured with the IO, this could be caused by the underlying IO to the server.
     /// Connection to server could be lost, or the server could have closed the connection.
-    IoError(Error),
+    #[error("I/O error: {0}")]
+    IoError(#[from] Error),
     /// An error occured while decoding the response from the server. This can be caused by an
     /// incompatible server version.
-    DecodeError(DecodeError),
+    #[error("failed to decode reply: {0}")]
+    DecodeError(#[from] DecodeError),
     /// An error occured while handling a command.
     /// This can be caused by an unexpected response from the server.
-    CommandError(CommandError),
+    #[error("{0}")]
+    CommandError(#[from] CommandError),
+    /// The connection was lost while sending a command or waiting for its reply, so the command
+    /// may or may not have been executed by the server. The client reconnects before returning
+    /// this error, but does not send the command again unless it is safe to repeat and a
+    /// [`RetryPolicy`](crate::config::RetryPolicy) allows it.
+    #[error("connection lost, the command may or may not have been executed: {0}")]
+    OutcomeUnknown(#[source] Error),
+    /// The server replied with more bytes than the maximum response size. The rest of the reply
+    /// is discarded by reconnecting before the next command.
+    #[error("reply of {observed} bytes exceeds the maximum response size of {limit} bytes")]
+    ResponseTooLarge {
+        /// The maximum response size in bytes.
+        limit: usize,
+        /// The size of the reply in bytes, as far as it was received or declared by its fields
+        /// when it was rejected.
+        observed: usize,
+    },
+    /// The encoded command is larger than the maximum command size, see
+    /// [`ClientBuilder::max_command_size`](crate::client::ClientBuilder::max_command_size). The
+    /// command was not sent and the connection can still be used.
+    #[error("command of {size} bytes exceeds the maximum command size of {limit} bytes")]
+    CommandTooLarge {
+        /// The size of the encoded command in bytes.
+        size: usize,
+        /// The maximum command size in bytes.
+        limit: usize,
+    },
+    /// A command stream received a watch notification where a command reply was expected, so
+    /// replies can no longer be matched to their commands. The client reconnects before the next
+    /// command.
+    #[error("received a watch notification instead of a command reply")]
+    ProtocolDesync,
+    /// The server did not answer the handshake of a new connection within the handshake
+    /// timeout.
+    #[error("handshake with {endpoint} timed out after {timeout:?}")]
+    HandshakeTimeout {
+        /// The host and port the connection was made to.
+        endpoint: String,
+        /// The handshake timeout.
+        timeout: Duration,
+    },
+    /// The server rejected the handshake of a new connection, or replied to it with something
+    /// else than `OK`. This can be caused by missing credentials or an incompatible server
+    /// version.
+    #[error("{}", handshake_message(reply, server_error.as_deref()))]
+    HandshakeFailed {
+        /// The value the server replied with, [`ScalarValue::VNull`] if it replied with an error.
+        reply: ScalarValue,
+        /// The error message the server replied with, if any.
+        server_error: Option<String>,
+    },
+    /// The connection was lost and could not be re-established within the reconnect policy.
+    #[error("failed to reconnect after {attempts} attempts in {elapsed:?}: {source}")]
+    ReconnectFailed {
+        /// The number of connection attempts made.
+        attempts: u64,
+        /// The time spent reconnecting.
+        elapsed: Duration,
+        /// The error of the last attempt.
+        source: Error,
+    },
+    /// The connection was lost and is not re-established automatically, as the reconnect policy
+    /// is [`disabled`](crate::config::ReconnectPolicy::disabled). Nothing was sent. Call
+    /// [`Client::reconnect`](crate::client::Client::reconnect) to restore the connection, or build
+    /// a new async client.
+    #[error("connection lost and automatic reconnection is disabled")]
+    ReconnectDisabled,
+    /// The circuit breaker of the client is open because the server could not be reached, so the
+    /// command was not sent, see
+    /// [`ClientBuilder::circuit_breaker`](crate::client::ClientBuilder::circuit_breaker).
+    #[error("circuit breaker is open, retry after {retry_after:?}")]
+    CircuitOpen {
+        /// How long until the breaker lets a probe through, zero if the probes are under way.
+        retry_after: Duration,
+    },
 }
 
-impl From<Error> for StreamError {
-    fn from(error: Error) -> Self {
-        StreamError::IoError(error)
+impl StreamError {
+    /// Returns the stable classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            StreamError::IoError(e) => io_kind(e),
+            StreamError::DecodeError(_) | StreamError::ProtocolDesync => ErrorKind::Protocol,
+            StreamError::HandshakeFailed { .. } => ErrorKind::Handshake,
+            StreamError::CommandError(e) => e.kind(),
+            StreamError::OutcomeUnknown(_) => ErrorKind::OutcomeUnknown,
+            StreamError::ResponseTooLarge { .. } => ErrorKind::ResponseTooLarge,
+            StreamError::CommandTooLarge { .. } => ErrorKind::InvalidInput,
+            StreamError::HandshakeTimeout { .. } => ErrorKind::Timeout,
+            StreamError::ReconnectFailed { .. }
+            | StreamError::ReconnectDisabled
+            | StreamError::CircuitOpen { .. } => ErrorKind::Connection,
+        }
+    }
+
+    /// Returns true if the connection to the server was lost or could not be re-established.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            StreamError::IoError(e) | StreamError::OutcomeUnknown(e) => !is_timeout(e),
+            StreamError::ReconnectFailed { .. } | StreamError::ReconnectDisabled => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the server did not reply in time, including to the handshake of a new
+    /// connection.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            StreamError::IoError(e) | StreamError::OutcomeUnknown(e) => is_timeout(e),
+            StreamError::HandshakeTimeout { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the server rejected the command with an error reply, see
+    /// [`CommandError::server_error_kind`].
+    pub fn is_server_error(&self) -> bool {
+        matches!(self, StreamError::CommandError(e) if e.server_error_kind().is_some())
+    }
+
+    /// Returns true if the error is transient, so a command that is safe to repeat may succeed
+    /// when sent again. A [`StreamError::ReconnectFailed`] is not, as the reconnect policy has
+    /// already given up. This is what a [`RetryPolicy`](crate::config::RetryPolicy) retries by
+    /// default.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StreamError::IoError(_)
+            | StreamError::OutcomeUnknown(_)
+            | StreamError::HandshakeTimeout { .. } => true,
+            _ => false,
+        }
     }
 }
 
-impl From<DecodeError> for StreamError {
-    fn from(error: DecodeError) -> Self {
-        StreamError::DecodeError(error)
+impl From<WatchStreamError> for StreamError {
+    fn from(error: WatchStreamError) -> Self {
+        match error {
+            WatchStreamError::IoError(e) => StreamError::IoError(e),
+            WatchStreamError::UnexpectedResponse(value) => {
+                StreamError::CommandError(CommandError::UnexpectedResponse(value))
+            }
+            WatchStreamError::StreamError(e)
+            | WatchStreamError::Handshake { source: e, .. }
+            | WatchStreamError::Subscribe { source: e, .. }
+            | WatchStreamError::Unsupported { source: e, .. } => e,
+            WatchStreamError::Connect { source, .. } => StreamError::IoError(source),
+        }
     }
 }
-impl From<CommandError> for StreamError {
-    fn from(error: CommandError) -> Self {
-        StreamError::CommandError(error)
+
+/// Whether the error is a read timeout, which is reported as `WouldBlock` on some platforms.
+pub(crate) fn is_timeout(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+    )
+}
+
+/// Whether the error means the connection is gone, as opposed to a timeout.
+pub(crate) fn is_connection_lost(error: &Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+    )
+}
+
+/// A connection could not be bound to the local address set with
+/// [`ClientBuilder::local_address`](crate::client::ClientBuilder::local_address), for example
+/// because no interface has that address. It is returned inside the [`Error`] of the connect
+/// attempt, with the kind of the original error, and classified as [`ErrorKind::Bind`].
+#[derive(Debug, thiserror::Error)]
+#[error("failed to bind to local address {address}: {source}")]
+pub struct BindError {
+    /// The local address the connection was bound to.
+    pub address: IpAddr,
+    /// The error of the bind.
+    #[source]
+    pub source: Error,
+}
+
+impl BindError {
+    /// Wraps the error of binding to `address` in an [`Error`] of the same kind.
+    pub(crate) fn wrap(address: IpAddr, source: Error) -> Error {
+        Error::new(source.kind(), BindError { address, source })
     }
 }
 
+/// Whether the error is a failure to bind to the configured local address.
+pub(crate) fn is_bind_error(error: &Error) -> bool {
+    error.get_ref().is_some_and(|e| e.is::<BindError>())
+}
+
+/// Whether the server rejected a command it does not know, as older servers do.
+pub(crate) fn is_unknown_command(error: &StreamError) -> bool {
+    matches!(
+        error,
+        StreamError::CommandError(CommandError::ServerError { message, .. })
+            if message.to_ascii_lowercase().contains("unknown command")
+    )
+}
+
 /// The errors that originates from the watch stream.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum WatchStreamError {
     /// An error occured with the IO, this could be caused by the underlying IO to the server.
     /// Connection to server could be lost, or the server could have closed the connection.
-    IoError(Error),
+    #[error("I/O error: {0}")]
+    IoError(#[from] Error),
     /// An error occured while decoding the response from the server. This can be caused by an
     /// incompatible server version.
+    #[error("unexpected watch reply: {0}")]
     UnexpectedResponse(ScalarValue),
     /// An error occured while handling a command.
-    StreamError(StreamError),
+    #[error("{0}")]
+    StreamError(#[from] StreamError),
+    /// The connection to watch a key could not be established within the connect timeout.
+    #[error("failed to connect to watch {key:?}: {source}")]
+    Connect {
+        /// The key to watch, scoped to the prefix of the client.
+        key: String,
+        /// The error of the last connection attempt.
+        source: Error,
+    },
+    /// The handshake of the connection to watch a key failed or timed out.
+    #[error("handshake to watch {key:?} failed: {source}")]
+    Handshake {
+        /// The key to watch, scoped to the prefix of the client.
+        key: String,
+        /// Why the handshake failed.
+        source: StreamError,
+    },
+    /// The server rejected the watch of a key, or did not reply to it in time.
+    #[error("failed to watch {key:?}: {source}")]
+    Subscribe {
+        /// The key to watch, scoped to the prefix of the client.
+        key: String,
+        /// Why the watch failed.
+        source: StreamError,
+    },
+    /// The server does not know the watch command, as servers older than the command do.
+    #[error("failed to watch {key:?}: the server does not support {command}")]
+    Unsupported {
+        /// The key to watch, scoped to the prefix of the client.
+        key: String,
+        /// The watch command the server rejected, such as `HGET.WATCH`.
+        command: &'static str,
+        /// The rejection of the server.
+        source: StreamError,
+    },
 }
 
-impl From<Error> for WatchStreamError {
-    fn from(error: Error) -> Self {
-        WatchStreamError::IoError(error)
+impl WatchStreamError {
+    /// Returns the stable classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            WatchStreamError::IoError(e) | WatchStreamError::Connect { source: e, .. } => {
+                io_kind(e)
+            }
+            WatchStreamError::UnexpectedResponse(_) => ErrorKind::Protocol,
+            WatchStreamError::StreamError(e)
+            | WatchStreamError::Handshake { source: e, .. }
+            | WatchStreamError::Subscribe { source: e, .. } => e.kind(),
+            WatchStreamError::Unsupported { .. } => ErrorKind::UnsupportedServer,
+        }
     }
 }
 
-impl From<StreamError> for WatchStreamError {
-    fn from(error: StreamError) -> Self {
-        WatchStreamError::StreamError(error)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    #[test]
+    fn test_server_error_kind() {
+        let cases = [
+            (
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+                ServerErrorKind::WrongType,
+            ),
+            (
+                "ERR wrongtype operation against a key holding the wrong kind of value",
+                ServerErrorKind::WrongType,
+            ),
+            ("ERR no such key", ServerErrorKind::NoSuchKey),
+            ("ERR syntax error", ServerErrorKind::Syntax),
+            (
+                "ERR wrong number of arguments for 'get' command",
+                ServerErrorKind::Syntax,
+            ),
+            ("ERR unknown command 'FOO'", ServerErrorKind::Syntax),
+            (
+                "ERR value is not an integer or out of range",
+                ServerErrorKind::OutOfRange,
+            ),
+            (
+                "ERR increment or decrement would overflow",
+                ServerErrorKind::OutOfRange,
+            ),
+            (
+                "ERR value is not a valid float",
+                ServerErrorKind::OutOfRange,
+            ),
+            ("NOAUTH Authentication required.", ServerErrorKind::Auth),
+            (
+                "WRONGPASS invalid username-password pair or user is disabled.",
+                ServerErrorKind::Auth,
+            ),
+            (
+                "ERR AUTH <password> called without any password configured for the default user",
+                ServerErrorKind::Auth,
+            ),
+            ("ERR something else went wrong", ServerErrorKind::Unknown),
+            ("", ServerErrorKind::Unknown),
+        ];
+        for (message, kind) in cases {
+            let error = CommandError::server(message.to_string());
+            assert_eq!(error.server_error_kind(), Some(kind), "{:?}", message);
+            match error {
+                CommandError::ServerError { message: kept, .. } => assert_eq!(kept, message),
+                other => panic!("expected ServerError, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_server_error_helpers() {
+        let error = CommandError::server("WRONGTYPE Operation against a key".to_string());
+        assert!(error.is_wrong_type());
+        assert!(!error.is_syntax());
+        let error = CommandError::server("ERR syntax error".to_string());
+        assert!(error.is_syntax());
+        assert!(CommandError::server("ERR no such key".to_string()).is_no_such_key());
+        assert!(CommandError::server("ERR value is out of range".to_string()).is_out_of_range());
+        assert!(CommandError::server("NOAUTH Authentication required.".to_string()).is_auth());
+        let error = CommandError::InvalidInput("no keys".to_string());
+        assert_eq!(error.server_error_kind(), None);
+        assert!(!error.is_wrong_type());
+    }
+
+    /// Follows the `source()` chain of `error` to its end.
+    fn root_cause<'a>(error: &'a (dyn error::Error + 'static)) -> &'a (dyn error::Error + 'static) {
+        let mut error = error;
+        while let Some(source) = error.source() {
+            error = source;
+        }
+        error
+    }
+
+    #[test]
+    fn test_display() {
+        let io = || Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+        let cases: [(Box<dyn error::Error>, &str); 12] = [
+            (
+                Box::new(ClientError::StreamError(StreamError::IoError(io()))),
+                "I/O error: connection reset",
+            ),
+            (
+                Box::new(StreamError::CommandError(CommandError::server(
+                    "ERR syntax error".to_string(),
+                ))),
+                "server error: ERR syntax error",
+            ),
+            (
+                Box::new(StreamError::ResponseTooLarge {
+                    limit: 10,
+                    observed: 20,
+                }),
+                "reply of 20 bytes exceeds the maximum response size of 10 bytes",
+            ),
+            (
+                Box::new(StreamError::CommandTooLarge {
+                    size: 20,
+                    limit: 10,
+                }),
+                "command of 20 bytes exceeds the maximum command size of 10 bytes",
+            ),
+            (
+                Box::new(StreamError::ReconnectFailed {
+                    attempts: 3,
+                    elapsed: Duration::from_secs(2),
+                    source: io(),
+                }),
+                "failed to reconnect after 3 attempts in 2s: connection reset",
+            ),
+            (
+                Box::new(CommandStreamError::HandshakeError(ScalarValue::VStr(
+                    "NOPE".to_string(),
+                ))),
+                "unexpected handshake reply: NOPE",
+            ),
+            (
+                Box::new(ClientError::UnsupportedServer {
+                    found: ServerVersion::new(0, 1, 0),
+                    minimum: ServerVersion::new(1, 0, 0),
+                }),
+                "server version 0.1.0 is not supported, the oldest supported version is 1.0.0",
+            ),
+            (
+                Box::new(ClientError::ChunkFailed {
+                    succeeded: 1,
+                    chunks: 3,
+                    source: Box::new(ClientError::StreamError(StreamError::IoError(io()))),
+                }),
+                "chunk 2 of 3 failed: I/O error: connection reset",
+            ),
+            (
+                Box::new(CommandError::Overflow {
+                    key: "views".to_string(),
+                    delta: 2,
+                }),
+                "adding 2 to counter \"views\" overflows",
+            ),
+            (
+                Box::new(CommandError::Rejected {
+                    command: "FLUSHDB".to_string(),
+                    reason: "not allowed in production".to_string(),
+                }),
+                "FLUSHDB rejected: not allowed in production",
+            ),
+            (
+                Box::new(ClientError::ReadOnlyViolation {
+                    command: "DEL".to_string(),
+                }),
+                "DEL changes data, which a read-only client does not allow",
+            ),
+            (
+                Box::new(StreamError::CircuitOpen {
+                    retry_after: Duration::from_secs(2),
+                }),
+                "circuit breaker is open, retry after 2s",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_source_chain_ends_at_io_error() {
+        let io = || Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+        let errors = [
+            ClientError::StreamError(StreamError::IoError(io())),
+            ClientError::WatchStreamError(WatchStreamError::StreamError(
+                StreamError::OutcomeUnknown(io()),
+            )),
+            ClientError::CommandStreamError(CommandStreamError::ReadError(io())),
+            ClientError::ConnectFailed {
+                attempts: 1,
+                source: io(),
+            },
+            ClientError::ChunkFailed {
+                succeeded: 0,
+                chunks: 2,
+                source: Box::new(ClientError::StreamError(StreamError::IoError(io()))),
+            },
+        ];
+        for error in errors {
+            let root = root_cause(&error);
+            let root = root
+                .downcast_ref::<Error>()
+                .expect("chain ends at io::Error");
+            assert_eq!(root.kind(), std::io::ErrorKind::ConnectionReset);
+        }
+    }
+
+    #[test]
+    fn test_source_chain_through_command_error() {
+        let decode = DecodeError::new("invalid wire type");
+        let error =
+            ClientError::StreamError(StreamError::CommandError(CommandError::BatchItemError {
+                index: 2,
+                source: Box::new(CommandError::DecodeError(decode)),
+            }));
+        let stream = error::Error::source(&error).unwrap();
+        assert!(stream.is::<StreamError>());
+        let command = stream.source().unwrap();
+        assert!(command.is::<CommandError>());
+        assert!(root_cause(&error).is::<DecodeError>());
+        assert!(error
+            .to_string()
+            .starts_with("item 2 failed: failed to decode reply: "));
+        // An error can be boxed and propagated with `?`.
+        let boxed: Box<dyn error::Error + Send + Sync> = error.into();
+        assert!(boxed.source().is_some());
+    }
+
+    #[test]
+    fn test_watch_stream_error_into_stream_error() {
+        let io = Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe");
+        let error = StreamError::from(WatchStreamError::from(io));
+        assert!(
+            matches!(&error, StreamError::IoError(e) if e.kind() == std::io::ErrorKind::BrokenPipe)
+        );
+        let value = ScalarValue::VInt(1);
+        let error = StreamError::from(WatchStreamError::UnexpectedResponse(value.clone()));
+        match error {
+            StreamError::CommandError(CommandError::UnexpectedResponse(v)) => assert_eq!(v, value),
+            other => panic!("expected UnexpectedResponse, got {:?}", other),
+        }
+        let inner = StreamError::ResponseTooLarge {
+            limit: 1,
+            observed: 2,
+        };
+        let error = StreamError::from(WatchStreamError::from(inner));
+        assert!(matches!(
+            error,
+            StreamError::ResponseTooLarge {
+                limit: 1,
+                observed: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_question_mark_conversions() {
+        fn decode() -> Result<(), CommandError> {
+            Err(DecodeError::new("truncated"))?
+        }
+        fn command() -> Result<(), StreamError> {
+            Ok(decode()?)
+        }
+        fn client() -> Result<(), ClientError> {
+            Ok(command()?)
+        }
+        let error = client().unwrap_err();
+        assert!(matches!(
+            &error,
+            ClientError::StreamError(StreamError::CommandError(CommandError::DecodeError(_)))
+        ));
+        assert!(error.to_string().starts_with("failed to decode reply: "));
+        assert!(error.to_string().contains("truncated"));
+        fn watch() -> Result<(), ClientError> {
+            Err(WatchStreamError::UnexpectedResponse(ScalarValue::VNull))?
+        }
+        assert!(matches!(
+            watch(),
+            Err(ClientError::WatchStreamError(
+                WatchStreamError::UnexpectedResponse(ScalarValue::VNull)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_stream_error_classification() {
+        use std::io::ErrorKind;
+
+        let io = |kind| Error::new(kind, "io");
+        // (error, connection, timeout, server, retryable)
+        let cases = [
+            (
+                StreamError::IoError(io(ErrorKind::ConnectionReset)),
+                true,
+                false,
+                false,
+                true,
+            ),
+            (
+                StreamError::IoError(io(ErrorKind::TimedOut)),
+                false,
+                true,
+                false,
+                true,
+            ),
+            (
+                StreamError::IoError(io(ErrorKind::WouldBlock)),
+                false,
+                true,
+                false,
+                true,
+            ),
+            (
+                StreamError::OutcomeUnknown(io(ErrorKind::UnexpectedEof)),
+                true,
+                false,
+                false,
+                true,
+            ),
+            (
+                StreamError::DecodeError(DecodeError::new("bad")),
+                false,
+                false,
+                false,
+                false,
+            ),
+            (
+                StreamError::CommandError(CommandError::server("ERR syntax error".to_string())),
+                false,
+                false,
+                true,
+                false,
+            ),
+            (
+                StreamError::CommandError(CommandError::InvalidInput("no keys".to_string())),
+                false,
+                false,
+                false,
+                false,
+            ),
+            (
+                StreamError::ResponseTooLarge {
+                    limit: 1,
+                    observed: 2,
+                },
+                false,
+                false,
+                false,
+                false,
+            ),
+            (StreamError::ProtocolDesync, false, false, false, false),
+            (
+                StreamError::HandshakeTimeout {
+                    endpoint: "localhost:7379".to_string(),
+                    timeout: Duration::from_secs(5),
+                },
+                false,
+                true,
+                false,
+                true,
+            ),
+            (
+                StreamError::HandshakeFailed {
+                    reply: ScalarValue::VNull,
+                    server_error: Some("ERR unsupported client".to_string()),
+                },
+                false,
+                false,
+                false,
+                false,
+            ),
+            (
+                StreamError::ReconnectFailed {
+                    attempts: 3,
+                    elapsed: Duration::from_secs(1),
+                    source: io(ErrorKind::ConnectionRefused),
+                },
+                true,
+                false,
+                false,
+                false,
+            ),
+        ];
+        for (error, connection, timeout, server, retryable) in cases {
+            assert_eq!(error.is_connection_error(), connection, "{:?}", error);
+            assert_eq!(error.is_timeout(), timeout, "{:?}", error);
+            assert_eq!(error.is_server_error(), server, "{:?}", error);
+            assert_eq!(error.is_retryable(), retryable, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_client_error_classification() {
+        use std::io::ErrorKind;
+
+        let io = |kind| Error::new(kind, "io");
+        let error = ClientError::from(StreamError::IoError(io(ErrorKind::BrokenPipe)));
+        assert!(error.is_connection_error() && error.is_retryable() && !error.is_timeout());
+        let error = ClientError::from(WatchStreamError::StreamError(StreamError::IoError(io(
+            ErrorKind::TimedOut,
+        ))));
+        assert!(error.is_timeout() && error.is_retryable() && !error.is_connection_error());
+        let error = ClientError::from(CommandError::server("WRONGTYPE".to_string()));
+        assert!(error.is_server_error() && !error.is_retryable());
+        let error = ClientError::ConnectFailed {
+            attempts: 1,
+            source: io(ErrorKind::ConnectionRefused),
+        };
+        assert!(error.is_connection_error() && !error.is_retryable());
+        let error = ClientError::from(CommandStreamError::ReadError(io(ErrorKind::TimedOut)));
+        assert!(error.is_timeout() && !error.is_connection_error());
+        let error = ClientError::from(WatchStreamError::IoError(io(ErrorKind::UnexpectedEof)));
+        assert!(error.is_connection_error());
+        let error = ClientError::from(WatchStreamError::Connect {
+            key: "key".to_string(),
+            source: io(ErrorKind::ConnectionRefused),
+        });
+        assert!(error.is_connection_error() && error.kind() == super::ErrorKind::Connection);
+        let error = ClientError::from(WatchStreamError::Subscribe {
+            key: "key".to_string(),
+            source: StreamError::IoError(io(ErrorKind::WouldBlock)),
+        });
+        assert!(error.is_timeout() && !error.is_connection_error());
+        assert_eq!(error.to_string(), "failed to watch \"key\": I/O error: io");
+        let error = ClientError::ConfigError("no host".to_string());
+        assert!(!error.is_connection_error() && !error.is_timeout());
+        assert!(!error.is_server_error() && !error.is_retryable());
+    }
+
+    #[test]
+    fn test_kind() {
+        let io = |kind| Error::new(kind, "io");
+        let cases = [
+            (
+                ClientError::from(StreamError::IoError(io(std::io::ErrorKind::BrokenPipe))),
+                ErrorKind::Connection,
+            ),
+            (
+                ClientError::from(WatchStreamError::IoError(io(std::io::ErrorKind::TimedOut))),
+                ErrorKind::Timeout,
+            ),
+            (
+                ClientError::from(StreamError::OutcomeUnknown(io(
+                    std::io::ErrorKind::UnexpectedEof,
+                ))),
+                ErrorKind::OutcomeUnknown,
+            ),
+            (
+                ClientError::from(CommandError::server("ERR syntax error".to_string())),
+                ErrorKind::Server,
+            ),
+            (
+                ClientError::from(CommandError::BatchItemError {
+                    index: 0,
+                    source: Box::new(CommandError::InvalidInput("empty".to_string())),
+                }),
+                ErrorKind::InvalidInput,
+            ),
+            (
+                ClientError::from(CommandError::ConversionError {
+                    key: "key".to_string(),
+                    value: ScalarValue::VStr("text".to_string()),
+                    target: "i64",
+                }),
+                ErrorKind::Conversion,
+            ),
+            (
+                ClientError::from(StreamError::ProtocolDesync),
+                ErrorKind::Protocol,
+            ),
+            (
+                ClientError::from(CommandError::ReadOnlyViolation {
+                    command: "DEL".to_string(),
+                }),
+                ErrorKind::InvalidInput,
+            ),
+            (
+                ClientError::from(CommandStreamError::HandshakeError(ScalarValue::VNull)),
+                ErrorKind::Handshake,
+            ),
+            (
+                ClientError::from(StreamError::ResponseTooLarge {
+                    limit: 1,
+                    observed: 2,
+                }),
+                ErrorKind::ResponseTooLarge,
+            ),
+            (
+                ClientError::ConfigError("no host".to_string()),
+                ErrorKind::Config,
+            ),
+            (
+                ClientError::ConnectFailed {
+                    attempts: 1,
+                    source: io(std::io::ErrorKind::ConnectionRefused),
+                },
+                ErrorKind::Connection,
+            ),
+            (
+                ClientError::ConnectFailed {
+                    attempts: 1,
+                    source: BindError::wrap(
+                        IpAddr::from([192, 0, 2, 1]),
+                        io(std::io::ErrorKind::AddrNotAvailable),
+                    ),
+                },
+                ErrorKind::Bind,
+            ),
+            (
+                ClientError::UnsupportedServer {
+                    found: ServerVersion::new(0, 1, 0),
+                    minimum: ServerVersion::new(1, 0, 0),
+                },
+                ErrorKind::UnsupportedServer,
+            ),
+            (
+                ClientError::ChunkFailed {
+                    succeeded: 0,
+                    chunks: 2,
+                    source: Box::new(ClientError::from(StreamError::ProtocolDesync)),
+                },
+                ErrorKind::Protocol,
+            ),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.kind(), kind, "{:?}", error);
+        }
+        let error = CommandError::SlotError {
+            index: 3,
+            command: "GET",
+            source: None,
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
     }
 }
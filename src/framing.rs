@@ -0,0 +1,233 @@
+//! # Framing Module
+//! The server writes replies as bare protobuf messages without a length prefix, so the end of a
+//! reply is found by walking the fields of the message.
+//!
+//! A reply is complete once every field in the buffer is complete. The server writes fields in
+//! field number order, so a field number lower than the previous one, or the same number again
+//! for a field that cannot be repeated, starts the next reply. A reply holds either an error or
+//! one value, so a second one of those fields also starts the next reply.
+
+/// The fields of a message that tell where it ends.
+struct Layout {
+    /// Fields that can appear more than once.
+    repeated: &'static [u64],
+    /// Fields of which a message holds at most one.
+    exclusive: &'static [u64],
+}
+
+/// `wire.Response`, with the repeated `v_list` and `v_ss_map`, and `err` next to the `value`
+/// oneof.
+const RESPONSE: Layout = Layout {
+    repeated: &[8, 9],
+    exclusive: &[1, 2, 3, 4, 5, 6],
+};
+
+/// `wire.Command`, with the repeated `args`.
+const COMMAND: Layout = Layout {
+    repeated: &[2],
+    exclusive: &[],
+};
+
+/// Protobuf wire types.
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// Returns the length of the first reply in `bytes`, or `None` if more bytes are needed to
+/// complete it. Malformed input is returned whole, so decoding it reports the error.
+pub(crate) fn message_len(bytes: &[u8]) -> Option<usize> {
+    frame_len(bytes, &RESPONSE).ok()
+}
+
+/// Returns how many bytes the first reply in `bytes` needs at least, as far as its fields tell.
+/// A reply that declares a field longer than the response limit can be rejected before it is
+/// read.
+pub(crate) fn needed_len(bytes: &[u8]) -> usize {
+    match frame_len(bytes, &RESPONSE) {
+        Ok(len) | Err(len) => len,
+    }
+}
+
+/// Like [`message_len`], but for commands, so commands written together can be told apart, e.g.
+/// by the fake server or in a recording.
+pub(crate) fn command_len(bytes: &[u8]) -> Option<usize> {
+    frame_len(bytes, &COMMAND).ok()
+}
+
+/// Returns the length of the first message with the given layout in `bytes`, or the number of
+/// bytes it needs at least if it is incomplete.
+fn frame_len(bytes: &[u8], layout: &Layout) -> Result<usize, usize> {
+    if bytes.is_empty() {
+        return Err(1);
+    }
+    let mut position = 0;
+    let mut previous_field = 0;
+    let mut seen_exclusive = false;
+    while position < bytes.len() {
+        let Some((key, key_len)) = varint(&bytes[position..]) else {
+            return Err(bytes.len() + 1);
+        };
+        let (field, wire_type) = (key >> 3, key & 0b111);
+        if field == 0 {
+            // Field numbers start at 1, so the message is malformed.
+            return Ok(bytes.len());
+        }
+        let exclusive = layout.exclusive.contains(&field);
+        if field < previous_field
+            || (field == previous_field && !layout.repeated.contains(&field))
+            || (exclusive && seen_exclusive)
+        {
+            return Ok(position);
+        }
+        seen_exclusive |= exclusive;
+        let value = &bytes[position + key_len..];
+        let value_len = match value_len(wire_type, value) {
+            Ok(Some(len)) => len,
+            Ok(None) => return Err(bytes.len() + 1),
+            Err(UnknownWireType) => return Ok(bytes.len()),
+        };
+        let end = (position + key_len).saturating_add(value_len);
+        if end > bytes.len() {
+            return Err(end);
+        }
+        position = end;
+        previous_field = field;
+    }
+    Ok(position)
+}
+
+/// Returns whether the complete message in `bytes` holds the field with the given number.
+pub(crate) fn has_field(bytes: &[u8], number: u64) -> bool {
+    let mut position = 0;
+    while position < bytes.len() {
+        let Some((key, key_len)) = varint(&bytes[position..]) else {
+            return false;
+        };
+        if key >> 3 == number {
+            return true;
+        }
+        let value = &bytes[position + key_len..];
+        match value_len(key & 0b111, value) {
+            Ok(Some(len)) if len <= value.len() => position += key_len + len,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// A wire type that protobuf does not define, so the message is malformed.
+struct UnknownWireType;
+
+/// Returns the length of a value of `wire_type` at the start of `value`, which can be more than
+/// the bytes available, or `None` if the length is not known yet.
+fn value_len(wire_type: u64, value: &[u8]) -> Result<Option<usize>, UnknownWireType> {
+    let len = match wire_type {
+        VARINT => varint(value).map(|(_, len)| len),
+        FIXED64 => Some(8),
+        LENGTH_DELIMITED => varint(value).map(|(len, prefix_len)| {
+            usize::try_from(len)
+                .ok()
+                .and_then(|len| len.checked_add(prefix_len))
+                .unwrap_or(usize::MAX)
+        }),
+        FIXED32 => Some(4),
+        _ => return Err(UnknownWireType),
+    };
+    Ok(len)
+}
+
+/// Decodes a varint, returning its value and length, or `None` if it is incomplete.
+fn varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (index, byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    if bytes.len() >= 10 {
+        // Too long to be a varint, let the decoder report it.
+        return Some((u64::MAX, bytes.len()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{encode_error_reply, encode_reply, encode_watch_reply, ScalarValue};
+
+    use super::*;
+
+    #[test]
+    fn test_message_len_complete() {
+        let reply = encode_reply(&ScalarValue::VStr("x".repeat(1000)));
+        assert_eq!(message_len(&reply), Some(reply.len()));
+        let reply = encode_reply(&ScalarValue::VInt(-1));
+        assert_eq!(message_len(&reply), Some(reply.len()));
+        let reply = encode_reply(&ScalarValue::VFloat(1.5));
+        assert_eq!(message_len(&reply), Some(reply.len()));
+    }
+
+    #[test]
+    fn test_message_len_incomplete() {
+        let reply = encode_reply(&ScalarValue::VStr("x".repeat(1000)));
+        for end in 0..reply.len() {
+            assert_eq!(message_len(&reply[..end]), None, "prefix of {} bytes", end);
+        }
+    }
+
+    #[test]
+    fn test_message_len_concatenated() {
+        let mut replies = encode_reply(&ScalarValue::VStr("first".to_string()));
+        let first_len = replies.len();
+        replies.extend(encode_reply(&ScalarValue::VStr("second".to_string())));
+        assert_eq!(message_len(&replies), Some(first_len));
+        let mut replies = encode_reply(&ScalarValue::VInt(1));
+        replies.extend(encode_reply(&ScalarValue::VInt(2)));
+        assert_eq!(message_len(&replies), Some(replies.len() / 2));
+    }
+
+    #[test]
+    fn test_message_len_concatenated_in_field_order() {
+        // Field numbers keep increasing across these replies, but each holds its own value.
+        let first = encode_reply(&ScalarValue::VInt(1));
+        let mut replies = first.clone();
+        replies.extend(encode_reply(&ScalarValue::VStr("second".to_string())));
+        assert_eq!(message_len(&replies), Some(first.len()));
+        let first = encode_error_reply("ERR wrong type");
+        let mut replies = first.clone();
+        replies.extend(encode_reply(&ScalarValue::VInt(2)));
+        assert_eq!(message_len(&replies), Some(first.len()));
+    }
+
+    #[test]
+    fn test_has_field() {
+        let reply = encode_reply(&ScalarValue::VInt(1));
+        assert!(has_field(&reply, 3));
+        assert!(!has_field(&reply, 7));
+        let push = encode_watch_reply(&ScalarValue::VInt(1), "fingerprint");
+        assert!(has_field(&push, 7));
+    }
+
+    #[test]
+    fn test_needed_len() {
+        let reply = encode_reply(&ScalarValue::VStr("x".repeat(1000)));
+        assert_eq!(needed_len(&reply), reply.len());
+        // Once the length prefix is read, the whole reply is known to be needed.
+        assert_eq!(needed_len(&reply[..3]), reply.len());
+        assert_eq!(needed_len(&reply[..1]), 2);
+        assert_eq!(needed_len(&[]), 1);
+        // A string field declaring a length of 2^40 bytes.
+        let huge = [0x22, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        assert!(needed_len(&huge) > 1 << 40);
+    }
+
+    #[test]
+    fn test_message_len_malformed() {
+        // Wire type 7 does not exist.
+        assert_eq!(message_len(&[0x0f, 0x01, 0x02]), Some(3));
+        // Field number 0 does not exist, and must not frame an empty reply.
+        assert_eq!(message_len(&[0x00, 0x01]), Some(2));
+    }
+}
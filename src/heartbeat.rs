@@ -0,0 +1,62 @@
+//! # Heartbeat Module
+//! Keeps idle connections alive by sending PING when the command stream has not been used for a
+//! while, so middleboxes do not drop them.
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    commands::{Command, Execute},
+    commandstream::{lock, CommandStream},
+    LOG_TARGET,
+};
+
+/// A background thread sending PING on the command stream once it has been idle for the
+/// interval. The stream is locked while pinging, so a ping never interleaves with a command. The
+/// thread stops when the heartbeat is dropped.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    stop: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    pub(crate) fn start(command_client: Arc<Mutex<CommandStream>>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut wait = interval;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                let mut stream = lock(&command_client);
+                let idle = stream.last_used.elapsed();
+                if idle < interval {
+                    wait = interval - idle;
+                    continue;
+                }
+                if let Err(e) = stream.execute_scalar_command(Command::PING) {
+                    log::warn!(target: LOG_TARGET, "Heartbeat failed: {:?}", e);
+                    stream.recover(e);
+                }
+                stream.last_used = Instant::now();
+                wait = interval;
+            }
+        });
+        Heartbeat {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! # Interceptor Module
+//! Hooks for enforcing policies on the commands of a client, such as forbidding commands or
+//! rewriting keys. Register implementations of [`CommandInterceptor`] with
+//! [`ClientBuilder::interceptor`](crate::client::ClientBuilder::interceptor).
+use std::{fmt, sync::Arc};
+
+use crate::{
+    commands::{Command, CommandDescriptor},
+    config::guard,
+    errors::{CommandError, StreamError},
+};
+
+/// Sees every command of a client before it is sent and its outcome after. Interceptors run in
+/// the order they were registered, each seeing the command as the previous one left it.
+///
+/// Commands are intercepted once their keys are scoped to the prefix of the client, and once
+/// however often they are retried. Pipelined commands are intercepted before any of them is
+/// sent, but [`CommandInterceptor::after_receive`] is not called for them. The commands a client
+/// sends on its own, such as the handshake and heartbeats, and watch commands are not
+/// intercepted.
+/// # Example
+/// ```
+/// use dicedb_rs::commands::CommandDescriptor;
+/// use dicedb_rs::errors::CommandError;
+/// use dicedb_rs::interceptor::CommandInterceptor;
+///
+/// struct NoFlush;
+///
+/// impl CommandInterceptor for NoFlush {
+///     fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+///         match command.name() {
+///             "FLUSHDB" => Err(CommandError::Rejected {
+///                 command: command.name().to_string(),
+///                 reason: "not allowed in production".to_string(),
+///             }),
+///             _ => Ok(()),
+///         }
+///     }
+/// }
+/// ```
+pub trait CommandInterceptor: Send + Sync {
+    /// Called before the command is sent. The name and arguments of the command can be changed,
+    /// which changes what is sent but not how the reply is read. Returning an error, usually a
+    /// [`CommandError::Rejected`], fails the command without sending it.
+    fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+        let _ = command;
+        Ok(())
+    }
+
+    /// Called with the command as it was sent and whether it succeeded. A panic inside this
+    /// method is caught and logged.
+    fn after_receive(&self, command: &CommandDescriptor, result: Result<(), &StreamError>) {
+        let _ = (command, result);
+    }
+}
+
+impl<T: CommandInterceptor + ?Sized> CommandInterceptor for Arc<T> {
+    fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+        (**self).before_send(command)
+    }
+
+    fn after_receive(&self, command: &CommandDescriptor, result: Result<(), &StreamError>) {
+        (**self).after_receive(command, result)
+    }
+}
+
+/// The interceptors registered for a client, in the order they run.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Vec<Arc<dyn CommandInterceptor>>);
+
+impl Interceptors {
+    pub(crate) fn push(&mut self, interceptor: impl CommandInterceptor + 'static) {
+        self.0.push(Arc::new(interceptor));
+    }
+
+    /// Whether no interceptor is registered, so the cost of intercepting can be skipped.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Passes the command through every interceptor, returning the command to send and what it
+    /// looks like on the wire.
+    pub(crate) fn before_send(
+        &self,
+        command: Command,
+    ) -> Result<(Command, CommandDescriptor), CommandError> {
+        let original = CommandDescriptor::from(command.clone());
+        let mut descriptor = original.clone();
+        for interceptor in &self.0 {
+            interceptor.before_send(&mut descriptor)?;
+        }
+        match descriptor == original {
+            true => Ok((command, descriptor)),
+            false => Ok((command.intercepted(descriptor.clone()), descriptor)),
+        }
+    }
+
+    pub(crate) fn after_receive(
+        &self,
+        command: &CommandDescriptor,
+        result: Result<(), &StreamError>,
+    ) {
+        for interceptor in &self.0 {
+            guard("after_receive", || {
+                interceptor.after_receive(command, result)
+            });
+        }
+    }
+}
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Interceptors").field(&self.0.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        client::Client,
+        commands::ScalarValue,
+        errors::ClientError,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    struct NoFlush;
+
+    impl CommandInterceptor for NoFlush {
+        fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+            match command.name() {
+                "FLUSHDB" => Err(CommandError::Rejected {
+                    command: command.name().to_string(),
+                    reason: "not allowed".to_string(),
+                }),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Moves the key of every command under a prefix, and records the commands it saw.
+    #[derive(Default)]
+    struct Tenant {
+        name: &'static str,
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl CommandInterceptor for Tenant {
+        fn before_send(&self, command: &mut CommandDescriptor) -> Result<(), CommandError> {
+            let mut args = command.args().to_vec();
+            args[0] = format!("{}:{}", self.name, args[0]);
+            *command = CommandDescriptor::new(command.name(), args);
+            Ok(())
+        }
+
+        fn after_receive(&self, command: &CommandDescriptor, result: Result<(), &StreamError>) {
+            let outcome = if result.is_ok() { "ok" } else { "failed" };
+            let line = format!(
+                "{} {} {}",
+                command.name(),
+                command.args().join(" "),
+                outcome
+            );
+            self.seen.lock().unwrap().push(line);
+        }
+    }
+
+    fn commands(server: FakeServer) -> Vec<String> {
+        server
+            .received()
+            .into_iter()
+            .skip(1)
+            .map(|(name, args)| format!("{} {}", name, args.join(" ")))
+            .collect()
+    }
+
+    #[test]
+    fn test_rejected_command_is_not_sent() {
+        let server = FakeServer::start(vec![vec![Reply::ok(), Reply::ok()]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .interceptor(NoFlush)
+            .build()
+            .unwrap();
+        let error = client.flushdb().unwrap_err();
+        assert_eq!(error.to_string(), "FLUSHDB rejected: not allowed");
+        assert!(matches!(
+            error,
+            ClientError::StreamError(StreamError::CommandError(CommandError::Rejected { .. }))
+        ));
+        assert_eq!(client.stats().commands_sent, 1);
+        client.ping().unwrap();
+        drop(client);
+        assert_eq!(commands(server), ["PING "]);
+    }
+
+    #[test]
+    fn test_interceptors_rewrite_commands_in_order() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("value".to_string())),
+            Reply::Value(ScalarValue::VInt(1)),
+        ]]);
+        let outer = Arc::new(Tenant {
+            name: "org",
+            ..Tenant::default()
+        });
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .interceptor(Tenant {
+                name: "team",
+                ..Tenant::default()
+            })
+            .interceptor(outer.clone())
+            .build()
+            .unwrap();
+        client.set("key", "value").unwrap();
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        let mut pipeline = client.pipeline();
+        let count = pipeline.incr("count");
+        let results = pipeline.execute().unwrap();
+        assert_eq!(count.get(&results).unwrap(), 1);
+        drop(client);
+        assert_eq!(
+            commands(server),
+            [
+                "SET org:team:key value",
+                "GET org:team:key",
+                "INCR org:team:count"
+            ]
+        );
+        assert_eq!(
+            *outer.seen.lock().unwrap(),
+            ["SET org:team:key value ok", "GET org:team:key ok"]
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    client::Client,
+    commands::{Command, Execute, ScalarValue, SetInput, SetOption},
+    errors::{ClientError, CommandError},
+    serialization::{from_fields, to_fields},
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+fn json_error(key: &str, source: serde_json::Error) -> ClientError {
+    CommandError::JsonError {
+        key: key.to_string(),
+        source,
+    }
+    .into()
+}
+
+fn to_json<T: Serialize>(key: &str, value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|source| json_error(key, source))
+}
+
+fn from_json<T: DeserializeOwned>(key: &str, value: ScalarValue) -> Result<Option<T>> {
+    let decoded = match value {
+        ScalarValue::VNull => return Ok(None),
+        ScalarValue::VStr(s) => serde_json::from_str(&s),
+        ScalarValue::VBytes(b) => serde_json::from_slice(&b),
+        value => serde_json::from_str(&value.to_string()),
+    };
+    decoded.map(Some).map_err(|source| json_error(key, source))
+}
+
+impl Client {
+    /// Serializes `value` as JSON and stores it at `key`.
+    /// # Arguments
+    /// * `key` - The key to set the value of.
+    /// * `value` - The value to serialize.
+    /// # Returns
+    /// * [`ScalarValue`] - A response from the server with an OK if succes.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the value could not be serialized.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn set_json<T: Serialize>(&mut self, key: &str, value: &T) -> Result<ScalarValue> {
+        let json = to_json(key, value)?;
+        let resp = self.execute_scalar_command(Command::SET {
+            key: key.to_string(),
+            value: SetInput::Str(json),
+            option: SetOption::None,
+            get: false,
+            nx: false,
+        })?;
+        Ok(resp)
+    }
+
+    /// Gets the value at `key` and deserializes it from JSON.
+    /// # Arguments
+    /// * `key` - The key to get the value of.
+    /// # Returns
+    /// * `Option<T>` - The deserialized value, or `None` if the key does not exist.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the value could not be deserialized.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn get_json<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>> {
+        let resp = self.execute_scalar_command(Command::GET {
+            key: key.to_string(),
+        })?;
+        from_json(key, resp)
+    }
+
+    /// Serializes `value` as JSON and stores it in `field` of the hash at `key`.
+    /// # Arguments
+    /// * `key` - The key of the hash.
+    /// * `field` - The field to set.
+    /// * `value` - The value to serialize.
+    /// # Returns
+    /// * [`ScalarValue`] - The number of fields that were added.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the value could not be serialized.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn hset_json<T: Serialize>(
+        &mut self,
+        key: &str,
+        field: &str,
+        value: &T,
+    ) -> Result<ScalarValue> {
+        let json = to_json(key, value)?;
+        let resp = self.execute_scalar_command(Command::HSET {
+            key: key.to_string(),
+            fields: vec![(field.to_string(), json)],
+        })?;
+        Ok(resp)
+    }
+
+    /// Gets `field` of the hash at `key` and deserializes it from JSON.
+    /// # Arguments
+    /// * `key` - The key of the hash.
+    /// * `field` - The field to get.
+    /// # Returns
+    /// * `Option<T>` - The deserialized value, or `None` if the key or field does not exist.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the value could not be deserialized.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn hget_json<T: DeserializeOwned>(&mut self, key: &str, field: &str) -> Result<Option<T>> {
+        let resp = self.execute_scalar_command(Command::HGET {
+            key: key.to_string(),
+            field: field.to_string(),
+        })?;
+        from_json(key, resp)
+    }
+
+    /// Gets all fields of the hash at `key` and deserializes them into a flat struct.
+    /// Every field is read as a string, numbers and booleans are parsed when the target field
+    /// asks for them. Missing fields deserialize as `None` for `Option` fields.
+    /// # Arguments
+    /// * `key` - The key of the hash.
+    /// # Returns
+    /// * `Option<T>` - The deserialized struct, or `None` if the hash does not exist.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the fields could not be deserialized.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn hgetall_as<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>> {
+        let resp = self.execute_map_command(Command::HGETALL {
+            key: key.to_string(),
+        })?;
+        // A hash cannot be empty, so a reply without fields is a missing key as well.
+        let resp = match resp {
+            Some(resp) if !resp.is_empty() => resp,
+            _ => return Ok(None),
+        };
+        from_fields(resp.fields)
+            .map(Some)
+            .map_err(|source| json_error(key, source))
+    }
+
+    /// Serializes a flat struct into field/value pairs and stores them in the hash at `key`.
+    /// Fields that serialize to null, such as `None`, are deleted from the hash with HDEL, sent in
+    /// the same pipeline as the HSET, so that a value stored before does not linger. Nested
+    /// structures are rejected with an error rather than being flattened.
+    /// # Arguments
+    /// * `key` - The key of the hash.
+    /// * `value` - The struct to store.
+    /// # Returns
+    /// * [`ScalarValue`] - The number of fields that were added.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, or
+    ///   [`CommandError::JsonError`] if the value is not a flat struct.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn hset_struct<T: Serialize>(&mut self, key: &str, value: &T) -> Result<ScalarValue> {
+        let (fields, nulls) = to_fields(value).map_err(|source| json_error(key, source))?;
+        if fields.is_empty() {
+            return Err(json_error(
+                key,
+                serde::ser::Error::custom("the struct has no fields to store"),
+            ));
+        }
+        let mut pipeline = self.pipeline();
+        let added = pipeline.queue(Command::HSET {
+            key: key.to_string(),
+            fields,
+        });
+        let cleared = (!nulls.is_empty()).then(|| {
+            pipeline.queue::<u64>(Command::HDEL {
+                key: key.to_string(),
+                fields: nulls,
+            })
+        });
+        let results = pipeline.execute()?;
+        if let Some(cleared) = cleared {
+            cleared.get(&results)?;
+        }
+        Ok(added.get(&results)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::errors::StreamError;
+    use crate::testutil::{FakeServer, Reply};
+    const HOST: &str = "localhost";
+    const PORT: u16 = 7379;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Address {
+        street: String,
+        zip: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        name: String,
+        age: i64,
+        tags: Vec<String>,
+        address: Address,
+        nickname: Option<String>,
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "Daniel".to_string(),
+            age: 42,
+            tags: vec!["admin".to_string(), "dice".to_string()],
+            address: Address {
+                street: "Some street 1".to_string(),
+                zip: 8000,
+            },
+            nickname: None,
+        }
+    }
+
+    #[test]
+    fn test_from_json_null_is_none() {
+        let value: Option<Profile> = from_json("key", ScalarValue::VNull).unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn test_from_json_int_reply() {
+        let value: Option<i64> = from_json("key", ScalarValue::VInt(5)).unwrap();
+        assert_eq!(value, Some(5));
+    }
+
+    #[test]
+    fn test_from_json_error_has_key() {
+        let result: Result<Option<Profile>> =
+            from_json("somekey", ScalarValue::VStr("not json".to_string()));
+        match result {
+            Err(ClientError::StreamError(StreamError::CommandError(CommandError::JsonError {
+                key,
+                ..
+            }))) => {
+                assert_eq!(key, "somekey")
+            }
+            other => panic!("Expected JsonError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_set_get_json() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = "testsetgetjson";
+        client.set_json(key, &profile()).unwrap();
+        let value: Option<Profile> = client.get_json(key).unwrap();
+        assert_eq!(value, Some(profile()));
+    }
+
+    #[test]
+    fn test_get_json_missing() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = "testgetjsonmissing";
+        client.del(key).unwrap();
+        let value: Option<Profile> = client.get_json(key).unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn test_hset_hget_json() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = "testhsethgetjson";
+        client.hset_json(key, "profile", &profile()).unwrap();
+        let value: Option<Profile> = client.hget_json(key, "profile").unwrap();
+        assert_eq!(value, Some(profile()));
+        let missing: Option<Profile> = client.hget_json(key, "missing").unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        name: String,
+        balance: i64,
+        rate: f64,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_hset_struct_hgetall_as() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = format!("testhsetstruct{}", uuid::Uuid::new_v4());
+        let account = Account {
+            name: "dice".to_string(),
+            balance: -12,
+            rate: 0.25,
+            note: Some("vip".to_string()),
+        };
+        client.hset_struct(&key, &account).unwrap();
+        let value: Option<Account> = client.hgetall_as(&key).unwrap();
+        assert_eq!(value, Some(account));
+    }
+
+    #[test]
+    fn test_hset_struct_none_field() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = format!("testhsetstructnone{}", uuid::Uuid::new_v4());
+        let account = Account {
+            name: "dice".to_string(),
+            balance: 1,
+            rate: 1.0,
+            note: None,
+        };
+        client.hset_struct(&key, &account).unwrap();
+        let value: Option<Account> = client.hgetall_as(&key).unwrap();
+        assert_eq!(value, Some(account));
+    }
+
+    #[test]
+    fn test_hset_struct_clears_field() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let key = format!("testhsetstructclear{}", uuid::Uuid::new_v4());
+        let mut account = Account {
+            name: "dice".to_string(),
+            balance: 1,
+            rate: 1.0,
+            note: Some("vip".to_string()),
+        };
+        client.hset_struct(&key, &account).unwrap();
+        account.note = None;
+        client.hset_struct(&key, &account).unwrap();
+        let value: Option<Account> = client.hgetall_as(&key).unwrap();
+        assert_eq!(value, Some(account));
+        assert_eq!(client.hget(&key, "note").unwrap(), ScalarValue::VNull);
+    }
+
+    #[test]
+    fn test_hset_struct_deletes_null_fields() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VInt(3)),
+            Reply::Value(ScalarValue::VInt(1)),
+        ]]);
+        let mut client = Client::new("127.0.0.1".to_string(), server.port).unwrap();
+        let account = Account {
+            name: "dice:null".to_string(),
+            balance: 1,
+            rate: 1.0,
+            note: None,
+        };
+        let added = client.hset_struct("account", &account).unwrap();
+        assert_eq!(added, ScalarValue::VInt(3));
+        drop(client);
+        let received = server.received();
+        let (name, args) = &received[1];
+        assert_eq!(name, "HSET");
+        // The values are stored exactly as they were serialized.
+        assert!(args.windows(2).any(|pair| pair == ["name", "dice:null"]));
+        assert!(!args.contains(&"note".to_string()));
+        assert_eq!(
+            received[2],
+            ("HDEL".to_string(), vec!["account".into(), "note".into()])
+        );
+    }
+
+    #[test]
+    fn test_hgetall_as_missing() {
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let value: Option<Account> = client.hgetall_as("testhgetallasmissing").unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn test_hset_struct_nested_is_error() {
+        #[derive(Serialize)]
+        struct Nested {
+            account: Account,
+        }
+        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let nested = Nested {
+            account: Account {
+                name: "dice".to_string(),
+                balance: 1,
+                rate: 1.0,
+                note: None,
+            },
+        };
+        let result = client.hset_struct("testhsetstructnested", &nested);
+        assert!(matches!(
+            result,
+            Err(ClientError::StreamError(StreamError::CommandError(
+                CommandError::JsonError { .. }
+            )))
+        ));
+    }
+}
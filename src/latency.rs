@@ -0,0 +1,257 @@
+//! # Latency Module
+//! Latency histograms of the commands of a client, enabled with
+//! [`ClientBuilder::track_latency`](crate::client::ClientBuilder::track_latency) and read with
+//! [`Client::latency_report`](crate::client::Client::latency_report).
+//!
+//! Every tracked command has a histogram of fixed, logarithmically spaced buckets, and commands
+//! outside the tracked set share the [`OTHER`] histogram, so the memory of the tracker does not
+//! grow with the number of commands. The percentiles are the upper bounds of the buckets they
+//! fall in, which are at most 19% above the exact value.
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The name commands outside the tracked set are reported under.
+pub const OTHER: &str = "other";
+
+/// The commands with a histogram of their own.
+const TRACKED: [&str; 22] = [
+    "DECR",
+    "DECRBY",
+    "DEL",
+    "ECHO",
+    "EXISTS",
+    "EXPIRE",
+    "EXPIREAT",
+    "EXPIRETIME",
+    "GET",
+    "GETDEL",
+    "GETEX",
+    "HGET",
+    "HGETALL",
+    "HSET",
+    "INCR",
+    "INCRBY",
+    "KEYS",
+    "PEXPIRETIME",
+    "PING",
+    "SET",
+    "TTL",
+    "TYPE",
+];
+
+/// Buckets per doubling of the latency.
+const BUCKETS_PER_DOUBLING: f64 = 4.0;
+/// Bucket `i` holds latencies below `2^((i + 1) / 4)` microseconds, so the last bucket starts at
+/// about an hour.
+const BUCKETS: usize = 128;
+
+/// The latency of a command, summarized from its histogram.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandLatency {
+    /// The number of times the command was executed.
+    pub count: u64,
+    /// The median latency.
+    pub p50: Duration,
+    /// The 95th percentile latency.
+    pub p95: Duration,
+    /// The 99th percentile latency.
+    pub p99: Duration,
+    /// The highest latency, exact rather than bucketed.
+    pub max: Duration,
+}
+
+/// The latency of every command a client executed since latency tracking started, returned by
+/// [`Client::latency_report`](crate::client::Client::latency_report).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// The latency by command name. Commands that were not executed are left out, and commands
+    /// outside the tracked set are reported together under [`OTHER`].
+    pub commands: HashMap<&'static str, CommandLatency>,
+}
+
+impl LatencyReport {
+    /// Returns the latency of the command with the given name, if it was executed.
+    pub fn get(&self, command: &str) -> Option<&CommandLatency> {
+        self.commands.get(command)
+    }
+}
+
+/// A histogram of latencies with fixed buckets.
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    max_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        self.buckets[bucket(elapsed)].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> CommandLatency {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let max = Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed));
+        let count = counts.iter().sum();
+        let percentile = |quantile: f64| {
+            // The rank of the percentile, counting from 1.
+            let rank = ((quantile * count as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            let index = counts
+                .iter()
+                .position(|&n| {
+                    seen += n;
+                    seen >= rank
+                })
+                .unwrap_or(BUCKETS - 1);
+            upper_bound(index).min(max)
+        };
+        CommandLatency {
+            count,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max,
+        }
+    }
+
+    fn reset(&self) {
+        self.buckets
+            .iter()
+            .for_each(|bucket| bucket.store(0, Ordering::Relaxed));
+        self.max_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The index of the bucket holding the given latency.
+fn bucket(elapsed: Duration) -> usize {
+    let micros = elapsed.as_secs_f64() * 1e6;
+    if micros < 1.0 {
+        return 0;
+    }
+    let index = (micros.log2() * BUCKETS_PER_DOUBLING) as usize;
+    index.min(BUCKETS - 1)
+}
+
+/// The latency below which every latency of the bucket lies.
+fn upper_bound(index: usize) -> Duration {
+    let micros = 2f64.powf((index + 1) as f64 / BUCKETS_PER_DOUBLING);
+    Duration::from_secs_f64(micros / 1e6)
+}
+
+/// Records the latency of the commands of a client, one histogram per tracked command and one for
+/// the others.
+pub(crate) struct LatencyTracker {
+    histograms: Vec<Histogram>,
+}
+
+impl LatencyTracker {
+    pub(crate) fn new() -> Self {
+        LatencyTracker {
+            histograms: (0..=TRACKED.len()).map(|_| Histogram::new()).collect(),
+        }
+    }
+
+    /// Records that the command with the given name took `elapsed` to complete.
+    pub(crate) fn record(&self, command: &str, elapsed: Duration) {
+        let index = TRACKED
+            .iter()
+            .position(|&name| name == command)
+            .unwrap_or(TRACKED.len());
+        self.histograms[index].record(elapsed);
+    }
+
+    pub(crate) fn report(&self) -> LatencyReport {
+        let commands = TRACKED
+            .iter()
+            .chain([&OTHER])
+            .zip(&self.histograms)
+            .map(|(&name, histogram)| (name, histogram.summary()))
+            .filter(|(_, latency)| latency.count > 0)
+            .collect();
+        LatencyReport { commands }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.histograms.iter().for_each(Histogram::reset);
+    }
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        LatencyTracker::new()
+    }
+}
+
+impl fmt::Debug for LatencyTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyTracker").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_cover_latency() {
+        for micros in [0, 1, 2, 3, 10, 999, 1000, 1001, 123_456, 10_000_000] {
+            let elapsed = Duration::from_micros(micros);
+            let index = bucket(elapsed);
+            assert!(elapsed < upper_bound(index), "{:?}", elapsed);
+            if index > 0 {
+                assert!(elapsed >= upper_bound(index - 1), "{:?}", elapsed);
+            }
+        }
+        assert_eq!(bucket(Duration::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let tracker = LatencyTracker::new();
+        for millis in 1..=100 {
+            tracker.record("GET", Duration::from_millis(millis));
+        }
+        tracker.record("SET", Duration::from_micros(500));
+        let report = tracker.report();
+        assert_eq!(report.commands.len(), 2);
+        let get = report.get("GET").unwrap();
+        assert_eq!(get.count, 100);
+        assert_eq!(get.max, Duration::from_millis(100));
+        for (latency, exact) in [(get.p50, 50), (get.p95, 95), (get.p99, 99)] {
+            let exact = Duration::from_millis(exact);
+            assert!(latency >= exact, "{:?} < {:?}", latency, exact);
+            assert!(latency <= exact.mul_f64(1.2), "{:?} > {:?}", latency, exact);
+        }
+        // A single sample is reported exactly, through the maximum.
+        let set = report.get("SET").unwrap();
+        assert_eq!((set.count, set.p99), (1, Duration::from_micros(500)));
+    }
+
+    #[test]
+    fn test_untracked_commands_share_a_histogram() {
+        let tracker = LatencyTracker::new();
+        tracker.record("HANDSHAKE", Duration::from_millis(1));
+        tracker.record("FLUSHDB", Duration::from_millis(2));
+        let report = tracker.report();
+        assert_eq!(report.commands.keys().collect::<Vec<_>>(), vec![&OTHER]);
+        assert_eq!(report.get(OTHER).unwrap().count, 2);
+        tracker.reset();
+        assert_eq!(tracker.report(), LatencyReport::default());
+    }
+}
@@ -64,11 +64,48 @@
 //! ```
 //! This SDK is a work in progress and is not yet stable. Please report any issues you encounter.
 
+#[cfg(feature = "async")]
+pub mod asyncclient;
+pub(crate) mod breaker;
 pub mod client;
+#[cfg(feature = "async")]
+pub mod codec;
 pub(crate) mod commandrpc;
 pub mod commands;
 pub(crate) mod commandstream;
+pub mod config;
+pub mod counter;
+#[cfg(feature = "serde")]
+pub mod dump;
 pub mod errors;
+pub(crate) mod framing;
+pub(crate) mod heartbeat;
+pub mod interceptor;
+#[cfg(feature = "serde")]
+pub(crate) mod jsonrpc;
+pub mod latency;
+pub mod lock;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod observer;
+pub mod pipeline;
+pub mod ratelimit;
+pub mod replay;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod stats;
 mod stream;
+pub mod subscription;
+#[cfg(test)]
+mod testserver;
+#[cfg(test)]
+mod testutil;
+pub(crate) mod transport;
+pub mod watchedmap;
 pub(crate) mod watchrpc;
 pub mod watchstream;
+#[cfg(feature = "wire")]
+pub mod wire;
+
+/// Target of the log records emitted by the client, so they can be filtered as one.
+pub(crate) const LOG_TARGET: &str = "dicedb_rs";
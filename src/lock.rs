@@ -0,0 +1,505 @@
+//! # Lock Module
+//! A lock on a named resource, held in a key of a single DiceDB server so that several processes
+//! can take turns on work that must not run concurrently.
+//!
+//! The lock is a key holding a random token that expires after a time to live. Acquiring sets the
+//! key only if it does not exist, and releasing deletes it only if it still holds the token of
+//! the guard, so a holder whose lock expired cannot release the lock of the next holder.
+//!
+//! ## Safety
+//! The lock is an efficiency mechanism, not a guarantee of mutual exclusion:
+//! * The lock lives on a single server. If the server restarts without persisting the key, or
+//!   fails over to a replica that has not seen it yet, another client can acquire it while the
+//!   first still believes it holds it.
+//! * The lock expires after its time to live even if the holder is still working, for instance
+//!   after a long garbage collection pause or a network partition. Call [`LockGuard::extend`]
+//!   before the time to live runs out when work can take longer, and check its result.
+//! * An extension reads the token before setting the new time to live, which leaves the value of
+//!   the key alone. If the lock expires and is acquired by another client between the two, the
+//!   lock of the other client gets the new time to live, and the extension reports success
+//!   although the guard no longer holds the lock.
+//! * The server has no conditional delete, so a release reads the token before deleting the key.
+//!   If the lock expires and is acquired by another client between the two, the release deletes
+//!   the new lock and puts it back with the time to live of the guard, leaving a short window in
+//!   which a third client can acquire it.
+//!
+//! Where correctness depends on exclusive access, protect the resource itself as well, for
+//! instance with a version that is checked on every write.
+use std::{
+    ops::{Deref, DerefMut},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    client::Client,
+    commands::{AsArg, Command, Execute, ScalarValue, SetInput, SetOption},
+    errors::{ClientError, CommandError},
+    LOG_TARGET,
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+/// How long to wait between attempts to acquire a lock that is held by someone else.
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Client {
+    /// Acquires the lock on a resource, waiting for it to be released or to expire if it is held
+    /// by someone else. See the [`lock`](crate::lock) module for the safety caveats.
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use dicedb_rs::client::Client;
+    ///
+    /// let mut client = Client::new("localhost".to_string(), 7379)?;
+    /// if let Some(mut guard) = client.lock("report", Duration::from_secs(30), Duration::from_secs(5))? {
+    ///     guard.set("report-status", "running")?;
+    ///     guard.release()?;
+    /// }
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Arguments
+    /// * `resource` - The name of the resource, used as the key of the lock.
+    /// * `ttl` - How long the lock is held unless it is released or extended first, rounded up to
+    /// whole milliseconds.
+    /// * `acquire_timeout` - How long to wait for the lock. With a zero timeout the lock is tried
+    /// once.
+    /// # Returns
+    /// * [`LockGuard`] - The held lock, or `None` if it could not be acquired in time.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::InvalidInput`] - If the time to live is zero or too large.
+    pub fn lock(
+        &mut self,
+        resource: &str,
+        ttl: Duration,
+        acquire_timeout: Duration,
+    ) -> Result<Option<LockGuard<'_>>> {
+        let token = Uuid::new_v4().to_string();
+        let deadline = Instant::now() + acquire_timeout;
+        loop {
+            if self.set_if_missing(resource, &token, ttl)? {
+                return Ok(Some(LockGuard {
+                    client: self,
+                    resource: resource.to_string(),
+                    token,
+                    ttl,
+                    released: false,
+                }));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            thread::sleep(left.min(RETRY_INTERVAL));
+        }
+    }
+
+    /// Sets the key to the value with an expiry if the key does not exist, returning whether it
+    /// was set.
+    fn set_if_missing(&mut self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let milliseconds = ttl_to_milliseconds(ttl)?;
+        let resp = self.execute_scalar_command(Command::SET {
+            key: key.to_string(),
+            value: SetInput::Str(value.to_string()),
+            option: SetOption::PX(milliseconds),
+            get: false,
+            nx: true,
+        })?;
+        Ok(resp != ScalarValue::VNull)
+    }
+}
+
+/// A held lock, returned by [`Client::lock`]. The guard borrows the client, which can still be
+/// used through it while the lock is held.
+///
+/// Dropping the guard releases the lock, logging a warning if that fails. Use
+/// [`LockGuard::release`] to handle the failure instead.
+#[derive(Debug)]
+pub struct LockGuard<'a> {
+    client: &'a mut Client,
+    resource: String,
+    token: String,
+    ttl: Duration,
+    released: bool,
+}
+
+impl LockGuard<'_> {
+    /// Returns the name of the locked resource.
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Releases the lock if it is still held by this guard.
+    /// # Returns
+    /// * `bool` - True if the lock was released, false if it had expired or was held by someone
+    /// else.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream, in which case the
+    /// lock expires after its time to live.
+    pub fn release(mut self) -> Result<bool> {
+        self.unlock()
+    }
+
+    /// Sets the time to live of the lock, if it is still held by this guard. The time to live is
+    /// rounded up to whole milliseconds.
+    ///
+    /// The token is read before the time to live is set with PEXPIRE, so the lock is never
+    /// written. See the [`lock`](crate::lock) module for what happens if the lock changes hands
+    /// in between.
+    /// # Returns
+    /// * `bool` - True if the lock was extended, false if it had expired or was held by someone
+    /// else.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::InvalidInput`] - If the time to live is zero or too large.
+    pub fn extend(&mut self, ttl: Duration) -> Result<bool> {
+        let milliseconds = ttl_to_milliseconds(ttl)?;
+        if !self.is_held()? {
+            return Ok(false);
+        }
+        // The server replies 0 if the lock expired after it was read.
+        let extended = self.client.execute_scalar_command(Command::PEXPIRE {
+            key: self.resource.clone(),
+            milliseconds,
+        })? == ScalarValue::VInt(1);
+        if extended {
+            self.ttl = ttl;
+        }
+        Ok(extended)
+    }
+
+    fn is_held(&mut self) -> Result<bool> {
+        let value = self.client.get(&self.resource)?;
+        Ok(value == ScalarValue::VStr(self.token.clone()))
+    }
+
+    fn unlock(&mut self) -> Result<bool> {
+        self.released = true;
+        if !self.is_held()? {
+            return Ok(false);
+        }
+        let removed = self.client.getdel(&self.resource)?;
+        if removed == ScalarValue::VStr(self.token.clone()) {
+            return Ok(true);
+        }
+        if removed != ScalarValue::VNull {
+            // The lock expired and was taken by someone else after it was read.
+            log::warn!(
+                target: LOG_TARGET,
+                "Lock on {} changed hands while it was released, restoring it",
+                self.resource
+            );
+            self.client
+                .set_if_missing(&self.resource, &removed.as_arg(), self.ttl)?;
+        }
+        Ok(false)
+    }
+}
+
+impl Deref for LockGuard<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client
+    }
+}
+
+impl DerefMut for LockGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Err(e) = self.unlock() {
+            log::warn!(
+                target: LOG_TARGET,
+                "Failed to release the lock on {}: {}",
+                self.resource,
+                e
+            );
+        }
+    }
+}
+
+/// The longest time to live of a lock. The server keeps expiries as signed milliseconds since the
+/// Unix epoch, so an expiry cannot be further from now than that.
+fn max_ttl_milliseconds() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| {
+            u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+        });
+    (i64::MAX as u64).saturating_sub(now)
+}
+
+fn ttl_to_milliseconds(ttl: Duration) -> std::result::Result<u64, CommandError> {
+    let milliseconds = ttl
+        .as_millis()
+        .checked_add(u128::from(ttl.subsec_nanos() % 1_000_000 > 0))
+        .and_then(|milliseconds| u64::try_from(milliseconds).ok());
+    let max = max_ttl_milliseconds();
+    match milliseconds {
+        Some(milliseconds) if (1..=max).contains(&milliseconds) => Ok(milliseconds),
+        _ => Err(CommandError::InvalidInput(format!(
+            "lock ttl {:?} must be between one millisecond and {} milliseconds",
+            ttl, max
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        commands::CommandDescriptor,
+        errors::StreamError,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    type Store = Arc<Mutex<HashMap<String, (String, Instant)>>>;
+
+    /// A client backed by a store shared with other clients, answering the commands of a lock
+    /// like a server would, including expiry.
+    fn shared_client(store: &Store) -> Client {
+        let store = store.clone();
+        Client::with_executor(move |command: &CommandDescriptor| {
+            let args = command.args();
+            let mut store = store.lock().unwrap();
+            store.retain(|_, (_, expires)| *expires > Instant::now());
+            let value = match command.name() {
+                "SET" => {
+                    assert_eq!(args[2], "PX");
+                    let ttl = Duration::from_millis(args[3].parse().unwrap());
+                    let previous = store.get(&args[0]).map(|(value, _)| value.clone());
+                    match args.get(4).map(String::as_str) {
+                        Some("NX") if previous.is_some() => return Ok(ScalarValue::VNull),
+                        Some("NX") | None => {}
+                        option => panic!("unexpected option {:?}", option),
+                    }
+                    store.insert(args[0].clone(), (args[1].clone(), Instant::now() + ttl));
+                    ScalarValue::VStr("OK".to_string())
+                }
+                "PEXPIRE" => match store.get_mut(&args[0]) {
+                    Some((_, expires)) => {
+                        *expires = Instant::now() + Duration::from_millis(args[1].parse().unwrap());
+                        ScalarValue::VInt(1)
+                    }
+                    None => ScalarValue::VInt(0),
+                },
+                "GET" => store
+                    .get(&args[0])
+                    .map_or(ScalarValue::VNull, |(value, _)| {
+                        ScalarValue::VStr(value.clone())
+                    }),
+                "GETDEL" => store
+                    .remove(&args[0])
+                    .map_or(ScalarValue::VNull, |(value, _)| ScalarValue::VStr(value)),
+                name => panic!("unexpected command {}", name),
+            };
+            Ok(value)
+        })
+    }
+
+    fn holder(store: &Store, resource: &str) -> Option<String> {
+        store
+            .lock()
+            .unwrap()
+            .get(resource)
+            .map(|(token, _)| token.clone())
+    }
+
+    #[test]
+    fn test_only_one_contender_acquires() {
+        let store = Store::default();
+        let mut first = shared_client(&store);
+        let mut second = shared_client(&store);
+        let guard = first
+            .lock("testlock", Duration::from_secs(10), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        let started = Instant::now();
+        assert!(second
+            .lock(
+                "testlock",
+                Duration::from_secs(10),
+                Duration::from_millis(120)
+            )
+            .unwrap()
+            .is_none());
+        assert!(started.elapsed() >= Duration::from_millis(120));
+        assert!(guard.release().unwrap());
+        assert_eq!(holder(&store, "testlock"), None);
+        let contender = second
+            .lock("testlock", Duration::from_secs(10), Duration::ZERO)
+            .unwrap();
+        assert!(contender.is_some());
+    }
+
+    #[test]
+    fn test_expired_lock_is_taken_over() {
+        let store = Store::default();
+        let mut first = shared_client(&store);
+        let mut second = shared_client(&store);
+        let mut stale = first
+            .lock("testlock", Duration::from_millis(100), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        let mut newer = second
+            .lock("testlock", Duration::from_secs(10), Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(holder(&store, "testlock"), Some(newer.token.clone()));
+
+        // The stale guard neither releases nor extends the lock of the newer holder.
+        assert!(!stale.extend(Duration::from_secs(60)).unwrap());
+        assert!(!stale.release().unwrap());
+        assert_eq!(holder(&store, "testlock"), Some(newer.token.clone()));
+        assert!(newer.extend(Duration::from_secs(60)).unwrap());
+        drop(newer);
+        assert_eq!(holder(&store, "testlock"), None);
+    }
+
+    #[test]
+    fn test_extend_in_milliseconds() {
+        let store = Store::default();
+        let mut client = shared_client(&store);
+        let mut guard = client
+            .lock("testlock", Duration::from_secs(10), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert!(guard.extend(Duration::from_millis(1500)).unwrap());
+        let expires = store.lock().unwrap()["testlock"].1;
+        let left = expires.saturating_duration_since(Instant::now());
+        assert!(left > Duration::from_millis(1000), "{:?}", left);
+        assert!(left <= Duration::from_millis(1500), "{:?}", left);
+        assert_eq!(holder(&store, "testlock"), Some(guard.token.clone()));
+    }
+
+    #[test]
+    fn test_extend_never_writes_the_lock() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        let mut token = String::new();
+        let mut client = Client::with_executor(move |command: &CommandDescriptor| {
+            log.lock().unwrap().push(command.clone());
+            let value = match command.name() {
+                "SET" => {
+                    token = command.args()[1].clone();
+                    ScalarValue::VStr("OK".to_string())
+                }
+                "GET" => ScalarValue::VStr(token.clone()),
+                // The lock expires after it is read.
+                "PEXPIRE" => ScalarValue::VInt(0),
+                name => panic!("unexpected command {}", name),
+            };
+            Ok(value)
+        });
+        let mut guard = client
+            .lock("testlock", Duration::from_millis(1500), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert!(!guard.extend(Duration::from_millis(2500)).unwrap());
+        assert_eq!(guard.ttl, Duration::from_millis(1500));
+        guard.released = true;
+        drop(guard);
+        let received = received.lock().unwrap();
+        let names: Vec<&str> = received.iter().map(CommandDescriptor::name).collect();
+        assert_eq!(names, ["SET", "GET", "PEXPIRE"]);
+        assert_eq!(received[2].args(), ["testlock", "2500"]);
+    }
+
+    #[test]
+    fn test_release_restores_lock_taken_in_between() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        let mut token = String::new();
+        let mut client = Client::with_executor(move |command: &CommandDescriptor| {
+            log.lock().unwrap().push(command.clone());
+            let value = match command.name() {
+                "SET" if token.is_empty() => {
+                    token = command.args()[1].clone();
+                    ScalarValue::VStr("OK".to_string())
+                }
+                "SET" => ScalarValue::VStr("OK".to_string()),
+                // The lock expires and is taken by someone else after it is read.
+                "GET" => ScalarValue::VStr(token.clone()),
+                _ => ScalarValue::VStr("other".to_string()),
+            };
+            Ok(value)
+        });
+        let guard = client
+            .lock("testlock", Duration::from_millis(1500), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert!(!guard.release().unwrap());
+        let received = received.lock().unwrap();
+        assert_eq!(
+            received.last().unwrap(),
+            &CommandDescriptor::new(
+                "SET",
+                ["testlock", "other", "PX", "1500", "NX"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn test_lock_command() {
+        let server = FakeServer::start(vec![vec![Reply::ok(), Reply::Value(ScalarValue::VNull)]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap()
+            .with_prefix("app");
+        assert!(client
+            .lock("testlock", Duration::from_micros(2500), Duration::ZERO)
+            .unwrap()
+            .is_none());
+        drop(client);
+        let (name, args) = server.received().pop().unwrap();
+        assert_eq!(name, "SET");
+        assert_eq!(args[0], "app:testlock");
+        assert_eq!(args[2..], ["PX", "3", "NX"]);
+    }
+
+    #[test]
+    fn test_invalid_ttl() {
+        let mut client = shared_client(&Store::default());
+        let result = client.lock("testlock", Duration::ZERO, Duration::ZERO);
+        assert!(matches!(
+            result,
+            Err(ClientError::StreamError(StreamError::CommandError(
+                CommandError::InvalidInput(_)
+            )))
+        ));
+        // An expiry past `i64::MAX` milliseconds since the Unix epoch cannot be kept.
+        let too_long = Duration::from_millis(i64::MAX as u64);
+        let error = ttl_to_milliseconds(too_long).unwrap_err().to_string();
+        let expected = format!(
+            "invalid input: lock ttl {:?} must be between one millisecond and ",
+            too_long
+        );
+        assert!(error.starts_with(&expected), "{}", error);
+        let max = max_ttl_milliseconds();
+        assert!(max < i64::MAX as u64 - 1_700_000_000_000);
+        assert!(error.ends_with(" milliseconds"), "{}", error);
+        let long = Duration::from_millis(max - 60_000);
+        assert_eq!(ttl_to_milliseconds(long).unwrap(), max - 60_000);
+    }
+}
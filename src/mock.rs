@@ -0,0 +1,420 @@
+//! # Mock Module
+//! Test doubles for code that uses a client, available with the `test-util` feature.
+//!
+//! [`MemoryStore`] answers the common string commands from a map, like a server would. It is a
+//! [`CommandExecutor`], so a blocking client can run against it with
+//! [`Client::with_executor`](crate::client::Client::with_executor). With the `async` feature,
+//! [`MockAsyncClient`] runs the same store behind
+//! [`AsyncDiceCommands`](crate::asyncclient::AsyncDiceCommands), with latencies and errors
+//! controlled by the test.
+//! # Example
+//! ```
+//! use dicedb_rs::client::Client;
+//! use dicedb_rs::commands::ScalarValue;
+//! use dicedb_rs::mock::MemoryStore;
+//!
+//! let mut client = Client::with_executor(MemoryStore::new());
+//! client.set("count", 1).unwrap();
+//! client.incr("count").unwrap();
+//! assert_eq!(client.get("count").unwrap(), ScalarValue::VInt(2));
+//! ```
+use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+#[cfg(feature = "async")]
+use crate::{
+    asyncclient::AsyncDiceCommands,
+    commands::{Command, DelInput, SetInput, SetOption},
+    errors::ClientError,
+};
+use crate::{
+    commands::{CommandDescriptor, CommandExecutor, ScalarValue},
+    errors::{CommandError, StreamError},
+};
+
+/// An in-memory store answering PING, ECHO, SET, GET, DEL, EXISTS, INCR, INCRBY, DECR, DECRBY
+/// and FLUSHDB. Other commands, and options of SET, fail with a server error.
+///
+/// Values that are integers are stored as integers, and INCR, INCRBY, DECR and DECRBY wrap around
+/// past [`i64::MAX`] and [`i64::MIN`], like the server does.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    values: HashMap<String, ScalarValue>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns the value of `key`, if any, without going through a command.
+    pub fn value(&self, key: &str) -> Option<&ScalarValue> {
+        self.values.get(key)
+    }
+
+    /// Adds `delta` to the integer at `key`, which starts at zero, wrapping around on overflow.
+    fn add(&mut self, key: &str, delta: i64) -> Result<ScalarValue, CommandError> {
+        let current = match self.values.get(key) {
+            None => 0,
+            Some(ScalarValue::VInt(value)) => *value,
+            Some(_) => {
+                return Err(CommandError::server(
+                    "ERR value is not an integer or out of range".to_string(),
+                ))
+            }
+        };
+        let value = current.wrapping_add(delta);
+        self.values
+            .insert(key.to_string(), ScalarValue::VInt(value));
+        Ok(ScalarValue::VInt(value))
+    }
+}
+
+/// Parses the delta argument of INCRBY and DECRBY.
+fn delta(arg: &str) -> Result<i64, CommandError> {
+    arg.parse().map_err(|_| {
+        CommandError::server("ERR value is not an integer or out of range".to_string())
+    })
+}
+
+impl CommandExecutor for MemoryStore {
+    fn execute(&mut self, command: &CommandDescriptor) -> Result<ScalarValue, StreamError> {
+        let ok = || ScalarValue::VStr("OK".to_string());
+        let value = match (command.name(), command.args()) {
+            ("PING", []) => ScalarValue::VStr("PONG".to_string()),
+            ("ECHO", [message]) => ScalarValue::VStr(message.clone()),
+            ("SET", [key, value]) => {
+                let value = match value.parse() {
+                    Ok(value) => ScalarValue::VInt(value),
+                    Err(_) => ScalarValue::VStr(value.clone()),
+                };
+                self.values.insert(key.clone(), value);
+                ok()
+            }
+            ("GET", [key]) => self.values.get(key).cloned().unwrap_or(ScalarValue::VNull),
+            ("DEL", keys) => {
+                let removed = keys.iter().filter(|key| self.values.remove(*key).is_some());
+                ScalarValue::VInt(removed.count() as i64)
+            }
+            ("EXISTS", keys) => {
+                let found = keys.iter().filter(|key| self.values.contains_key(*key));
+                ScalarValue::VInt(found.count() as i64)
+            }
+            ("INCR", [key]) => self.add(key, 1)?,
+            ("INCRBY", [key, by]) => self.add(key, delta(by)?)?,
+            ("DECR", [key]) => self.add(key, -1)?,
+            ("DECRBY", [key, by]) => self.add(key, delta(by)?.wrapping_neg())?,
+            ("FLUSHDB", []) => {
+                self.values.clear();
+                ok()
+            }
+            (name, _) => {
+                return Err(CommandError::server(format!(
+                    "ERR unsupported command or arguments for the memory store '{}'",
+                    name
+                ))
+                .into())
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// A stand-in for an [`AsyncClient`](crate::asyncclient::AsyncClient), answering from a
+/// [`MemoryStore`]. Clones share the store and the settings, so a test keeps a clone to steer the
+/// one handed to the code under test.
+///
+/// Every command waits for the latency of the mock with [`tokio::time::sleep`], so under a
+/// runtime with paused time, like `#[tokio::test(start_paused = true)]`, latencies and timeouts
+/// pass instantly and deterministically.
+/// # Example
+/// ```
+/// use std::time::Duration;
+///
+/// use dicedb_rs::asyncclient::AsyncDiceCommands;
+/// use dicedb_rs::commands::ScalarValue;
+/// use dicedb_rs::errors::StreamError;
+/// use dicedb_rs::mock::MockAsyncClient;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(async {
+/// let mock = MockAsyncClient::new();
+/// mock.set_latency(Duration::from_millis(1));
+/// mock.fail_next(StreamError::ProtocolDesync);
+/// assert!(mock.get("key").await.is_err());
+/// assert_eq!(mock.get("key").await.unwrap(), ScalarValue::VNull);
+/// # });
+/// ```
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug, Clone, Default)]
+pub struct MockAsyncClient {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug, Default)]
+struct MockState {
+    store: MemoryStore,
+    latency: Duration,
+    read_timeout: Option<Duration>,
+    errors: VecDeque<StreamError>,
+    commands: Vec<CommandDescriptor>,
+}
+
+#[cfg(feature = "async")]
+impl MockAsyncClient {
+    /// Create a mock with an empty store, no latency and no read timeout.
+    pub fn new() -> Self {
+        MockAsyncClient::default()
+    }
+
+    /// Create a mock answering from `store`.
+    pub fn with_store(store: MemoryStore) -> Self {
+        let mock = MockAsyncClient::new();
+        mock.state().store = store;
+        mock
+    }
+
+    /// Set how long every command takes from now on.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Bound how long a command waits for its reply, like
+    /// [`ClientBuilder::read_timeout`](crate::client::ClientBuilder::read_timeout) does for the
+    /// async client. A command slower than the timeout fails with a timed out IO error once the
+    /// timeout elapsed, without changing the store.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.state().read_timeout = timeout;
+    }
+
+    /// Fail the next command with `error` once its latency passed, without running it. Errors
+    /// queued by several calls fail the next commands in turn.
+    pub fn fail_next(&self, error: impl Into<StreamError>) {
+        self.state().errors.push_back(error.into());
+    }
+
+    /// Returns every command the mock received, in order, including failed ones.
+    pub fn commands(&self) -> Vec<CommandDescriptor> {
+        self.state().commands.clone()
+    }
+
+    /// Returns a copy of the store as it is now.
+    pub fn store(&self) -> MemoryStore {
+        self.state().store.clone()
+    }
+
+    async fn execute_scalar(&self, command: Command) -> Result<ScalarValue, ClientError> {
+        command.check_args()?;
+        let command = CommandDescriptor::from(command);
+        let (latency, read_timeout, error) = {
+            let mut state = self.state();
+            state.commands.push(command.clone());
+            (state.latency, state.read_timeout, state.errors.pop_front())
+        };
+        if let Some(timeout) = read_timeout.filter(|timeout| *timeout < latency) {
+            tokio::time::sleep(timeout).await;
+            return Err(StreamError::from(io::Error::from(io::ErrorKind::TimedOut)).into());
+        }
+        tokio::time::sleep(latency).await;
+        if let Some(error) = error {
+            return Err(error.into());
+        }
+        Ok(self.state().store.execute(&command)?)
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncDiceCommands for MockAsyncClient {
+    async fn decr(&self, key: &str) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::DECR {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    async fn decrby(&self, key: &str, delta: i64) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::DECRBY {
+            key: key.to_string(),
+            delta,
+        })
+        .await
+    }
+
+    async fn del<'a, T: Into<DelInput<'a>> + Send>(
+        &self,
+        keys: T,
+    ) -> Result<ScalarValue, ClientError> {
+        let keys = keys.into().into_keys();
+        self.execute_scalar(Command::DEL { keys }).await
+    }
+
+    async fn echo(&self, message: &str) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::ECHO {
+            message: message.to_string(),
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::GET {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    async fn incr(&self, key: &str) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::INCR {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    async fn incrby(&self, key: &str, delta: i64) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::INCRBY {
+            key: key.to_string(),
+            delta,
+        })
+        .await
+    }
+
+    async fn ping(&self) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::PING).await
+    }
+
+    async fn set<T: Into<SetInput> + Send>(
+        &self,
+        key: &str,
+        value: T,
+    ) -> Result<ScalarValue, ClientError> {
+        self.execute_scalar(Command::SET {
+            key: key.to_string(),
+            value: value.into(),
+            option: SetOption::None,
+            get: false,
+            nx: false,
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_store_behind_client() {
+        let mut client = Client::with_executor(MemoryStore::new());
+        client.set("name", "dice").unwrap();
+        client.set("count", 5).unwrap();
+        assert_eq!(client.decrby("count", 7).unwrap(), ScalarValue::VInt(-2));
+        assert!(client
+            .incr("name")
+            .unwrap_err()
+            .to_string()
+            .contains("not an integer"));
+        assert_eq!(client.del_count(vec!["name", "missing"]).unwrap(), 1);
+        assert_eq!(client.get("name").unwrap(), ScalarValue::VNull);
+    }
+
+    #[test]
+    fn test_memory_store_wraps_around() {
+        let mut client = Client::with_executor(MemoryStore::new());
+        client.set("count", i64::MAX).unwrap();
+        assert_eq!(client.incr("count").unwrap(), ScalarValue::VInt(i64::MIN));
+        assert_eq!(client.decr("count").unwrap(), ScalarValue::VInt(i64::MAX));
+        client.set("count", 0).unwrap();
+        assert_eq!(
+            client.decrby("count", i64::MIN).unwrap(),
+            ScalarValue::VInt(i64::MIN)
+        );
+        assert_eq!(
+            client.incrby("count", i64::MIN).unwrap(),
+            ScalarValue::VInt(0)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    mod asynctests {
+        use std::future::Future;
+
+        use tokio::time::Instant;
+
+        use super::*;
+
+        /// A service under test: counts a visit, retrying once if the store timed out.
+        async fn count_visit(db: &impl AsyncDiceCommands) -> Result<ScalarValue, ClientError> {
+            match db.incr("visits").await {
+                Err(e) if e.is_timeout() => db.incr("visits").await,
+                result => result,
+            }
+        }
+
+        fn elapsed<F: Future>(future: F) -> impl Future<Output = (F::Output, Duration)> {
+            async move {
+                let started = Instant::now();
+                let output = future.await;
+                (output, started.elapsed())
+            }
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn test_error_injection() {
+            let mock = MockAsyncClient::new();
+            mock.set_latency(Duration::from_millis(30));
+            mock.fail_next(io::Error::from(io::ErrorKind::ConnectionReset));
+            mock.fail_next(CommandError::server("ERR busy".to_string()));
+
+            let (result, took) = elapsed(mock.set("key", "value")).await;
+            assert!(result.unwrap_err().is_connection_error());
+            assert_eq!(took, Duration::from_millis(30));
+            assert!(mock.get("key").await.unwrap_err().is_server_error());
+            // Failed commands did not run.
+            assert_eq!(mock.store().value("key"), None);
+            assert_eq!(mock.get("key").await.unwrap(), ScalarValue::VNull);
+            let names: Vec<_> = mock
+                .commands()
+                .iter()
+                .map(|command| command.name().to_string())
+                .collect();
+            assert_eq!(names, ["SET", "GET", "GET"]);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn test_timeout_in_virtual_time() {
+            let mock = MockAsyncClient::new();
+            mock.set_read_timeout(Some(Duration::from_secs(1)));
+            mock.set_latency(Duration::from_secs(60));
+
+            let (result, took) = elapsed(count_visit(&mock)).await;
+            assert!(result.unwrap_err().is_timeout());
+            // Both attempts timed out after a virtual second each.
+            assert_eq!(took, Duration::from_secs(2));
+            assert_eq!(mock.store().value("visits"), None);
+
+            mock.fail_next(io::Error::from(io::ErrorKind::TimedOut));
+            mock.set_latency(Duration::from_millis(200));
+            let (result, took) = elapsed(count_visit(&mock)).await;
+            assert_eq!(result.unwrap(), ScalarValue::VInt(1));
+            assert_eq!(took, Duration::from_millis(400));
+
+            // A caller side timeout cancels a command before it ran.
+            let slow = tokio::time::timeout(Duration::from_millis(100), mock.incr("visits"));
+            assert!(slow.await.is_err());
+            assert_eq!(mock.store().value("visits"), Some(&ScalarValue::VInt(1)));
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! # Observer Module
+//! Hooks for collecting client metrics, such as command latencies, error counts and reconnects.
+//! Register an implementation of [`CommandObserver`] with
+//! [`ClientBuilder::observer`](crate::client::ClientBuilder::observer).
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+    commands::{wire, Command, WatchValue},
+    config::{guard, ConnInfo},
+    errors::StreamError,
+};
+
+/// Describes a command passed to [`CommandObserver::on_command`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandMeta {
+    name: &'static str,
+    arg_count: usize,
+    encoded_len: usize,
+}
+
+impl CommandMeta {
+    pub(crate) fn new(command: &Command) -> Self {
+        let encoded: wire::Command = command.clone().into();
+        CommandMeta {
+            name: command.name(),
+            arg_count: encoded.args.len(),
+            encoded_len: prost::Message::encoded_len(&encoded),
+        }
+    }
+
+    /// The name of the command as sent to the server, such as `GET` or `GET.WATCH`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The number of arguments sent with the command.
+    pub fn arg_count(&self) -> usize {
+        self.arg_count
+    }
+
+    /// The size of the command on the wire in bytes.
+    pub fn encoded_len(&self) -> usize {
+        self.encoded_len
+    }
+}
+
+/// Receives a call for every command a client executes, every reconnect and every watch message.
+/// Every method does nothing by default, so implement only the ones you need. A panic inside a
+/// method is caught and logged.
+/// # Example
+/// ```
+/// use dicedb_rs::errors::StreamError;
+/// use dicedb_rs::observer::{CommandMeta, CommandObserver};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct ErrorCounter(AtomicU64);
+///
+/// impl CommandObserver for ErrorCounter {
+///     fn on_command(&self, _: &CommandMeta, _: Duration, result: Result<(), &StreamError>) {
+///         if result.is_err() {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait CommandObserver: Send + Sync {
+    /// Called after every attempt to execute a command with the time it took and whether it
+    /// succeeded. A retried command is reported once per attempt.
+    fn on_command(&self, meta: &CommandMeta, elapsed: Duration, result: Result<(), &StreamError>) {
+        let _ = (meta, elapsed, result);
+    }
+
+    /// Called after a lost connection is re-established, with the number of attempts it took.
+    fn on_reconnect(&self, info: &ConnInfo, attempts: u32) {
+        let _ = (info, attempts);
+    }
+
+    /// Called for every value received on a watch stream.
+    fn on_watch_message(&self, value: &WatchValue) {
+        let _ = value;
+    }
+}
+
+impl<T: CommandObserver + ?Sized> CommandObserver for Arc<T> {
+    fn on_command(&self, meta: &CommandMeta, elapsed: Duration, result: Result<(), &StreamError>) {
+        (**self).on_command(meta, elapsed, result)
+    }
+
+    fn on_reconnect(&self, info: &ConnInfo, attempts: u32) {
+        (**self).on_reconnect(info, attempts)
+    }
+
+    fn on_watch_message(&self, value: &WatchValue) {
+        (**self).on_watch_message(value)
+    }
+}
+
+/// The observer registered for a client, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Observer(Option<Arc<dyn CommandObserver>>);
+
+impl Observer {
+    pub(crate) fn new(observer: impl CommandObserver + 'static) -> Self {
+        Observer(Some(Arc::new(observer)))
+    }
+
+    /// Whether an observer is registered, so the cost of observing can be skipped otherwise.
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub(crate) fn command(
+        &self,
+        meta: &CommandMeta,
+        elapsed: Duration,
+        result: Result<(), &StreamError>,
+    ) {
+        if let Some(observer) = &self.0 {
+            guard("on_command", || observer.on_command(meta, elapsed, result));
+        }
+    }
+
+    pub(crate) fn reconnected(&self, info: &ConnInfo, attempts: u32) {
+        if let Some(observer) = &self.0 {
+            guard("on_reconnect", || observer.on_reconnect(info, attempts));
+        }
+    }
+
+    pub(crate) fn watch_message(&self, value: &WatchValue) {
+        if let Some(observer) = &self.0 {
+            guard("on_watch_message", || observer.on_watch_message(value));
+        }
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Observer").field(&self.is_set()).finish()
+    }
+}
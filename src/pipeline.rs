@@ -0,0 +1,659 @@
+//! # Pipeline Module
+//! Pipelines send several commands before reading any of their replies, so a batch of commands
+//! costs one round trip instead of one per command. Create one with [`Client::pipeline`].
+use std::{fmt, marker::PhantomData, time::Instant};
+
+use crate::{
+    client::Client,
+    commands::{
+        Command, DelInput, ExpireAtOption, ExpireOption, FromScalarValue, GetexOption, HSetInput,
+        ScalarValue, SetInput, SetOption,
+    },
+    commandstream::CommandStream,
+    errors::{ClientError, CommandError, StreamError},
+    stream::{CommandSender, ScalarValueReceiver},
+};
+
+/// A batch of commands written to the server together and answered in order.
+///
+/// The command methods queue a command and return a [`Slot`] to retrieve its reply with, typed
+/// after the reply of the command. Nothing is sent until [`Pipeline::execute`] is called. Only
+/// commands with scalar replies can be pipelined, so HGETALL and the watch commands are not
+/// available, and pipelined commands are not retried.
+/// # Example
+/// ```no_run
+/// use dicedb_rs::client::Client;
+/// let mut client = Client::new("localhost".to_string(), 7379).unwrap();
+/// let mut pipeline = client.pipeline();
+/// pipeline.set("counter", 1);
+/// let counter = pipeline.incr("counter");
+/// let name = pipeline.get_as::<String>("name");
+/// let results = pipeline.execute().unwrap();
+/// let counter: i64 = counter.get(&results).unwrap();
+/// let name: Option<String> = name.get(&results).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    commands: Vec<Command>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub(crate) fn new(client: &'a mut Client) -> Self {
+        Pipeline {
+            client,
+            commands: Vec::new(),
+        }
+    }
+
+    /// The number of queued commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns true if no commands are queued.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Writes every queued command, then reads their replies.
+    /// # Returns
+    /// * `Vec<Result<ScalarValue, CommandError>>` - The reply of every command, in the order the
+    /// commands were queued. A command the server rejected does not affect the others.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream. The replies of a
+    /// batch that failed this way are lost, and the commands may or may not have been executed.
+    pub fn execute(self) -> Result<Vec<Result<ScalarValue, CommandError>>, ClientError> {
+        if self.commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        let client = self.client;
+        // A rejected command fails the pipeline before any command is sent.
+        let commands = match self
+            .commands
+            .into_iter()
+            .map(|command| client.prepare(command).map(|(command, _)| command))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(commands) => commands,
+            Err(e) => {
+                client.counters.error(e.kind());
+                return Err(e.into());
+            }
+        };
+        let names: Vec<&'static str> = commands.iter().map(Command::name).collect();
+        let started = Instant::now();
+        let result = client.through_breaker(|| match client.stream() {
+            Some(mut stream) => {
+                stream.refresh_if_idle();
+                let result = execute_batch(&mut stream, commands).map_err(|e| stream.recover(e));
+                stream.last_used = Instant::now();
+                result
+            }
+            None => client.execute_each(commands).unwrap_or(Ok(Vec::new())),
+        });
+        // Every command of the batch waited for the whole batch to complete.
+        if let Some(latency) = &client.options.latency {
+            let elapsed = started.elapsed();
+            names.iter().for_each(|name| latency.record(name, elapsed));
+        }
+        match &result {
+            Ok(replies) => replies
+                .iter()
+                .filter_map(|reply| reply.as_ref().err())
+                .for_each(|e| client.counters.error(e.kind())),
+            Err(e) => client.counters.error(e.kind()),
+        }
+        Ok(result?)
+    }
+
+    pub(crate) fn queue<T>(&mut self, command: Command) -> Slot<T> {
+        let slot = Slot {
+            index: self.commands.len(),
+            command: command.name(),
+            target: PhantomData,
+        };
+        self.commands.push(command);
+        slot
+    }
+
+    /// Queues DECR, see [`Client::decr`].
+    pub fn decr(&mut self, key: &str) -> Slot<i64> {
+        self.queue(Command::DECR {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues DECRBY, see [`Client::decrby`].
+    pub fn decrby(&mut self, key: &str, delta: i64) -> Slot<i64> {
+        self.queue(Command::DECRBY {
+            key: key.to_string(),
+            delta,
+        })
+    }
+
+    /// Queues DEL, see [`Client::del`].
+    pub fn del<'k, T: Into<DelInput<'k>>>(&mut self, keys: T) -> Slot<u64> {
+        let keys = keys.into().into_keys();
+        self.queue(Command::DEL { keys })
+    }
+
+    /// Queues ECHO, see [`Client::echo`].
+    pub fn echo(&mut self, message: &str) -> Slot<String> {
+        self.queue(Command::ECHO {
+            message: message.to_string(),
+        })
+    }
+
+    /// Queues EXISTS, see [`Client::exists`].
+    pub fn exists(&mut self, key: &str, additional_keys: Vec<&str>) -> Slot<u64> {
+        self.queue(Command::EXISTS {
+            key: key.to_string(),
+            additional_keys: additional_keys.iter().map(|&x| x.to_string()).collect(),
+        })
+    }
+
+    /// Queues EXPIRE, see [`Client::expire`].
+    pub fn expire(&mut self, key: &str, seconds: i64, option: ExpireOption) -> Slot<i64> {
+        self.queue(Command::EXPIRE {
+            key: key.to_string(),
+            seconds,
+            option,
+        })
+    }
+
+    /// Queues EXPIREAT, see [`Client::expireat`].
+    pub fn expireat(&mut self, key: &str, timestamp: i64, option: ExpireAtOption) -> Slot<i64> {
+        self.queue(Command::EXPIREAT {
+            key: key.to_string(),
+            timestamp,
+            option,
+        })
+    }
+
+    /// Queues EXPIRETIME, see [`Client::expiretime`].
+    pub fn expiretime(&mut self, key: &str) -> Slot<i64> {
+        self.queue(Command::EXPIRETIME {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues GET, see [`Client::get`].
+    pub fn get(&mut self, key: &str) -> Slot<ScalarValue> {
+        self.queue(Command::GET {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues GET with the reply converted to `T`, `None` if the key does not exist.
+    pub fn get_as<T: FromScalarValue>(&mut self, key: &str) -> Slot<Option<T>> {
+        self.queue(Command::GET {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues GETDEL, see [`Client::getdel`].
+    pub fn getdel(&mut self, key: &str) -> Slot<ScalarValue> {
+        self.queue(Command::GETDEL {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues GETEX, see [`Client::getex`].
+    pub fn getex(&mut self, key: &str, option: GetexOption) -> Slot<ScalarValue> {
+        self.queue(Command::GETEX {
+            key: key.to_string(),
+            ex: option,
+        })
+    }
+
+    /// Queues HGET, see [`Client::hget`].
+    pub fn hget(&mut self, key: &str, field: &str) -> Slot<ScalarValue> {
+        self.queue(Command::HGET {
+            key: key.to_string(),
+            field: field.to_string(),
+        })
+    }
+
+    /// Queues HDEL, see [`Client::hdel`].
+    pub fn hdel(&mut self, key: &str, fields: Vec<&str>) -> Slot<u64> {
+        self.queue(Command::HDEL {
+            key: key.to_string(),
+            fields: fields.into_iter().map(str::to_string).collect(),
+        })
+    }
+
+    /// Queues HSET, see [`Client::hset`].
+    pub fn hset<'k, T: Into<HSetInput<'k>>>(&mut self, key: &str, fields: T) -> Slot<ScalarValue> {
+        let fields = match fields.into() {
+            HSetInput::Single(field, value) => vec![(field.to_string(), value.to_string())],
+            HSetInput::Multiple(fields) => fields
+                .iter()
+                .map(|(f, v)| (f.to_string(), v.to_string()))
+                .collect(),
+        };
+        self.queue(Command::HSET {
+            key: key.to_string(),
+            fields,
+        })
+    }
+
+    /// Queues INCR, see [`Client::incr`].
+    pub fn incr(&mut self, key: &str) -> Slot<i64> {
+        self.queue(Command::INCR {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues INCRBY, see [`Client::incrby`].
+    pub fn incrby(&mut self, key: &str, delta: i64) -> Slot<i64> {
+        self.queue(Command::INCRBY {
+            key: key.to_string(),
+            delta,
+        })
+    }
+
+    /// Queues PING, see [`Client::ping`].
+    pub fn ping(&mut self) -> Slot<String> {
+        self.queue(Command::PING)
+    }
+
+    /// Queues SET, see [`Client::set`].
+    pub fn set<T: Into<SetInput>>(&mut self, key: &str, value: T) -> Slot<String> {
+        self.setex(key, value, SetOption::None)
+    }
+
+    /// Queues SET with GET, see [`Client::setget`].
+    pub fn setget<T: Into<SetInput>>(&mut self, key: &str, value: T) -> Slot<ScalarValue> {
+        self.queue(Command::SET {
+            key: key.to_string(),
+            value: value.into(),
+            option: SetOption::None,
+            get: true,
+            nx: false,
+        })
+    }
+
+    /// Queues SET with an option, see [`Client::setex`].
+    pub fn setex<T: Into<SetInput>>(
+        &mut self,
+        key: &str,
+        value: T,
+        option: SetOption,
+    ) -> Slot<String> {
+        self.queue(Command::SET {
+            key: key.to_string(),
+            value: value.into(),
+            option,
+            get: false,
+            nx: false,
+        })
+    }
+
+    /// Queues TTL, see [`Client::ttl`].
+    pub fn ttl(&mut self, key: &str) -> Slot<i64> {
+        self.queue(Command::TTL {
+            key: key.to_string(),
+        })
+    }
+
+    /// Queues TYPE, see [`Client::dtype`].
+    pub fn dtype(&mut self, key: &str) -> Slot<String> {
+        self.queue(Command::TYPE {
+            key: key.to_string(),
+        })
+    }
+}
+
+/// A handle to the reply of a command queued on a [`Pipeline`], converting it to `T`.
+pub struct Slot<T> {
+    index: usize,
+    command: &'static str,
+    target: PhantomData<fn() -> T>,
+}
+
+impl<T: FromScalarValue> Slot<T> {
+    /// The position of the command in the pipeline.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the reply of the command from the results of [`Pipeline::execute`], converted to
+    /// `T`.
+    /// # Errors
+    /// * [`CommandError::SlotError`] - If the results hold no reply for the command, the server
+    /// rejected the command, or the reply cannot be converted to `T`.
+    pub fn get(&self, results: &[Result<ScalarValue, CommandError>]) -> Result<T, CommandError> {
+        let error = |source: Option<CommandError>| CommandError::SlotError {
+            index: self.index,
+            command: self.command,
+            source: source.map(Box::new),
+        };
+        match results.get(self.index) {
+            None => Err(error(None)),
+            Some(Err(e)) => Err(error(Some(copy_error(e)))),
+            Some(Ok(value)) => T::from_scalar_value(value.clone())
+                .map_err(|value| error(Some(CommandError::UnexpectedResponse(value)))),
+        }
+    }
+}
+
+impl<T> Clone for Slot<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Slot<T> {}
+
+impl<T> fmt::Debug for Slot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Slot")
+            .field("index", &self.index)
+            .field("command", &self.command)
+            .field("target", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+/// Copies the error of a pipelined reply, which is one the server replied with or a reply that
+/// could not be decoded.
+fn copy_error(error: &CommandError) -> CommandError {
+    match error {
+        CommandError::ServerError { kind, message } => CommandError::ServerError {
+            kind: *kind,
+            message: message.clone(),
+        },
+        CommandError::DecodeError(e) => CommandError::DecodeError(e.clone()),
+        CommandError::TrailingData { remaining } => CommandError::TrailingData {
+            remaining: *remaining,
+        },
+        CommandError::UnexpectedResponse(value) => CommandError::UnexpectedResponse(value.clone()),
+        other => CommandError::server(format!("{:?}", other)),
+    }
+}
+
+/// Writes the commands together and reads one reply per command. Errors the server replied with
+/// are kept with the command they belong to, any other error fails the batch.
+fn execute_batch(
+    stream: &mut CommandStream,
+    commands: Vec<Command>,
+) -> Result<Vec<Result<ScalarValue, CommandError>>, StreamError> {
+    let count = commands.len();
+    stream.send_commands(commands)?;
+    let mut replies = Vec::with_capacity(count);
+    for _ in 0..count {
+        match stream.receive_scalar_value() {
+            Ok(value) => replies.push(Ok(value)),
+            Err(StreamError::CommandError(e)) => replies.push(Err(e)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::ClientBuilder,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        let mut replies = vec![Reply::ok()];
+        for i in 0..100 {
+            replies.push(match i % 3 {
+                0 => Reply::ok(),
+                1 => Reply::Value(ScalarValue::VStr(i.to_string())),
+                _ => Reply::Value(ScalarValue::VInt(i)),
+            });
+        }
+        let server = FakeServer::start(vec![replies]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            match i % 3 {
+                0 => {
+                    pipeline.set(&key, i);
+                }
+                1 => {
+                    pipeline.get(&key);
+                }
+                _ => {
+                    pipeline.incr(&key);
+                }
+            }
+        }
+        assert_eq!(pipeline.len(), 100);
+        let results = pipeline.execute().unwrap();
+        assert_eq!(results.len(), 100);
+        for (i, result) in results.into_iter().enumerate() {
+            let expected = match i % 3 {
+                0 => ScalarValue::VStr("OK".to_string()),
+                1 => ScalarValue::VStr(i.to_string()),
+                _ => ScalarValue::VInt(i64::try_from(i).unwrap()),
+            };
+            assert_eq!(result.unwrap(), expected, "reply {}", i);
+        }
+        drop(client);
+        let received = server.received();
+        assert_eq!(received.len(), 101);
+        for (i, (name, args)) in received.into_iter().skip(1).enumerate() {
+            let expected = ["SET", "GET", "INCR"][i % 3];
+            assert_eq!(
+                (name.as_str(), args[0].as_str()),
+                (expected, format!("key{}", i).as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipeline_latency() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::ok(),
+            Reply::Value(ScalarValue::VInt(1)),
+            Reply::Value(ScalarValue::VInt(2)),
+            Reply::Value(ScalarValue::VInt(3)),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .track_latency(true)
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        pipeline.set("a", 0);
+        pipeline.incr("a");
+        pipeline.incr("a");
+        pipeline.incr("a");
+        let started = Instant::now();
+        pipeline.execute().unwrap();
+        let elapsed = started.elapsed();
+        let report = client.latency_report();
+        let (set, incr) = (report.get("SET").unwrap(), report.get("INCR").unwrap());
+        assert_eq!((set.count, incr.count), (1, 3));
+        // Every queued command is recorded with the latency of the batch.
+        assert_eq!(set.max, incr.max);
+        assert_eq!(incr.p50, incr.max);
+        assert!(!incr.max.is_zero() && incr.max <= elapsed);
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_pipeline_failing_command() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VInt(1)),
+            Reply::Error("ERR value is not an integer or out of range".to_string()),
+            Reply::Value(ScalarValue::VInt(2)),
+            Reply::Value(ScalarValue::VStr("PONG".to_string())),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        pipeline.incr("a");
+        pipeline.incr("text");
+        pipeline.incr("a");
+        let results = pipeline.execute().unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &ScalarValue::VInt(1));
+        assert!(matches!(&results[1], Err(CommandError::ServerError { .. })));
+        assert_eq!(results[2].as_ref().unwrap(), &ScalarValue::VInt(2));
+        // The connection is still in sync after the batch.
+        assert_eq!(
+            client.ping().unwrap(),
+            ScalarValue::VStr("PONG".to_string())
+        );
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_pipeline_command_larger_than_limit() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("PONG".to_string())),
+            Reply::ok(),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .max_command_size(1024)
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        pipeline.incr("a");
+        pipeline.set("key", SetInput::Str("x".repeat(2048)));
+        let error = pipeline.execute().unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::StreamError(StreamError::CommandTooLarge { .. })
+        ));
+        // None of the batch was sent, not even with the next command.
+        assert_eq!(
+            client.ping().unwrap(),
+            ScalarValue::VStr("PONG".to_string())
+        );
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["HANDSHAKE", "PING"]);
+    }
+
+    #[test]
+    fn test_empty_pipeline() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        assert!(client.pipeline().execute().unwrap().is_empty());
+        drop(client);
+        assert_eq!(server.received().len(), 1);
+    }
+
+    #[test]
+    fn test_slots() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::ok(),
+            Reply::Value(ScalarValue::VInt(3)),
+            Reply::Value(ScalarValue::VStr("value".to_string())),
+            Reply::Value(ScalarValue::VNull),
+            Reply::Error("ERR value is not an integer or out of range".to_string()),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        let set = pipeline.set("k", "value");
+        let count = pipeline.incr("counter");
+        let value = pipeline.get_as::<String>("k");
+        let missing = pipeline.get_as::<String>("missing");
+        let failed = pipeline.incr("k");
+        let results = pipeline.execute().unwrap();
+        assert_eq!(set.get(&results).unwrap(), "OK");
+        assert_eq!(count.get(&results).unwrap(), 3);
+        assert_eq!(value.get(&results).unwrap(), Some("value".to_string()));
+        assert_eq!(missing.get(&results).unwrap(), None);
+        match failed.get(&results) {
+            Err(CommandError::SlotError {
+                index: 4,
+                command: "INCR",
+                source: Some(source),
+            }) => assert!(matches!(*source, CommandError::ServerError { .. })),
+            other => panic!("unexpected result {:?}", other),
+        }
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_slot_mismatch() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            Reply::Value(ScalarValue::VStr("not a number".to_string())),
+        ]]);
+        let mut client = ClientBuilder {
+            options: server.options(),
+        }
+        .build()
+        .unwrap();
+        let mut pipeline = client.pipeline();
+        let count = pipeline.incr("counter");
+        let results = pipeline.execute().unwrap();
+        assert!(matches!(
+            count.get(&results),
+            Err(CommandError::SlotError {
+                index: 0,
+                command: "INCR",
+                source: Some(source),
+            }) if matches!(*source, CommandError::UnexpectedResponse(_))
+        ));
+        // A slot has no reply in results of a smaller pipeline.
+        assert!(matches!(
+            count.get(&[]),
+            Err(CommandError::SlotError {
+                index: 0,
+                command: "INCR",
+                source: None,
+            })
+        ));
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_slots_live() {
+        let mut client = Client::new("localhost".to_string(), 7379).unwrap();
+        let key = uuid::Uuid::new_v4().to_string();
+        let mut pipeline = client.pipeline();
+        pipeline.set(&key, 41);
+        let count = pipeline.incr(&key);
+        let value = pipeline.get_as::<u32>(&key);
+        let missing = pipeline.get_as::<String>(&format!("{}-missing", key));
+        let results = pipeline.execute().unwrap();
+        assert_eq!(count.get(&results).unwrap(), 42);
+        assert_eq!(value.get(&results).unwrap(), Some(42));
+        assert_eq!(missing.get(&results).unwrap(), None);
+        client.del(key.as_str()).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+//! # RateLimit Module
+//! Rate limiters counting the requests of every caller in a key, so that the limit holds across
+//! every process sharing the server. Create one with [`RateLimiter::fixed_window`] or
+//! [`RateLimiter::sliding_window`].
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    client::Client,
+    commands::{duration_to_seconds, ExpireOption, ScalarValue, Ttl},
+    errors::{ClientError, CommandError},
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+/// Limits how many requests every caller can make in a window of time.
+///
+/// Every call to [`RateLimiter::check`] counts as a request, including denied ones, so a caller
+/// that keeps retrying stays denied until the window rolls over.
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use dicedb_rs::{client::Client, ratelimit::RateLimiter};
+///
+/// let mut client = Client::new("localhost".to_string(), 7379)?;
+/// let mut limiter = RateLimiter::fixed_window(&mut client, "api", 100, Duration::from_secs(60));
+/// let decision = limiter.check("user-42")?;
+/// if !decision.allowed {
+///     println!("retry in {:?}", decision.reset_after);
+/// }
+/// # Ok::<(), dicedb_rs::errors::ClientError>(())
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter<'a> {
+    client: &'a mut Client,
+    key_prefix: String,
+    limit: u64,
+    window: Duration,
+    algorithm: Algorithm,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Algorithm {
+    FixedWindow,
+    SlidingWindow,
+}
+
+/// The outcome of a [`RateLimiter::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the request is within the limit.
+    pub allowed: bool,
+    /// How many more requests are allowed in the current window.
+    pub remaining: u64,
+    /// How long until the current window ends. With a fixed window the count starts over then,
+    /// with a sliding window the requests of the window start to count less.
+    pub reset_after: Duration,
+}
+
+impl<'a> RateLimiter<'a> {
+    /// Creates a limiter allowing `limit` requests per caller in windows of a fixed length, which
+    /// start with the first request of a caller. The window is rounded up to whole seconds.
+    ///
+    /// A fixed window needs one round trip per check, plus one at the start of every window. A
+    /// caller can make up to twice the limit in a short time around the end of a window.
+    /// # Arguments
+    /// * `client` - The client to count requests with.
+    /// * `key_prefix` - The prefix of the counter keys, followed by `:` and the caller id.
+    /// * `limit` - How many requests are allowed per window.
+    /// * `window` - The length of a window.
+    pub fn fixed_window(
+        client: &'a mut Client,
+        key_prefix: &str,
+        limit: u64,
+        window: Duration,
+    ) -> Self {
+        RateLimiter::new(client, key_prefix, limit, window, Algorithm::FixedWindow)
+    }
+
+    /// Creates a limiter allowing about `limit` requests per caller in any window of the given
+    /// length. The requests of the previous window are weighted by how much of it still overlaps
+    /// the sliding window, which smooths out the bursts a fixed window allows at its end.
+    ///
+    /// Windows are aligned to the clock of the client, so the clocks of clients sharing a limit
+    /// should be synchronized.
+    /// # Arguments
+    /// * `client` - The client to count requests with.
+    /// * `key_prefix` - The prefix of the counter keys, followed by `:`, the caller id, `:` and
+    /// the number of the window.
+    /// * `limit` - How many requests are allowed per window.
+    /// * `window` - The length of a window, in whole milliseconds.
+    pub fn sliding_window(
+        client: &'a mut Client,
+        key_prefix: &str,
+        limit: u64,
+        window: Duration,
+    ) -> Self {
+        RateLimiter::new(client, key_prefix, limit, window, Algorithm::SlidingWindow)
+    }
+
+    fn new(
+        client: &'a mut Client,
+        key_prefix: &str,
+        limit: u64,
+        window: Duration,
+        algorithm: Algorithm,
+    ) -> Self {
+        RateLimiter {
+            client,
+            key_prefix: key_prefix.to_string(),
+            limit,
+            window,
+            algorithm,
+        }
+    }
+
+    /// Counts a request of the caller and decides whether it is within the limit.
+    /// # Arguments
+    /// * `id` - The caller, such as a user id or an IP address.
+    /// # Returns
+    /// * [`Decision`] - Whether the request is allowed, and how many more are.
+    /// # Errors
+    /// * [`ClientError`] - If an error occured in the communication stream.
+    /// * [`CommandError::InvalidInput`] - If the window is shorter than a millisecond.
+    pub fn check(&mut self, id: &str) -> Result<Decision> {
+        if self.window.as_millis() == 0 {
+            return Err(CommandError::InvalidInput(format!(
+                "rate limit window {:?} must be at least a millisecond",
+                self.window
+            ))
+            .into());
+        }
+        let key = format!("{}:{}", self.key_prefix, id);
+        match self.algorithm {
+            Algorithm::FixedWindow => self.check_fixed(&key),
+            Algorithm::SlidingWindow => self.check_sliding(&key),
+        }
+    }
+
+    fn check_fixed(&mut self, key: &str) -> Result<Decision> {
+        let seconds = duration_to_seconds(self.window)?;
+        let window = Duration::from_secs(seconds.unsigned_abs());
+        let mut pipeline = self.client.pipeline();
+        let count = pipeline.incr(key);
+        let ttl = pipeline.ttl(key);
+        let results = pipeline.execute()?;
+        let count = count.get(&results)?;
+        let ttl = Ttl::from_seconds_reply(ScalarValue::VInt(ttl.get(&results)?))?;
+        let reset_after = match ttl {
+            Ttl::Expires(left) if count != 1 => left,
+            // The counter was just created, or whoever created it failed to set its expiry, so
+            // the window starts now. Expiring only new counters lets the window roll over.
+            _ => {
+                self.client.expire(key, seconds, ExpireOption::NX)?;
+                window
+            }
+        };
+        Ok(self.decide(count.unsigned_abs(), reset_after))
+    }
+
+    fn check_sliding(&mut self, key: &str) -> Result<Decision> {
+        let window = self.window.as_millis();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let (index, elapsed) = (now / window, now % window);
+        let current_key = format!("{}:{}", key, index);
+        let mut pipeline = self.client.pipeline();
+        let current = pipeline.incr(&current_key);
+        let previous = pipeline.get_as::<u64>(&format!("{}:{}", key, index.saturating_sub(1)));
+        let results = pipeline.execute()?;
+        let current = current.get(&results)?.unsigned_abs();
+        let previous = previous.get(&results)?.unwrap_or(0);
+        if current == 1 {
+            // The counter is read while the next window is current, so it has to outlive it.
+            self.client
+                .expire_in(&current_key, self.window * 2, ExpireOption::NX)?;
+        }
+        let count = sliding_count(previous, current, elapsed, window);
+        let reset_after =
+            Duration::from_millis(u64::try_from(window - elapsed).unwrap_or(u64::MAX));
+        Ok(self.decide(count, reset_after))
+    }
+
+    fn decide(&self, count: u64, reset_after: Duration) -> Decision {
+        Decision {
+            allowed: count <= self.limit,
+            remaining: self.limit.saturating_sub(count),
+            reset_after,
+        }
+    }
+}
+
+/// Estimates the requests in a sliding window from the counts of the current and the previous
+/// fixed window, assuming the requests of the previous window were spread evenly over it.
+fn sliding_count(previous: u64, current: u64, elapsed: u128, window: u128) -> u64 {
+    let overlap = u128::from(previous) * (window - elapsed) / window;
+    u64::try_from(overlap)
+        .unwrap_or(u64::MAX)
+        .saturating_add(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{
+        commands::CommandDescriptor,
+        errors::StreamError,
+        testserver,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    fn int(value: i64) -> Reply {
+        Reply::Value(ScalarValue::VInt(value))
+    }
+
+    #[test]
+    fn test_fixed_window_expires_new_counters_only() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            // INCR and TTL of a new counter, which starts the window.
+            int(1),
+            int(-1),
+            int(1),
+            int(2),
+            int(10),
+            int(3),
+            int(9),
+            // A counter left without an expiry gets one.
+            int(4),
+            int(-1),
+            int(1),
+        ]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let mut limiter =
+            RateLimiter::fixed_window(&mut client, "limit", 2, Duration::from_millis(10_500));
+        let decisions: Vec<Decision> = (0..4).map(|_| limiter.check("user").unwrap()).collect();
+        assert_eq!(
+            decisions,
+            vec![
+                Decision {
+                    allowed: true,
+                    remaining: 1,
+                    reset_after: Duration::from_secs(11),
+                },
+                Decision {
+                    allowed: true,
+                    remaining: 0,
+                    reset_after: Duration::from_secs(10),
+                },
+                Decision {
+                    allowed: false,
+                    remaining: 0,
+                    reset_after: Duration::from_secs(9),
+                },
+                Decision {
+                    allowed: false,
+                    remaining: 0,
+                    reset_after: Duration::from_secs(11),
+                },
+            ]
+        );
+        drop(client);
+        let received: Vec<String> = server
+            .received()
+            .into_iter()
+            .skip(1)
+            .map(|(name, args)| format!("{} {}", name, args.join(" ")))
+            .collect();
+        let window = ["INCR limit:user", "TTL limit:user"];
+        let expire = "EXPIRE limit:user 11 NX";
+        assert_eq!(
+            received,
+            [&window[..], &[expire], &window, &window, &window, &[expire]].concat()
+        );
+    }
+
+    #[test]
+    fn test_sliding_window_counts_previous_window() {
+        let server = FakeServer::start(vec![vec![
+            Reply::ok(),
+            int(1),
+            Reply::Value(ScalarValue::VNull),
+            int(1),
+        ]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let window = Duration::from_secs(3600);
+        let mut limiter = RateLimiter::sliding_window(&mut client, "limit", 5, window);
+        let decision = limiter.check("user").unwrap();
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 4);
+        assert!(decision.reset_after <= window);
+        drop(client);
+        let received = server.received();
+        let index = received[1].1[0]
+            .rsplit(':')
+            .next()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        assert_eq!(received[1].0, "INCR");
+        assert_eq!(received[2].0, "GET");
+        assert_eq!(received[2].1, vec![format!("limit:user:{}", index - 1)]);
+        assert_eq!(received[3].0, "EXPIRE");
+        assert_eq!(received[3].1[1..], ["7200", "NX"]);
+
+        assert_eq!(sliding_count(10, 1, 0, 1000), 11);
+        assert_eq!(sliding_count(10, 1, 250, 1000), 8);
+        assert_eq!(sliding_count(10, 1, 999, 1000), 1);
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        let mut client =
+            Client::with_executor(|_: &CommandDescriptor| unreachable!("no command is sent"));
+        let mut limiter = RateLimiter::sliding_window(&mut client, "limit", 1, Duration::ZERO);
+        assert!(matches!(
+            limiter.check("user"),
+            Err(ClientError::StreamError(StreamError::CommandError(
+                CommandError::InvalidInput(_)
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_limits_reset_after_window() {
+        let mut client = testserver::client();
+        let id = uuid::Uuid::new_v4().to_string();
+        let window = Duration::from_secs(1);
+        for sliding in [false, true] {
+            let mut limiter = match sliding {
+                false => RateLimiter::fixed_window(&mut client, "testratelimit", 3, window),
+                true => RateLimiter::sliding_window(&mut client, "testratelimit", 3, window),
+            };
+            let allowed: Vec<bool> = (0..6)
+                .map(|_| limiter.check(&id).unwrap().allowed)
+                .collect();
+            // A sliding window can straddle two windows, so only the first request is certain.
+            match sliding {
+                false => assert_eq!(allowed, [true, true, true, false, false, false]),
+                true => assert!(allowed[0] && allowed.iter().filter(|a| !**a).count() >= 2),
+            }
+            thread::sleep(window * 2 + Duration::from_millis(100));
+            let decision = limiter.check(&id).unwrap();
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, 2);
+        }
+    }
+}
@@ -0,0 +1,560 @@
+//! # Replay Module
+//! Records the commands a client sends and the replies it receives to a file, to find out after
+//! the fact what was exchanged with the server, e.g. when debugging a production incident.
+//! Register a [`Recorder`] with [`ClientBuilder::recorder`](crate::client::ClientBuilder::recorder)
+//! and read the file back with [`read_recording`].
+//!
+//! A recording holds the keys and values that were sent and received as they are, so keep it as
+//! safe as the data itself. Only the password sent with AUTH is left out.
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use prost::Message as _;
+
+use crate::{
+    commands::{decode_command, wire, HSetValue, ListValue, ScalarValue},
+    errors::CommandError,
+    framing, LOG_TARGET,
+};
+
+/// The first bytes of a recording, which tell it apart from other files.
+const MAGIC: &[u8; 8] = b"DICEREC1";
+/// How many entries can wait to be written before new ones are dropped.
+const QUEUE_CAPACITY: usize = 1024;
+/// Written in place of the password of an AUTH command.
+const REDACTED: &str = "<redacted>";
+
+/// The kinds of entries in a recording.
+const COMMAND: u8 = 1;
+const REPLY: u8 = 2;
+const PUSH: u8 = 3;
+const RECONNECT: u8 = 4;
+
+/// Writes the commands and replies of the clients it is registered with to a file, see the
+/// [module documentation](self). Clones write to the same file.
+///
+/// Entries are handed to a thread that writes them, so a slow disk never holds up a command. If
+/// the thread falls behind by more than a thousand entries, new entries are dropped and counted,
+/// see [`Recorder::dropped`].
+/// # File format
+/// The file starts with the 8 bytes `DICEREC1`, followed by one entry per command, reply or
+/// reconnect. An entry is its kind as a byte, the time it was recorded in microseconds since the
+/// Unix epoch as a little endian `u64`, the id of the connection as a little endian `u16` length
+/// and UTF-8 bytes, and the command or reply as a little endian `u32` length and the protobuf
+/// bytes sent or received.
+/// # Example
+/// ```no_run
+/// use dicedb_rs::{client::Client, replay::{self, Recorder}};
+///
+/// let recorder = Recorder::create("dicedb.rec")?;
+/// let mut client = Client::builder().recorder(recorder.clone()).build()?;
+/// client.set("key", "value")?;
+/// recorder.flush()?;
+/// for exchange in replay::read_recording("dicedb.rec")? {
+///     println!("{:?}", exchange);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    sender: SyncSender<Request>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// What the writing thread is asked to do.
+enum Request {
+    Write(Entry),
+    /// Flush what was written so far and report whether writing failed.
+    Flush(SyncSender<io::Result<()>>),
+}
+
+struct Entry {
+    kind: u8,
+    at: SystemTime,
+    connection: String,
+    bytes: Vec<u8>,
+}
+
+impl Recorder {
+    /// Creates the file, replacing an existing one, and starts the thread writing to it. The
+    /// thread stops once the recorder and its clones are dropped.
+    /// # Errors
+    /// * [`io::Error`] - If the file could not be created or the thread could not be started.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::default());
+        let writer = Writer {
+            file: BufWriter::new(file),
+            dropped: dropped.clone(),
+            error: None,
+        };
+        thread::Builder::new()
+            .name("dicedb-recorder".to_string())
+            .spawn(move || writer.run(&receiver))?;
+        Ok(Recorder { sender, dropped })
+    }
+
+    /// Returns how many entries were dropped, either because the writing thread fell behind or
+    /// because writing to the file failed.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits until every entry recorded so far is written to the file.
+    /// # Errors
+    /// * [`io::Error`] - If writing to the file failed, now or for an earlier entry.
+    pub fn flush(&self) -> io::Result<()> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let stopped = || io::Error::new(io::ErrorKind::Other, "the recorder thread stopped");
+        self.sender
+            .send(Request::Flush(sender))
+            .map_err(|_| stopped())?;
+        receiver.recv().map_err(|_| stopped())?
+    }
+
+    /// Records commands written together to a connection.
+    pub(crate) fn written(&self, connection: &str, commands: &[u8]) {
+        self.record(COMMAND, connection, commands);
+    }
+
+    /// Records a reply, which is a watch notification if no command was waiting for it.
+    pub(crate) fn received(&self, connection: &str, reply: &[u8], push: bool) {
+        self.record(if push { PUSH } else { REPLY }, connection, reply);
+    }
+
+    /// Records that a connection was replaced, so replies to commands sent before will not come.
+    pub(crate) fn reconnected(&self, connection: &str) {
+        self.record(RECONNECT, connection, &[]);
+    }
+
+    fn record(&self, kind: u8, connection: &str, bytes: &[u8]) {
+        let entry = Entry {
+            kind,
+            at: SystemTime::now(),
+            connection: connection.to_string(),
+            bytes: bytes.to_vec(),
+        };
+        match self.sender.try_send(Request::Write(entry)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recorder({} dropped)", self.dropped())
+    }
+}
+
+/// Writes entries on the thread of a recorder.
+struct Writer {
+    file: BufWriter<File>,
+    dropped: Arc<AtomicU64>,
+    /// The first error writing to the file, after which entries are dropped.
+    error: Option<io::Error>,
+}
+
+impl Writer {
+    fn run(mut self, receiver: &Receiver<Request>) {
+        if let Err(e) = self.file.write_all(MAGIC) {
+            self.failed(e);
+        }
+        loop {
+            let request = match receiver.try_recv() {
+                Ok(request) => request,
+                // Writes are flushed whenever the thread catches up, so they reach the file soon
+                // without a flush for every entry.
+                Err(TryRecvError::Empty) => {
+                    self.flush();
+                    match receiver.recv() {
+                        Ok(request) => request,
+                        Err(_) => return,
+                    }
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.flush();
+                    return;
+                }
+            };
+            match request {
+                Request::Write(_) if self.error.is_some() => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Request::Write(entry) => {
+                    if let Err(e) = self.write(&entry) {
+                        self.failed(e);
+                    }
+                }
+                Request::Flush(reply) => {
+                    self.flush();
+                    let result = match &self.error {
+                        Some(e) => Err(io::Error::new(e.kind(), e.to_string())),
+                        None => Ok(()),
+                    };
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    }
+
+    /// Writes an entry, split into one entry per command if it holds several.
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if entry.kind != COMMAND {
+            return self.write_entry(entry, &entry.bytes);
+        }
+        let mut rest = entry.bytes.as_slice();
+        while let Some(len) = framing::command_len(rest).filter(|len| *len > 0) {
+            let (command, next) = rest.split_at(len);
+            self.write_entry(entry, &redact(command))?;
+            rest = next;
+        }
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &Entry, bytes: &[u8]) -> io::Result<()> {
+        let at = entry.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let at = u64::try_from(at.as_micros()).unwrap_or(u64::MAX);
+        let connection = entry.connection.as_bytes();
+        let connection_len = u16::try_from(connection.len()).map_err(invalid_data)?;
+        let len = u32::try_from(bytes.len()).map_err(invalid_data)?;
+        self.file.write_all(&[entry.kind])?;
+        self.file.write_all(&at.to_le_bytes())?;
+        self.file.write_all(&connection_len.to_le_bytes())?;
+        self.file.write_all(connection)?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(bytes)
+    }
+
+    fn flush(&mut self) {
+        if self.error.is_none() {
+            if let Err(e) = self.file.flush() {
+                self.failed(e);
+            }
+        }
+    }
+
+    fn failed(&mut self, error: io::Error) {
+        log::warn!(
+            target: LOG_TARGET,
+            "Failed to write the recording, recording stopped: {}",
+            error
+        );
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.error = Some(error);
+    }
+}
+
+/// Replaces the password of an AUTH command.
+fn redact(command: &[u8]) -> Cow<'_, [u8]> {
+    match wire::Command::decode(command) {
+        Ok(mut decoded) if decoded.cmd == "AUTH" => {
+            decoded.args = vec![REDACTED.to_string()];
+            Cow::Owned(decoded.encode_to_vec())
+        }
+        _ => Cow::Borrowed(command),
+    }
+}
+
+/// A command read from a recording, paired with its reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    /// The id of the connection, which is the client id for the connection of a client.
+    pub connection: String,
+    /// The command, or `None` for a watch notification, which no command asked for.
+    pub command: Option<RecordedCommand>,
+    /// The reply, or `None` if the recording ended or the connection was lost before it came.
+    pub reply: Option<RecordedReply>,
+}
+
+/// A command as it was written to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCommand {
+    /// When the command was written.
+    pub at: SystemTime,
+    /// The name of the command, such as `GET`.
+    pub name: String,
+    /// The arguments of the command.
+    pub args: Vec<String>,
+}
+
+/// A reply as it was received from the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedReply {
+    /// When the reply was read.
+    pub at: SystemTime,
+    /// The protobuf bytes of the reply.
+    pub bytes: Vec<u8>,
+}
+
+impl RecordedReply {
+    /// Decodes the reply to a command returning a single value.
+    /// # Errors
+    /// * [`CommandError`] - If the reply is an error or does not hold a single value.
+    pub fn value(&self) -> Result<ScalarValue, CommandError> {
+        ScalarValue::decode(&self.bytes)
+    }
+
+    /// Decodes the reply to a command returning a hash, such as HGETALL, or `None` for a nil reply.
+    /// # Errors
+    /// * [`CommandError`] - If the reply is an error or could not be decoded.
+    pub fn hash(&self) -> Result<Option<HSetValue>, CommandError> {
+        HSetValue::decode(&self.bytes)
+    }
+
+    /// Decodes the reply to a command returning a list, such as KEYS.
+    /// # Errors
+    /// * [`CommandError`] - If the reply is an error or does not hold a list.
+    pub fn list(&self) -> Result<ListValue, CommandError> {
+        ListValue::decode(&self.bytes)
+    }
+}
+
+/// Reads a recording written by a [`Recorder`], pairing every command with its reply in the order
+/// the commands were written. A recording cut short, e.g. because the process was killed, is read
+/// up to its last complete entry.
+/// # Errors
+/// * [`io::Error`] - If the file could not be read or is not a recording.
+pub fn read_recording(path: impl AsRef<Path>) -> io::Result<Vec<Exchange>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("the file is not a recording"));
+    }
+    let mut exchanges = Vec::new();
+    // The exchanges waiting for a reply on each connection, oldest first.
+    let mut pending: HashMap<String, VecDeque<usize>> = HashMap::new();
+    while let Some(entry) = read_entry(&mut reader)? {
+        let reply = || RecordedReply {
+            at: entry.at,
+            bytes: entry.bytes.clone(),
+        };
+        match entry.kind {
+            COMMAND => {
+                let (name, args) = decode_command(&entry.bytes).map_err(invalid_data)?;
+                pending
+                    .entry(entry.connection.clone())
+                    .or_default()
+                    .push_back(exchanges.len());
+                exchanges.push(Exchange {
+                    connection: entry.connection,
+                    command: Some(RecordedCommand {
+                        at: entry.at,
+                        name,
+                        args,
+                    }),
+                    reply: None,
+                });
+            }
+            REPLY | PUSH => {
+                let waiting = match entry.kind {
+                    REPLY => pending
+                        .get_mut(&entry.connection)
+                        .and_then(VecDeque::pop_front),
+                    _ => None,
+                };
+                match waiting {
+                    Some(index) => exchanges[index].reply = Some(reply()),
+                    None => exchanges.push(Exchange {
+                        reply: Some(reply()),
+                        connection: entry.connection,
+                        command: None,
+                    }),
+                }
+            }
+            RECONNECT => {
+                pending.remove(&entry.connection);
+            }
+            kind => return Err(invalid_data(format!("unknown entry kind {}", kind))),
+        }
+    }
+    Ok(exchanges)
+}
+
+/// Reads the next entry, or `None` at the end of the recording.
+fn read_entry(reader: &mut impl Read) -> io::Result<Option<Entry>> {
+    let mut kind = [0; 1];
+    if reader.read(&mut kind)? == 0 {
+        return Ok(None);
+    }
+    match read_entry_after_kind(reader, kind[0]) {
+        Ok(entry) => Ok(Some(entry)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_entry_after_kind(reader: &mut impl Read, kind: u8) -> io::Result<Entry> {
+    let mut at = [0; 8];
+    reader.read_exact(&mut at)?;
+    let mut connection_len = [0; 2];
+    reader.read_exact(&mut connection_len)?;
+    let mut connection = vec![0; usize::from(u16::from_le_bytes(connection_len))];
+    reader.read_exact(&mut connection)?;
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u64::from(u32::from_le_bytes(len));
+    let mut bytes = Vec::new();
+    // Read through `take`, so a corrupt length cannot allocate more than the file holds.
+    if reader.take(len).read_to_end(&mut bytes)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Entry {
+        kind,
+        at: UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(at)),
+        connection: String::from_utf8(connection).map_err(invalid_data)?,
+        bytes,
+    })
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use crate::{
+        client::Client,
+        commands::encode_watch_reply,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    /// A path in the temporary directory that is removed when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new() -> Self {
+            let name = format!("dicedb-recording-{}", uuid::Uuid::new_v4());
+            TempPath(env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn command(exchange: &Exchange) -> (&str, Vec<&str>) {
+        let command = exchange.command.as_ref().unwrap();
+        let args = command.args.iter().map(String::as_str).collect();
+        (command.name.as_str(), args)
+    }
+
+    #[test]
+    fn test_records_commands_and_replies() {
+        let path = TempPath::new();
+        let recorder = Recorder::create(&path.0).unwrap();
+        let server = FakeServer::start(vec![
+            vec![
+                Reply::ok(),
+                Reply::ok(),
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("value".to_string())),
+            ],
+            vec![
+                Reply::ok(),
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(1)),
+                Reply::Push(encode_watch_reply(&ScalarValue::VInt(2), "key")),
+                Reply::ok(),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .auth("secret")
+            .client_id("recorded")
+            .recorder(recorder.clone())
+            .build()
+            .unwrap();
+        client.set("key", "value").unwrap();
+        assert_eq!(
+            client.get("key").unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        let (mut stream, _) = client.get_watch("key").unwrap();
+        stream.next().unwrap();
+        drop(stream);
+        recorder.flush().unwrap();
+        assert_eq!(recorder.dropped(), 0);
+
+        let exchanges = read_recording(&path.0).unwrap();
+        let commands: Vec<_> = exchanges[..4].iter().map(command).collect();
+        assert_eq!(commands[0], ("AUTH", vec![REDACTED]));
+        assert_eq!(commands[1].0, "HANDSHAKE");
+        assert_eq!(commands[2], ("SET", vec!["key", "value"]));
+        assert_eq!(commands[3], ("GET", vec!["key"]));
+        assert!(exchanges[..4].iter().all(|e| e.connection == "recorded"));
+        let reply = exchanges[3].reply.as_ref().unwrap();
+        assert_eq!(
+            reply.value().unwrap(),
+            ScalarValue::VStr("value".to_string())
+        );
+        assert!(reply.at >= exchanges[3].command.as_ref().unwrap().at);
+
+        // The watch stream has a connection of its own, and its notification no command.
+        let watch = &exchanges[4..];
+        assert_eq!(command(&watch[2]), ("GET.WATCH", vec!["key"]));
+        assert_ne!(watch[2].connection, "recorded");
+        assert_eq!(watch[3].command, None);
+        assert_eq!(
+            watch[3].reply.as_ref().unwrap().value().unwrap(),
+            ScalarValue::VInt(2)
+        );
+        assert_eq!(command(&watch[4]), ("UNWATCH", vec!["key"]));
+        assert!(watch.iter().all(|e| e.reply.is_some()));
+    }
+
+    #[test]
+    fn test_reads_recording_cut_short() {
+        let path = TempPath::new();
+        let recorder = Recorder::create(&path.0).unwrap();
+        let get = crate::commands::Command::GET {
+            key: "key".to_string(),
+        };
+        let mut bytes = Vec::new();
+        get.clone().into_wire().encode_into(&mut bytes);
+        get.into_wire().encode_into(&mut bytes);
+        // Commands written together are recorded one by one.
+        recorder.written("connection", &bytes);
+        recorder.reconnected("connection");
+        recorder.received("connection", &[], false);
+        recorder.flush().unwrap();
+        let exchanges = read_recording(&path.0).unwrap();
+        assert_eq!(exchanges.len(), 3);
+        assert!(exchanges[..2].iter().all(|e| e.reply.is_none()));
+        // Replies after a reconnect are not paired with commands sent before.
+        assert_eq!(exchanges[2].command, None);
+
+        let complete = fs::read(&path.0).unwrap();
+        fs::write(&path.0, &complete[..complete.len() - 3]).unwrap();
+        assert_eq!(read_recording(&path.0).unwrap().len(), 2);
+        fs::write(&path.0, b"not a recording").unwrap();
+        assert_eq!(
+            read_recording(&path.0).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
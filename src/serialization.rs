@@ -0,0 +1,527 @@
+use std::{borrow::Cow, collections::HashMap, fmt, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{
+    de::{self, value::MapDeserializer, DeserializeOwned, IntoDeserializer, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    commands::{DiceType, ScalarValue, SetInput, BYTES_MARKER},
+    errors::CommandError,
+};
+
+/// [`ScalarValue::VNull`] serializes as a unit (`null` in JSON) and [`ScalarValue::VBytes`] as a
+/// string holding [`BYTES_MARKER`] followed by the base64 encoded bytes, so that it can be told
+/// apart from [`ScalarValue::VStr`] when deserializing. A [`ScalarValue::VStr`] that starts with
+/// the marker itself gets a second marker in front, which base64 never starts with.
+impl Serialize for ScalarValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ScalarValue::VStr(s) => serializer.serialize_str(&escape_str(s)),
+            ScalarValue::VInt(i) => serializer.serialize_i64(*i),
+            ScalarValue::VFloat(f) => serializer.serialize_f64(*f),
+            ScalarValue::VBool(b) => serializer.serialize_bool(*b),
+            ScalarValue::VBytes(b) => {
+                serializer.serialize_str(&format!("{}{}", BYTES_MARKER, BASE64.encode(b)))
+            }
+            ScalarValue::VNull => serializer.serialize_unit(),
+        }
+    }
+}
+
+/// Escapes a string that starts with [`BYTES_MARKER`], so it is not read back as bytes.
+fn escape_str(s: &str) -> Cow<'_, str> {
+    match s.starts_with(BYTES_MARKER) {
+        true => Cow::Owned(format!("{}{}", BYTES_MARKER, s)),
+        false => Cow::Borrowed(s),
+    }
+}
+
+struct ScalarValueVisitor;
+
+impl<'de> Visitor<'de> for ScalarValueVisitor {
+    type Value = ScalarValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a string, number, boolean, bytes or null")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VBool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VInt(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(ScalarValue::VInt)
+            .map_err(|_| E::custom(format!("integer {} is out of range for i64", v)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VFloat(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        match v.strip_prefix(BYTES_MARKER) {
+            Some(escaped) if escaped.starts_with(BYTES_MARKER) => {
+                Ok(ScalarValue::VStr(escaped.to_string()))
+            }
+            Some(encoded) => BASE64
+                .decode(encoded)
+                .map(ScalarValue::VBytes)
+                .map_err(|e| E::custom(format!("invalid base64 bytes value: {}", e))),
+            None => Ok(ScalarValue::VStr(v.to_string())),
+        }
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VBytes(v.to_vec()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VNull)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ScalarValue::VNull)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(ScalarValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScalarValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ScalarValueVisitor)
+    }
+}
+
+/// A [`DiceType`] serializes as the name the server reports for it, such as `"int"`.
+impl Serialize for DiceType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DiceType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|name| DiceType::from(name.as_str()))
+    }
+}
+
+/// Deserializes a single hash field. Hash fields are always strings on the server, so numbers and
+/// booleans are parsed from the string when the target type asks for them.
+struct FieldDeserializer(String);
+
+impl FieldDeserializer {
+    fn parse<T: FromStr>(&self, expected: &str) -> Result<T, serde_json::Error> {
+        self.0
+            .parse()
+            .map_err(|_| de::Error::custom(format!("expected {} but found {:?}", expected, self.0)))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $t:ty),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.parse::<$t>(stringify!($t))?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for FieldDeserializer {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    deserialize_parsed!(
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+        deserialize_char => visit_char: char
+    );
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl IntoDeserializer<'_, serde_json::Error> for FieldDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+/// Deserializes the fields of a hash into a flat struct.
+pub(crate) fn from_fields<T: DeserializeOwned>(
+    fields: HashMap<String, String>,
+) -> Result<T, serde_json::Error> {
+    let map = MapDeserializer::new(
+        fields
+            .into_iter()
+            .map(|(field, value)| (field, FieldDeserializer(value))),
+    );
+    T::deserialize(map)
+}
+
+/// Serializes a flat struct into hash fields, returned together with the names of the fields
+/// that serialize to null, which have no value to store. Nested arrays or objects are rejected
+/// instead of being flattened.
+pub(crate) fn to_fields<T: Serialize>(
+    value: &T,
+) -> Result<(Vec<(String, String)>, Vec<String>), serde_json::Error> {
+    let serde_json::Value::Object(object) = serde_json::to_value(value)? else {
+        return Err(serde::ser::Error::custom(
+            "only structs and maps can be stored as hash fields",
+        ));
+    };
+    let mut fields = Vec::with_capacity(object.len());
+    let mut nulls = Vec::new();
+    for (field, value) in object {
+        let value = match value {
+            serde_json::Value::Null => {
+                nulls.push(field);
+                continue;
+            }
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Bool(b) => b.to_string(),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                return Err(serde::ser::Error::custom(format!(
+                    "field {:?} is nested, only flat structs can be stored as hash fields",
+                    field
+                )))
+            }
+        };
+        fields.push((field, value));
+    }
+    Ok((fields, nulls))
+}
+
+/// Non-finite floats have no JSON number representation and become strings such as `"NaN"` or
+/// `"inf"`, and [`ScalarValue::VBytes`] and strings starting with [`BYTES_MARKER`] become the same
+/// marked strings as when serialized.
+impl From<ScalarValue> for serde_json::Value {
+    fn from(value: ScalarValue) -> Self {
+        match value {
+            ScalarValue::VStr(s) => serde_json::Value::String(escape_str(&s).into_owned()),
+            ScalarValue::VInt(i) => serde_json::Value::from(i),
+            ScalarValue::VFloat(f) => serde_json::Number::from_f64(f)
+                .map_or_else(|| serde_json::Value::String(f.to_string()), Into::into),
+            ScalarValue::VBool(b) => serde_json::Value::Bool(b),
+            ScalarValue::VBytes(b) => {
+                serde_json::Value::String(format!("{}{}", BYTES_MARKER, BASE64.encode(b)))
+            }
+            ScalarValue::VNull => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Strings and numbers can be stored, integers that fit in an `i64` as [`SetInput::Int`] and
+/// other numbers as [`SetInput::Float`]. Nulls, booleans, arrays and objects are rejected with
+/// [`CommandError::InvalidInput`].
+impl TryFrom<serde_json::Value> for SetInput {
+    type Error = CommandError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::String(s) => Ok(SetInput::Str(s)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Ok(SetInput::Int(i)),
+                None => n.as_f64().map(SetInput::Float).ok_or_else(|| {
+                    CommandError::InvalidInput(format!("number {} cannot be stored", n))
+                }),
+            },
+            serde_json::Value::Null => Err(CommandError::InvalidInput(
+                "null cannot be stored as a value".to_string(),
+            )),
+            serde_json::Value::Bool(_) => Err(CommandError::InvalidInput(
+                "booleans cannot be stored as a value".to_string(),
+            )),
+            serde_json::Value::Array(_) => Err(CommandError::InvalidInput(
+                "arrays cannot be stored as a value".to_string(),
+            )),
+            serde_json::Value::Object(_) => Err(CommandError::InvalidInput(
+                "objects cannot be stored as a value".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::commands::{ChangeKind, HSetValue, WatchValue};
+
+    use super::*;
+
+    fn round_trip(value: ScalarValue) -> ScalarValue {
+        let json = serde_json::to_string(&value).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_scalar_value_round_trip() {
+        let values = vec![
+            ScalarValue::VStr("hello".to_string()),
+            ScalarValue::VStr("".to_string()),
+            ScalarValue::VInt(-42),
+            ScalarValue::VInt(i64::MAX),
+            ScalarValue::VFloat(1.5),
+            ScalarValue::VBool(true),
+            ScalarValue::VBytes(vec![0, 1, 2, 255]),
+            ScalarValue::VNull,
+            // Strings that look like marked bytes stay strings.
+            ScalarValue::VStr(BYTES_MARKER.to_string()),
+            ScalarValue::VStr(format!("{}aGk=", BYTES_MARKER)),
+            ScalarValue::VStr(format!("{}{}", BYTES_MARKER, BYTES_MARKER)),
+            ScalarValue::VBytes(format!("{}aGk=", BYTES_MARKER).into_bytes()),
+        ];
+        for value in values {
+            assert_eq!(round_trip(value.clone()), value);
+            let json = serde_json::Value::from(value.clone());
+            assert_eq!(serde_json::from_value::<ScalarValue>(json).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_scalar_value_json_shape() {
+        assert_eq!(serde_json::to_string(&ScalarValue::VNull).unwrap(), "null");
+        assert_eq!(serde_json::to_string(&ScalarValue::VInt(1)).unwrap(), "1");
+        assert_eq!(
+            serde_json::to_string(&ScalarValue::VStr("a".to_string())).unwrap(),
+            "\"a\""
+        );
+        assert_eq!(
+            serde_json::to_string(&ScalarValue::VBytes(b"hi".to_vec())).unwrap(),
+            format!("\"{}aGk=\"", BYTES_MARKER)
+        );
+        assert_eq!(
+            serde_json::to_string(&ScalarValue::VStr(format!("{}aGk=", BYTES_MARKER))).unwrap(),
+            format!("\"{}{}aGk=\"", BYTES_MARKER, BYTES_MARKER)
+        );
+    }
+
+    #[test]
+    fn test_scalar_value_rejects_out_of_range_int() {
+        let result: Result<ScalarValue, _> = serde_json::from_str("18446744073709551615");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_watch_value_round_trip() {
+        let value = WatchValue {
+            value: ScalarValue::VInt(5),
+            fingerprint: "3975712615".to_string(),
+            change: ChangeKind::Updated,
+        };
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"value":5,"fingerprint":"3975712615"}"#);
+        let decoded: WatchValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.value, value.value);
+        assert_eq!(decoded.fingerprint, value.fingerprint);
+        assert_eq!(decoded.change, ChangeKind::Updated);
+        let removed = WatchValue {
+            value: ScalarValue::VNull,
+            fingerprint: "3975712615".to_string(),
+            change: ChangeKind::Removed,
+        };
+        let json = serde_json::to_string(&removed).unwrap();
+        assert_eq!(
+            json,
+            r#"{"value":null,"fingerprint":"3975712615","change":"removed"}"#
+        );
+        let decoded: WatchValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.change, ChangeKind::Removed);
+    }
+
+    #[test]
+    fn test_hset_value_round_trip() {
+        let mut fields = HashMap::new();
+        fields.insert("field".to_string(), "value".to_string());
+        let value = HSetValue { fields };
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"field":"value"}"#);
+        let decoded: HSetValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_dice_type_round_trip() {
+        for dtype in [
+            DiceType::Int,
+            DiceType::SortedSet,
+            DiceType::Other("stream".to_string()),
+        ] {
+            let json = serde_json::to_string(&dtype).unwrap();
+            assert_eq!(json, format!("\"{}\"", dtype));
+            assert_eq!(serde_json::from_str::<DiceType>(&json).unwrap(), dtype);
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        name: String,
+        zip: String,
+        age: i64,
+        score: f64,
+        nickname: Option<String>,
+        active: bool,
+    }
+
+    fn record() -> Record {
+        Record {
+            name: "dice".to_string(),
+            zip: "8000".to_string(),
+            age: 42,
+            score: 1.5,
+            nickname: None,
+            active: true,
+        }
+    }
+
+    #[test]
+    fn test_fields_round_trip() {
+        let (fields, nulls) = to_fields(&record()).unwrap();
+        assert!(!fields.iter().any(|(field, _)| field == "nickname"));
+        assert_eq!(nulls, ["nickname"]);
+        let decoded: Record = from_fields(fields.into_iter().collect()).unwrap();
+        assert_eq!(decoded, record());
+    }
+
+    #[test]
+    fn test_from_fields_coerces_strings() {
+        let mut fields = HashMap::new();
+        fields.insert("name".to_string(), "dice".to_string());
+        fields.insert("zip".to_string(), "8000".to_string());
+        fields.insert("age".to_string(), "7".to_string());
+        fields.insert("score".to_string(), "2.25".to_string());
+        fields.insert("nickname".to_string(), "d".to_string());
+        fields.insert("active".to_string(), "false".to_string());
+        let decoded: Record = from_fields(fields).unwrap();
+        assert_eq!(decoded.age, 7);
+        assert_eq!(decoded.score, 2.25);
+        assert_eq!(decoded.nickname, Some("d".to_string()));
+        assert!(!decoded.active);
+    }
+
+    #[test]
+    fn test_from_fields_invalid_number() {
+        let mut fields = HashMap::new();
+        fields.insert("age".to_string(), "old".to_string());
+        let result: Result<Record, _> = from_fields(fields);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_to_fields_rejects_nested() {
+        #[derive(Serialize)]
+        struct Nested {
+            inner: Vec<i64>,
+        }
+        let result = to_fields(&Nested { inner: vec![1] });
+        assert!(result.unwrap_err().to_string().contains("inner"));
+        assert!(to_fields(&5).is_err());
+    }
+
+    #[test]
+    fn test_json_value_round_trip() {
+        let values = vec![
+            ScalarValue::VStr("hello".to_string()),
+            ScalarValue::VInt(-42),
+            ScalarValue::VInt(i64::MAX),
+            ScalarValue::VFloat(1.5),
+        ];
+        for value in values {
+            let json = serde_json::Value::from(value.clone());
+            let input: ScalarValue = SetInput::try_from(json).unwrap().into();
+            assert_eq!(input, value);
+        }
+        assert_eq!(
+            serde_json::Value::from(ScalarValue::VNull),
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            serde_json::Value::from(ScalarValue::VBool(true)),
+            serde_json::Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_json_value_non_finite_floats() {
+        assert_eq!(
+            serde_json::Value::from(ScalarValue::VFloat(f64::NAN)),
+            serde_json::Value::String("NaN".to_string())
+        );
+        assert_eq!(
+            serde_json::Value::from(ScalarValue::VFloat(f64::INFINITY)),
+            serde_json::Value::String("inf".to_string())
+        );
+        assert_eq!(
+            serde_json::Value::from(ScalarValue::VFloat(f64::NEG_INFINITY)),
+            serde_json::Value::String("-inf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_set_input_from_json_rejects_non_scalars() {
+        let values = vec![
+            serde_json::Value::Null,
+            serde_json::Value::Bool(false),
+            serde_json::json!([1, 2]),
+            serde_json::json!({"a": 1}),
+        ];
+        for value in values {
+            assert!(matches!(
+                SetInput::try_from(value),
+                Err(CommandError::InvalidInput(_))
+            ));
+        }
+        assert_eq!(
+            SetInput::try_from(serde_json::json!(u64::MAX)).unwrap(),
+            SetInput::Float(u64::MAX as f64)
+        );
+    }
+}
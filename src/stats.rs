@@ -0,0 +1,218 @@
+//! # Stats Module
+//! Counters of the traffic of a client and of its watch streams, for capacity planning. Read them
+//! with [`Client::stats`](crate::client::Client::stats) and
+//! [`WatchStream::stats`](crate::watchstream::WatchStream::stats). The counters are atomic and
+//! updated as commands are written and replies read, so keeping them costs next to nothing.
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::errors::ErrorKind;
+
+/// A snapshot of the counters of a client, returned by
+/// [`Client::stats`](crate::client::Client::stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// The commands written to the server, including handshakes and heartbeats.
+    pub commands_sent: u64,
+    /// The replies read from the server.
+    pub responses_received: u64,
+    /// The bytes written to the server.
+    pub bytes_written: u64,
+    /// The bytes read from the server.
+    pub bytes_read: u64,
+    /// The failed commands by the kind of their error. Kinds without errors are left out.
+    pub errors: HashMap<ErrorKind, u64>,
+    /// The number of times the connection was re-established.
+    pub reconnects: u64,
+    /// How long ago the current connection was established.
+    pub uptime: Duration,
+    /// The times an idle connection was validated before a command, see
+    /// [`ClientBuilder::max_idle`](crate::client::ClientBuilder::max_idle).
+    pub idle_validations: u64,
+    /// The size in bytes of the largest reply read.
+    pub largest_response: u64,
+    /// The largest size in bytes the read buffer grew to, for tuning
+    /// [`ClientBuilder::initial_read_buffer`](crate::client::ClientBuilder::initial_read_buffer)
+    /// and [`ClientBuilder::shrink_after`](crate::client::ClientBuilder::shrink_after).
+    pub read_buffer_high_water: u64,
+}
+
+/// A snapshot of the counters of a watch stream, returned by
+/// [`WatchStream::stats`](crate::watchstream::WatchStream::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WatchStats {
+    /// The values the stream yielded.
+    pub messages_received: u64,
+    /// The bytes read from the server, including the replies to the watch command itself.
+    pub bytes_read: u64,
+    /// The messages that were received but could not be decoded, and so were not yielded.
+    pub dropped_values: u64,
+    /// The size in bytes of the largest message read.
+    pub largest_response: u64,
+    /// The largest size in bytes the read buffer grew to.
+    pub read_buffer_high_water: u64,
+}
+
+/// Every error kind, in the order of [`Counters::errors`].
+const ERROR_KINDS: [ErrorKind; 12] = [
+    ErrorKind::Connection,
+    ErrorKind::Timeout,
+    ErrorKind::OutcomeUnknown,
+    ErrorKind::Server,
+    ErrorKind::InvalidInput,
+    ErrorKind::Conversion,
+    ErrorKind::Protocol,
+    ErrorKind::ResponseTooLarge,
+    ErrorKind::Config,
+    ErrorKind::UnsupportedServer,
+    ErrorKind::Handshake,
+    ErrorKind::Bind,
+];
+
+/// The counters behind the snapshots, shared between a client and its command stream.
+#[derive(Debug)]
+pub(crate) struct Counters {
+    commands_sent: AtomicU64,
+    responses_received: AtomicU64,
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+    errors: [AtomicU64; ERROR_KINDS.len()],
+    reconnects: AtomicU64,
+    idle_validations: AtomicU64,
+    messages_received: AtomicU64,
+    dropped_values: AtomicU64,
+    largest_response: AtomicU64,
+    read_buffer_high_water: AtomicU64,
+    /// When the counters were created, which `connected_at` is relative to.
+    created: Instant,
+    /// The nanoseconds from `created` until the current connection was established.
+    connected_at: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn new() -> Self {
+        Counters {
+            commands_sent: AtomicU64::default(),
+            responses_received: AtomicU64::default(),
+            bytes_written: AtomicU64::default(),
+            bytes_read: AtomicU64::default(),
+            errors: Default::default(),
+            reconnects: AtomicU64::default(),
+            idle_validations: AtomicU64::default(),
+            messages_received: AtomicU64::default(),
+            dropped_values: AtomicU64::default(),
+            largest_response: AtomicU64::default(),
+            read_buffer_high_water: AtomicU64::default(),
+            created: Instant::now(),
+            connected_at: AtomicU64::default(),
+        }
+    }
+
+    pub(crate) fn sent(&self, commands: usize, bytes: usize) {
+        add(&self.commands_sent, commands);
+        add(&self.bytes_written, bytes);
+    }
+
+    pub(crate) fn read(&self, bytes: usize) {
+        add(&self.bytes_read, bytes);
+    }
+
+    pub(crate) fn received(&self, bytes: usize) {
+        add(&self.responses_received, 1);
+        max(&self.largest_response, bytes);
+    }
+
+    pub(crate) fn read_buffer(&self, size: usize) {
+        max(&self.read_buffer_high_water, size);
+    }
+
+    pub(crate) fn error(&self, kind: ErrorKind) {
+        if let Some(index) = ERROR_KINDS.iter().position(|k| *k == kind) {
+            add(&self.errors[index], 1);
+        }
+    }
+
+    pub(crate) fn reconnected(&self) {
+        add(&self.reconnects, 1);
+        let since_created = u64::try_from(self.created.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.connected_at.store(since_created, Ordering::Relaxed);
+    }
+
+    pub(crate) fn idle_validation(&self) {
+        add(&self.idle_validations, 1);
+    }
+
+    pub(crate) fn watch_message(&self) {
+        add(&self.messages_received, 1);
+    }
+
+    pub(crate) fn dropped(&self) {
+        add(&self.dropped_values, 1);
+    }
+
+    /// Sets every counter back to zero. The uptime is kept, as the connection is.
+    pub(crate) fn reset(&self) {
+        let counters = [
+            &self.commands_sent,
+            &self.responses_received,
+            &self.bytes_written,
+            &self.bytes_read,
+            &self.reconnects,
+            &self.idle_validations,
+            &self.messages_received,
+            &self.dropped_values,
+            &self.largest_response,
+            &self.read_buffer_high_water,
+        ];
+        for counter in counters.into_iter().chain(&self.errors) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn client_stats(&self) -> ClientStats {
+        let errors = ERROR_KINDS
+            .iter()
+            .zip(&self.errors)
+            .map(|(kind, count)| (*kind, get(count)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let connected_at = Duration::from_nanos(get(&self.connected_at));
+        ClientStats {
+            commands_sent: get(&self.commands_sent),
+            responses_received: get(&self.responses_received),
+            bytes_written: get(&self.bytes_written),
+            bytes_read: get(&self.bytes_read),
+            errors,
+            reconnects: get(&self.reconnects),
+            uptime: self.created.elapsed().saturating_sub(connected_at),
+            idle_validations: get(&self.idle_validations),
+            largest_response: get(&self.largest_response),
+            read_buffer_high_water: get(&self.read_buffer_high_water),
+        }
+    }
+
+    pub(crate) fn watch_stats(&self) -> WatchStats {
+        WatchStats {
+            messages_received: get(&self.messages_received),
+            bytes_read: get(&self.bytes_read),
+            dropped_values: get(&self.dropped_values),
+            largest_response: get(&self.largest_response),
+            read_buffer_high_water: get(&self.read_buffer_high_water),
+        }
+    }
+}
+
+fn add(counter: &AtomicU64, value: usize) {
+    counter.fetch_add(value as u64, Ordering::Relaxed);
+}
+
+fn max(counter: &AtomicU64, value: usize) {
+    counter.fetch_max(value as u64, Ordering::Relaxed);
+}
+
+fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
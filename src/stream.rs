@@ -1,28 +1,354 @@
-use std::io::{Read, Write};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    time::Instant,
+};
 
 use crate::{
-    commands::{Command, CommandExecutor, ScalarValue, WatchValue},
-    errors::StreamError,
+    commands::{
+        AsArg, AttrValue, Command, Execute, ExecutionMode, ScalarValue, WatchValue, WireCommand,
+    },
+    config::{
+        random_unit, ConnInfo, ConnectionOptions, ConnectionState, DEFAULT_SHRINK_READ_BUFFER_AFTER,
+    },
+    errors::{is_connection_lost, is_timeout, CommandError, StreamError},
+    framing,
+    stats::Counters,
+    transport::Transport,
+    LOG_TARGET,
 };
 
 pub trait Stream {
-    fn host(&self) -> &str;
-    fn port(&self) -> u16;
-    fn set_stream(&mut self, stream: std::net::TcpStream);
-    fn tcp_stream(&mut self) -> &std::net::TcpStream;
+    fn options(&self) -> &ConnectionOptions;
+    fn mode(&self) -> ExecutionMode;
+    fn set_transport(&mut self, transport: Transport);
+    fn transport(&mut self) -> &mut Transport;
+    /// The buffer replies are read into, kept between reads to avoid allocating for every reply.
+    fn read_buffer(&mut self) -> &mut ReadBuffer;
+    /// The buffer commands are queued in until they are written, kept between commands for the
+    /// same reason.
+    fn write_buffer(&mut self) -> &mut WriteBuffer;
     fn handshake(&mut self) -> Result<(), StreamError>;
+    /// The id the stream identifies itself with in the handshake.
+    fn id(&self) -> &str;
+    /// The traffic counters of the stream.
+    fn counters(&self) -> &Counters;
+    /// Whether the connection can be used, as last observed by the stream.
+    fn state(&self) -> ConnectionState;
+    fn set_state(&mut self, state: ConnectionState);
+    /// Marks the connection as out of sync with the server, e.g. because a reply was not read.
+    /// A closed connection stays closed.
+    fn mark_dirty(&mut self) {
+        if self.state() == ConnectionState::Healthy {
+            self.set_state(ConnectionState::Dirty);
+        }
+    }
+    /// Whether the connection must be re-established before the next command.
+    fn needs_reconnect(&self) -> bool {
+        self.state() != ConnectionState::Healthy
+    }
+}
+
+/// Write buffers that grew beyond this size for a large command are shrunk back afterwards.
+const SHRINK_BUFFER_SIZE: usize = DEFAULT_SHRINK_READ_BUFFER_AFTER;
+
+/// The buffer a stream reads replies into. It grows for large replies and keeps bytes received
+/// beyond the current reply for the next read.
+#[derive(Default)]
+pub(crate) struct ReadBuffer {
+    data: Vec<u8>,
+    filled: usize,
+    /// The number of replies still due for commands that were written.
+    expected: usize,
+    /// The size the buffer starts at and is shrunk back to.
+    initial: usize,
+    /// The size beyond which the buffer is shrunk back, if ever.
+    shrink_after: Option<usize>,
+}
+
+impl ReadBuffer {
+    /// An empty buffer sized per the options, allocated on the first read.
+    pub(crate) fn new(options: &ConnectionOptions) -> Self {
+        ReadBuffer {
+            initial: options.initial_read_buffer.max(1),
+            shrink_after: options.shrink_read_buffer_after,
+            ..ReadBuffer::default()
+        }
+    }
+
+    /// Discards buffered bytes, e.g. because they belong to a previous connection.
+    pub(crate) fn clear(&mut self) {
+        self.filled = 0;
+        self.expected = 0;
+        self.shrink();
+    }
+
+    /// Returns the length of the first complete reply in the buffer, if any.
+    fn next_reply(&self) -> Option<usize> {
+        framing::message_len(&self.data[..self.filled])
+    }
+
+    /// Removes the first `len` bytes, keeping the rest for the next reply.
+    fn consume(&mut self, len: usize) {
+        self.data.copy_within(len..self.filled, 0);
+        self.filled -= len;
+        self.expected = self.expected.saturating_sub(1);
+        self.shrink();
+    }
+
+    /// Shrinks a buffer that grew beyond the shrink threshold for a large reply, once the
+    /// buffered bytes fit the initial size again.
+    fn shrink(&mut self) {
+        let Some(threshold) = self.shrink_after else {
+            return;
+        };
+        if self.data.len() > threshold.max(self.initial) && self.filled <= self.initial {
+            self.data.truncate(self.initial);
+            self.data.shrink_to_fit();
+        }
+    }
+
+    /// Reads more bytes from `transport`, doubling the buffer when it is full, up to `limit`, and
+    /// returns how many were read. Must only be called with fewer than `limit` bytes buffered.
+    fn read_from(&mut self, transport: &mut Transport, limit: usize) -> std::io::Result<usize> {
+        if self.filled == self.data.len() {
+            let grown = (self.data.len() * 2).max(self.initial).min(limit);
+            self.data.resize(grown, 0);
+        }
+        match transport.read(&mut self.data[self.filled..])? {
+            0 => Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Connection closed by the server",
+            )),
+            read => {
+                self.filled += read;
+                Ok(read)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ReadBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ReadBuffer({}/{} bytes)", self.filled, self.data.len())
+    }
+}
+
+/// The buffer commands are encoded into until they are written. Commands queued together are
+/// written with a single write.
+#[derive(Default)]
+pub(crate) struct WriteBuffer {
+    data: Vec<u8>,
+    /// Whether every queued command is safe to send again after a failed write.
+    resendable: bool,
+    /// The number of queued commands.
+    count: usize,
+}
+
+impl WriteBuffer {
+    fn push(&mut self, command: WireCommand, resendable: bool) {
+        self.resendable = (self.data.is_empty() || self.resendable) && resendable;
+        command.encode_into(&mut self.data);
+        self.count += 1;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Discards the queued commands, keeping the allocation unless it grew for a large command.
+    fn clear(&mut self) {
+        if self.data.capacity() > SHRINK_BUFFER_SIZE {
+            self.data = Vec::new();
+        }
+        self.data.clear();
+        self.count = 0;
+    }
+}
+
+impl std::fmt::Debug for WriteBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WriteBuffer({} bytes)", self.data.len())
+    }
+}
+
+/// Reads a reply into the read buffer of the stream and decodes it, marking the stream dirty if
+/// the read fails, as a reply that arrives later would otherwise be read as the reply of the next
+/// command. Reads are repeated until the buffer holds a complete reply, as a reply can arrive in
+/// several pieces.
+///
+/// When a command stream loses its connection while waiting for the reply, the command may or
+/// may not have been executed, which is reported as [`StreamError::OutcomeUnknown`]. A reply
+/// larger than the maximum response size is not read to the end, which also leaves the stream
+/// dirty, as does a watch notification read where a command stream expects a reply.
+fn read_reply<T: Stream, V>(
+    stream: &mut T,
+    decode: impl FnOnce(&[u8]) -> Result<V, CommandError>,
+) -> Result<V, StreamError> {
+    // A reply never arrives for a command that is still queued.
+    stream.flush_commands()?;
+    let limit = stream.options().read_limit();
+    let mut buffer = std::mem::take(stream.read_buffer());
+    let result = loop {
+        match buffer.next_reply() {
+            Some(len)
+                if len <= limit
+                    && stream.mode() == ExecutionMode::Command
+                    && WatchValue::is_watch_push(&buffer.data[..len]) =>
+            {
+                record_reply(stream, &buffer, len);
+                buffer.consume(len);
+                stream.mark_dirty();
+                break Err(StreamError::ProtocolDesync);
+            }
+            Some(len) if len <= limit => {
+                record_reply(stream, &buffer, len);
+                let decoded = decode(&buffer.data[..len]);
+                buffer.consume(len);
+                stream.counters().received(len);
+                if let Err(CommandError::DecodeError(_)) = decoded {
+                    // The reply may not have ended where it was cut, so the next one is suspect.
+                    stream.mark_dirty();
+                }
+                break decoded.map_err(StreamError::from);
+            }
+            // A reply that declares more bytes than the limit is rejected before reading them.
+            None if framing::needed_len(&buffer.data[..buffer.filled]) <= limit => {
+                match buffer.read_from(stream.transport(), limit) {
+                    Ok(read) => {
+                        stream.counters().read(read);
+                        stream.counters().read_buffer(buffer.data.len());
+                    }
+                    // A watch stream waiting for the next push owes no reply, so timing out while
+                    // nothing is buffered leaves it in sync.
+                    Err(e)
+                        if is_timeout(&e)
+                            && stream.mode() == ExecutionMode::Watch
+                            && buffer.filled == 0
+                            && buffer.expected == 0 =>
+                    {
+                        break Err(e.into())
+                    }
+                    Err(e) => break Err(read_failed(stream, e)),
+                }
+            }
+            _ => {
+                stream.mark_dirty();
+                break Err(StreamError::ResponseTooLarge {
+                    limit,
+                    observed: framing::needed_len(&buffer.data[..buffer.filled]),
+                });
+            }
+        }
+    };
+    *stream.read_buffer() = buffer;
+    result
+}
+
+/// Checks the reply of the server to a handshake, keeping the error message of a rejected one.
+pub(crate) fn check_handshake(reply: Result<ScalarValue, StreamError>) -> Result<(), StreamError> {
+    match reply {
+        Ok(ScalarValue::VStr(v)) if v == "OK" => Ok(()),
+        Ok(reply) => Err(StreamError::HandshakeFailed {
+            reply,
+            server_error: None,
+        }),
+        Err(StreamError::CommandError(CommandError::ServerError { message, .. })) => {
+            Err(StreamError::HandshakeFailed {
+                reply: ScalarValue::VNull,
+                server_error: Some(message),
+            })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs the handshake `exchange` with reads bounded by the handshake timeout, so a server that
+/// accepts connections but never replies cannot block the handshake forever. The read timeout of
+/// the stream is restored afterwards.
+pub(crate) fn bounded_handshake<T: Stream>(
+    stream: &mut T,
+    exchange: impl FnOnce(&mut T) -> Result<(), StreamError>,
+) -> Result<(), StreamError> {
+    let timeout = stream.options().handshake_timeout;
+    stream.transport().tcp().set_read_timeout(Some(timeout))?;
+    let result = exchange(stream);
+    let read_timeout = stream.options().read_timeout_for(&stream.mode());
+    let restored = stream.transport().tcp().set_read_timeout(read_timeout);
+    match result {
+        Err(StreamError::IoError(e)) if is_timeout(&e) => {
+            let (host, port) = stream.options().active_endpoint();
+            Err(StreamError::HandshakeTimeout {
+                endpoint: format!("{}:{}", host, port),
+                timeout,
+            })
+        }
+        Err(e) => Err(e),
+        Ok(()) => Ok(restored?),
+    }
+}
+
+/// Marks the stream dirty after a failed read, or closed if the connection was lost.
+fn read_failed<T: Stream>(stream: &mut T, e: std::io::Error) -> StreamError {
+    if !is_connection_lost(&e) {
+        stream.mark_dirty();
+        return e.into();
+    }
+    stream.set_state(ConnectionState::Closed);
+    let error = match stream.mode() {
+        ExecutionMode::Command => StreamError::OutcomeUnknown(e),
+        ExecutionMode::Watch => StreamError::IoError(e),
+    };
+    let info = conn_info(stream);
+    stream.options().events.disconnected(&info, &error);
+    error
+}
+
+/// Describes the current connection of a stream for [`ConnectionEvents`] callbacks.
+pub(crate) fn conn_info<T: Stream>(stream: &mut T) -> ConnInfo {
+    let (host, port) = stream.options().active_endpoint();
+    ConnInfo {
+        host: host.to_string(),
+        port,
+        peer_addr: stream.transport().tcp().peer_addr().ok(),
+        mode: stream.mode(),
+        client_id: stream.id().to_string(),
+    }
+}
+
+pub trait Authenticator {
+    fn authenticate(&mut self) -> Result<(), StreamError>;
 }
 
 pub trait Reconnectable {
-    fn reconnect(&mut self, max_tries: u64) -> Result<(), StreamError>;
+    fn reconnect(&mut self) -> Result<(), StreamError>;
+}
+
+impl<T: Stream> Authenticator for T {
+    fn authenticate(&mut self) -> Result<(), StreamError> {
+        let Some(password) = self.options().password.clone() else {
+            return Ok(());
+        };
+        match self.execute_scalar_command(Command::AUTH { password })? {
+            ScalarValue::VStr(v) if v == "OK" => Ok(()),
+            value => Err(CommandError::UnexpectedResponse(value).into()),
+        }
+    }
 }
 
 pub trait ScalarValueReceiver {
     fn receive_scalar_value(&mut self) -> Result<ScalarValue, StreamError>;
+    fn receive_scalar_value_with_attrs(
+        &mut self,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError>;
+}
+
+pub trait MapValueReceiver {
+    fn receive_map_value(&mut self) -> Result<Option<crate::commands::MapValue>, StreamError>;
 }
 
-pub trait HsetValueReceiver {
-    fn receive_hset_value(&mut self) -> Result<crate::commands::HSetValue, StreamError>;
+pub trait ListValueReceiver {
+    fn receive_list_value(&mut self) -> Result<crate::commands::ListValue, StreamError>;
 }
 
 pub trait WatchValueReceiver {
@@ -30,108 +356,838 @@ pub trait WatchValueReceiver {
 }
 
 pub trait CommandSender {
-    fn send_command(&mut self, command: Command) -> Result<(), StreamError>;
+    /// Queues a command and writes every queued command.
+    fn send_command(&mut self, command: Command) -> Result<(), StreamError> {
+        self.queue_command(command)?;
+        self.flush_commands()
+    }
+    /// Queues several commands and writes them together, with as few writes as the transport
+    /// allows. If one of them cannot be queued, none of them is written.
+    fn send_commands(&mut self, commands: Vec<Command>) -> Result<(), StreamError>;
+    /// Queues a command to be written with the next flush. Queued commands are flushed before a
+    /// reply is read.
+    fn queue_command(&mut self, command: Command) -> Result<(), StreamError>;
+    /// Writes every queued command.
+    fn flush_commands(&mut self) -> Result<(), StreamError>;
 }
 
 impl<T: Stream> Reconnectable for T {
-    fn reconnect(&mut self, max_tries: u64) -> Result<(), StreamError> {
+    fn reconnect(&mut self) -> Result<(), StreamError> {
+        let policy = self.options().reconnect_policy;
+        let previous = self.transport().tcp().peer_addr().ok();
+        let started = Instant::now();
         let mut tries = 0;
-        while tries < max_tries {
+        let mut last_error = None;
+        while tries < policy.max_attempts() {
+            if tries > 0 {
+                std::thread::sleep(policy.delay(tries, random_unit()));
+            }
             tries += 1;
-            let stream = std::net::TcpStream::connect(format!("{}:{}", self.host(), self.port()));
-            match stream {
+            match self.options().connect(&self.mode(), previous) {
                 Ok(stream) => {
-                    self.set_stream(stream);
-                    self.handshake()?;
+                    self.set_transport(stream);
+                    self.read_buffer().clear();
+                    if let Some(recorder) = &self.options().recorder {
+                        recorder.reconnected(self.id());
+                    }
+                    // The handshake runs on the new connection, which must not be replaced again.
+                    self.set_state(ConnectionState::Healthy);
+                    if let Err(e) = self.handshake() {
+                        self.mark_dirty();
+                        return Err(e);
+                    }
+                    let attempts = u32::try_from(tries).unwrap_or(u32::MAX);
+                    self.counters().reconnected();
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "Reconnected {} as {} after {} attempts",
+                        self.mode().as_arg(),
+                        self.id(),
+                        attempts
+                    );
+                    let info = conn_info(self);
+                    self.options().events.reconnected(&info, attempts);
+                    self.options().observer.reconnected(&info, attempts);
                     return Ok(());
                 }
-                Err(_) => {
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                    continue;
-                }
+                Err(e) => last_error = Some(e),
             }
         }
-        Err(StreamError::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Max attempts reached",
-        )))
+        Err(StreamError::ReconnectFailed {
+            attempts: tries,
+            elapsed: started.elapsed(),
+            source: last_error.unwrap_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::Other, "no reconnect attempts allowed")
+            }),
+        })
     }
 }
 
-const MAX_REQUEST_SIZE: usize = 32 * 1024 * 1024; // 32MB per session, meeh probably too much, fi.
-
 impl<T: Stream> WatchValueReceiver for T {
     fn recieve_watchvalue(&mut self) -> Result<WatchValue, StreamError> {
-        let mut buffer = vec![0; MAX_REQUEST_SIZE];
-        let size = self.tcp_stream().read(&mut buffer)?;
-        let reply_slice = &buffer[..size];
-        let val = WatchValue::decode_watchvalue(reply_slice)?;
-        Ok(val)
+        read_reply(self, WatchValue::decode_watchvalue)
     }
 }
 
 impl<T: Stream> ScalarValueReceiver for T {
     fn receive_scalar_value(&mut self) -> Result<ScalarValue, StreamError> {
-        let mut buffer = vec![0; MAX_REQUEST_SIZE];
-        let size = self.tcp_stream().read(&mut buffer)?;
-        let reply_slice = &buffer[..size];
-        let val = ScalarValue::decode(reply_slice)?;
-        Ok(val)
+        read_reply(self, ScalarValue::decode)
+    }
+
+    fn receive_scalar_value_with_attrs(
+        &mut self,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError> {
+        read_reply(self, ScalarValue::decode_with_attrs)
     }
 }
 
-impl<T: Stream> HsetValueReceiver for T {
-    fn receive_hset_value(&mut self) -> Result<crate::commands::HSetValue, StreamError> {
-        let mut buffer = vec![0; MAX_REQUEST_SIZE];
-        let size = self.tcp_stream().read(&mut buffer)?;
-        let reply_slice = &buffer[..size];
-        let val = crate::commands::HSetValue::decode(reply_slice)?;
-        Ok(val)
+impl<T: Stream> MapValueReceiver for T {
+    fn receive_map_value(&mut self) -> Result<Option<crate::commands::MapValue>, StreamError> {
+        read_reply(self, crate::commands::MapValue::decode)
+    }
+}
+
+impl<T: Stream> ListValueReceiver for T {
+    fn receive_list_value(&mut self) -> Result<crate::commands::ListValue, StreamError> {
+        read_reply(self, crate::commands::ListValue::decode)
     }
 }
 
 impl<T: Stream> CommandSender for T {
-    fn send_command(&mut self, command: Command) -> Result<(), StreamError> {
-        eprintln!("Sending command: {:?}", command);
-        let serialized_command = command.encode();
-        eprintln!("Sending command: {:?}", serialized_command);
-        match self.tcp_stream().write_all(&serialized_command) {
-            Ok(_) => Ok(()),
-            Err(_) => {
-                self.reconnect(10)?;
-                self.tcp_stream().write_all(&serialized_command)?;
-                Ok(())
+    fn queue_command(&mut self, command: Command) -> Result<(), StreamError> {
+        // Connection setup is repeated by reconnecting anyway, so it is safe to send again.
+        let resendable = command.is_idempotent()
+            || matches!(command, Command::AUTH { .. } | Command::HANDSHAKE { .. });
+        let name = command.name();
+        let command = command.into_wire();
+        let limit = self.options().max_command_size;
+        if command.len > limit {
+            // Nothing was written, so the connection stays usable.
+            return Err(StreamError::CommandTooLarge {
+                size: command.len,
+                limit,
+            });
+        }
+        // Replies still due for commands written earlier would be read as replies to this one.
+        let unread = self.mode() == ExecutionMode::Command
+            && self.write_buffer().is_empty()
+            && self.read_buffer().expected > 0;
+        if self.needs_reconnect() || unread {
+            log::debug!(
+                target: LOG_TARGET,
+                "Connection is out of sync, reconnecting before sending {}",
+                name
+            );
+            reconnect_automatically(self)?;
+        }
+        self.write_buffer().push(command, resendable);
+        Ok(())
+    }
+
+    fn send_commands(&mut self, commands: Vec<Command>) -> Result<(), StreamError> {
+        for command in commands {
+            if let Err(e) = self.queue_command(command) {
+                self.write_buffer().clear();
+                return Err(e);
             }
         }
+        self.flush_commands()
+    }
+
+    fn flush_commands(&mut self) -> Result<(), StreamError> {
+        if self.write_buffer().is_empty() {
+            return Ok(());
+        }
+        let mut buffer = std::mem::take(self.write_buffer());
+        let result = write_commands(self, &buffer.data, buffer.resendable);
+        if result.is_ok() {
+            self.read_buffer().expected += buffer.count;
+            self.counters().sent(buffer.count, buffer.data.len());
+        }
+        buffer.clear();
+        *self.write_buffer() = buffer;
+        result
+    }
+}
+
+/// Writes encoded commands, reconnecting if the write fails. The commands are written again after
+/// reconnecting only if they are `resendable`.
+fn write_commands<T: Stream>(
+    stream: &mut T,
+    commands: &[u8],
+    resendable: bool,
+) -> Result<(), StreamError> {
+    match stream.transport().write_all(commands) {
+        Ok(_) => {
+            record_commands(stream, commands);
+            Ok(())
+        }
+        Err(e) => {
+            // Part of the commands may have reached the server before the write failed, so only
+            // commands that are safe to repeat are sent again.
+            let error = if resendable {
+                StreamError::IoError(e)
+            } else {
+                StreamError::OutcomeUnknown(e)
+            };
+            stream.set_state(ConnectionState::Closed);
+            let info = conn_info(stream);
+            stream.options().events.disconnected(&info, &error);
+            if !stream.options().reconnect_policy.is_automatic() {
+                return Err(error);
+            }
+            stream.reconnect()?;
+            if !resendable {
+                return Err(error);
+            }
+            stream.transport().write_all(commands)?;
+            record_commands(stream, commands);
+            Ok(())
+        }
     }
 }
 
-impl<T: Stream> CommandExecutor for T {
+/// Re-establishes a lost connection before the next command, unless the reconnect policy leaves
+/// that to the caller.
+pub(crate) fn reconnect_automatically<T: Stream>(stream: &mut T) -> Result<(), StreamError> {
+    if !stream.options().reconnect_policy.is_automatic() {
+        return Err(StreamError::ReconnectDisabled);
+    }
+    stream.reconnect()
+}
+
+/// Passes written commands to the recorder of the stream, if any.
+fn record_commands<T: Stream>(stream: &T, commands: &[u8]) {
+    if let Some(recorder) = &stream.options().recorder {
+        recorder.written(stream.id(), commands);
+    }
+}
+
+/// Passes the first `len` bytes of the buffer, a reply, to the recorder of the stream, if any. A
+/// reply that no command is waiting for is a watch notification.
+fn record_reply<T: Stream>(stream: &T, buffer: &ReadBuffer, len: usize) {
+    if let Some(recorder) = &stream.options().recorder {
+        recorder.received(stream.id(), &buffer.data[..len], buffer.expected == 0);
+    }
+}
+
+impl<T: Stream> Execute for T {
     fn execute_scalar_command(&mut self, command: Command) -> Result<ScalarValue, StreamError> {
         self.send_command(command)?;
         self.receive_scalar_value()
     }
 
-    fn execute_hset_command(
+    fn execute_scalar_command_with_attrs(
+        &mut self,
+        command: Command,
+    ) -> Result<(ScalarValue, HashMap<String, AttrValue>), StreamError> {
+        self.send_command(command)?;
+        self.receive_scalar_value_with_attrs()
+    }
+
+    fn execute_map_command(
         &mut self,
         command: Command,
-    ) -> Result<crate::commands::HSetValue, StreamError> {
+    ) -> Result<Option<crate::commands::MapValue>, StreamError> {
         self.send_command(command)?;
-        self.receive_hset_value()
+        self.receive_map_value()
+    }
+
+    fn execute_list_command(
+        &mut self,
+        command: Command,
+    ) -> Result<crate::commands::ListValue, StreamError> {
+        self.send_command(command)?;
+        self.receive_list_value()
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::commandstream::CommandStream;
+    use crate::{
+        commandstream::CommandStream,
+        config::{ReconnectPolicy, DEFAULT_INITIAL_READ_BUFFER},
+        testutil::{FakeServer, Reply},
+        watchstream::WatchStream,
+    };
 
     use super::*;
 
     #[test]
     fn test_reconnect() {
-        let mut command_client = CommandStream::new("localhost".to_string(), 7379).unwrap();
-        let reconnect_result = command_client.reconnect(10);
+        let options = ConnectionOptions {
+            reconnect_policy: ReconnectPolicy::fixed(10, std::time::Duration::from_secs(1)),
+            ..ConnectionOptions::default()
+        };
+        let mut command_client = CommandStream::new(options).unwrap();
+        let reconnect_result = command_client.reconnect();
         assert!(reconnect_result.is_ok());
     }
+
+    #[test]
+    fn test_failed_read_marks_dirty() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let options = ConnectionOptions {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            read_timeout: Some(std::time::Duration::from_millis(10)),
+            ..ConnectionOptions::default()
+        };
+        let mut command_client = CommandStream::new(options).unwrap();
+        let _server = listener.accept().unwrap();
+        assert!(command_client.receive_scalar_value().is_err());
+        assert_eq!(command_client.state(), ConnectionState::Dirty);
+        assert!(command_client.needs_reconnect());
+    }
+
+    #[test]
+    fn test_unexpected_handshake_reply() {
+        let nope = || Reply::Value(ScalarValue::VStr("NOPE".to_string()));
+        let server = FakeServer::start(vec![vec![Reply::ok()], vec![nope(), Reply::ok()]]);
+        let mut options = server.options();
+        options.reconnect_policy = ReconnectPolicy::fixed(1, std::time::Duration::ZERO);
+        let mut command_client = CommandStream::new(options).unwrap();
+        match command_client.reconnect() {
+            Err(StreamError::HandshakeFailed {
+                reply: ScalarValue::VStr(v),
+                server_error: None,
+            }) => assert_eq!(v, "NOPE"),
+            other => panic!("expected HandshakeFailed, got {:?}", other),
+        }
+        assert_eq!(command_client.state(), ConnectionState::Dirty);
+        let server = FakeServer::start(vec![vec![nope(), Reply::ok()]]);
+        let mut watch_client = WatchStream::new(server.options()).unwrap();
+        match watch_client.handshake() {
+            Err(StreamError::HandshakeFailed {
+                reply: ScalarValue::VStr(v),
+                server_error: None,
+            }) => assert_eq!(v, "NOPE"),
+            other => panic!("expected HandshakeFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_watch_stream_keeps_id_across_reconnect() {
+        let server = FakeServer::start(vec![vec![Reply::ok()], vec![Reply::ok()]]);
+        let mut watch_client = WatchStream::new(server.options()).unwrap();
+        watch_client.handshake().unwrap();
+        let id = watch_client.client_id().to_string();
+        watch_client.reconnect().unwrap();
+        assert_eq!(watch_client.client_id(), id);
+        drop(watch_client);
+        let received = server.received();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|(_, args)| args[0] == id));
+    }
+
+    #[test]
+    fn test_watch_stream_honors_disabled_reconnect() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let mut options = server.options();
+        options.reconnect_policy = ReconnectPolicy::disabled();
+        let mut watch_client = WatchStream::new(options).unwrap();
+        watch_client.handshake().unwrap();
+        watch_client.mark_dirty();
+        assert!(matches!(
+            watch_client.execute_scalar_command(Command::PING),
+            Err(StreamError::ReconnectDisabled)
+        ));
+        drop(watch_client);
+        assert_eq!(server.received().len(), 1);
+    }
+
+    #[test]
+    fn test_rejected_handshake_keeps_server_error() {
+        let rejected = || Reply::Error("ERR unsupported client version".to_string());
+        let server = FakeServer::start(vec![vec![Reply::ok()], vec![rejected(), Reply::ok()]]);
+        let mut options = server.options();
+        options.reconnect_policy = ReconnectPolicy::fixed(1, std::time::Duration::ZERO);
+        let mut command_client = CommandStream::new(options).unwrap();
+        let error = command_client.reconnect().unwrap_err();
+        match &error {
+            StreamError::HandshakeFailed {
+                reply: ScalarValue::VNull,
+                server_error: Some(message),
+            } => assert_eq!(message, "ERR unsupported client version"),
+            other => panic!("expected HandshakeFailed, got {:?}", other),
+        }
+        assert_eq!(
+            error.to_string(),
+            "handshake failed: ERR unsupported client version"
+        );
+        let server = FakeServer::start(vec![vec![rejected(), Reply::ok()]]);
+        let mut watch_client = WatchStream::new(server.options()).unwrap();
+        assert!(matches!(
+            watch_client.handshake(),
+            Err(StreamError::HandshakeFailed {
+                server_error: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_connection_state_transitions() {
+        // Wire type 7 does not exist, so this frames as a reply that fails to decode.
+        let garbage = vec![0x0f, 0x01, 0x02];
+        let server = FakeServer::start(vec![
+            vec![Reply::Push(garbage), Reply::Value(ScalarValue::VInt(1))],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(2)),
+                Reply::Close,
+            ],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(3))],
+        ]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        assert_eq!(command_client.state(), ConnectionState::Healthy);
+        let get = || Command::GET {
+            key: "key".to_string(),
+        };
+        assert!(matches!(
+            command_client.execute_scalar_command(get()),
+            Err(StreamError::CommandError(CommandError::DecodeError(_)))
+        ));
+        assert_eq!(command_client.state(), ConnectionState::Dirty);
+        // The reply to the first command is discarded by reconnecting.
+        assert_eq!(
+            command_client.execute_scalar_command(get()).unwrap(),
+            ScalarValue::VInt(2)
+        );
+        assert_eq!(command_client.state(), ConnectionState::Healthy);
+        assert!(matches!(
+            command_client.execute_scalar_command(get()),
+            Err(StreamError::OutcomeUnknown(_))
+        ));
+        assert_eq!(command_client.state(), ConnectionState::Closed);
+        // Marking a closed connection dirty keeps it closed.
+        command_client.mark_dirty();
+        assert_eq!(command_client.state(), ConnectionState::Closed);
+        assert_eq!(
+            command_client.execute_scalar_command(get()).unwrap(),
+            ScalarValue::VInt(3)
+        );
+        assert_eq!(command_client.state(), ConnectionState::Healthy);
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_reconnect_keeps_nodelay() {
+        let server = FakeServer::start(vec![vec![], vec![Reply::ok()]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        command_client.reconnect().unwrap();
+        assert!(command_client.stream.tcp().nodelay().unwrap());
+        drop(command_client);
+        assert_eq!(server.received()[0].0, "HANDSHAKE");
+    }
+
+    #[test]
+    fn test_reconnect_tries_every_address() {
+        let server = FakeServer::start(vec![vec![], vec![Reply::ok()]]);
+        let options = ConnectionOptions {
+            // Re-resolved on reconnect; only the 127.0.0.1 address has a listener.
+            host: "localhost".to_string(),
+            ..server.options()
+        };
+        let mut command_client = CommandStream::new(options).unwrap();
+        command_client.reconnect().unwrap();
+        assert_eq!(
+            command_client.stream.tcp().peer_addr().unwrap().ip(),
+            std::net::Ipv4Addr::LOCALHOST
+        );
+        drop(command_client);
+        assert_eq!(server.received().last().unwrap().0, "HANDSHAKE");
+    }
+
+    #[test]
+    fn test_reconnect_after_server_restart() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let options = ConnectionOptions {
+            reconnect_policy: ReconnectPolicy::exponential(
+                10,
+                std::time::Duration::from_millis(20),
+                std::time::Duration::from_millis(200),
+            ),
+            ..server.options()
+        };
+        let port = server.port;
+        let mut command_client = CommandStream::new(options).unwrap();
+        command_client.handshake().unwrap();
+        server.received();
+        let restart = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            FakeServer::start_on(port, vec![vec![Reply::ok()]])
+        });
+        command_client.reconnect().unwrap();
+        drop(command_client);
+        assert_eq!(restart.join().unwrap().received()[0].0, "HANDSHAKE");
+    }
+
+    #[test]
+    fn test_reconnect_failure_reports_attempts() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let options = ConnectionOptions {
+            reconnect_policy: ReconnectPolicy::fixed(3, std::time::Duration::from_millis(10)),
+            ..server.options()
+        };
+        let mut command_client = CommandStream::new(options).unwrap();
+        command_client.handshake().unwrap();
+        server.received();
+        match command_client.reconnect() {
+            Err(StreamError::ReconnectFailed {
+                attempts, elapsed, ..
+            }) => {
+                assert_eq!(attempts, 3);
+                assert!(elapsed >= std::time::Duration::from_millis(20));
+            }
+            other => panic!("expected ReconnectFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reconnect_handshake_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accepts both connections but never replies.
+        let silent = std::thread::spawn(move || {
+            let _first = listener.accept().unwrap();
+            let _second = listener.accept().unwrap();
+            std::thread::sleep(std::time::Duration::from_secs(2));
+        });
+        let options = ConnectionOptions {
+            host: "127.0.0.1".to_string(),
+            port,
+            handshake_timeout: std::time::Duration::from_millis(100),
+            read_timeout: Some(std::time::Duration::from_secs(30)),
+            reconnect_policy: ReconnectPolicy::fixed(1, std::time::Duration::ZERO),
+            ..ConnectionOptions::default()
+        };
+        let mut command_client = CommandStream::new(options).unwrap();
+        let started = Instant::now();
+        assert!(matches!(
+            command_client.reconnect(),
+            Err(StreamError::HandshakeTimeout { .. })
+        ));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        // The read timeout is restored for later commands.
+        assert_eq!(
+            command_client.stream.tcp().read_timeout().unwrap(),
+            Some(std::time::Duration::from_secs(30))
+        );
+        silent.join().unwrap();
+    }
+
+    #[test]
+    fn test_reply_declaring_more_than_limit() {
+        // A string value declaring 2^40 bytes, of which none follow.
+        let huge = vec![0x22, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        let server = FakeServer::start(vec![vec![Reply::Push(huge), Reply::ok()]]);
+        let mut options = server.options();
+        // Fail instead of waiting forever for the declared bytes.
+        options.read_timeout = Some(std::time::Duration::from_secs(5));
+        let mut command_client = CommandStream::new(options).unwrap();
+        match command_client.execute_scalar_command(Command::PING) {
+            Err(StreamError::ResponseTooLarge { limit, observed }) => {
+                assert_eq!(limit, crate::config::DEFAULT_MAX_RESPONSE_SIZE);
+                assert!(observed > 1 << 40);
+            }
+            other => panic!("expected ResponseTooLarge, got {:?}", other),
+        }
+        assert!(command_client.read_buffer().data.len() <= DEFAULT_INITIAL_READ_BUFFER);
+        assert_eq!(command_client.state(), ConnectionState::Dirty);
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_reply_larger_than_read_buffer() {
+        let value = "x".repeat(3 * DEFAULT_INITIAL_READ_BUFFER);
+        let server = FakeServer::start(vec![vec![Reply::Value(ScalarValue::VStr(value.clone()))]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        command_client.send_command(Command::PING).unwrap();
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VStr(value)
+        );
+        assert!(command_client.read_buffer().data.len() > DEFAULT_INITIAL_READ_BUFFER);
+        let stats = command_client.counters().client_stats();
+        assert!(stats.largest_response > 3 * DEFAULT_INITIAL_READ_BUFFER as u64);
+        assert_eq!(
+            stats.read_buffer_high_water,
+            command_client.read_buffer().data.len() as u64
+        );
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_read_buffer_shrinks_after_threshold() {
+        let large = || Reply::Value(ScalarValue::VStr("x".repeat(16 * 1024)));
+        let small = || Reply::Value(ScalarValue::VInt(1));
+        let server = FakeServer::start(vec![
+            vec![Reply::ok(), large(), small()],
+            vec![Reply::ok(), large(), small()],
+        ]);
+        let mut options = server.options();
+        options.initial_read_buffer = 512;
+        options.shrink_read_buffer_after = Some(8 * 1024);
+        let mut command_client = CommandStream::new(options.clone()).unwrap();
+        command_client.handshake().unwrap();
+        command_client
+            .execute_scalar_command(Command::PING)
+            .unwrap();
+        // The buffer is shrunk back as soon as the large reply is consumed.
+        assert_eq!(command_client.read_buffer().data.len(), 512);
+        assert!(
+            command_client
+                .counters()
+                .client_stats()
+                .read_buffer_high_water
+                >= 16 * 1024
+        );
+        command_client
+            .execute_scalar_command(Command::PING)
+            .unwrap();
+        assert_eq!(command_client.read_buffer().data.len(), 512);
+        drop(command_client);
+
+        // Without a threshold the buffer keeps its largest size.
+        options.shrink_read_buffer_after = None;
+        let mut command_client = CommandStream::new(options).unwrap();
+        command_client.handshake().unwrap();
+        command_client
+            .execute_scalar_command(Command::PING)
+            .unwrap();
+        command_client
+            .execute_scalar_command(Command::PING)
+            .unwrap();
+        assert!(command_client.read_buffer().data.len() >= 16 * 1024);
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_max_read_buffer_applies_to_watch_stream() {
+        let large = "x".repeat(4 * 1024);
+        let server = FakeServer::start(vec![vec![Reply::Value(ScalarValue::VStr(large))]]);
+        let mut options = server.options();
+        options.initial_read_buffer = 256;
+        options.max_read_buffer = Some(1024);
+        let mut watch_client = WatchStream::new(options).unwrap();
+        match watch_client.handshake() {
+            Err(StreamError::ResponseTooLarge { limit, .. }) => assert_eq!(limit, 1024),
+            other => panic!("expected ResponseTooLarge, got {:?}", other),
+        }
+        assert!(watch_client.read_buffer().data.len() <= 1024);
+        // The reply declares its size up front, so it is rejected before the buffer grows.
+        assert!(watch_client.counters().watch_stats().read_buffer_high_water <= 1024);
+        drop(watch_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_write_buffer_is_reused() {
+        let server = FakeServer::start(vec![vec![Reply::ok(), Reply::ok()]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        let echo = |message: String| Command::ECHO { message };
+        command_client
+            .execute_scalar_command(echo("x".repeat(100)))
+            .unwrap();
+        let capacity = command_client.write_buffer().data.capacity();
+        assert!(command_client.write_buffer().is_empty());
+        assert!(capacity >= 100);
+        command_client
+            .execute_scalar_command(echo("x".to_string()))
+            .unwrap();
+        assert_eq!(command_client.write_buffer().data.capacity(), capacity);
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_queued_command_is_flushed_before_reading() {
+        let server = FakeServer::start(vec![vec![Reply::Value(ScalarValue::VInt(1))]]);
+        let mut options = server.options();
+        // Fail instead of waiting forever if the command is never written.
+        options.read_timeout = Some(std::time::Duration::from_secs(5));
+        let mut command_client = CommandStream::new(options).unwrap();
+        command_client.queue_command(Command::PING).unwrap();
+        assert!(!command_client.write_buffer().is_empty());
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VInt(1)
+        );
+        assert!(command_client.write_buffer().is_empty());
+        drop(command_client);
+        assert_eq!(server.received()[0].0, "PING");
+    }
+
+    #[test]
+    fn test_send_commands() {
+        let replies = (1..=3).map(|i| Reply::Value(ScalarValue::VInt(i)));
+        let server = FakeServer::start(vec![replies.collect()]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        let incr = |key: &str| Command::INCR {
+            key: key.to_string(),
+        };
+        command_client
+            .send_commands(vec![incr("a"), incr("b"), incr("c")])
+            .unwrap();
+        for i in 1..=3 {
+            assert_eq!(
+                command_client.receive_scalar_value().unwrap(),
+                ScalarValue::VInt(i)
+            );
+        }
+        drop(command_client);
+        let keys: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(_, args)| args[0].clone())
+            .collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_unread_reply_is_discarded() {
+        let stale = ScalarValue::VStr("stale".to_string());
+        let fresh = ScalarValue::VStr("fresh".to_string());
+        let server = FakeServer::start(vec![
+            vec![Reply::Value(stale)],
+            vec![Reply::ok(), Reply::Value(fresh.clone())],
+        ]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        let get = || Command::GET {
+            key: "key".to_string(),
+        };
+        // The reply to this command is never read.
+        command_client.send_command(get()).unwrap();
+        assert_eq!(command_client.execute_scalar_command(get()).unwrap(), fresh);
+        drop(command_client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["GET", "HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_watch_push_on_command_stream_is_a_desync() {
+        let push = crate::commands::encode_watch_reply(&ScalarValue::VInt(1), "fingerprint");
+        let fresh = ScalarValue::VStr("fresh".to_string());
+        let server = FakeServer::start(vec![
+            vec![Reply::Push(push), Reply::Value(ScalarValue::VInt(2))],
+            vec![Reply::ok(), Reply::Value(fresh.clone())],
+        ]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        let get = || Command::GET {
+            key: "key".to_string(),
+        };
+        assert!(matches!(
+            command_client.execute_scalar_command(get()),
+            Err(StreamError::ProtocolDesync)
+        ));
+        // The reply to the first command is still on its way, so the stream reconnects.
+        assert_eq!(command_client.execute_scalar_command(get()).unwrap(), fresh);
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_coalesced_replies_are_kept() {
+        let mut replies = crate::commands::encode_reply(&ScalarValue::VInt(1));
+        replies.extend(crate::commands::encode_reply(&ScalarValue::VStr(
+            "two".to_string(),
+        )));
+        let server = FakeServer::start(vec![vec![Reply::Push(replies), Reply::ok()]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        let get = |key: &str| Command::GET {
+            key: key.to_string(),
+        };
+        command_client
+            .send_commands(vec![get("first"), get("second")])
+            .unwrap();
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VInt(1)
+        );
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VStr("two".to_string())
+        );
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_reply_split_into_single_bytes() {
+        let value = "x".repeat(2 * DEFAULT_INITIAL_READ_BUFFER);
+        let server = FakeServer::start(vec![vec![
+            Reply::Trickle(ScalarValue::VStr(value.clone())),
+            Reply::Trickle(ScalarValue::VInt(42)),
+        ]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        command_client.send_command(Command::PING).unwrap();
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VStr(value)
+        );
+        command_client.send_command(Command::PING).unwrap();
+        assert_eq!(
+            command_client.receive_scalar_value().unwrap(),
+            ScalarValue::VInt(42)
+        );
+        drop(command_client);
+        server.received();
+    }
+
+    #[test]
+    fn test_failed_write_resends_idempotent_command() {
+        let server = FakeServer::start(vec![
+            vec![],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1))],
+        ]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        command_client
+            .stream
+            .tcp()
+            .shutdown(std::net::Shutdown::Write)
+            .unwrap();
+        let get = Command::GET {
+            key: "key".to_string(),
+        };
+        assert_eq!(
+            command_client.execute_scalar_command(get).unwrap(),
+            ScalarValue::VInt(1)
+        );
+        drop(command_client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE", "GET"]);
+    }
+
+    #[test]
+    fn test_failed_write_does_not_resend_non_idempotent_command() {
+        let server = FakeServer::start(vec![vec![], vec![Reply::ok()]]);
+        let mut command_client = CommandStream::new(server.options()).unwrap();
+        command_client
+            .stream
+            .tcp()
+            .shutdown(std::net::Shutdown::Write)
+            .unwrap();
+        let incr = Command::INCR {
+            key: "key".to_string(),
+        };
+        assert!(matches!(
+            command_client.send_command(incr),
+            Err(StreamError::OutcomeUnknown(_))
+        ));
+        // The stream reconnected, so it is not left dirty.
+        assert_eq!(command_client.state(), ConnectionState::Healthy);
+        drop(command_client);
+        let commands: Vec<String> = server.received().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(commands, vec!["HANDSHAKE"]);
+    }
 }
@@ -0,0 +1,415 @@
+//! # Subscription Module
+//! Scoped watches of a single key, which follow its changes on a thread of their own and unwatch
+//! the key when they go out of scope. Create one with [`Client::subscribe`] or
+//! [`Client::watch_scope`].
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Iter, Receiver, Sender, TryIter},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    client::Client,
+    commands::ScalarValue,
+    errors::{is_timeout, ClientError, StreamError},
+    stream::Stream,
+    watchedmap::POLL_INTERVAL,
+    watchstream::WatchStream,
+    LOG_TARGET,
+};
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+impl Client {
+    /// Watches the key until the returned [`Subscription`] is dropped. Keys are scoped to the
+    /// prefix of the client, if any.
+    ///
+    /// A key can only be subscribed to once at a time, unless allowed with
+    /// [`ClientBuilder::allow_duplicate_subscriptions`](crate::client::ClientBuilder::allow_duplicate_subscriptions).
+    /// # Example
+    /// ```no_run
+    /// use dicedb_rs::client::Client;
+    ///
+    /// let client = Client::new("localhost".to_string(), 7379)?;
+    /// let flag = client.subscribe("config:flag")?;
+    /// println!("flag is {}", flag.latest());
+    /// for value in flag.changes() {
+    ///     println!("flag changed to {}", value);
+    /// }
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Errors
+    /// * [`ClientError::AlreadySubscribed`] - If a subscription to the key is still alive.
+    /// * [`ClientError`] - If the key could not be watched, see [`Client::get_watch`].
+    pub fn subscribe(&self, key: &str) -> Result<Subscription> {
+        let scoped_key = self.scoped_key(key);
+        let registration = self
+            .subscriptions
+            .register(&scoped_key, self.options.duplicate_subscriptions)
+            .ok_or(ClientError::AlreadySubscribed { key: scoped_key })?;
+        let (stream, value) = self.open_watch(key)?;
+        Subscription::start(
+            key,
+            stream,
+            value,
+            registration,
+            self.options.handshake_timeout,
+        )
+    }
+
+    /// Watches the key while `f` runs, and unwatches it once `f` returns or panics.
+    /// # Example
+    /// ```no_run
+    /// use dicedb_rs::client::Client;
+    ///
+    /// let client = Client::new("localhost".to_string(), 7379)?;
+    /// let enabled = client.watch_scope("config:flag", |flag| {
+    ///     flag.changes().any(|value| value.to_string() == "on")
+    /// })?;
+    /// # Ok::<(), dicedb_rs::errors::ClientError>(())
+    /// ```
+    /// # Errors
+    /// * [`ClientError`] - If the key could not be watched, see [`Client::subscribe`].
+    pub fn watch_scope<R>(&self, key: &str, f: impl FnOnce(&Subscription) -> R) -> Result<R> {
+        let subscription = self.subscribe(key)?;
+        Ok(f(&subscription))
+    }
+
+    /// Returns the keys of the subscriptions of the client that are alive, scoped to the prefix
+    /// of the client and sorted. A key is listed once per subscription.
+    pub fn active_watches(&self) -> Vec<String> {
+        self.subscriptions.keys()
+    }
+}
+
+/// The keys a client is subscribed to, shared with its subscriptions so each can remove its key
+/// when dropped, even after the client.
+#[derive(Debug, Default)]
+pub(crate) struct Registry(Mutex<Vec<String>>);
+
+impl Registry {
+    /// Adds the key, unless it is already present and duplicates are not allowed.
+    fn register(self: &Arc<Self>, key: &str, allow_duplicates: bool) -> Option<Registration> {
+        let mut keys = self.lock();
+        if !allow_duplicates && keys.iter().any(|k| k == key) {
+            return None;
+        }
+        keys.push(key.to_string());
+        Some(Registration {
+            registry: self.clone(),
+            key: key.to_string(),
+        })
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys = self.lock().clone();
+        keys.sort();
+        keys
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A key in a [`Registry`], removed when dropped.
+#[derive(Debug)]
+struct Registration {
+    registry: Arc<Registry>,
+    key: String,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut keys = self.registry.lock();
+        if let Some(index) = keys.iter().position(|k| *k == self.key) {
+            keys.remove(index);
+        }
+    }
+}
+
+/// A watch of a single key, returned by [`Client::subscribe`]. A thread receives the changes of
+/// the key, which are kept as the latest value and queued for [`Subscription::changes`].
+///
+/// Dropping the subscription stops the thread and waits for it to unwatch the key, which takes
+/// no longer than the handshake timeout. The key is not watched again if the connection is lost,
+/// see [`Subscription::is_active`].
+#[derive(Debug)]
+pub struct Subscription {
+    key: String,
+    latest: Arc<RwLock<ScalarValue>>,
+    changes: Receiver<ScalarValue>,
+    active: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+    // Dropped after the listener is joined, so the key is listed until it is unwatched.
+    _registration: Registration,
+}
+
+impl Subscription {
+    fn start(
+        key: &str,
+        stream: WatchStream,
+        value: ScalarValue,
+        registration: Registration,
+        unwatch_timeout: Duration,
+    ) -> Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let listener = Listener {
+            key: key.to_string(),
+            latest: Arc::new(RwLock::new(value)),
+            changes: sender,
+            active: Arc::new(AtomicBool::new(true)),
+            stop: Arc::default(),
+            unwatch_timeout,
+        };
+        let mut subscription = Subscription {
+            key: key.to_string(),
+            latest: listener.latest.clone(),
+            changes,
+            active: listener.active.clone(),
+            stop: listener.stop.clone(),
+            listener: None,
+            _registration: registration,
+        };
+        let handle = thread::Builder::new()
+            .name(format!("dicedb-subscription-{}", key))
+            .spawn(move || listener.run(stream))?;
+        subscription.listener = Some(handle);
+        Ok(subscription)
+    }
+
+    /// Returns the key of the subscription, as passed to [`Client::subscribe`].
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the latest value observed for the key.
+    pub fn latest(&self) -> ScalarValue {
+        self.latest
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns an iterator over the changes of the key that were not taken yet, which waits for
+    /// the next change and ends once the subscription is no longer active.
+    pub fn changes(&self) -> Iter<'_, ScalarValue> {
+        self.changes.iter()
+    }
+
+    /// Like [`Subscription::changes`], but ends once every change received so far was taken.
+    pub fn try_changes(&self) -> TryIter<'_, ScalarValue> {
+        self.changes.try_iter()
+    }
+
+    /// Returns true while the key is being watched, which ends if the connection of the watch is
+    /// lost.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(listener) = self.listener.take() {
+            // A panicking listener has already logged its panic.
+            let _ = listener.join();
+        }
+    }
+}
+
+/// Receives the changes of a subscription on its own thread.
+struct Listener {
+    key: String,
+    latest: Arc<RwLock<ScalarValue>>,
+    changes: Sender<ScalarValue>,
+    active: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    unwatch_timeout: Duration,
+}
+
+impl Listener {
+    /// Receives changes until the subscription is dropped or the connection is lost.
+    fn run(self, mut stream: WatchStream) {
+        if let Err(e) = stream.stream.tcp().set_read_timeout(Some(POLL_INTERVAL)) {
+            log::warn!(target: LOG_TARGET, "Failed to watch {}: {}", self.key, e);
+            self.active.store(false, Ordering::Relaxed);
+            return;
+        }
+        while !self.stop.load(Ordering::Relaxed) {
+            match stream.receive() {
+                Ok(change) => {
+                    *self.latest.write().unwrap_or_else(PoisonError::into_inner) =
+                        change.value.clone();
+                    // The subscription may be dropped at any time, with its receiver.
+                    let _ = self.changes.send(change.value);
+                }
+                Err(StreamError::IoError(e)) if is_timeout(&e) && !stream.needs_reconnect() => {}
+                Err(e) => {
+                    log::warn!(target: LOG_TARGET, "Lost the watch of {}: {}", self.key, e);
+                    self.active.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        // The stream unwatches the key when dropped.
+        let _ = stream
+            .stream
+            .tcp()
+            .set_read_timeout(Some(self.unwatch_timeout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        time::Instant,
+    };
+
+    use crate::{
+        commands::encode_watch_reply,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    fn builder(server: &FakeServer) -> crate::client::ClientBuilder {
+        Client::builder().host("127.0.0.1").port(server.port)
+    }
+
+    /// The commands the server received, without handshakes.
+    fn commands(server: FakeServer) -> Vec<String> {
+        server
+            .received()
+            .into_iter()
+            .filter(|(name, _)| name != "HANDSHAKE")
+            .map(|(name, args)| format!("{} {}", name, args.join(" ")))
+            .collect()
+    }
+
+    /// Waits up to five seconds for the condition to hold.
+    fn eventually(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition not met in time");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_nested_scopes() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            // The watch of the outer key is lost after its first change.
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(1)),
+                Reply::Push(encode_watch_reply(&ScalarValue::VInt(2), "outer")),
+            ],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(10)),
+                Reply::ok(),
+            ],
+        ]);
+        let client = builder(&server).build().unwrap();
+        let outer_value = client
+            .watch_scope("outer", |outer| {
+                assert_eq!(outer.changes().collect::<Vec<_>>(), [ScalarValue::VInt(2)]);
+                assert!(!outer.is_active());
+                let inner_value = client
+                    .watch_scope("inner", |inner| {
+                        assert_eq!(client.active_watches(), ["inner", "outer"]);
+                        assert!(inner.is_active());
+                        inner.latest()
+                    })
+                    .unwrap();
+                assert_eq!(inner_value, ScalarValue::VInt(10));
+                assert_eq!(client.active_watches(), ["outer"]);
+                outer.latest()
+            })
+            .unwrap();
+        assert_eq!(outer_value, ScalarValue::VInt(2));
+        assert!(client.active_watches().is_empty());
+        drop(client);
+        assert_eq!(
+            commands(server),
+            ["GET.WATCH outer", "GET.WATCH inner", "UNWATCH inner"]
+        );
+    }
+
+    #[test]
+    fn test_unwatches_on_panic_and_after_client() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1)), Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(1)),
+                Reply::Push(encode_watch_reply(&ScalarValue::VInt(2), "app:key")),
+                Reply::ok(),
+            ],
+        ]);
+        let client = builder(&server).build().unwrap().with_prefix("app");
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            client.watch_scope("key", |_| panic!("consumer failed"))
+        }));
+        assert!(result.is_err());
+        assert!(client.active_watches().is_empty());
+
+        // A subscription outlives its client.
+        let subscription = client.subscribe("key").unwrap();
+        assert_eq!(subscription.key(), "key");
+        drop(client);
+        eventually(|| subscription.latest() == ScalarValue::VInt(2));
+        assert_eq!(
+            subscription.try_changes().collect::<Vec<_>>(),
+            [ScalarValue::VInt(2)]
+        );
+        drop(subscription);
+        let commands = commands(server);
+        assert_eq!(commands[1], "UNWATCH app:key");
+        assert_eq!(commands.last().unwrap(), "UNWATCH app:key");
+    }
+
+    #[test]
+    fn test_duplicate_subscriptions() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1)), Reply::ok()],
+        ]);
+        let client = builder(&server).build().unwrap();
+        let first = client.subscribe("key").unwrap();
+        let error = client.subscribe("key").unwrap_err();
+        assert!(matches!(&error, ClientError::AlreadySubscribed { key } if key == "key"));
+        assert_eq!(client.active_watches(), ["key"]);
+        drop(first);
+        drop(client);
+        assert_eq!(commands(server), ["GET.WATCH key", "UNWATCH key"]);
+
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            // The first watch is lost, so the server can accept the second one.
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1))],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1)), Reply::ok()],
+        ]);
+        let client = builder(&server)
+            .allow_duplicate_subscriptions(true)
+            .build()
+            .unwrap();
+        let first = client.subscribe("key").unwrap();
+        let second = client.subscribe("key").unwrap();
+        assert_eq!(client.active_watches(), ["key", "key"]);
+        drop(first);
+        assert_eq!(client.active_watches(), ["key"]);
+        drop(second);
+        assert!(client.active_watches().is_empty());
+    }
+}
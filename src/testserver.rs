@@ -0,0 +1,128 @@
+//! A DiceDB server for the tests that need a real one. The server is found once per test binary:
+//! `DICEDB_HOST` and `DICEDB_PORT` point at a server that is already running, and otherwise a
+//! `dicedb/dicedb` container is started on a port Docker picks, so it cannot collide with a local
+//! server. The container is removed when the test binary exits, or is interrupted.
+use std::{
+    env,
+    os::raw::c_int,
+    sync::{Mutex, OnceLock, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use testcontainers::{core::ContainerPort, runners::SyncRunner, Container, GenericImage, ImageExt};
+
+use crate::client::Client;
+
+const IMAGE: &str = "dicedb/dicedb";
+/// The release the tests run against, pinned so a new release cannot change the results of a
+/// test run. This is the [`MINIMUM_SERVER_VERSION`](crate::client::MINIMUM_SERVER_VERSION); bump
+/// both together.
+const TAG: &str = "v1.0.0";
+/// The port the server listens on inside the container, and the default of `DICEDB_PORT`.
+const SERVER_PORT: u16 = 7379;
+/// How long the container gets to start and accept a connection.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where the server of the test binary listens.
+struct Server {
+    host: String,
+    port: u16,
+}
+
+/// The server, or why none could be found, so every test reports it without trying again.
+static SERVER: OnceLock<Result<Server, String>> = OnceLock::new();
+
+/// The started container, taken out and removed when the test binary exits.
+static CONTAINER: Mutex<Option<Container<GenericImage>>> = Mutex::new(None);
+
+// The container lives in a static, and statics are never dropped, so it is removed by a function
+// registered with `atexit` instead. The `watchdog` feature of testcontainers only removes
+// containers when the process is interrupted by a signal, not when it exits normally.
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+/// Returns the host and port of the server, starting it on first use.
+pub(crate) fn address() -> (&'static str, u16) {
+    let server = SERVER.get_or_init(|| from_env().map_or_else(start_container, Ok));
+    match server {
+        Ok(server) => (&server.host, server.port),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Returns a new client connected to the server.
+pub(crate) fn client() -> Client {
+    let (host, port) = address();
+    Client::new(host.to_string(), port).unwrap()
+}
+
+/// The server named by `DICEDB_HOST` and `DICEDB_PORT`, if either is set.
+fn from_env() -> Option<Server> {
+    let host = env::var("DICEDB_HOST").ok();
+    let port = env::var("DICEDB_PORT").ok();
+    if host.is_none() && port.is_none() {
+        return None;
+    }
+    let port = port.map_or(SERVER_PORT, |port| {
+        port.parse()
+            .unwrap_or_else(|_| panic!("DICEDB_PORT is not a port number: {}", port))
+    });
+    Some(Server {
+        host: host.unwrap_or_else(|| "localhost".to_string()),
+        port,
+    })
+}
+
+fn start_container() -> Result<Server, String> {
+    let container = GenericImage::new(IMAGE, TAG)
+        .with_exposed_port(ContainerPort::Tcp(SERVER_PORT))
+        .with_startup_timeout(STARTUP_TIMEOUT)
+        .start()
+        .map_err(|e| {
+            format!(
+                "failed to start a DiceDB container, set DICEDB_HOST and DICEDB_PORT to test \
+                 against a running server instead: {}",
+                e
+            )
+        })?;
+    let host = container.get_host().map_err(|e| e.to_string())?.to_string();
+    let port = container
+        .get_host_port_ipv4(SERVER_PORT)
+        .map_err(|e| e.to_string())?;
+    *CONTAINER.lock().unwrap_or_else(PoisonError::into_inner) = Some(container);
+    // SAFETY: `atexit` only keeps the function to call it when the process exits.
+    unsafe { atexit(remove_container) };
+    wait_until_ready(&host, port)?;
+    Ok(Server { host, port })
+}
+
+/// Waits until the server accepts a client, as the port is mapped before the server listens.
+fn wait_until_ready(host: &str, port: u16) -> Result<(), String> {
+    let started = Instant::now();
+    loop {
+        match Client::new(host.to_string(), port) {
+            Ok(_) => return Ok(()),
+            Err(e) if started.elapsed() > STARTUP_TIMEOUT => {
+                return Err(format!(
+                    "the DiceDB container did not accept a client: {}",
+                    e
+                ))
+            }
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+extern "C" fn remove_container() {
+    let container = CONTAINER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    if let Some(container) = container {
+        if let Err(e) = container.rm() {
+            eprintln!("failed to remove the DiceDB container: {}", e);
+        }
+    }
+}
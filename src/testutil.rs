@@ -0,0 +1,181 @@
+//! Test helpers, most notably a scripted stand-in for the server so that connection handling can
+//! be tested without a running DiceDB.
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    commands::{decode_command, encode_error_reply, encode_list_reply, encode_reply, ScalarValue},
+    config::ConnectionOptions,
+    framing,
+};
+
+pub(crate) trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+/// What the fake server does after receiving a command.
+#[derive(Debug, Clone)]
+pub(crate) enum Reply {
+    /// Reply with the value.
+    Value(ScalarValue),
+    /// Reply with a list of values.
+    List(Vec<ScalarValue>),
+    /// Reply with an error, as for a command the server rejected.
+    Error(String),
+    /// Reply with the value, written one byte at a time.
+    Trickle(ScalarValue),
+    /// Wait for the given time, then reply with the value, like a slow server.
+    Delay(Duration, ScalarValue),
+    /// Write the bytes right away, without waiting for a command, like a watch push.
+    Push(Vec<u8>),
+    /// Close the connection without replying, and accept the next connection.
+    Close,
+    /// Stay silent for the given time, then close the connection like [`Reply::Close`].
+    Stall(Duration),
+}
+
+impl Reply {
+    /// The reply the server sends to a successful handshake.
+    pub(crate) fn ok() -> Reply {
+        Reply::Value(ScalarValue::VStr("OK".to_string()))
+    }
+}
+
+/// A server accepting one connection per script, answering each received command with the next
+/// reply of the script. The connection is closed when its script runs out.
+pub(crate) struct FakeServer {
+    pub(crate) port: u16,
+    handle: JoinHandle<Vec<(String, Vec<String>)>>,
+}
+
+/// CA certificate the fake server's TLS certificate for `localhost` and `127.0.0.1` is signed by.
+#[cfg(feature = "tls")]
+pub(crate) const TEST_CA: &[u8] = include_bytes!("../testdata/tls/ca.pem");
+
+impl FakeServer {
+    pub(crate) fn start(connections: Vec<Vec<Reply>>) -> FakeServer {
+        FakeServer::start_on(0, connections)
+    }
+
+    /// Like [`FakeServer::start`], but listening on the given port, such as the port of a server
+    /// that was stopped, to simulate a restart.
+    pub(crate) fn start_on(port: u16, connections: Vec<Vec<Reply>>) -> FakeServer {
+        FakeServer::spawn(port, connections, |socket| Box::new(socket))
+    }
+
+    /// Like [`FakeServer::start`], but the connections are encrypted with TLS.
+    #[cfg(feature = "tls")]
+    pub(crate) fn start_tls(connections: Vec<Vec<Reply>>) -> FakeServer {
+        use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+        let chain = CertificateDer::pem_slice_iter(include_bytes!("../testdata/tls/server.pem"))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key =
+            PrivateKeyDer::from_pem_slice(include_bytes!("../testdata/tls/server.key")).unwrap();
+        let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap();
+        let config = std::sync::Arc::new(config);
+        FakeServer::spawn(0, connections, move |socket| {
+            let connection = rustls::ServerConnection::new(config.clone()).unwrap();
+            Box::new(rustls::StreamOwned::new(connection, socket))
+        })
+    }
+
+    fn spawn(
+        port: u16,
+        connections: Vec<Vec<Reply>>,
+        wrap: impl Fn(TcpStream) -> Box<dyn ReadWrite> + Send + 'static,
+    ) -> FakeServer {
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buffer = vec![0; 64 * 1024];
+            for script in connections {
+                let (socket, _) = listener.accept().unwrap();
+                socket.set_nodelay(true).unwrap();
+                let mut socket = wrap(socket);
+                // Bytes received but not yet handled, as several commands can arrive together.
+                let mut pending = Vec::new();
+                for reply in script {
+                    if let Reply::Push(bytes) = &reply {
+                        socket.write_all(bytes).unwrap();
+                        socket.flush().unwrap();
+                        continue;
+                    }
+                    let len = loop {
+                        if let Some(len) = framing::command_len(&pending) {
+                            break Some(len);
+                        }
+                        match socket.read(&mut buffer) {
+                            Ok(0) | Err(_) => break None,
+                            Ok(size) => pending.extend_from_slice(&buffer[..size]),
+                        }
+                    };
+                    let Some(len) = len else { break };
+                    received.push(decode_command(&pending[..len]).unwrap());
+                    pending.drain(..len);
+                    match reply {
+                        Reply::Value(value) => {
+                            socket.write_all(&encode_reply(&value)).unwrap();
+                            socket.flush().unwrap();
+                        }
+                        Reply::List(values) => {
+                            socket.write_all(&encode_list_reply(&values)).unwrap();
+                            socket.flush().unwrap();
+                        }
+                        Reply::Error(message) => {
+                            socket.write_all(&encode_error_reply(&message)).unwrap();
+                            socket.flush().unwrap();
+                        }
+                        Reply::Trickle(value) => {
+                            for byte in encode_reply(&value) {
+                                socket.write_all(&[byte]).unwrap();
+                                socket.flush().unwrap();
+                            }
+                        }
+                        Reply::Delay(duration, value) => {
+                            thread::sleep(duration);
+                            // The client may have given up and closed the connection.
+                            let _ = socket.write_all(&encode_reply(&value));
+                            let _ = socket.flush();
+                        }
+                        Reply::Push(_) => unreachable!("pushes are written without a command"),
+                        Reply::Close => break,
+                        Reply::Stall(duration) => {
+                            thread::sleep(duration);
+                            break;
+                        }
+                    }
+                }
+            }
+            received
+        });
+        FakeServer { port, handle }
+    }
+
+    /// Connection options pointing at the fake server.
+    pub(crate) fn options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            ..ConnectionOptions::default()
+        }
+    }
+
+    /// Waits for every script to finish and returns the names and arguments of the received
+    /// commands, in order.
+    pub(crate) fn received(self) -> Vec<(String, Vec<String>)> {
+        self.handle.join().unwrap()
+    }
+}
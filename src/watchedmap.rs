@@ -0,0 +1,376 @@
+//! # WatchedMap Module
+//! A local copy of a few keys that is kept up to date by watching them, for values that are read
+//! often and change rarely, such as configuration.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::Client,
+    commands::ScalarValue,
+    config::{random_unit, ConnectionOptions},
+    errors::{is_timeout, ClientError, StreamError},
+    stream::Stream,
+    watchrpc::watch,
+    watchstream::WatchStream,
+    LOG_TARGET,
+};
+
+/// How often a listener checks whether the map was dropped while no change arrives.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The latest values of a set of keys, kept up to date by a thread per key that watches it with
+/// GET.WATCH. Reading a value takes a shared lock and never waits for the server.
+///
+/// When the connection of a watch is lost, the key is marked stale and watched again following
+/// the reconnect policy of the client, see [`WatchedMap::is_stale`]. With
+/// [`ReconnectPolicy::disabled`](crate::config::ReconnectPolicy::disabled) the key is not watched
+/// again and stays stale. Dropping the map stops the threads and unwatches the keys.
+/// # Example
+/// ```no_run
+/// use dicedb_rs::{client::Client, watchedmap::WatchedMap};
+///
+/// let mut client = Client::new("localhost".to_string(), 7379)?;
+/// let config = WatchedMap::new(&mut client, &["feature-flag", "rate-limit"])?;
+/// if !config.is_stale("feature-flag") {
+///     println!("feature flag: {:?}", config.get("feature-flag"));
+/// }
+/// # Ok::<(), dicedb_rs::errors::ClientError>(())
+/// ```
+#[derive(Debug)]
+pub struct WatchedMap {
+    entries: Arc<RwLock<HashMap<String, Entry>>>,
+    stop: Arc<AtomicBool>,
+    listeners: Vec<JoinHandle<()>>,
+}
+
+/// The latest value of a key.
+#[derive(Debug)]
+struct Entry {
+    value: ScalarValue,
+    updated: Instant,
+    /// Whether the key is being watched, so changes are seen as they happen.
+    subscribed: bool,
+}
+
+impl WatchedMap {
+    /// Watches the keys and returns a map holding their current values. Keys are scoped to the
+    /// prefix of the client, if any.
+    /// # Errors
+    /// * [`ClientError`] - If a key could not be watched, in which case no key is watched.
+    pub fn new(client: &mut Client, keys: &[&str]) -> Result<Self, ClientError> {
+        let mut map = WatchedMap {
+            entries: Arc::default(),
+            stop: Arc::default(),
+            listeners: Vec::with_capacity(keys.len()),
+        };
+        let mut streams = Vec::with_capacity(keys.len());
+        for key in keys {
+            if map.read().contains_key(*key) {
+                continue;
+            }
+            let (stream, value) = client.get_watch(key)?;
+            map.write().insert(key.to_string(), Entry::new(value));
+            streams.push((key.to_string(), stream));
+        }
+        for (key, stream) in streams {
+            let listener = Listener {
+                scoped_key: client.scoped_key(&key),
+                key,
+                options: client.options.clone(),
+                entries: map.entries.clone(),
+                stop: map.stop.clone(),
+            };
+            let handle = thread::Builder::new()
+                .name(format!("dicedb-watch-{}", listener.key))
+                .spawn(move || listener.run(stream))?;
+            map.listeners.push(handle);
+        }
+        Ok(map)
+    }
+
+    /// Returns the latest value observed for the key, or `None` if the key is not in the map.
+    pub fn get(&self, key: &str) -> Option<ScalarValue> {
+        self.read().get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Returns true if the key is not being watched at the moment because its connection was
+    /// lost, so its value may be outdated until it is watched again. A key that is not in the
+    /// map is always stale.
+    pub fn is_stale(&self, key: &str) -> bool {
+        self.read().get(key).map_or(true, |entry| !entry.subscribed)
+    }
+
+    /// Returns when the value of the key was last observed, either as a change or when the key
+    /// was watched again after losing its connection.
+    pub fn last_updated(&self, key: &str) -> Option<Instant> {
+        self.read().get(key).map(|entry| entry.updated)
+    }
+
+    /// Returns the keys in the map.
+    pub fn keys(&self) -> Vec<String> {
+        self.read().keys().cloned().collect()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Entry>> {
+        self.entries.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Entry>> {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for WatchedMap {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for listener in self.listeners.drain(..) {
+            // A panicking listener has already logged its panic.
+            let _ = listener.join();
+        }
+    }
+}
+
+impl Entry {
+    fn new(value: ScalarValue) -> Self {
+        Entry {
+            value,
+            updated: Instant::now(),
+            subscribed: true,
+        }
+    }
+}
+
+/// Watches a single key of a map on its own thread.
+struct Listener {
+    key: String,
+    scoped_key: String,
+    options: ConnectionOptions,
+    entries: Arc<RwLock<HashMap<String, Entry>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Listener {
+    /// Receives changes of the key until the map is dropped, watching the key again whenever its
+    /// connection is lost, unless reconnecting is disabled.
+    fn run(self, stream: WatchStream) {
+        let mut stream = Some(stream);
+        let mut attempts = 0;
+        while !self.stopped() {
+            let current = match stream.take() {
+                Some(current) => current,
+                None if !self.options.reconnect_policy.is_automatic() => {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "Not watching {} again, reconnecting is disabled",
+                        self.key
+                    );
+                    return;
+                }
+                None => match watch(self.options.clone(), self.scoped_key.clone()) {
+                    Ok((current, value)) => {
+                        attempts = 0;
+                        self.update(|entry| *entry = Entry::new(value));
+                        current
+                    }
+                    Err(e) => {
+                        attempts += 1;
+                        log::warn!(
+                            target: LOG_TARGET,
+                            "Failed to watch {} again: {}",
+                            self.key,
+                            e
+                        );
+                        let delay = self.options.reconnect_policy.delay(attempts, random_unit());
+                        self.sleep(delay);
+                        continue;
+                    }
+                },
+            };
+            self.listen(current);
+        }
+    }
+
+    /// Receives changes until the map is dropped or the connection is lost.
+    fn listen(&self, mut stream: WatchStream) {
+        if let Err(e) = stream.stream.tcp().set_read_timeout(Some(POLL_INTERVAL)) {
+            log::warn!(target: LOG_TARGET, "Failed to watch {}: {}", self.key, e);
+            self.update(|entry| entry.subscribed = false);
+            return;
+        }
+        while !self.stopped() {
+            match stream.receive() {
+                Ok(change) => self.update(|entry| *entry = Entry::new(change.value)),
+                Err(StreamError::IoError(e)) if is_timeout(&e) && !stream.needs_reconnect() => {}
+                Err(e) => {
+                    log::warn!(target: LOG_TARGET, "Lost the watch of {}: {}", self.key, e);
+                    self.update(|entry| entry.subscribed = false);
+                    return;
+                }
+            }
+        }
+        // The stream unwatches the key when dropped, which waits no longer than a handshake.
+        let _ = stream
+            .stream
+            .tcp()
+            .set_read_timeout(Some(self.options.handshake_timeout));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Entry)) {
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = entries.get_mut(&self.key) {
+            f(entry);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for the delay, waking up early if the map is dropped.
+    fn sleep(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        while !self.stopped() {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return;
+            }
+            thread::sleep(left.min(POLL_INTERVAL));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::encode_watch_reply,
+        config::ReconnectPolicy,
+        testserver,
+        testutil::{FakeServer, Reply},
+    };
+
+    use super::*;
+
+    /// Waits up to five seconds for the condition to hold.
+    fn eventually(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition not met in time");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_follows_changes_and_unwatches_on_drop() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(1)),
+                Reply::Push(encode_watch_reply(&ScalarValue::VInt(2), "config")),
+                Reply::ok(),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let map = WatchedMap::new(&mut client, &["config", "config"]).unwrap();
+        assert_eq!(map.keys(), vec!["config"]);
+        eventually(|| map.get("config") == Some(ScalarValue::VInt(2)));
+        assert!(!map.is_stale("config"));
+        assert!(map.is_stale("unknown"));
+        assert_eq!(map.get("unknown"), None);
+        // Dropping the map waits for its thread, which unwatches the key.
+        drop(map);
+        drop(client);
+        let received = server.received();
+        assert_eq!(
+            received.last().unwrap(),
+            &("UNWATCH".to_string(), vec!["config".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_watches_again_after_losing_connection() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            // The connection is closed after the first value.
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1))],
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(5)), Reply::ok()],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .reconnect_policy(ReconnectPolicy::fixed(3, Duration::from_millis(10)))
+            .build()
+            .unwrap();
+        let map = WatchedMap::new(&mut client, &["config"]).unwrap();
+        eventually(|| map.get("config") == Some(ScalarValue::VInt(5)));
+        assert!(!map.is_stale("config"));
+        drop(map);
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "HANDSHAKE",
+                "HANDSHAKE",
+                "GET.WATCH",
+                "HANDSHAKE",
+                "GET.WATCH",
+                "UNWATCH"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stays_stale_when_reconnecting_is_disabled() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            // The connection is closed after the first value.
+            vec![Reply::ok(), Reply::Value(ScalarValue::VInt(1))],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .reconnect_policy(ReconnectPolicy::disabled())
+            .build()
+            .unwrap();
+        let map = WatchedMap::new(&mut client, &["config"]).unwrap();
+        // The listener gives up on the key instead of watching it again.
+        eventually(|| map.listeners.iter().all(JoinHandle::is_finished));
+        assert!(map.is_stale("config"));
+        assert_eq!(map.get("config"), Some(ScalarValue::VInt(1)));
+        drop(map);
+        drop(client);
+        let names: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["HANDSHAKE", "HANDSHAKE", "GET.WATCH"]);
+    }
+
+    #[test]
+    fn test_reflects_changes_by_another_client() {
+        let mut client = testserver::client();
+        let mut writer = testserver::client();
+        writer.set("watchedmapkey", 1).unwrap();
+        let map = WatchedMap::new(&mut client, &["watchedmapkey"]).unwrap();
+        assert_eq!(map.get("watchedmapkey"), Some(ScalarValue::VInt(1)));
+        writer.set("watchedmapkey", 2).unwrap();
+        eventually(|| map.get("watchedmapkey") == Some(ScalarValue::VInt(2)));
+    }
+}
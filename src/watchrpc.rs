@@ -1,7 +1,10 @@
+use std::time::Duration;
+
 use crate::{
     client::Client,
-    commands::{Command, CommandExecutor, ScalarValue},
-    errors::ClientError,
+    commands::{Command, Execute, ScalarValue},
+    config::ConnectionOptions,
+    errors::{is_unknown_command, ClientError, StreamError, WatchStreamError},
     stream::Stream,
     watchstream::WatchStream,
 };
@@ -17,29 +20,145 @@ impl Client {
     /// # Returns
     /// * A watch stream and the first value of the key
     /// # Errors
-    /// * If the watch stream could not be created
+    /// * [`WatchStreamError::Connect`] - If the connection for the watch could not be
+    /// established within the connect timeout.
+    /// * [`WatchStreamError::Handshake`] - If the handshake of the connection failed or took
+    /// longer than the handshake timeout.
+    /// * [`WatchStreamError::Subscribe`] - If the server rejected the watch, or did not reply to
+    /// it within the read timeout, or the handshake timeout if no read timeout is set.
     pub fn get_watch(&mut self, key: &str) -> Result<(WatchStream, ScalarValue)> {
-        let mut new_watch_stream = WatchStream::new(self.host.clone(), self.port)?;
-        new_watch_stream.handshake()?;
-        let get_watch = Command::GETWATCH {
-            key: key.to_string(),
-        };
-        let reply = new_watch_stream.execute_scalar_command(get_watch)?;
-        new_watch_stream.fingerprint = Some(key.to_string());
-        Ok((new_watch_stream, reply))
+        self.open_watch(key)
+    }
+
+    /// Get a watch stream for a single field of a hash, which only yields changes of that field.
+    /// The field is unwatched together with the key when the stream is dropped.
+    /// # Arguments
+    /// * `key` - The key of the hash
+    /// * `field` - The field to watch
+    /// # Returns
+    /// * A watch stream and the first value of the field
+    /// # Errors
+    /// * [`WatchStreamError::Unsupported`] - If the server does not know HGET.WATCH.
+    /// * [`ClientError`] - If the field could not be watched otherwise, see
+    /// [`Client::get_watch`].
+    pub fn hget_watch(&mut self, key: &str, field: &str) -> Result<(WatchStream, ScalarValue)> {
+        self.check_watch()?;
+        watch_field(
+            self.options.clone(),
+            self.scoped_key(key),
+            field.to_string(),
+        )
+    }
+
+    /// Like [`Client::get_watch`], needing only a shared reference as the watch gets a
+    /// connection of its own.
+    pub(crate) fn open_watch(&self, key: &str) -> Result<(WatchStream, ScalarValue)> {
+        self.check_watch()?;
+        watch(self.options.clone(), self.scoped_key(key))
+    }
+
+    fn check_watch(&self) -> Result<()> {
+        match self.stream() {
+            Some(_) => Ok(()),
+            None => Err(ClientError::ConfigError(
+                "watching a key needs a connection to a server".to_string(),
+            )),
+        }
     }
 }
 
+/// Opens a watch stream for a key that is already scoped, and returns it with the current value
+/// of the key.
+///
+/// The stream is only returned once the server accepted the watch. A stream that failed before is
+/// dropped without a fingerprint, so it closes its connection without unwatching anything.
+pub(crate) fn watch(options: ConnectionOptions, key: String) -> Result<(WatchStream, ScalarValue)> {
+    open(options, key, None)
+}
+
+/// Opens a watch stream for a field of a hash whose key is already scoped, and returns it with the
+/// current value of the field.
+pub(crate) fn watch_field(
+    options: ConnectionOptions,
+    key: String,
+    field: String,
+) -> Result<(WatchStream, ScalarValue)> {
+    open(options, key, Some(field))
+}
+
+fn open(
+    options: ConnectionOptions,
+    key: String,
+    field: Option<String>,
+) -> Result<(WatchStream, ScalarValue)> {
+    let subscribe_timeout = options.read_timeout.unwrap_or(options.handshake_timeout);
+    let mut new_watch_stream =
+        WatchStream::new(options).map_err(|source| WatchStreamError::Connect {
+            key: key.clone(),
+            source,
+        })?;
+    new_watch_stream
+        .handshake()
+        .map_err(|source| WatchStreamError::Handshake {
+            key: key.clone(),
+            source,
+        })?;
+    let command = match &field {
+        Some(field) => Command::HGETWATCH {
+            key: key.clone(),
+            field: field.clone(),
+        },
+        None => Command::GETWATCH { key: key.clone() },
+    };
+    let name = command.name();
+    let reply = subscribe(&mut new_watch_stream, command, subscribe_timeout).map_err(|source| {
+        match is_unknown_command(&source) {
+            true => WatchStreamError::Unsupported {
+                key: key.clone(),
+                command: name,
+                source,
+            },
+            false => WatchStreamError::Subscribe {
+                key: key.clone(),
+                source,
+            },
+        }
+    })?;
+    new_watch_stream.fingerprint = Some(key);
+    new_watch_stream.field = field;
+    Ok((new_watch_stream, reply))
+}
+
+/// Sends the watch command and waits for its reply no longer than `timeout`. Watch streams wait
+/// for changes indefinitely afterwards.
+fn subscribe(
+    stream: &mut WatchStream,
+    command: Command,
+    timeout: Duration,
+) -> std::result::Result<ScalarValue, StreamError> {
+    stream.stream.tcp().set_read_timeout(Some(timeout))?;
+    let reply = stream.execute_scalar_command(command)?;
+    let read_timeout = stream.options().read_timeout_for(&stream.mode());
+    stream.stream.tcp().set_read_timeout(read_timeout)?;
+    Ok(reply)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
+        net::TcpListener,
         sync::{Arc, Mutex},
         thread,
+        time::Instant,
     };
 
     use super::*;
-    const HOST: &str = "localhost";
-    const PORT: u16 = 7379;
+    use crate::{
+        commands::{encode_watch_reply, ChangeKind, WatchValue},
+        stats::WatchStats,
+        testserver,
+        testutil::{FakeServer, Reply},
+    };
 
     // BUG: When keys contain underscores, it seems to give inconsistent behaviors
     #[allow(dead_code)]
@@ -61,7 +180,7 @@ mod tests {
 
     #[test]
     fn test_create_watcher() {
-        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let mut client = testserver::client();
         let key = KEYS[0];
         let watch_stream = client.get_watch(key);
         assert!(watch_stream.is_ok());
@@ -69,7 +188,7 @@ mod tests {
 
     #[test]
     fn test_get_watch_first_value_null() {
-        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let mut client = testserver::client();
         let key = KEYS[1];
         let watch_stream = client.get_watch(key).unwrap();
         let (_, first_value) = watch_stream;
@@ -78,7 +197,7 @@ mod tests {
 
     #[test]
     fn test_get_watch_first_val_int() {
-        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let mut client = testserver::client();
         let key = KEYS[2];
         client.set(key, 1).unwrap();
         let watch_stream = client.get_watch(key).unwrap();
@@ -86,11 +205,186 @@ mod tests {
         assert_eq!(first_value, ScalarValue::VInt(1));
     }
 
+    #[test]
+    fn test_get_watch_prefixed() {
+        let mut client = testserver::client().with_prefix("watchprefix");
+        let mut unscoped = testserver::client();
+        unscoped.set("watchprefix:watchkey", 7).unwrap();
+        let (watch_stream, first_value) = client.get_watch("watchkey").unwrap();
+        assert_eq!(first_value, ScalarValue::VInt(7));
+        assert_eq!(
+            watch_stream.fingerprint.as_deref(),
+            Some("watchprefix:watchkey")
+        );
+    }
+
+    #[test]
+    fn test_hget_watch_field() {
+        let key = "hgetwatchkey";
+        let mut client = testserver::client();
+        client.del(key).unwrap();
+        client.hset(key, ("status", "online")).unwrap();
+        let (watch_stream, first_value) = client.hget_watch(key, "status").unwrap();
+        assert_eq!(first_value, ScalarValue::VStr("online".to_string()));
+        let (sender, changes) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for change in watch_stream {
+                if sender.send(change.value).is_err() {
+                    break;
+                }
+            }
+        });
+        client.hset(key, ("status", "away")).unwrap();
+        assert_eq!(
+            changes.recv_timeout(Duration::from_secs(2)).unwrap(),
+            ScalarValue::VStr("away".to_string())
+        );
+        // Changes of other fields of the hash are not watched.
+        client.hset(key, ("name", "alice")).unwrap();
+        assert!(changes.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    /// Watches the key on a thread of its own, sending every change to the returned receiver.
+    fn watch_changes(client: &mut Client, key: &str) -> std::sync::mpsc::Receiver<WatchValue> {
+        let (watch_stream, _) = client.get_watch(key).unwrap();
+        let (sender, changes) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for change in watch_stream {
+                if sender.send(change).is_err() {
+                    break;
+                }
+            }
+        });
+        changes
+    }
+
+    #[test]
+    fn test_watch_key_deleted() {
+        let key = "watchkeydeleted";
+        let mut client = testserver::client();
+        client.set(key, "placeholder").unwrap();
+        let changes = watch_changes(&mut client, key);
+        client.set(key, "").unwrap();
+        let updated = changes.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(updated.change, ChangeKind::Updated);
+        client.del(key).unwrap();
+        let removed = changes.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(removed.change, ChangeKind::Removed);
+        assert_eq!(removed.value, ScalarValue::VNull);
+    }
+
+    #[test]
+    fn test_watch_key_expired() {
+        let key = "watchkeyexpired";
+        let mut client = testserver::client();
+        client.set(key, 1).unwrap();
+        let changes = watch_changes(&mut client, key);
+        client
+            .expire(key, 1, crate::commands::ExpireOption::None)
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let removed = loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let change = changes.recv_timeout(timeout).unwrap();
+            if change.change.is_removed() {
+                break change;
+            }
+        };
+        assert_eq!(removed.value, ScalarValue::VNull);
+    }
+
+    #[test]
+    fn test_watch_change_kind() {
+        let pushes: Vec<u8> = [ScalarValue::VStr(String::new()), ScalarValue::VNull]
+            .iter()
+            .flat_map(|value| encode_watch_reply(value, "fingerprint"))
+            .collect();
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("placeholder".to_string())),
+                Reply::Push(pushes),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let (watch_stream, _) = client.get_watch("key").unwrap();
+        let changes: Vec<ChangeKind> = watch_stream.map(|value| value.change).collect();
+        assert_eq!(changes, [ChangeKind::Updated, ChangeKind::Removed]);
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_hget_watch_unwatches_field() {
+        let push = encode_watch_reply(&ScalarValue::VStr("away".to_string()), "fingerprint");
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VStr("online".to_string())),
+                Reply::Push(push),
+                Reply::ok(),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap()
+            .with_prefix("app");
+        let (mut watch_stream, first) = client.hget_watch("user:42", "status").unwrap();
+        assert_eq!(first, ScalarValue::VStr("online".to_string()));
+        assert_eq!(
+            watch_stream.next().unwrap().value,
+            ScalarValue::VStr("away".to_string())
+        );
+        drop(watch_stream);
+        drop(client);
+        let received = server.received();
+        let field_args = vec!["app:user:42".to_string(), "status".to_string()];
+        assert_eq!(received[2], ("HGET.WATCH".to_string(), field_args.clone()));
+        assert_eq!(received[3], ("UNWATCH".to_string(), field_args));
+    }
+
+    #[test]
+    fn test_hget_watch_unsupported() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Error("ERR unknown command 'HGET.WATCH'".to_string()),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let error = client.hget_watch("user:42", "status").unwrap_err();
+        assert!(matches!(
+            &error,
+            ClientError::WatchStreamError(WatchStreamError::Unsupported { key, command, .. })
+                if key == "user:42" && *command == "HGET.WATCH"
+        ));
+        assert_eq!(error.kind(), crate::errors::ErrorKind::UnsupportedServer);
+        assert_eq!(
+            error.to_string(),
+            "failed to watch \"user:42\": the server does not support HGET.WATCH"
+        );
+        drop(client);
+        server.received();
+    }
+
     #[test]
     #[ignore] // BUG: Flaky test
     fn test_get_watch_iter() {
         let key = KEYS[3];
-        let mut client = Client::new(HOST.to_string(), PORT).unwrap();
+        let mut client = testserver::client();
         client.del(key).unwrap();
         thread::sleep(std::time::Duration::from_secs(1));
         let (watch_stream, _) = client.get_watch(key).unwrap();
@@ -123,4 +417,153 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_watch_pushes_in_one_write() {
+        let pushes: Vec<u8> = (1..=3)
+            .flat_map(|i| encode_watch_reply(&ScalarValue::VInt(i), "fingerprint"))
+            .collect();
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(0)),
+                Reply::Push(pushes),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let (watch_stream, first) = client.get_watch("key").unwrap();
+        assert_eq!(first, ScalarValue::VInt(0));
+        let values: Vec<ScalarValue> = watch_stream.map(|value| value.value).collect();
+        assert_eq!(
+            values,
+            vec![
+                ScalarValue::VInt(1),
+                ScalarValue::VInt(2),
+                ScalarValue::VInt(3)
+            ]
+        );
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_watch_stats() {
+        let mut pushes: Vec<u8> = (1..=2)
+            .flat_map(|i| encode_watch_reply(&ScalarValue::VInt(i), "fingerprint"))
+            .collect();
+        // Wire type 7 does not exist, so the last message cannot be decoded.
+        pushes.extend([0x0f, 0x01, 0x02]);
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![
+                Reply::ok(),
+                Reply::Value(ScalarValue::VInt(0)),
+                Reply::Push(pushes),
+            ],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        let (mut watch_stream, _) = client.get_watch("key").unwrap();
+        assert_eq!(watch_stream.by_ref().count(), 2);
+        let stats = watch_stream.stats();
+        assert_eq!(stats.messages_received, 2);
+        assert_eq!(stats.dropped_values, 1);
+        assert!(stats.bytes_read > 0);
+        watch_stream.reset_stats();
+        assert_eq!(watch_stream.stats(), WatchStats::default());
+        drop(watch_stream);
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_get_watch_connect_error() {
+        let server = FakeServer::start(vec![vec![Reply::ok()]]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .build()
+            .unwrap();
+        // A port nothing listens on anymore.
+        client.options.port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let error = client.get_watch("key").unwrap_err();
+        assert!(matches!(
+            &error,
+            ClientError::WatchStreamError(WatchStreamError::Connect { key, .. }) if key == "key"
+        ));
+        assert!(error.is_connection_error());
+        drop(client);
+        server.received();
+    }
+
+    #[test]
+    fn test_get_watch_handshake_timeout() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![Reply::Stall(Duration::from_millis(500))],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .handshake_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        let error = client.get_watch("key").unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(matches!(
+            &error,
+            ClientError::WatchStreamError(WatchStreamError::Handshake { key, .. }) if key == "key"
+        ));
+        assert!(error.is_timeout());
+        drop(client);
+        let received = server.received();
+        assert_eq!(received.len(), 2);
+    }
+
+    #[test]
+    fn test_get_watch_subscribe_timeout() {
+        let server = FakeServer::start(vec![
+            vec![Reply::ok()],
+            vec![Reply::ok(), Reply::Stall(Duration::from_millis(500))],
+        ]);
+        let mut client = Client::builder()
+            .host("127.0.0.1")
+            .port(server.port)
+            .read_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap()
+            .with_prefix("app");
+        let started = Instant::now();
+        let error = client.get_watch("key").unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(matches!(
+            &error,
+            ClientError::WatchStreamError(WatchStreamError::Subscribe { key, .. }) if key == "app:key"
+        ));
+        assert!(error.is_timeout());
+        assert!(error
+            .to_string()
+            .starts_with("failed to watch \"app:key\": "));
+        drop(client);
+        // The stream that never subscribed closes without unwatching.
+        let commands: Vec<String> = server
+            .received()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(commands, ["HANDSHAKE", "HANDSHAKE", "GET.WATCH"]);
+    }
 }
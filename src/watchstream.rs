@@ -1,13 +1,17 @@
 //! # WatchStream Module
 //! The watchstream module contains the WatchStream struct and its implementation.
-use std::io;
-
 use uuid::Uuid;
 
 use crate::{
-    commands::{Command, CommandExecutor, ExecutionMode, ScalarValue, WatchValue},
-    errors::{StreamError, WatchStreamError},
-    stream::{Stream, WatchValueReceiver},
+    commands::{Command, Execute, ExecutionMode, WatchValue},
+    config::{ConnectionOptions, ConnectionState},
+    errors::StreamError,
+    stats::{Counters, WatchStats},
+    stream::{
+        bounded_handshake, check_handshake, conn_info, Authenticator, ReadBuffer, Stream,
+        WatchValueReceiver, WriteBuffer,
+    },
+    transport::Transport,
 };
 
 /// WatchStream is a stream that is used to watch for changes in a key.
@@ -36,32 +40,83 @@ use crate::{
 /// ```
 #[derive(Debug)]
 pub struct WatchStream {
-    host: String,
-    port: u16,
+    options: ConnectionOptions,
     pub(crate) fingerprint: Option<String>,
+    /// The field watched with HGET.WATCH, unwatched together with the key.
+    pub(crate) field: Option<String>,
     pub(crate) id: String,
-    pub(crate) stream: std::net::TcpStream,
+    pub(crate) stream: Transport,
+    read_buffer: ReadBuffer,
+    write_buffer: WriteBuffer,
+    state: ConnectionState,
+    counters: Counters,
 }
 
 impl WatchStream {
-    pub(crate) fn new(host: String, port: u16) -> Result<Self, WatchStreamError> {
-        let stream = std::net::TcpStream::connect(format!("{}:{}", host, port))?;
+    pub(crate) fn new(options: ConnectionOptions) -> std::io::Result<Self> {
+        let stream = options.connect(&ExecutionMode::Watch, None)?;
         let id = Uuid::new_v4().to_string();
         let fingerprint = None;
+        let read_buffer = ReadBuffer::new(&options);
         Ok(WatchStream {
             stream,
             id,
             fingerprint,
-            host,
-            port,
+            field: None,
+            options,
+            read_buffer,
+            write_buffer: WriteBuffer::default(),
+            state: ConnectionState::Healthy,
+            counters: Counters::new(),
         })
     }
+
+    /// Returns the id the stream identifies itself with in the handshake. It is generated when the
+    /// stream is created and kept across reconnects.
+    pub fn client_id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns a snapshot of the counters of the stream: the values it yielded, the bytes it read
+    /// and the messages it could not decode.
+    pub fn stats(&self) -> WatchStats {
+        self.counters.watch_stats()
+    }
+
+    /// Sets the counters returned by [`WatchStream::stats`] back to zero.
+    pub fn reset_stats(&self) {
+        self.counters.reset();
+    }
+
+    /// Receives the next value, counting it and passing it to the observer.
+    pub(crate) fn receive(&mut self) -> Result<WatchValue, StreamError> {
+        let value = self.recieve_watchvalue();
+        match &value {
+            Ok(val) => {
+                self.counters.watch_message();
+                self.options.observer.watch_message(val);
+            }
+            Err(StreamError::CommandError(_)) => self.counters.dropped(),
+            Err(_) => {}
+        }
+        value
+    }
 }
 
 impl Drop for WatchStream {
     fn drop(&mut self) {
+        // A lost connection already ended the watch, so there is no need to reconnect for it.
+        if self.needs_reconnect() {
+            return;
+        }
         match &self.fingerprint {
-            Some(f) => _ = self.execute_scalar_command(Command::UNWATCH { key: f.to_string() }),
+            Some(f) => {
+                let unwatch = Command::UNWATCH {
+                    key: f.to_string(),
+                    field: self.field.clone(),
+                };
+                _ = self.execute_scalar_command(unwatch)
+            }
             None => {}
         }
     }
@@ -71,43 +126,62 @@ impl Iterator for WatchStream {
     type Item = WatchValue;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let value = self.recieve_watchvalue();
-        match value {
-            Ok(val) => Some(val),
-            Err(_) => None,
-        }
+        self.receive().ok()
     }
 }
 
 impl Stream for WatchStream {
-    fn host(&self) -> &str {
-        self.host.as_str()
+    fn options(&self) -> &ConnectionOptions {
+        &self.options
     }
 
-    fn port(&self) -> u16 {
-        self.port
+    fn mode(&self) -> ExecutionMode {
+        ExecutionMode::Watch
     }
 
-    fn set_stream(&mut self, stream: std::net::TcpStream) {
-        self.stream = stream;
+    fn set_transport(&mut self, transport: Transport) {
+        self.stream = transport;
     }
 
-    fn tcp_stream(&mut self) -> &std::net::TcpStream {
-        &self.stream
+    fn transport(&mut self) -> &mut Transport {
+        &mut self.stream
+    }
+
+    fn read_buffer(&mut self) -> &mut ReadBuffer {
+        &mut self.read_buffer
+    }
+
+    fn write_buffer(&mut self) -> &mut WriteBuffer {
+        &mut self.write_buffer
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        self.state = state;
     }
 
     fn handshake(&mut self) -> Result<(), StreamError> {
-        let handshake = Command::HANDSHAKE {
-            client_id: self.id.clone(),
-            execution_mode: ExecutionMode::Watch,
-        };
-        let reply = self.execute_scalar_command(handshake)?;
-        match reply {
-            ScalarValue::VStr(v) if v == "OK" => Ok(()),
-            value => Err(StreamError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Handshake error: {:?}", value),
-            ))),
-        }
+        bounded_handshake(self, |stream| {
+            stream.authenticate()?;
+            let handshake = Command::HANDSHAKE {
+                client_id: stream.id.clone(),
+                execution_mode: ExecutionMode::Watch,
+            };
+            check_handshake(stream.execute_scalar_command(handshake))?;
+            let info = conn_info(stream);
+            stream.options.events.connected(&info);
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,42 @@
+//! # Wire Module
+//! The protobuf messages of the wire protocol, as generated from the protocol definition of the
+//! server, for debugging protocol issues, building proxies and writing custom tooling.
+//!
+//! The types follow the protocol definition, not the API of this crate, so they are **exempt
+//! from semantic versioning** and may change in any release along with the server.
+//!
+//! Commands and replies are bare protobuf messages without a length prefix: the server reads one
+//! command per message it receives, and a reply ends where its last field ends.
+//! # Example
+//! ```
+//! use dicedb_rs::wire::{self, decode_response, encode_command};
+//!
+//! let command = wire::Command {
+//!     cmd: "SET".to_string(),
+//!     args: vec!["key".to_string(), "value".to_string()],
+//! };
+//! let bytes = encode_command(&command);
+//! // Write `bytes` to the connection, and decode the reply read from it.
+//! # let bytes = prost::Message::encode_to_vec(&wire::Response {
+//! #     value: Some(wire::response::Value::VStr("OK".to_string())),
+//! #     ..Default::default()
+//! # });
+//! let response = decode_response(&bytes)?;
+//! assert_eq!(response.value, Some(wire::response::Value::VStr("OK".to_string())));
+//! # Ok::<(), prost::DecodeError>(())
+//! ```
+use prost::{DecodeError, Message};
+
+pub use crate::commands::wire::*;
+
+/// Encodes a command the way the client sends it.
+pub fn encode_command(command: &Command) -> Vec<u8> {
+    command.encode_to_vec()
+}
+
+/// Decodes a reply of the server. `bytes` must hold exactly one reply.
+/// # Errors
+/// Returns a [`DecodeError`] if the bytes are not a valid reply.
+pub fn decode_response(bytes: &[u8]) -> Result<Response, DecodeError> {
+    Response::decode(bytes)
+}